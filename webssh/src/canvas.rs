@@ -0,0 +1,43 @@
+// The canvas of the terminal is shared with webtelnet in webterm, its events
+// are sent along with those of the panels around it
+
+use crate::castpanel::CastEvent;
+use crate::filepanel::FileEvent;
+use crate::findbar::{self, FindEvent};
+use crate::tabbar::TabEvent;
+use crate::terminal::input::{KeyInput, MouseInput};
+
+use webterm::canvas::{self, Options};
+
+pub type CanvasUtils = canvas::CanvasUtils<CanvasEvent>;
+
+pub const OPTIONS: Options<CanvasEvent> = Options {
+    canvas_id: "ssh-canvas",
+    find: Some(findbar::open),
+};
+
+/// Input from the page to the session
+#[derive(Debug, Clone)]
+pub enum CanvasEvent {
+    Key(KeyInput),
+    Mouse(MouseInput),
+    /// Text pasted from the clipboard panel
+    Paste(String),
+    Find(FindEvent),
+    Files(FileEvent),
+    Tab(TabEvent),
+    Cast(CastEvent),
+    /// The page fits a terminal of (cols, rows) now
+    Resize(usize, usize),
+}
+
+impl From<canvas::CanvasEvent> for CanvasEvent {
+    fn from(event: canvas::CanvasEvent) -> Self {
+        match event {
+            canvas::CanvasEvent::Key(key) => CanvasEvent::Key(key),
+            canvas::CanvasEvent::Mouse(mouse) => CanvasEvent::Mouse(mouse),
+            canvas::CanvasEvent::Paste(text) => CanvasEvent::Paste(text),
+            canvas::CanvasEvent::Resize(cols, rows) => CanvasEvent::Resize(cols, rows),
+        }
+    }
+}
@@ -0,0 +1,471 @@
+// asciicast file format (version 2)
+//     https://docs.asciinema.org/manual/asciicast/v2/
+
+use crate::terminal::Terminal;
+use serde_json::{json, Value};
+
+pub const MIME: &str = "application/x-asciicast";
+
+/// The speeds offered by the player
+pub const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    /// Unix time of the start of the recording
+    pub timestamp: Option<u64>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    Output,
+    Input,
+    /// The terminal is resized to (cols, rows)
+    Resize(usize, usize),
+    Marker,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Seconds since the start of the recording
+    pub time: f64,
+    pub kind: EventKind,
+    pub data: String,
+}
+
+impl Event {
+    fn code(&self) -> &'static str {
+        match self.kind {
+            EventKind::Output => "o",
+            EventKind::Input => "i",
+            EventKind::Resize(..) => "r",
+            EventKind::Marker => "m",
+        }
+    }
+
+    fn to_line(&self) -> String {
+        // like asciinema, microseconds are enough
+        let time = (self.time * 1e6).round() / 1e6;
+        json!([time, self.code(), self.data]).to_string()
+    }
+}
+
+/// Records a terminal session, the times are in milliseconds as given by the
+/// clock of the browser
+pub struct Recorder {
+    header: Header,
+    start: f64,
+    input: bool,
+    events: Vec<Event>,
+    // the bytes of an incomplete UTF-8 character, per output and input
+    partial: [Vec<u8>; 2],
+}
+
+impl Recorder {
+    /// Start to record the terminal described by `header`, `input` tells if
+    /// the keys typed are recorded as well
+    pub fn new(header: Header, now: f64, input: bool) -> Self {
+        Self {
+            header,
+            start: now,
+            input,
+            events: Vec::new(),
+            partial: [Vec::new(), Vec::new()],
+        }
+    }
+
+    pub fn records_input(&self) -> bool {
+        self.input
+    }
+
+    fn elapsed(&self, now: f64) -> f64 {
+        // the events are ordered even if the clock goes back
+        let time = ((now - self.start) / 1000.0).max(0.0);
+        self.events.last().map_or(time, |e| time.max(e.time))
+    }
+
+    // The events carry text, an UTF-8 sequence split over two chunks is
+    // kept until it is complete
+    fn push_bytes(&mut self, now: f64, kind: EventKind, data: &[u8]) {
+        let partial = &mut self.partial[(kind == EventKind::Input) as usize];
+        partial.extend_from_slice(data);
+        let valid = match std::str::from_utf8(partial) {
+            Ok(_) => partial.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => partial.len(),
+        };
+        let rest = partial.split_off(valid);
+        let text = String::from_utf8_lossy(partial).into_owned();
+        *partial = rest;
+        if !text.is_empty() {
+            let time = self.elapsed(now);
+            self.events.push(Event {
+                time,
+                kind,
+                data: text,
+            });
+        }
+    }
+
+    pub fn output(&mut self, now: f64, data: &[u8]) {
+        self.push_bytes(now, EventKind::Output, data);
+    }
+
+    /// Ignored unless the input is recorded
+    pub fn input(&mut self, now: f64, data: &[u8]) {
+        if self.input {
+            self.push_bytes(now, EventKind::Input, data);
+        }
+    }
+
+    pub fn resize(&mut self, now: f64, cols: usize, rows: usize) {
+        let time = self.elapsed(now);
+        self.events.push(Event {
+            time,
+            kind: EventKind::Resize(cols, rows),
+            data: format!("{}x{}", cols, rows),
+        });
+    }
+
+    /// The recording as asciicast v2, a header line and a line per event
+    pub fn finish(&self) -> String {
+        let mut header = json!({
+            "version": 2,
+            "width": self.header.width,
+            "height": self.header.height,
+            "env": { "TERM": "xterm-256color" },
+        });
+        if let Some(timestamp) = self.header.timestamp {
+            header["timestamp"] = json!(timestamp);
+        }
+        if let Some(title) = &self.header.title {
+            header["title"] = json!(title);
+        }
+        let mut cast = header.to_string();
+        cast.push('\n');
+        for event in &self.events {
+            cast.push_str(&event.to_line());
+            cast.push('\n');
+        }
+        cast
+    }
+}
+
+/// A recording loaded from an asciicast v2 file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+fn parse_size(size: &str) -> Option<(usize, usize)> {
+    let (cols, rows) = size.split_once('x')?;
+    Some((cols.parse().ok()?, rows.parse().ok()?))
+}
+
+impl Cast {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let (_, first) = lines.next().ok_or("Empty recording")?;
+        let header: Value =
+            serde_json::from_str(first).map_err(|e| format!("Bad header: {}", e))?;
+        if header["version"] != 2 {
+            return Err("Only asciicast version 2 is supported".to_owned());
+        }
+        let size = |name: &str| {
+            header[name]
+                .as_u64()
+                .filter(|size| *size > 0)
+                .map(|size| size as usize)
+                .ok_or_else(|| format!("Bad header: no {}", name))
+        };
+        let header = Header {
+            width: size("width")?,
+            height: size("height")?,
+            timestamp: header["timestamp"].as_u64(),
+            title: header["title"].as_str().map(str::to_owned),
+        };
+
+        let mut events = Vec::new();
+        for (n, line) in lines {
+            let bad = || format!("Bad event on line {}", n + 1);
+            let event: Value = serde_json::from_str(line).map_err(|_| bad())?;
+            let time = event[0].as_f64().ok_or_else(bad)?;
+            let data = event[2].as_str().ok_or_else(bad)?.to_owned();
+            let kind = match event[1].as_str().ok_or_else(bad)? {
+                "o" => EventKind::Output,
+                "i" => EventKind::Input,
+                "r" => {
+                    let (cols, rows) = parse_size(&data).ok_or_else(bad)?;
+                    EventKind::Resize(cols, rows)
+                }
+                "m" => EventKind::Marker,
+                // unknown events are skipped, as the format asks
+                _ => continue,
+            };
+            events.push(Event { time, kind, data });
+        }
+        // tolerate recordings out of order
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Self { header, events })
+    }
+
+    /// The time of the last event, in seconds
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+}
+
+/// Replays a recording into its own terminal
+pub struct Player {
+    cast: Cast,
+    term: Terminal,
+    // the position in the recording, in seconds
+    time: f64,
+    // the index of the next event to play
+    next: usize,
+    speed: f64,
+    playing: bool,
+}
+
+impl Player {
+    pub fn new(cast: Cast) -> Self {
+        let term = Terminal::new(cast.header.width, cast.header.height);
+        Self {
+            cast,
+            term,
+            time: 0.0,
+            next: 0,
+            speed: 1.0,
+            playing: false,
+        }
+    }
+
+    pub fn cast(&self) -> &Cast {
+        &self.cast
+    }
+
+    pub fn term_mut(&mut self) -> &mut Terminal {
+        &mut self.term
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.cast.duration()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed > 0.0 {
+            self.speed = speed;
+        }
+    }
+
+    /// Play or pause, playing at the end starts over
+    pub fn toggle(&mut self) {
+        if !self.playing && self.next >= self.cast.events.len() {
+            self.seek(0.0);
+        }
+        self.playing = !self.playing;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Move forward by `elapsed` seconds of real time, scaled by the speed
+    pub fn tick(&mut self, elapsed: f64) {
+        if !self.playing {
+            return;
+        }
+        self.advance(self.time + elapsed * self.speed);
+        if self.next >= self.cast.events.len() {
+            self.playing = false;
+        }
+    }
+
+    /// Go to `time`, going back replays from the start
+    pub fn seek(&mut self, time: f64) {
+        let time = time.clamp(0.0, self.duration());
+        if time < self.time {
+            let header = &self.cast.header;
+            self.term = Terminal::new(header.width, header.height);
+            self.next = 0;
+        }
+        self.advance(time);
+        self.term.screen_mut().grid_mut().mark_dirty();
+    }
+
+    // Play the events up to `time`
+    fn advance(&mut self, time: f64) {
+        while let Some(event) = self.cast.events.get(self.next) {
+            if event.time > time {
+                break;
+            }
+            match event.kind {
+                EventKind::Output => self.term.feed(event.data.as_bytes()),
+                EventKind::Resize(cols, rows) => self.term.resize(cols, rows),
+                EventKind::Input | EventKind::Marker => (),
+            }
+            self.next += 1;
+        }
+        // the replies and the requests of the terminal go nowhere
+        self.term.take_output();
+        self.term.take_title();
+        self.term.take_clipboard();
+        self.time = time.min(self.duration());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            width: 10,
+            height: 3,
+            timestamp: Some(1700000000),
+            title: Some("test".to_owned()),
+        }
+    }
+
+    fn text(term: &Terminal, y: usize) -> String {
+        term.screen().grid().row(y).text()
+    }
+
+    #[test]
+    fn test_record() {
+        let mut recorder = Recorder::new(header(), 1000.0, false);
+        recorder.output(1500.0, b"hello\r\n");
+        recorder.input(1600.0, b"ls\r");
+        // a split character is written once complete
+        recorder.output(2000.0, &[0xe4, 0xb8]);
+        recorder.output(2250.0, &[0xad, b'"']);
+        recorder.resize(3000.0, 20, 5);
+        assert_eq!(
+            recorder.finish(),
+            concat!(
+                r#"{"env":{"TERM":"xterm-256color"},"height":3,"timestamp":1700000000,"title":"test","version":2,"width":10}"#,
+                "\n",
+                r#"[0.5,"o","hello\r\n"]"#,
+                "\n",
+                r#"[1.25,"o","中\""]"#,
+                "\n",
+                r#"[2.0,"r","20x5"]"#,
+                "\n",
+            )
+        );
+
+        let mut recorder = Recorder::new(header(), 0.0, true);
+        recorder.input(10.0, b"\x1b[A");
+        assert_eq!(
+            recorder.finish().lines().nth(1),
+            Some(r#"[0.01,"i","\u001b[A"]"#)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut recorder = Recorder::new(header(), 0.0, true);
+        recorder.output(100.0, b"a");
+        recorder.input(200.0, b"b");
+        recorder.resize(300.0, 4, 2);
+        let cast = Cast::parse(&recorder.finish()).unwrap();
+        assert_eq!(cast.header, header());
+        assert_eq!(cast.duration(), 0.3);
+        assert_eq!(
+            cast.events.iter().map(|e| &e.kind).collect::<Vec<_>>(),
+            [
+                &EventKind::Output,
+                &EventKind::Input,
+                &EventKind::Resize(4, 2)
+            ]
+        );
+
+        // unknown events are skipped
+        let cast =
+            Cast::parse("{\"version\": 2, \"width\": 3, \"height\": 1}\n\n[1, \"x\", \"\"]\n")
+                .unwrap();
+        assert!(cast.events.is_empty());
+
+        assert!(Cast::parse("").is_err());
+        assert!(Cast::parse("{\"version\": 1, \"width\": 3, \"height\": 1}").is_err());
+        assert!(Cast::parse("{\"version\": 2, \"height\": 1}").is_err());
+        assert_eq!(
+            Cast::parse("{\"version\": 2, \"width\": 3, \"height\": 1}\n[1, \"o\"]"),
+            Err("Bad event on line 2".to_owned())
+        );
+        assert!(
+            Cast::parse("{\"version\": 2, \"width\": 3, \"height\": 1}\n[1, \"r\", \"3\"]")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_player() {
+        let cast = Cast::parse(concat!(
+            "{\"version\": 2, \"width\": 10, \"height\": 3}\n",
+            "[1.0, \"o\", \"one\\r\\n\"]\n",
+            "[2.0, \"o\", \"two\\r\\n\"]\n",
+            "[2.5, \"i\", \"x\"]\n",
+            "[3.0, \"r\", \"12x4\"]\n",
+            "[4.0, \"o\", \"three\\u001b[6n\"]\n",
+        ))
+        .unwrap();
+        let mut player = Player::new(cast);
+        assert_eq!(player.duration(), 4.0);
+
+        // paused, nothing happens
+        player.tick(5.0);
+        assert_eq!(player.time(), 0.0);
+
+        player.toggle();
+        player.tick(1.5);
+        assert_eq!(text(player.term_mut(), 0), "one");
+        assert_eq!(text(player.term_mut(), 1), "");
+        player.set_speed(2.0);
+        player.tick(0.5);
+        assert_eq!(player.time(), 2.5);
+        assert_eq!(text(player.term_mut(), 1), "two");
+
+        // to the end, the size follows the recording
+        player.tick(10.0);
+        assert!(!player.is_playing());
+        assert_eq!(player.time(), 4.0);
+        assert_eq!(player.term_mut().screen().cols(), 12);
+        assert_eq!(text(player.term_mut(), 2), "three");
+        assert!(player.term_mut().take_output().is_empty());
+
+        // back, the terminal starts over
+        player.seek(1.0);
+        assert_eq!(player.term_mut().screen().cols(), 10);
+        assert_eq!(text(player.term_mut(), 0), "one");
+        assert_eq!(text(player.term_mut(), 1), "");
+        player.seek(2.0);
+        assert_eq!(text(player.term_mut(), 1), "two");
+
+        // playing at the end starts over
+        player.seek(100.0);
+        assert_eq!(player.time(), 4.0);
+        player.toggle();
+        assert!(player.is_playing());
+        assert_eq!(player.time(), 0.0);
+        assert_eq!(text(player.term_mut(), 0), "");
+    }
+}
@@ -0,0 +1,187 @@
+use crate::canvas::CanvasEvent;
+use crate::cast::{Player, SPEEDS};
+
+use tokio::sync::mpsc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{HtmlElement, HtmlInputElement, HtmlSelectElement};
+
+// How often the player moves on, in ms
+const TICK_INTERVAL: i32 = 40;
+
+/// Input from the recording and replay controls
+#[derive(Debug, Clone)]
+pub enum CastEvent {
+    /// Start or stop to record the shown tab
+    Record,
+    /// A recording is opened, with its content
+    Load(String),
+    /// Play or pause
+    Toggle,
+    /// Go to a time, in seconds
+    Seek(f64),
+    Speed(f64),
+    /// Time to move on
+    Tick,
+}
+
+fn element<T: JsCast>(id: &str) -> T {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<T>()
+        .unwrap()
+}
+
+fn send(sender: &mpsc::Sender<CanvasEvent>, event: CastEvent) {
+    let sender = sender.clone();
+    futures::executor::block_on(async move {
+        let _ = sender.send(CanvasEvent::Cast(event)).await;
+    });
+}
+
+fn set_display(id: &str, display: &str) {
+    let _ = element::<HtmlElement>(id)
+        .style()
+        .set_property("display", display);
+}
+
+/// Bind the record button of a live session
+pub fn bind_record(sender: mpsc::Sender<CanvasEvent>) {
+    let record = move || send(&sender, CastEvent::Record);
+    let record = Closure::wrap(Box::new(record) as Box<dyn FnMut()>);
+    element::<HtmlElement>("record").set_onclick(Some(record.as_ref().unchecked_ref()));
+    record.forget();
+}
+
+/// Whether the keys typed are recorded as well
+pub fn record_input() -> bool {
+    element::<HtmlInputElement>("record_input").checked()
+}
+
+/// Show if the shown tab is being recorded
+pub fn set_recording(recording: bool) {
+    element::<HtmlElement>("record").set_text_content(Some(if recording {
+        "Stop recording"
+    } else {
+        "Record"
+    }));
+}
+
+/// Show the player in place of the session controls and bind it
+pub fn bind_player(sender: mpsc::Sender<CanvasEvent>) {
+    set_display("tools", "none");
+    set_display("tabs", "none");
+    set_display("player", "block");
+
+    let speed = element::<HtmlSelectElement>("player_speed");
+    for value in SPEEDS {
+        let _ = speed.add_with_html_option_element(
+            &web_sys::HtmlOptionElement::new_with_text_and_value(
+                &format!("{}x", value),
+                &value.to_string(),
+            )
+            .unwrap(),
+        );
+    }
+    speed.set_value("1");
+    let s = sender.clone();
+    let select = speed.clone();
+    let onchange = move || {
+        if let Ok(value) = select.value().parse() {
+            send(&s, CastEvent::Speed(value));
+        }
+    };
+    let onchange = Closure::wrap(Box::new(onchange) as Box<dyn FnMut()>);
+    speed.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+    onchange.forget();
+
+    let s = sender.clone();
+    let toggle = move || send(&s, CastEvent::Toggle);
+    let toggle = Closure::wrap(Box::new(toggle) as Box<dyn FnMut()>);
+    element::<HtmlElement>("player_play").set_onclick(Some(toggle.as_ref().unchecked_ref()));
+    toggle.forget();
+
+    let seek = element::<HtmlInputElement>("player_seek");
+    let s = sender.clone();
+    let range = seek.clone();
+    let oninput = move || {
+        if let Ok(time) = range.value().parse() {
+            send(&s, CastEvent::Seek(time));
+        }
+    };
+    let oninput = Closure::wrap(Box::new(oninput) as Box<dyn FnMut()>);
+    seek.set_oninput(Some(oninput.as_ref().unchecked_ref()));
+    oninput.forget();
+
+    let input = element::<HtmlInputElement>("player_file");
+    let s = sender.clone();
+    let picker = input.clone();
+    let onchange = move || {
+        let Some(file) = picker.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        picker.set_value("");
+        let s = s.clone();
+        spawn_local(async move {
+            match JsFuture::from(file.text()).await {
+                Ok(text) => {
+                    let _ = s
+                        .send(CanvasEvent::Cast(CastEvent::Load(
+                            text.as_string().unwrap_or_default(),
+                        )))
+                        .await;
+                }
+                Err(_) => set_message("Cannot read the file"),
+            }
+        });
+    };
+    let onchange = Closure::wrap(Box::new(onchange) as Box<dyn FnMut()>);
+    input.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+    onchange.forget();
+
+    // the ticks are dropped rather than queued when the player is busy
+    let tick = move || {
+        let _ = sender.try_send(CanvasEvent::Cast(CastEvent::Tick));
+    };
+    let tick = Closure::wrap(Box::new(tick) as Box<dyn FnMut()>);
+    web_sys::window()
+        .unwrap()
+        .set_interval_with_callback_and_timeout_and_arguments_0(
+            tick.as_ref().unchecked_ref(),
+            TICK_INTERVAL,
+        )
+        .unwrap();
+    tick.forget();
+}
+
+pub fn set_message(msg: &str) {
+    element::<HtmlElement>("player_message").set_text_content(Some(msg));
+}
+
+// `1:05` and the like
+fn clock(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Show the position and the state of the player
+pub fn render(player: &Player) {
+    element::<HtmlElement>("player_play").set_text_content(Some(if player.is_playing() {
+        "Pause"
+    } else {
+        "Play"
+    }));
+    let seek = element::<HtmlInputElement>("player_seek");
+    seek.set_max(&player.duration().to_string());
+    seek.set_value(&player.time().to_string());
+    element::<HtmlElement>("player_time").set_text_content(Some(&format!(
+        "{} / {}",
+        clock(player.time()),
+        clock(player.duration())
+    )));
+}
@@ -0,0 +1,304 @@
+use crate::canvas::CanvasEvent;
+use crate::files::{FileSession, TransferState};
+
+use tokio::sync::mpsc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Document, DragEvent, Element, File, FileList, HtmlElement, HtmlInputElement};
+
+/// Input from the file panel
+#[derive(Debug, Clone)]
+pub enum FileEvent {
+    /// The panel is shown, sftp is started the first time
+    Show,
+    /// Go to a directory or download a file, by its path
+    Open(String),
+    Cd(String),
+    Up,
+    Refresh,
+    Download(String, Option<u64>),
+    /// Upload a file to the current directory, by name and content
+    Upload(String, Vec<u8>),
+    Mkdir(String),
+    Rename(String, String),
+    /// Remove a file, or a directory if set
+    Remove(String, bool),
+    Cancel(u32),
+    ClearTransfers,
+}
+
+fn document() -> Document {
+    web_sys::window().unwrap().document().unwrap()
+}
+
+fn element<T: JsCast>(id: &str) -> T {
+    document()
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<T>()
+        .unwrap()
+}
+
+fn send(sender: &mpsc::Sender<CanvasEvent>, event: FileEvent) {
+    let sender = sender.clone();
+    futures::executor::block_on(async move {
+        let _ = sender.send(CanvasEvent::Files(event)).await;
+    });
+}
+
+fn on_click(id: &str, handler: impl FnMut(web_sys::MouseEvent) + 'static) {
+    let handler = Closure::wrap(Box::new(handler) as Box<dyn FnMut(_)>);
+    element::<HtmlElement>(id).set_onclick(Some(handler.as_ref().unchecked_ref()));
+    handler.forget();
+}
+
+fn prompt(message: &str, default: &str) -> Option<String> {
+    web_sys::window()
+        .unwrap()
+        .prompt_with_message_and_default(message, default)
+        .ok()
+        .flatten()
+        .filter(|s| !s.is_empty())
+}
+
+fn confirm(message: &str) -> bool {
+    web_sys::window()
+        .unwrap()
+        .confirm_with_message(message)
+        .unwrap_or(false)
+}
+
+/// `1.5 MiB` and the like
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// Read the files in the browser and upload them one by one
+fn upload(sender: &mpsc::Sender<CanvasEvent>, files: FileList) {
+    let files: Vec<File> = (0..files.length()).filter_map(|i| files.get(i)).collect();
+    let sender = sender.clone();
+    spawn_local(async move {
+        for file in files {
+            let Ok(buffer) = JsFuture::from(file.array_buffer()).await else {
+                continue;
+            };
+            let data = js_sys::Uint8Array::new(&buffer).to_vec();
+            let _ = sender
+                .send(CanvasEvent::Files(FileEvent::Upload(file.name(), data)))
+                .await;
+        }
+    });
+}
+
+/// Bind the controls of the file panel
+pub fn bind(sender: mpsc::Sender<CanvasEvent>) {
+    let s = sender.clone();
+    on_click("files_toggle", move |_| {
+        let panel = element::<HtmlElement>("files");
+        let shown = panel.style().get_property_value("display").ok() == Some("block".into());
+        let _ = panel
+            .style()
+            .set_property("display", if shown { "none" } else { "block" });
+        if !shown {
+            send(&s, FileEvent::Show);
+        }
+    });
+
+    let s = sender.clone();
+    on_click("files_up", move |_| send(&s, FileEvent::Up));
+    let s = sender.clone();
+    on_click("files_refresh", move |_| send(&s, FileEvent::Refresh));
+    let s = sender.clone();
+    on_click("files_go", move |_| {
+        let path = element::<HtmlInputElement>("files_path").value();
+        send(&s, FileEvent::Open(path));
+    });
+    let s = sender.clone();
+    on_click("files_mkdir", move |_| {
+        if let Some(name) = prompt("New folder", "") {
+            send(&s, FileEvent::Mkdir(name));
+        }
+    });
+    let s = sender.clone();
+    on_click("files_clear", move |_| send(&s, FileEvent::ClearTransfers));
+
+    // the rows of the listing carry their entry
+    let s = sender.clone();
+    on_click("files_list", move |e| {
+        let Some(target) = e.target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+            return;
+        };
+        let Some(row) = target.closest("tr").ok().flatten() else {
+            return;
+        };
+        let Some(name) = row.get_attribute("data-name") else {
+            return;
+        };
+        let dir = row.has_attribute("data-dir");
+        let size = row
+            .get_attribute("data-size")
+            .and_then(|size| size.parse().ok());
+        match target.get_attribute("data-action").as_deref() {
+            Some("rename") => {
+                if let Some(to) = prompt("Rename to", &name) {
+                    send(&s, FileEvent::Rename(name, to));
+                }
+            }
+            Some("remove") => {
+                if confirm(&format!("Remove {}?", name)) {
+                    send(&s, FileEvent::Remove(name, dir));
+                }
+            }
+            _ if dir => send(&s, FileEvent::Cd(name)),
+            _ => send(&s, FileEvent::Download(name, size)),
+        }
+    });
+
+    let s = sender.clone();
+    on_click("files_transfers", move |e| {
+        let id = e
+            .target()
+            .and_then(|t| t.dyn_into::<Element>().ok())
+            .and_then(|t| t.get_attribute("data-cancel"))
+            .and_then(|id| id.parse().ok());
+        if let Some(id) = id {
+            send(&s, FileEvent::Cancel(id));
+        }
+    });
+
+    let input = element::<HtmlInputElement>("files_upload");
+    let s = sender.clone();
+    let picker = input.clone();
+    let onchange = move || {
+        if let Some(files) = picker.files() {
+            upload(&s, files);
+        }
+        picker.set_value("");
+    };
+    let onchange = Closure::wrap(Box::new(onchange) as Box<dyn FnMut()>);
+    input.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+    onchange.forget();
+
+    let panel = element::<HtmlElement>("files");
+    let dragover = move |e: DragEvent| e.prevent_default();
+    let dragover = Closure::wrap(Box::new(dragover) as Box<dyn FnMut(_)>);
+    panel
+        .add_event_listener_with_callback("dragover", dragover.as_ref().unchecked_ref())
+        .unwrap();
+    dragover.forget();
+    let drop = move |e: DragEvent| {
+        e.prevent_default();
+        if let Some(files) = e.data_transfer().and_then(|t| t.files()) {
+            upload(&sender, files);
+        }
+    };
+    let drop = Closure::wrap(Box::new(drop) as Box<dyn FnMut(_)>);
+    panel
+        .add_event_listener_with_callback("drop", drop.as_ref().unchecked_ref())
+        .unwrap();
+    drop.forget();
+}
+
+pub fn set_message(msg: &str) {
+    element::<HtmlElement>("files_message").set_text_content(Some(msg));
+}
+
+fn cell(document: &Document, row: &Element, text: &str) -> Element {
+    let cell = document.create_element("td").unwrap();
+    cell.set_text_content(Some(text));
+    let _ = row.append_child(&cell);
+    cell
+}
+
+fn button(document: &Document, parent: &Element, text: &str, attr: (&str, &str)) {
+    let button = document.create_element("button").unwrap();
+    let _ = button.set_attribute("type", "button");
+    let _ = button.set_attribute(attr.0, attr.1);
+    button.set_text_content(Some(text));
+    let _ = parent.append_child(&button);
+}
+
+/// Show the state of the session
+pub fn render(session: &FileSession) {
+    let document = document();
+    let path = element::<HtmlInputElement>("files_path");
+    if document.active_element().as_ref() != Some(path.as_ref()) {
+        path.set_value(&session.cwd);
+    }
+    set_message(&session.message);
+
+    let list = element::<Element>("files_list");
+    list.set_inner_html("");
+    for entry in &session.entries {
+        let row = document.create_element("tr").unwrap();
+        let _ = row.set_attribute("data-name", &entry.filename);
+        let dir = entry.attrs.is_dir();
+        if dir {
+            let _ = row.set_attribute("data-dir", "");
+        }
+        let size = entry.attrs.size.unwrap_or_default();
+        let _ = row.set_attribute("data-size", &size.to_string());
+        let name = if dir {
+            format!("{}/", entry.filename)
+        } else {
+            entry.filename.clone()
+        };
+        let _ = cell(&document, &row, &name).set_attribute("class", "files_name");
+        cell(
+            &document,
+            &row,
+            &if dir { String::new() } else { human_size(size) },
+        );
+        let actions = cell(&document, &row, "");
+        button(&document, &actions, "Rename", ("data-action", "rename"));
+        button(&document, &actions, "Remove", ("data-action", "remove"));
+        let _ = list.append_child(&row);
+    }
+
+    let transfers = element::<Element>("files_transfers");
+    transfers.set_inner_html("");
+    for (id, transfer) in &session.transfers {
+        let div = document.create_element("div").unwrap();
+        let name = document.create_element("span").unwrap();
+        name.set_text_content(Some(&format!(
+            "{} {} ",
+            if transfer.upload {
+                "\u{2191}"
+            } else {
+                "\u{2193}"
+            },
+            transfer.name
+        )));
+        let _ = div.append_child(&name);
+        let progress = document.create_element("progress").unwrap();
+        if let Some(size) = transfer.size.filter(|s| *s > 0) {
+            let _ = progress.set_attribute("max", &size.to_string());
+            let _ = progress.set_attribute("value", &transfer.done.min(size).to_string());
+        }
+        let _ = div.append_child(&progress);
+        let state = document.create_element("span").unwrap();
+        state.set_text_content(Some(&match &transfer.state {
+            TransferState::Running => format!(" {}", human_size(transfer.done)),
+            TransferState::Done => " done".to_owned(),
+            TransferState::Failed(msg) => format!(" failed: {}", msg),
+            TransferState::Cancelled => " cancelled".to_owned(),
+        }));
+        let _ = div.append_child(&state);
+        if transfer.state == TransferState::Running {
+            button(&document, &div, "Cancel", ("data-cancel", &id.to_string()));
+        }
+        let _ = transfers.append_child(&div);
+    }
+}
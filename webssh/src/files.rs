@@ -0,0 +1,715 @@
+//! The file browser over sftp
+//!
+//! Directories are listed and files are moved with the requests of
+//! [Sftp], several reads or writes of a transfer are in flight at once.
+//! Nothing here depends on the browser, the panel shows the state and the
+//! finished downloads are handed over to be saved
+
+use crate::ssh::{
+    sftp::{self, DirEntry, FileAttrs, Reply, Sftp, SSH_FX_EOF, SSH_FX_OK},
+    SshResult,
+};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+/// The size of a read or write request
+const CHUNK_SIZE: u32 = 32 * 1024;
+/// Requests of a transfer in flight at once
+const MAX_OUTSTANDING: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferState {
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub struct Transfer {
+    pub name: String,
+    pub upload: bool,
+    /// The total size, if known
+    pub size: Option<u64>,
+    /// The bytes transferred
+    pub done: u64,
+    pub state: TransferState,
+    path: String,
+    handle: Option<Vec<u8>>,
+    data: Vec<u8>,
+    // the next offset to request
+    offset: u64,
+    outstanding: usize,
+    eof: bool,
+    closing: bool,
+}
+
+impl Transfer {
+    fn new(name: &str, path: String, upload: bool, size: Option<u64>, data: Vec<u8>) -> Self {
+        Self {
+            name: name.to_owned(),
+            upload,
+            size,
+            done: 0,
+            state: TransferState::Running,
+            path,
+            handle: None,
+            data,
+            offset: 0,
+            outstanding: 0,
+            eof: false,
+            closing: false,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.state == TransferState::Running
+    }
+}
+
+// What a request in flight is for
+enum Pending {
+    RealPath,
+    Stat(String),
+    OpenDir(String),
+    ReadDir(String, Vec<u8>),
+    CloseDir,
+    // mkdir, rename & remove, with what is shown if it fails
+    Change(String),
+    Open(u32),
+    Read {
+        transfer: u32,
+        offset: u64,
+        len: u32,
+    },
+    Write {
+        transfer: u32,
+        len: u32,
+    },
+    Close(u32),
+}
+
+pub struct FileSession {
+    sftp: Sftp,
+    ready: bool,
+    /// The directory shown
+    pub cwd: String,
+    /// The entries of the directory, the directories first
+    pub entries: Vec<DirEntry>,
+    // the entries of the directory being listed
+    listing: Vec<DirEntry>,
+    pending: HashMap<u32, Pending>,
+    pub transfers: BTreeMap<u32, Transfer>,
+    next_transfer: u32,
+    downloads: Vec<(String, Vec<u8>)>,
+    /// The outcome of the last operation
+    pub message: String,
+    changed: bool,
+}
+
+fn status_text(code: u32, message: &str) -> String {
+    if message.is_empty() {
+        sftp::status_message(code).to_owned()
+    } else {
+        message.to_owned()
+    }
+}
+
+impl Default for FileSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSession {
+    pub fn new() -> Self {
+        Self {
+            sftp: Sftp::new(),
+            ready: false,
+            cwd: String::new(),
+            entries: Vec::new(),
+            listing: Vec::new(),
+            pending: HashMap::new(),
+            transfers: BTreeMap::new(),
+            next_transfer: 0,
+            downloads: Vec::new(),
+            message: "Starting sftp".to_owned(),
+            changed: true,
+        }
+    }
+
+    /// The bytes to be sent to the channel
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.sftp.take_output()
+    }
+
+    /// Whether the state has changed since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// The downloads finished since the last call, as (name, content)
+    pub fn take_downloads(&mut self) -> Vec<(String, Vec<u8>)> {
+        std::mem::take(&mut self.downloads)
+    }
+
+    fn set_message(&mut self, message: String) {
+        info!("sftp: {}", message);
+        self.message = message;
+        self.changed = true;
+    }
+
+    /// Go to the directory `path`, relative to the current one
+    pub fn cd(&mut self, path: &str) {
+        if !self.ready {
+            return;
+        }
+        let path = sftp::join(&self.cwd, path);
+        let id = self.sftp.opendir(&path);
+        self.pending.insert(id, Pending::OpenDir(path));
+    }
+
+    pub fn up(&mut self) {
+        let parent = sftp::parent(&self.cwd);
+        self.cd(&parent);
+    }
+
+    pub fn refresh(&mut self) {
+        let cwd = self.cwd.clone();
+        self.cd(&cwd);
+    }
+
+    /// Go to `path` if it is a directory, download it otherwise
+    pub fn open(&mut self, path: &str) {
+        if !self.ready {
+            return;
+        }
+        let path = sftp::join(&self.cwd, path);
+        let id = self.sftp.stat(&path);
+        self.pending.insert(id, Pending::Stat(path));
+    }
+
+    pub fn mkdir(&mut self, name: &str) {
+        let path = sftp::join(&self.cwd, name);
+        let id = self.sftp.mkdir(&path, &FileAttrs::default());
+        self.pending
+            .insert(id, Pending::Change(format!("mkdir {}", name)));
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let id = self
+            .sftp
+            .rename(&sftp::join(&self.cwd, from), &sftp::join(&self.cwd, to));
+        self.pending
+            .insert(id, Pending::Change(format!("rename {} to {}", from, to)));
+    }
+
+    /// Remove the file or the empty directory `name`
+    pub fn remove(&mut self, name: &str, dir: bool) {
+        let path = sftp::join(&self.cwd, name);
+        let id = if dir {
+            self.sftp.rmdir(&path)
+        } else {
+            self.sftp.remove(&path)
+        };
+        self.pending
+            .insert(id, Pending::Change(format!("remove {}", name)));
+    }
+
+    fn start(&mut self, transfer: Transfer, flags: u32) -> u32 {
+        let id = self.next_transfer;
+        self.next_transfer += 1;
+        let request = self.sftp.open(&transfer.path, flags, &FileAttrs::default());
+        self.pending.insert(request, Pending::Open(id));
+        self.transfers.insert(id, transfer);
+        self.changed = true;
+        id
+    }
+
+    /// Download `name` of the current directory, returns the transfer id
+    pub fn download(&mut self, name: &str, size: Option<u64>) -> u32 {
+        let path = sftp::join(&self.cwd, name);
+        let name = name.rsplit('/').next().unwrap_or(name);
+        let transfer = Transfer::new(name, path, false, size, Vec::new());
+        self.start(transfer, sftp::SSH_FXF_READ)
+    }
+
+    /// Upload `data` as `name` to the current directory, returns the transfer id
+    pub fn upload(&mut self, name: &str, data: Vec<u8>) -> u32 {
+        let path = sftp::join(&self.cwd, name);
+        let size = Some(data.len() as u64);
+        let transfer = Transfer::new(name, path, true, size, data);
+        self.start(
+            transfer,
+            sftp::SSH_FXF_WRITE | sftp::SSH_FXF_CREAT | sftp::SSH_FXF_TRUNC,
+        )
+    }
+
+    /// Stop a transfer, the file is closed once the requests in flight are answered
+    pub fn cancel(&mut self, id: u32) {
+        if let Some(transfer) = self.transfers.get_mut(&id) {
+            if transfer.is_running() {
+                transfer.state = TransferState::Cancelled;
+                transfer.data = Vec::new();
+                self.changed = true;
+            }
+        }
+        self.pump(id);
+    }
+
+    /// Forget the transfers which are over
+    pub fn clear_transfers(&mut self) {
+        self.transfers
+            .retain(|_, t| t.is_running() || t.outstanding > 0 || t.closing);
+        self.changed = true;
+    }
+
+    // Issue the next requests of a transfer, or close it when it is over
+    fn pump(&mut self, id: u32) {
+        let Some(transfer) = self.transfers.get_mut(&id) else {
+            return;
+        };
+        let Some(handle) = transfer.handle.clone() else {
+            return;
+        };
+        if transfer.is_running() {
+            while transfer.outstanding < MAX_OUTSTANDING {
+                if transfer.upload {
+                    let size = transfer.data.len() as u64;
+                    if transfer.offset >= size {
+                        break;
+                    }
+                    let len = (size - transfer.offset).min(CHUNK_SIZE as u64) as u32;
+                    let start = transfer.offset as usize;
+                    let chunk = &transfer.data[start..start + len as usize];
+                    let request = self.sftp.write(&handle, transfer.offset, chunk);
+                    self.pending
+                        .insert(request, Pending::Write { transfer: id, len });
+                } else {
+                    // past the expected size, a single read finds the end
+                    if transfer.size.is_some_and(|s| transfer.offset >= s) {
+                        if transfer.outstanding > 0 {
+                            break;
+                        }
+                        transfer.offset = transfer.offset.min(transfer.data.len() as u64);
+                    }
+                    if transfer.eof {
+                        break;
+                    }
+                    let request = self.sftp.read(&handle, transfer.offset, CHUNK_SIZE);
+                    self.pending.insert(
+                        request,
+                        Pending::Read {
+                            transfer: id,
+                            offset: transfer.offset,
+                            len: CHUNK_SIZE,
+                        },
+                    );
+                }
+                transfer.offset += CHUNK_SIZE as u64;
+                transfer.outstanding += 1;
+            }
+            let finished = if transfer.upload {
+                transfer.offset >= transfer.data.len() as u64
+            } else {
+                transfer.eof
+            };
+            if !finished || transfer.outstanding > 0 {
+                return;
+            }
+        } else if transfer.outstanding > 0 {
+            return;
+        }
+        if !transfer.closing {
+            transfer.closing = true;
+            let request = self.sftp.close(&handle);
+            self.pending.insert(request, Pending::Close(id));
+        }
+    }
+
+    fn fail(&mut self, id: u32, message: String) {
+        if let Some(transfer) = self.transfers.get_mut(&id) {
+            if transfer.is_running() {
+                warn!("sftp: {} failed: {}", transfer.name, message);
+                transfer.state = TransferState::Failed(message);
+                transfer.data = Vec::new();
+                self.changed = true;
+            }
+        }
+        self.pump(id);
+    }
+
+    /// Process the data received from the channel
+    pub fn feed(&mut self, data: &[u8]) -> SshResult<()> {
+        self.sftp.feed(data);
+        while let Some(reply) = self.sftp.next_reply()? {
+            self.handle(reply);
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, reply: Reply) {
+        let Some(id) = reply.id() else {
+            if let Reply::Version(version) = reply {
+                info!("sftp version {}", version);
+                self.ready = true;
+                let id = self.sftp.realpath(".");
+                self.pending.insert(id, Pending::RealPath);
+            }
+            return;
+        };
+        let Some(pending) = self.pending.remove(&id) else {
+            warn!("sftp: unexpected reply {}", id);
+            return;
+        };
+        match (pending, reply) {
+            (Pending::RealPath, Reply::Name { entries, .. }) => {
+                let home = entries.into_iter().next().map(|e| e.filename);
+                self.cd(&home.unwrap_or_else(|| "/".to_owned()));
+            }
+            (Pending::Stat(path), Reply::Attrs { attrs, .. }) => {
+                if attrs.is_dir() {
+                    self.cd(&path);
+                } else {
+                    self.download(&path, attrs.size);
+                }
+            }
+            (Pending::OpenDir(path), Reply::Handle { handle, .. }) => {
+                self.listing.clear();
+                let id = self.sftp.readdir(&handle);
+                self.pending.insert(id, Pending::ReadDir(path, handle));
+            }
+            (Pending::ReadDir(path, handle), Reply::Name { entries, .. }) => {
+                self.listing.extend(
+                    entries
+                        .into_iter()
+                        .filter(|e| e.filename != "." && e.filename != ".."),
+                );
+                let id = self.sftp.readdir(&handle);
+                self.pending.insert(id, Pending::ReadDir(path, handle));
+            }
+            (Pending::ReadDir(path, handle), Reply::Status { code, message, .. }) => {
+                let id = self.sftp.close(&handle);
+                self.pending.insert(id, Pending::CloseDir);
+                let mut entries = std::mem::take(&mut self.listing);
+                entries.sort_by(|a, b| {
+                    (!a.attrs.is_dir(), &a.filename).cmp(&(!b.attrs.is_dir(), &b.filename))
+                });
+                self.entries = entries;
+                self.cwd = path;
+                if code == SSH_FX_EOF {
+                    self.set_message(format!("{} entries", self.entries.len()));
+                } else {
+                    self.set_message(status_text(code, &message));
+                }
+            }
+            (Pending::CloseDir, _) => (),
+            (Pending::Change(what), Reply::Status { code, message, .. }) => {
+                if code == SSH_FX_OK {
+                    self.refresh();
+                } else {
+                    self.set_message(format!("{}: {}", what, status_text(code, &message)));
+                }
+            }
+            (Pending::Open(transfer), Reply::Handle { handle, .. }) => {
+                if let Some(t) = self.transfers.get_mut(&transfer) {
+                    t.handle = Some(handle);
+                }
+                self.pump(transfer);
+            }
+            (
+                Pending::Read {
+                    transfer: t,
+                    offset,
+                    len,
+                },
+                Reply::Data { data, .. },
+            ) => {
+                if let Some(transfer) = self.transfers.get_mut(&t) {
+                    transfer.outstanding -= 1;
+                    if transfer.is_running() {
+                        let start = offset as usize;
+                        let end = start + data.len();
+                        if transfer.data.len() < end {
+                            transfer.data.resize(end, 0);
+                        }
+                        transfer.data[start..end].copy_from_slice(&data);
+                        transfer.done += data.len() as u64;
+                        self.changed = true;
+                        // a short read before the end, the rest is asked again
+                        let rest = len as usize - data.len().min(len as usize);
+                        let at_end = transfer.size.is_some_and(|s| end as u64 >= s);
+                        if rest > 0 && !data.is_empty() && !at_end && !transfer.eof {
+                            if let Some(handle) = transfer.handle.clone() {
+                                let request = self.sftp.read(&handle, end as u64, rest as u32);
+                                self.pending.insert(
+                                    request,
+                                    Pending::Read {
+                                        transfer: t,
+                                        offset: end as u64,
+                                        len: rest as u32,
+                                    },
+                                );
+                                transfer.outstanding += 1;
+                            }
+                        }
+                    }
+                }
+                self.pump(t);
+            }
+            (Pending::Read { transfer: t, .. }, Reply::Status { code, message, .. }) => {
+                if let Some(transfer) = self.transfers.get_mut(&t) {
+                    transfer.outstanding -= 1;
+                    transfer.eof = true;
+                }
+                if code == SSH_FX_EOF {
+                    self.pump(t);
+                } else {
+                    self.fail(t, status_text(code, &message));
+                }
+            }
+            (Pending::Write { transfer: t, len }, Reply::Status { code, message, .. }) => {
+                if let Some(transfer) = self.transfers.get_mut(&t) {
+                    transfer.outstanding -= 1;
+                    transfer.done += len as u64;
+                    self.changed = true;
+                }
+                if code == SSH_FX_OK {
+                    self.pump(t);
+                } else {
+                    self.fail(t, status_text(code, &message));
+                }
+            }
+            (Pending::Close(t), _) => {
+                let Some(transfer) = self.transfers.get_mut(&t) else {
+                    return;
+                };
+                transfer.closing = false;
+                transfer.handle = None;
+                self.changed = true;
+                if !transfer.is_running() {
+                    return;
+                }
+                transfer.state = TransferState::Done;
+                let data = std::mem::take(&mut transfer.data);
+                let name = transfer.name.clone();
+                if transfer.upload {
+                    self.set_message(format!("Uploaded {}", name));
+                    self.refresh();
+                } else {
+                    self.set_message(format!("Downloaded {}", name));
+                    self.downloads.push((name, data));
+                }
+            }
+            (pending, Reply::Status { code, message, .. }) => {
+                let text = status_text(code, &message);
+                match pending {
+                    Pending::Open(t) => self.fail(t, text),
+                    Pending::Stat(path) | Pending::OpenDir(path) => {
+                        self.set_message(format!("{}: {}", path, text))
+                    }
+                    _ => self.set_message(text),
+                }
+            }
+            (_, reply) => warn!("sftp: unexpected reply {:?}", reply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ssh::{
+        sftp::{SSH_FXP_DATA, SSH_FXP_HANDLE, SSH_FXP_NAME, SSH_FXP_STATUS, SSH_FXP_VERSION},
+        wire::{SshReader, SshWriter},
+    };
+
+    // The (kind, id) of the requests sent
+    fn requests(session: &mut FileSession) -> Vec<(u8, u32)> {
+        let output = session.take_output();
+        let mut r = SshReader::new(&output);
+        let mut out = Vec::new();
+        while r.remain() > 0 {
+            let packet = r.read_string().unwrap();
+            let id = u32::from_be_bytes(packet[1..5].try_into().unwrap());
+            out.push((packet[0], id));
+        }
+        out
+    }
+
+    fn reply(session: &mut FileSession, kind: u8, body: impl FnOnce(&mut SshWriter)) {
+        let mut w = SshWriter::new();
+        w.write_u8(kind);
+        body(&mut w);
+        let mut packet = (w.len() as u32).to_be_bytes().to_vec();
+        packet.extend_from_slice(w.get_inner());
+        session.feed(&packet).unwrap();
+    }
+
+    fn status(session: &mut FileSession, id: u32, code: u32) {
+        reply(session, SSH_FXP_STATUS, |w| {
+            w.write_u32(id);
+            w.write_u32(code);
+            w.write_str("");
+            w.write_str("");
+        });
+    }
+
+    fn name(session: &mut FileSession, id: u32, names: &[(&str, u32)]) {
+        reply(session, SSH_FXP_NAME, |w| {
+            w.write_u32(id);
+            w.write_u32(names.len() as u32);
+            for (name, permissions) in names {
+                w.write_str(name);
+                w.write_str("");
+                w.write_u32(sftp::SSH_FILEXFER_ATTR_PERMISSIONS);
+                w.write_u32(*permissions);
+            }
+        });
+    }
+
+    fn handle(session: &mut FileSession, id: u32) {
+        reply(session, SSH_FXP_HANDLE, |w| {
+            w.write_u32(id);
+            w.write_string(b"h");
+        });
+    }
+
+    // A session listing an empty home directory
+    fn session() -> FileSession {
+        let mut session = FileSession::new();
+        session.take_output();
+        reply(&mut session, SSH_FXP_VERSION, |w| w.write_u32(3));
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_REALPATH, 0)]);
+        name(&mut session, 0, &[("/home/user", 0)]);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPENDIR, 1)]);
+        handle(&mut session, 1);
+        status(&mut session, 2, SSH_FX_EOF);
+        assert_eq!(
+            requests(&mut session),
+            [(sftp::SSH_FXP_READDIR, 2), (sftp::SSH_FXP_CLOSE, 3)]
+        );
+        status(&mut session, 3, SSH_FX_OK);
+        session
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut session = session();
+        assert_eq!(session.cwd, "/home/user");
+        session.cd("src");
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPENDIR, 4)]);
+        handle(&mut session, 4);
+        name(
+            &mut session,
+            5,
+            &[
+                (".", 0o40755),
+                ("b", 0o100644),
+                ("z", 0o40755),
+                ("a", 0o100644),
+            ],
+        );
+        name(&mut session, 6, &[("..", 0o40755)]);
+        status(&mut session, 7, SSH_FX_EOF);
+        assert_eq!(session.cwd, "/home/user/src");
+        let names: Vec<_> = session.entries.iter().map(|e| &e.filename[..]).collect();
+        assert_eq!(names, ["z", "a", "b"]);
+
+        assert_eq!(
+            requests(&mut session),
+            [
+                (sftp::SSH_FXP_READDIR, 5),
+                (sftp::SSH_FXP_READDIR, 6),
+                (sftp::SSH_FXP_READDIR, 7),
+                (sftp::SSH_FXP_CLOSE, 8)
+            ]
+        );
+
+        // errors are shown, the listing is kept
+        session.up();
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPENDIR, 9)]);
+        status(&mut session, 9, sftp::SSH_FX_PERMISSION_DENIED);
+        assert_eq!(session.message, "/home/user: Permission denied");
+        assert_eq!(session.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_download() {
+        let mut session = session();
+        let t = session.download("big", Some(40000));
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPEN, 4)]);
+        handle(&mut session, 4);
+        // both chunks of the size, then one read to find the end
+        assert_eq!(
+            requests(&mut session),
+            [(sftp::SSH_FXP_READ, 5), (sftp::SSH_FXP_READ, 6)]
+        );
+        // a short read is completed
+        reply(&mut session, SSH_FXP_DATA, |w| {
+            w.write_u32(5);
+            w.write_string(&[1; 30000]);
+        });
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_READ, 7)]);
+        reply(&mut session, SSH_FXP_DATA, |w| {
+            w.write_u32(6);
+            w.write_string(&[3; 7232]);
+        });
+        assert_eq!(requests(&mut session), []);
+        reply(&mut session, SSH_FXP_DATA, |w| {
+            w.write_u32(7);
+            w.write_string(&[2; 2768]);
+        });
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_READ, 8)]);
+        assert_eq!(session.transfers[&t].done, 40000);
+        status(&mut session, 8, SSH_FX_EOF);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_CLOSE, 9)]);
+        assert!(session.take_downloads().is_empty());
+        status(&mut session, 9, SSH_FX_OK);
+        assert_eq!(session.transfers[&t].state, TransferState::Done);
+        let downloads = session.take_downloads();
+        let (name, data) = &downloads[0];
+        assert_eq!(name, "big");
+        assert_eq!(data.len(), 40000);
+        assert_eq!(
+            (data[29999], data[30000], data[32767], data[32768]),
+            (1, 2, 2, 3)
+        );
+    }
+
+    #[test]
+    fn test_upload() {
+        let mut session = session();
+        let t = session.upload("up", vec![0; CHUNK_SIZE as usize * 20]);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPEN, 4)]);
+        handle(&mut session, 4);
+        assert_eq!(requests(&mut session).len(), MAX_OUTSTANDING);
+        status(&mut session, 5, SSH_FX_OK);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_WRITE, 21)]);
+        assert_eq!(session.transfers[&t].done, CHUNK_SIZE as u64);
+
+        // cancelled, closed once the writes in flight are answered
+        session.cancel(t);
+        for id in 6..21 {
+            status(&mut session, id, SSH_FX_OK);
+        }
+        assert_eq!(requests(&mut session), []);
+        status(&mut session, 21, sftp::SSH_FX_FAILURE);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_CLOSE, 22)]);
+        status(&mut session, 22, SSH_FX_OK);
+        assert_eq!(session.transfers[&t].state, TransferState::Cancelled);
+        session.clear_transfers();
+        assert!(session.transfers.is_empty());
+
+        // an empty file
+        let t = session.upload("empty", Vec::new());
+        handle(&mut session, 23);
+        assert_eq!(
+            requests(&mut session),
+            [(sftp::SSH_FXP_OPEN, 23), (sftp::SSH_FXP_CLOSE, 24)]
+        );
+        status(&mut session, 24, SSH_FX_OK);
+        assert_eq!(session.transfers[&t].state, TransferState::Done);
+        assert_eq!(requests(&mut session), [(sftp::SSH_FXP_OPENDIR, 25)]);
+    }
+}
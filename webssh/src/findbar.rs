@@ -0,0 +1,111 @@
+use crate::canvas::CanvasEvent;
+
+use tokio::sync::mpsc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement, HtmlInputElement, KeyboardEvent};
+
+/// Input from the search bar
+#[derive(Debug, Clone)]
+pub enum FindEvent {
+    /// The pattern or the options have changed
+    Query {
+        pattern: String,
+        regex: bool,
+        case_sensitive: bool,
+    },
+    /// Move to the next match above
+    Previous,
+    /// Move to the next match below
+    Next,
+    Close,
+}
+
+fn element<T: JsCast>(id: &str) -> T {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<T>()
+        .unwrap()
+}
+
+fn send(sender: &mpsc::Sender<CanvasEvent>, event: FindEvent) {
+    let sender = sender.clone();
+    futures::executor::block_on(async move {
+        let _ = sender.send(CanvasEvent::Find(event)).await;
+    });
+}
+
+fn query() -> FindEvent {
+    FindEvent::Query {
+        pattern: element::<HtmlInputElement>("search_text").value(),
+        regex: element::<HtmlInputElement>("search_regex").checked(),
+        case_sensitive: element::<HtmlInputElement>("search_case").checked(),
+    }
+}
+
+/// Show the search bar, the current pattern is searched again
+pub fn open(sender: &mpsc::Sender<CanvasEvent>) {
+    let _ = element::<HtmlElement>("search")
+        .style()
+        .set_property("display", "block");
+    let text = element::<HtmlInputElement>("search_text");
+    let _ = text.focus();
+    text.select();
+    send(sender, query());
+}
+
+fn close(sender: &mpsc::Sender<CanvasEvent>) {
+    let _ = element::<HtmlElement>("search")
+        .style()
+        .set_property("display", "none");
+    set_status("");
+    send(sender, FindEvent::Close);
+    let _ = element::<HtmlElement>("ssh-canvas").focus();
+}
+
+pub fn set_status(msg: &str) {
+    element::<HtmlElement>("search_status").set_text_content(Some(msg));
+}
+
+/// Bind the controls of the search bar
+pub fn bind(sender: mpsc::Sender<CanvasEvent>) {
+    let s = sender.clone();
+    let oninput = move || send(&s, query());
+    let oninput = Closure::wrap(Box::new(oninput) as Box<dyn FnMut()>);
+    for id in ["search_text", "search_regex", "search_case"] {
+        element::<HtmlElement>(id).set_oninput(Some(oninput.as_ref().unchecked_ref()));
+    }
+    oninput.forget();
+
+    // Enter goes up as the search starts from the bottom, Shift+Enter down
+    let s = sender.clone();
+    let key_down = move |e: KeyboardEvent| match e.key().as_str() {
+        "Enter" if e.shift_key() => send(&s, FindEvent::Next),
+        "Enter" => send(&s, FindEvent::Previous),
+        "Escape" => close(&s),
+        _ => (),
+    };
+    let key_down = Closure::wrap(Box::new(key_down) as Box<dyn FnMut(_)>);
+    element::<HtmlElement>("search_text").set_onkeydown(Some(key_down.as_ref().unchecked_ref()));
+    key_down.forget();
+
+    for (id, event) in [
+        ("search_prev", FindEvent::Previous),
+        ("search_next", FindEvent::Next),
+    ] {
+        let s = sender.clone();
+        let onclick = move || send(&s, event.clone());
+        let onclick = Closure::wrap(Box::new(onclick) as Box<dyn FnMut()>);
+        element::<HtmlElement>(id).set_onclick(Some(onclick.as_ref().unchecked_ref()));
+        onclick.forget();
+    }
+
+    let onclick = move || close(&sender);
+    let onclick = Closure::wrap(Box::new(onclick) as Box<dyn FnMut()>);
+    element::<HtmlElement>("search_close").set_onclick(Some(onclick.as_ref().unchecked_ref()));
+    onclick.forget();
+}
@@ -0,0 +1,196 @@
+use crate::login::LoginForm;
+use crate::ssh::{
+    key::PublicKey,
+    knownhosts::{host_name, HostKeyStatus, KnownHosts},
+};
+use crate::utils;
+
+use tracing::{info, warn};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{HtmlElement, HtmlInputElement, Storage};
+
+const STORAGE_KEY: &str = "webssh_known_hosts";
+const EXPORT_NAME: &str = "known_hosts";
+
+fn storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// The known hosts saved in the localStorage of the browser
+pub fn load() -> KnownHosts {
+    storage()
+        .and_then(|s| s.get_item(STORAGE_KEY).ok()?)
+        .map(|text| KnownHosts::parse(&text))
+        .unwrap_or_default()
+}
+
+pub fn save(known: &KnownHosts) {
+    if let Some(storage) = storage() {
+        if storage.set_item(STORAGE_KEY, &known.to_string()).is_err() {
+            warn!("Failed to save the known hosts");
+        }
+    }
+}
+
+/// The name of the host the gateway proxies to
+///
+/// The gateway does not tell its target, so the page has to name it with
+/// `?target=host:port`, no key is stored without it
+pub fn target() -> Option<String> {
+    utils::query_param("target").map(|target| host_name(&target))
+}
+
+/// The types of the keys known for `host`, to be offered first
+pub fn key_types(host: &str) -> Vec<String> {
+    load().key_types(host)
+}
+
+/// The host reached through the target, used as a jump host
+///
+/// It is named by the page with `?jump_to=host:port`, the target is the last
+/// host if not set
+pub fn destination() -> Option<String> {
+    utils::query_param("jump_to")
+}
+
+/// Check the host key of `host` against the store
+///
+/// An unknown key is trusted on first use if the user accepts it, a changed
+/// or revoked key is an error. The key of an unnamed host can only be
+/// accepted for this connection
+pub async fn verify(form: &LoginForm, host: Option<&str>, key: &PublicKey) -> Result<(), String> {
+    let Some(host) = host else {
+        let text = format!(
+            "The gateway does not name the host it connects to, \
+             so its {} key {} can't be checked.\n\
+             Are you sure you want to continue connecting?",
+            key.key_type(),
+            key.fingerprint()
+        );
+        return if form.confirm("Unverified host key", &text, "Connect").await {
+            warn!("Host key of the unnamed target is not verified");
+            Ok(())
+        } else {
+            Err("Host key verification failed".to_owned())
+        };
+    };
+    let mut known = load();
+    match known.check(host, key) {
+        HostKeyStatus::Trusted => {
+            info!("Host key of {} is trusted", host);
+            Ok(())
+        }
+        HostKeyStatus::Unknown => {
+            let text = format!(
+                "The authenticity of host '{}' can't be established.\n\
+                 {} key fingerprint is {}.\n\
+                 Are you sure you want to continue connecting?",
+                host,
+                key.key_type(),
+                key.fingerprint()
+            );
+            if form.confirm("Unknown host key", &text, "Trust").await {
+                known.add(host, key);
+                save(&known);
+                info!("Added {} to the known hosts", host);
+                Ok(())
+            } else {
+                Err("Host key verification failed".to_owned())
+            }
+        }
+        HostKeyStatus::Changed {
+            key_type,
+            fingerprint,
+        } => Err(format!(
+            "WARNING: REMOTE HOST IDENTIFICATION HAS CHANGED! \
+             Someone could be eavesdropping on you right now. \
+             The {} key of '{}' was {}, it is now the {} key {}. \
+             Forget the host if the change is expected.",
+            key_type,
+            host,
+            fingerprint,
+            key.key_type(),
+            key.fingerprint()
+        )),
+        HostKeyStatus::Revoked => Err(format!(
+            "WARNING: the {} key {} of '{}' is revoked",
+            key.key_type(),
+            key.fingerprint(),
+            host
+        )),
+    }
+}
+
+/// Bind the export, forget & import controls of the known hosts
+pub fn bind(set_status: fn(&str)) {
+    let document = web_sys::window().unwrap().document().unwrap();
+
+    let export = document
+        .get_element_by_id("known_hosts_export")
+        .unwrap()
+        .dyn_into::<HtmlElement>()
+        .unwrap();
+    let onclick = move || {
+        utils::download(EXPORT_NAME, load().to_string().as_bytes(), "text/plain");
+    };
+    let onclick = Closure::wrap(Box::new(onclick) as Box<dyn FnMut()>);
+    export.set_onclick(Some(onclick.as_ref().unchecked_ref()));
+    onclick.forget();
+
+    let forget = document
+        .get_element_by_id("known_hosts_forget")
+        .unwrap()
+        .dyn_into::<HtmlElement>()
+        .unwrap();
+    let onclick = move || {
+        let mut hosts: Vec<String> = target().into_iter().collect();
+        hosts.extend(destination().map(|dest| host_name(&dest)));
+        if hosts.is_empty() {
+            set_status("No target host to forget");
+            return;
+        }
+        let mut known = load();
+        let removed: usize = hosts.iter().map(|host| known.remove(host)).sum();
+        save(&known);
+        set_status(&format!(
+            "Forgot {} host keys of {}",
+            removed,
+            hosts.join(", ")
+        ));
+    };
+    let onclick = Closure::wrap(Box::new(onclick) as Box<dyn FnMut()>);
+    forget.set_onclick(Some(onclick.as_ref().unchecked_ref()));
+    onclick.forget();
+
+    let import = document
+        .get_element_by_id("known_hosts_import")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let input = import.clone();
+    let onchange = move || {
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        input.set_value("");
+        spawn_local(async move {
+            let Some(text) = JsFuture::from(file.text())
+                .await
+                .ok()
+                .and_then(|t| t.as_string())
+            else {
+                set_status("Failed to read the known hosts");
+                return;
+            };
+            let mut known = load();
+            let added = known.merge(KnownHosts::parse(&text));
+            save(&known);
+            set_status(&format!("Imported {} host keys", added));
+        });
+    };
+    let onchange = Closure::wrap(Box::new(onchange) as Box<dyn FnMut()>);
+    import.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+    onchange.forget();
+}
@@ -1,6 +1,47 @@
+mod canvas;
+pub mod cast;
+mod castpanel;
+mod filepanel;
+pub mod files;
+mod findbar;
+mod hoststore;
+mod login;
+pub mod ssh;
+mod tabbar;
+pub mod tabs;
 mod utils;
 
+pub use webterm::terminal;
+
+use canvas::{CanvasEvent, CanvasUtils};
+use cast::{Cast, Header, Player, Recorder};
+use castpanel::CastEvent;
+use filepanel::FileEvent;
+use files::FileSession;
+use findbar::FindEvent;
+use fluvio_wasm_timer::Interval;
+use futures::{Stream, StreamExt};
+use login::LoginForm;
+use ssh::{
+    knownhosts::{host_name, host_port},
+    sftp,
+    wire::SshWriter,
+    ChannelEvent, Connection, SshConnector, Transport,
+};
+use std::{pin::Pin, time::Duration};
+use tabbar::TabEvent;
+use tabs::{Tab, Tabs};
+use terminal::{
+    input::{self, KeyInput, MouseAction, MouseInput},
+    ClipboardRequest, MouseTracking, Terminal, DEFAULT_SCROLLBACK,
+};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+use tracing_wasm::WASMLayerConfigBuilder;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+use ws_stream_wasm::WsMeta;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -8,17 +49,807 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+const TERM: &str = "xterm-256color";
+// Seconds between the keepalives by default, the connection is given up when
+// this many are not answered
+const KEEPALIVE_INTERVAL: u64 = 60;
+const KEEPALIVE_COUNT_MAX: u32 = 3;
+
 #[wasm_bindgen]
 extern "C" {
-    fn alert(s: &str);
+    pub fn setClipBoard(s: String);
+    pub fn getClipBoard() -> String;
+}
+
+fn set_status(msg: &str) {
+    let status_bar = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("ssh_status")
+        .unwrap();
+    status_bar.set_text_content(Some(msg));
+}
+
+fn set_title(title: &str) {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .set_title(title);
+}
+
+// Whether the user lets the host read the clipboard with OSC 52
+fn clipboard_readable() -> bool {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("clipboard_read")
+        .and_then(|e| e.dyn_into::<web_sys::HtmlInputElement>().ok())
+        .is_some_and(|e| e.checked())
+}
+
+// Open a hyperlink of the terminal, unless it could run in the page
+fn open_link(uri: &str) {
+    const SCHEMES: [&str; 4] = ["http:", "https:", "ftp:", "mailto:"];
+    if !SCHEMES
+        .iter()
+        .any(|scheme| uri.to_ascii_lowercase().starts_with(scheme))
+    {
+        info!("Link not opened: {}", uri);
+        return;
+    }
+    let _ = web_sys::window()
+        .unwrap()
+        .open_with_url_and_target_and_features(uri, "_blank", "noopener");
 }
 
 fn start_websocket() -> Result<(), JsValue> {
+    // connect
+    let url = format!(
+        "{scheme}://{host}/websockify",
+        scheme = if web_sys::window()
+            .unwrap()
+            .location()
+            .protocol()?
+            .starts_with("https")
+        {
+            "wss"
+        } else {
+            "ws"
+        },
+        host = web_sys::window().unwrap().location().host()?
+    );
+
+    spawn_local(async move {
+        let (event_sender, event_receiver) = tokio::sync::mpsc::channel(4096);
+        findbar::bind(event_sender.clone());
+        filepanel::bind(event_sender.clone());
+        tabbar::bind(event_sender.clone());
+        castpanel::bind_record(event_sender.clone());
+        let mut canvas = CanvasUtils::new(canvas::OPTIONS, event_sender);
+        let (cols, rows) = canvas.fit();
+        canvas.init(cols, rows);
+        let scrollback = utils::query_param("scrollback")
+            .and_then(|lines| lines.parse().ok())
+            .unwrap_or(DEFAULT_SCROLLBACK);
+        let mut term = Terminal::new(cols, rows);
+        term.set_scrollback(scrollback);
+        term.feed(format!("Connecting to {}\r\n", url).as_bytes());
+        canvas.render(&mut term);
+
+        // start websocket
+        let (_ws, wsio) = match WsMeta::connect(url, vec!["binary"]).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                show_error(&mut term, &canvas, &format!("websocket error {}", e));
+                return;
+            }
+        };
+
+        // ssh connect, asking for the keys already known first
+        let target = hoststore::target();
+        let mut transport = match connector(wsio.into_io())
+            .prefer_host_keys(&known_key_types(target.as_deref()))
+            .connect()
+            .await
+        {
+            Ok(transport) => transport,
+            Err(e) => {
+                show_error(&mut term, &canvas, &format!("connect error {}", e));
+                return;
+            }
+        };
+        let mut form = LoginForm::new();
+        let Some(user) = login(
+            &mut transport,
+            &mut form,
+            target.as_deref(),
+            &mut term,
+            &canvas,
+        )
+        .await
+        else {
+            return;
+        };
+        let Some(dest) = hoststore::destination() else {
+            session(transport, user, term, canvas, event_receiver, scrollback).await;
+            return;
+        };
+
+        // the destination, through a channel of the jump host
+        let (host, port) = host_port(&dest);
+        let through = target.as_deref().unwrap_or("the gateway");
+        term.feed(format!("Connecting to {} through {}\r\n", dest, through).as_bytes());
+        canvas.render(&mut term);
+        let (stream, pump) = match ssh::tunnel(Connection::new(transport), host, port).await {
+            Ok(tunnel) => tunnel,
+            Err(e) => {
+                show_error(&mut term, &canvas, &format!("jump error {}", e));
+                return;
+            }
+        };
+        spawn_local(async move {
+            if let Err(e) = pump.await {
+                error!("Jump host: {}", e);
+            }
+        });
+        let dest_host = host_name(&dest);
+        let mut transport = match connector(stream)
+            .prefer_host_keys(&known_key_types(Some(&dest_host)))
+            .connect()
+            .await
+        {
+            Ok(transport) => transport,
+            Err(e) => {
+                show_error(&mut term, &canvas, &format!("connect error {}", e));
+                return;
+            }
+        };
+        let Some(user) = login(
+            &mut transport,
+            &mut form,
+            Some(&dest_host),
+            &mut term,
+            &canvas,
+        )
+        .await
+        else {
+            return;
+        };
+        session(transport, user, term, canvas, event_receiver, scrollback).await;
+    });
+
+    Ok(())
+}
+
+fn known_key_types(host: Option<&str>) -> Vec<String> {
+    host.map(hoststore::key_types).unwrap_or_default()
+}
+
+// The transport options of the page, `?rekey_mb=1024&rekey_minutes=60` and
+// `?compression=1`
+fn connector<S>(stream: S) -> SshConnector<S>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let param = |name, default: u64| {
+        utils::query_param(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    SshConnector::new(stream)
+        .set_rekey_limit(
+            param("rekey_mb", 1024) << 20,
+            Duration::from_secs(param("rekey_minutes", 60) * 60),
+        )
+        .set_compression(utils::query_param("compression").is_some_and(|v| v != "0"))
+}
+
+fn show_error(term: &mut Terminal, canvas: &CanvasUtils, msg: &str) {
+    error!(msg);
+    set_status(msg);
+    term.feed(format!("{}\r\n", msg).as_bytes());
+    canvas.render(term);
+}
+
+// Check the host key of `host`, then ask the user and authenticate, each hop
+// has its own. The name of the user is returned, the transport is
+// disconnected on failure
+async fn login<S>(
+    transport: &mut Transport<S>,
+    form: &mut LoginForm,
+    host: Option<&str>,
+    term: &mut Terminal,
+    canvas: &CanvasUtils,
+) -> Option<String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    info!("Connected to {}", transport.server_id());
+    set_status(&format!("Connected to {}", transport.server_id()));
+    term.feed(format!("Connected to {}\r\n", transport.server_id()).as_bytes());
+    canvas.render(term);
+
+    // host key verification, before any credential is sent
+    let host_key = transport.host_key().cloned().unwrap();
+    if let Err(msg) = hoststore::verify(form, host, &host_key).await {
+        show_error(term, canvas, &msg);
+        let _ = transport
+            .disconnect(
+                ssh::msg::SSH_DISCONNECT_HOST_KEY_NOT_VERIFIABLE,
+                "host key verification failed",
+            )
+            .await;
+        return None;
+    }
+
+    // ssh authenticate
+    let user = loop {
+        match form.ask_login(host.unwrap_or("the gateway")).await {
+            Some(user) if !user.is_empty() => break user.to_string(),
+            Some(_) => continue,
+            None => {
+                set_status("Login cancelled");
+                let _ = transport
+                    .disconnect(
+                        ssh::msg::SSH_DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE,
+                        "cancelled",
+                    )
+                    .await;
+                return None;
+            }
+        }
+    };
+    if let Err(e) = transport.authenticate(&user, form).await {
+        show_error(term, canvas, &e.to_string());
+        let _ = transport
+            .disconnect(
+                ssh::msg::SSH_DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE,
+                "authentication failed",
+            )
+            .await;
+        return None;
+    }
+    set_status(&format!("{}@{}", user, transport.server_id()));
+    Some(user)
+}
+
+// The shells and the files of the authenticated `transport`, until it is
+// disconnected
+async fn session<S>(
+    transport: Transport<S>,
+    user: String,
+    term: Terminal,
+    mut canvas: CanvasUtils,
+    mut event_receiver: mpsc::Receiver<CanvasEvent>,
+    scrollback: usize,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut cols, mut rows) = (term.screen().cols(), term.screen().rows());
+    canvas.focus();
+
+    // the first shell, in the terminal showing the login
+    let mut conn = Connection::new(transport);
+    let mut tabs = Tabs::new();
+    open_tab(&mut conn, &mut tabs, term);
+
+    // the sftp channel of the file panel, opened on demand
+    let mut files: Option<(u32, FileSession)> = None;
+
+    // `?keepalive=<seconds>`, 0 to disable them
+    let interval = utils::query_param("keepalive")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(KEEPALIVE_INTERVAL);
+    let mut keepalive: Pin<Box<dyn Stream<Item = ()>>> = if interval > 0 {
+        Box::pin(Interval::new(Duration::from_secs(interval)))
+    } else {
+        Box::pin(futures::stream::pending())
+    };
+
+    let reason = loop {
+        if let Err(e) = conn.flush().await {
+            break e.to_string();
+        }
+        tokio::select! {
+            event = conn.recv() => match event {
+                Ok(event) => {
+                    let id = event_channel(&event);
+                    if tabs.get_mut(id).is_some() {
+                        if let Err(reason) = tab_event(&mut conn, &mut tabs, event) {
+                            break reason;
+                        }
+                    } else {
+                        sftp_event(&mut conn, &mut files, event);
+                    }
+                }
+                Err(e) => break e.to_string(),
+            },
+            Some(()) = keepalive.next() => {
+                if conn.unanswered_keepalives() >= KEEPALIVE_COUNT_MAX {
+                    break "Keepalive timed out".to_owned();
+                }
+                conn.keepalive();
+            }
+            Some(event) = event_receiver.recv() => match event {
+                CanvasEvent::Key(key) => {
+                    let tab = tabs.active();
+                    if !scroll_key(&mut tab.term, &key) {
+                        if let Some(bytes) = input::encode(&key, tab.term.screen().modes()) {
+                            tab.term.scroll_to_bottom();
+                            send_input(&mut conn, tab, &bytes);
+                        }
+                    }
+                }
+                CanvasEvent::Mouse(input) => mouse(&mut conn, tabs.active(), &input),
+                CanvasEvent::Paste(text) => {
+                    let tab = tabs.active();
+                    let bytes = input::paste(&text, tab.term.screen().modes());
+                    tab.term.scroll_to_bottom();
+                    send_input(&mut conn, tab, &bytes);
+                }
+                CanvasEvent::Find(event) => find(&mut tabs.active().term, event),
+                CanvasEvent::Files(event) => file_event(&mut conn, &mut files, event),
+                CanvasEvent::Tab(TabEvent::New) => {
+                    let mut term = Terminal::new(cols, rows);
+                    term.set_scrollback(scrollback);
+                    open_tab(&mut conn, &mut tabs, term);
+                    canvas.focus();
+                }
+                CanvasEvent::Tab(TabEvent::Select(id)) => {
+                    if tabs.select(id) {
+                        tabs.active().term.screen_mut().grid_mut().mark_dirty();
+                    }
+                    canvas.focus();
+                }
+                CanvasEvent::Cast(CastEvent::Record) => {
+                    let title = format!("{}@{}", user, conn.transport().server_id());
+                    record(tabs.active(), &title);
+                }
+                CanvasEvent::Cast(_) => (),
+                CanvasEvent::Tab(TabEvent::Close(id)) => {
+                    conn.close(id);
+                    if let Err(reason) = close_tab(&mut tabs, id) {
+                        break reason;
+                    }
+                }
+                CanvasEvent::Resize(new_cols, new_rows) => {
+                    (cols, rows) = (new_cols, new_rows);
+                    canvas.init(cols, rows);
+                    for tab in tabs.iter_mut() {
+                        tab.term.resize(cols, rows);
+                        if let Some(recorder) = &mut tab.recorder {
+                            recorder.resize(js_sys::Date::now(), cols, rows);
+                        }
+                        conn.window_change(tab.channel, cols as u32, rows as u32);
+                    }
+                    tabs.active().term.screen_mut().grid_mut().mark_dirty();
+                }
+            }
+        }
+        if let Some((id, session)) = &mut files {
+            flush_files(&mut conn, *id, session);
+        }
+        if tabs.take_changed() {
+            tabbar::render(&tabs.titles(), channel_of(&tabs));
+            castpanel::set_recording(tabs.active().recorder.is_some());
+            set_title(&tabs.active().title);
+        }
+        canvas.render(&mut tabs.active().term);
+    };
+    info!("Disconnected: {}", reason);
+    set_status(&format!("Disconnected: {}", reason));
+    for tab in tabs.iter_mut() {
+        save_recording(tab);
+    }
+    castpanel::set_recording(false);
+    let mut term = match tabs.active_channel() {
+        Some(id) => tabs.remove(id).unwrap().term,
+        None => Terminal::new(cols, rows),
+    };
+    term.screen_mut().grid_mut().mark_dirty();
+    term.feed(format!("\r\n{}\r\n", reason).as_bytes());
+    canvas.render(&mut term);
+    tabbar::render(&[], 0);
+    let _ = conn.disconnect().await;
+}
+
+// Open a shell on a new channel, shown in a new tab with `term`
+fn open_tab<S>(conn: &mut Connection<S>, tabs: &mut Tabs, term: Terminal)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let id = conn.open_session();
+    let (cols, rows) = (term.screen().cols(), term.screen().rows());
+    conn.request_pty(id, TERM, cols as u32, rows as u32);
+    conn.request_shell(id);
+    tabs.add(id, term);
+    tabs.active().term.screen_mut().grid_mut().mark_dirty();
+}
+
+// Remove the tab of a closed channel, the connection ends with the last one
+fn close_tab(tabs: &mut Tabs, id: u32) -> Result<(), String> {
+    if tabs.len() == 1 && tabs.active_channel() == Some(id) {
+        return Err("Connection closed".to_owned());
+    }
+    if let Some(mut tab) = tabs.remove(id) {
+        save_recording(&mut tab);
+        if !tabs.is_empty() {
+            tabs.active().term.screen_mut().grid_mut().mark_dirty();
+        }
+    }
     Ok(())
 }
 
+fn channel_of(tabs: &Tabs) -> u32 {
+    tabs.active_channel().unwrap_or_default()
+}
+
+fn event_channel(event: &ChannelEvent) -> u32 {
+    match event {
+        ChannelEvent::Opened(id)
+        | ChannelEvent::OpenFailed(id, _)
+        | ChannelEvent::Data(id, _)
+        | ChannelEvent::ExtendedData(id, _, _)
+        | ChannelEvent::WindowAdjust(id)
+        | ChannelEvent::RequestReply(id, _)
+        | ChannelEvent::ExitStatus(id, _)
+        | ChannelEvent::ExitSignal(id, _)
+        | ChannelEvent::Eof(id)
+        | ChannelEvent::Closed(id) => *id,
+    }
+}
+
+// An event of the channel of a tab, the error ends the connection
+fn tab_event<S>(
+    conn: &mut Connection<S>,
+    tabs: &mut Tabs,
+    event: ChannelEvent,
+) -> Result<(), String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    match event {
+        ChannelEvent::Data(id, data) | ChannelEvent::ExtendedData(id, _, data) => {
+            let Some(tab) = tabs.get_mut(id) else {
+                return Ok(());
+            };
+            tab.term.feed(&data);
+            if let Some(recorder) = &mut tab.recorder {
+                recorder.output(js_sys::Date::now(), &data);
+            }
+            let reply = tab.term.take_output();
+            if !reply.is_empty() {
+                conn.data(id, &reply);
+            }
+            clipboard(conn, tab);
+            if let Some(title) = tab.term.take_title() {
+                tabs.set_title(id, title);
+            }
+        }
+        ChannelEvent::OpenFailed(_, reason) if tabs.len() == 1 => return Err(reason),
+        ChannelEvent::RequestReply(_, false) if tabs.len() == 1 => {
+            return Err("Shell request rejected".to_owned());
+        }
+        ChannelEvent::OpenFailed(id, reason) => {
+            set_status(&reason);
+            close_tab(tabs, id)?;
+        }
+        ChannelEvent::RequestReply(id, false) => {
+            set_status("Shell request rejected");
+            conn.close(id);
+            close_tab(tabs, id)?;
+        }
+        ChannelEvent::Closed(id) => close_tab(tabs, id)?,
+        ChannelEvent::WindowAdjust(_) => (),
+        event => info!("{:?}", event),
+    }
+    Ok(())
+}
+
+// Start to record the tab, or stop and download the recording
+fn record(tab: &mut Tab, title: &str) {
+    if tab.recorder.is_some() {
+        save_recording(tab);
+    } else {
+        let now = js_sys::Date::now();
+        let header = Header {
+            width: tab.term.screen().cols(),
+            height: tab.term.screen().rows(),
+            timestamp: Some((now / 1000.0) as u64),
+            title: Some(title.to_owned()),
+        };
+        tab.recorder = Some(Recorder::new(header, now, castpanel::record_input()));
+    }
+    castpanel::set_recording(tab.recorder.is_some());
+}
+
+fn save_recording(tab: &mut Tab) {
+    if let Some(recorder) = tab.recorder.take() {
+        let name = format!("webssh-{}.cast", (js_sys::Date::now() / 1000.0) as u64);
+        utils::download(&name, recorder.finish().as_bytes(), cast::MIME);
+    }
+}
+
+// Shift+PageUp/PageDown scroll by pages, Shift+Home/End to the ends
+fn scroll_key(term: &mut Terminal, key: &KeyInput) -> bool {
+    if !key.shift || key.ctrl || key.alt {
+        return false;
+    }
+    let page = term.screen().rows() as isize - 1;
+    match key.key.as_str() {
+        "PageUp" => term.scroll(page.max(1)),
+        "PageDown" => term.scroll(-page.max(1)),
+        "Home" => term.scroll(isize::MAX),
+        "End" => term.scroll_to_bottom(),
+        _ => return false,
+    }
+    true
+}
+
+// The OSC 52 requests of the shell of the tab
+fn clipboard<S>(conn: &mut Connection<S>, tab: &mut Tab)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    for request in tab.term.take_clipboard() {
+        match request {
+            ClipboardRequest::Set(text) => setClipBoard(text),
+            ClipboardRequest::Query(selection) if clipboard_readable() => {
+                conn.data(
+                    tab.channel,
+                    &input::clipboard_reply(&selection, &getClipBoard()),
+                );
+            }
+            ClipboardRequest::Query(_) => info!("Clipboard read refused"),
+        }
+    }
+}
+
+// Send what the user typed to the shell of the tab
+fn send_input<S>(conn: &mut Connection<S>, tab: &mut Tab, bytes: &[u8])
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    if let Some(recorder) = &mut tab.recorder {
+        recorder.input(js_sys::Date::now(), bytes);
+    }
+    conn.data(tab.channel, bytes);
+}
+
+// Ctrl+click opens a hyperlink. The mouse goes to the application which
+// tracks it, unless Shift is held. Otherwise the left button selects, and the
+// selection is copied to the clipboard, and the wheel scrolls back
+fn mouse<S>(conn: &mut Connection<S>, tab: &mut Tab, mouse: &MouseInput)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let term = &mut tab.term;
+    if mouse.action == MouseAction::Press && mouse.button == 0 && mouse.ctrl {
+        if let Some(uri) = term.link_at(mouse.row, mouse.col) {
+            open_link(uri);
+            return;
+        }
+    }
+    let modes = *term.screen().modes();
+    // the rows shown are not the ones of the application when scrolled back
+    if modes.mouse != MouseTracking::Off && !mouse.shift && term.view().offset() == 0 {
+        if let Some(bytes) = input::encode_mouse(mouse, &modes) {
+            send_input(conn, tab, &bytes);
+        }
+        return;
+    }
+    match mouse.action {
+        MouseAction::Press if mouse.button == 0 => term.select_start(mouse.row, mouse.col),
+        MouseAction::Move if mouse.buttons & 1 != 0 => term.select_to(mouse.row, mouse.col),
+        MouseAction::Release if mouse.button == 0 => {
+            if let Some(text) = term.selection_text() {
+                setClipBoard(text);
+            }
+        }
+        MouseAction::Wheel(lines) => term.scroll(lines),
+        _ => (),
+    }
+}
+
+fn find(term: &mut Terminal, event: FindEvent) {
+    let found = match event {
+        FindEvent::Query {
+            pattern,
+            regex,
+            case_sensitive,
+        } => match term.search(&pattern, regex, case_sensitive) {
+            Ok(found) => found || pattern.is_empty(),
+            Err(e) => {
+                findbar::set_status(&e.to_string());
+                return;
+            }
+        },
+        FindEvent::Previous => term.find(true),
+        FindEvent::Next => term.find(false),
+        FindEvent::Close => {
+            term.close_search();
+            true
+        }
+    };
+    findbar::set_status(if found { "" } else { "No more matches" });
+}
+
+fn file_event<S>(conn: &mut Connection<S>, files: &mut Option<(u32, FileSession)>, event: FileEvent)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let Some((_, session)) = files else {
+        if let FileEvent::Show = event {
+            let id = conn.open_session();
+            let mut w = SshWriter::new();
+            w.write_str(sftp::SUBSYSTEM);
+            conn.request(id, "subsystem", true, w.get_inner());
+            *files = Some((id, FileSession::new()));
+        } else {
+            filepanel::set_message("Sftp is not started");
+        }
+        return;
+    };
+    match event {
+        FileEvent::Show => (),
+        FileEvent::Open(path) => session.open(&path),
+        FileEvent::Cd(path) => session.cd(&path),
+        FileEvent::Up => session.up(),
+        FileEvent::Refresh => session.refresh(),
+        FileEvent::Download(name, size) => {
+            session.download(&name, size);
+        }
+        FileEvent::Upload(name, data) => {
+            session.upload(&name, data);
+        }
+        FileEvent::Mkdir(name) => session.mkdir(&name),
+        FileEvent::Rename(from, to) => session.rename(&from, &to),
+        FileEvent::Remove(name, dir) => session.remove(&name, dir),
+        FileEvent::Cancel(id) => session.cancel(id),
+        FileEvent::ClearTransfers => session.clear_transfers(),
+    }
+}
+
+fn sftp_event<S>(
+    conn: &mut Connection<S>,
+    files: &mut Option<(u32, FileSession)>,
+    event: ChannelEvent,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let Some((id, session)) = files else {
+        if !matches!(event, ChannelEvent::WindowAdjust(_)) {
+            info!("{:?}", event);
+        }
+        return;
+    };
+    let id = *id;
+    match event {
+        ChannelEvent::Data(channel, data) if channel == id => {
+            if let Err(e) = session.feed(&data) {
+                error!("sftp: {}", e);
+                filepanel::set_message(&format!("Sftp error: {}", e));
+                conn.close(id);
+            }
+        }
+        ChannelEvent::RequestReply(channel, false) if channel == id => {
+            filepanel::set_message("The server has no sftp subsystem");
+            conn.close(id);
+        }
+        ChannelEvent::OpenFailed(channel, reason) if channel == id => {
+            filepanel::set_message(&reason);
+            *files = None;
+        }
+        ChannelEvent::Closed(channel) if channel == id => {
+            info!("Sftp channel closed");
+            *files = None;
+        }
+        ChannelEvent::WindowAdjust(_) => (),
+        event => info!("{:?}", event),
+    }
+}
+
+// Send the requests of the file session and show its state
+fn flush_files<S>(conn: &mut Connection<S>, id: u32, session: &mut FileSession)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let output = session.take_output();
+    if !output.is_empty() {
+        conn.data(id, &output);
+    }
+    for (name, data) in session.take_downloads() {
+        utils::download(&name, &data, "application/octet-stream");
+    }
+    if session.take_changed() {
+        filepanel::render(session);
+    }
+}
+
+// Replay recordings in the page, no connection is made
+fn start_replay() {
+    spawn_local(async move {
+        let (event_sender, mut event_receiver) = tokio::sync::mpsc::channel(4096);
+        castpanel::bind_player(event_sender.clone());
+        let mut canvas = CanvasUtils::new(canvas::OPTIONS, event_sender);
+        let (cols, rows) = canvas.fit();
+        canvas.init(cols, rows);
+        let mut size = (cols, rows);
+        let mut term = Terminal::new(cols, rows);
+        term.feed(b"Open a recording to replay it\r\n");
+        canvas.render(&mut term);
+
+        let mut player: Option<Player> = None;
+        let mut last_tick = js_sys::Date::now();
+        while let Some(event) = event_receiver.recv().await {
+            let CanvasEvent::Cast(event) = event else {
+                if let (CanvasEvent::Key(key), Some(player)) = (event, &mut player) {
+                    match key.key.as_str() {
+                        " " => player.toggle(),
+                        "ArrowLeft" => player.seek(player.time() - 5.0),
+                        "ArrowRight" => player.seek(player.time() + 5.0),
+                        _ => continue,
+                    }
+                    castpanel::render(player);
+                }
+                continue;
+            };
+            match (event, &mut player) {
+                (CastEvent::Load(text), _) => match Cast::parse(&text) {
+                    Ok(cast) => {
+                        castpanel::set_message(cast.header.title.as_deref().unwrap_or(""));
+                        let mut loaded = Player::new(cast);
+                        loaded.term_mut().screen_mut().grid_mut().mark_dirty();
+                        loaded.toggle();
+                        player = Some(loaded);
+                    }
+                    Err(e) => castpanel::set_message(&e),
+                },
+                (CastEvent::Tick, Some(player)) => {
+                    let now = js_sys::Date::now();
+                    player.tick((now - last_tick) / 1000.0);
+                    last_tick = now;
+                }
+                (CastEvent::Toggle, Some(player)) => player.toggle(),
+                (CastEvent::Seek(time), Some(player)) => player.seek(time),
+                (CastEvent::Speed(speed), Some(player)) => player.set_speed(speed),
+                _ => {
+                    last_tick = js_sys::Date::now();
+                    continue;
+                }
+            }
+            let Some(player) = &mut player else {
+                continue;
+            };
+            // the canvas follows the size of the recording
+            let term = player.term_mut();
+            let shown = (term.screen().cols(), term.screen().rows());
+            if shown != size {
+                size = shown;
+                canvas.init(size.0, size.1);
+                term.screen_mut().grid_mut().mark_dirty();
+            }
+            canvas.render(term);
+            castpanel::render(player);
+        }
+    });
+}
+
 #[wasm_bindgen(start)]
 pub fn run_app() -> Result<(), JsValue> {
     utils::set_panic_hook();
+    tracing_wasm::set_as_global_default_with_config(
+        WASMLayerConfigBuilder::new()
+            .set_max_level(tracing::Level::INFO)
+            .build(),
+    );
+    if utils::query_param("replay").is_some() {
+        start_replay();
+        return Ok(());
+    }
+    hoststore::bind(set_status);
     start_websocket()
 }
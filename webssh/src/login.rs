@@ -0,0 +1,230 @@
+use crate::ssh::{private_key::PrivateKey, AuthHandler, Prompt, PromptRequest, SshError};
+
+use async_trait::async_trait;
+use std::{cell::RefCell, rc::Rc};
+use tokio::sync::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Document, Event, HtmlElement, HtmlFormElement, HtmlInputElement};
+use zeroize::Zeroizing;
+
+fn login_request(host: &str) -> PromptRequest {
+    PromptRequest {
+        title: format!("Login to {}", host),
+        instruction: String::new(),
+        prompts: vec![Prompt {
+            prompt: "Username:".to_owned(),
+            echo: true,
+        }],
+    }
+}
+
+// Read the selected file into wasm memory, wiping the copy of javascript
+async fn read_file(input: &HtmlInputElement) -> Option<(String, Zeroizing<Vec<u8>>)> {
+    let file = input.files()?.get(0)?;
+    let buffer = JsFuture::from(file.array_buffer()).await.ok()?;
+    let array = js_sys::Uint8Array::new(&buffer);
+    let data = Zeroizing::new(array.to_vec());
+    array.fill(0, 0, array.length());
+    Some((file.name(), data))
+}
+
+/// The in-page form answering the questions of the authentication
+pub struct LoginForm {
+    document: Document,
+    // the name and the content of the private key file
+    key: Option<(String, Zeroizing<Vec<u8>>)>,
+}
+
+impl LoginForm {
+    pub fn new() -> Self {
+        Self {
+            document: web_sys::window().unwrap().document().unwrap(),
+            key: None,
+        }
+    }
+
+    fn element<T: JsCast>(&self, id: &str) -> T {
+        self.document
+            .get_element_by_id(id)
+            .unwrap()
+            .dyn_into::<T>()
+            .map_err(|_| ())
+            .unwrap()
+    }
+
+    fn show(&self, visible: bool) {
+        let _ = self
+            .element::<HtmlElement>("login")
+            .style()
+            .set_property("display", if visible { "block" } else { "none" });
+    }
+
+    /// Show a message under the prompts
+    pub fn set_message(&self, msg: &str) {
+        self.element::<HtmlElement>("login_message")
+            .set_text_content(Some(msg));
+    }
+
+    fn append_banner(&self, msg: &str) {
+        let banner = self.element::<HtmlElement>("login_banner");
+        let text = banner.text_content().unwrap_or_default();
+        banner.set_text_content(Some(&format!("{}{}", text, msg)));
+    }
+
+    /// Ask the username on `host`, along with an optional private key file
+    pub async fn ask_login(&mut self, host: &str) -> Option<Zeroizing<String>> {
+        let key_row = self.element::<HtmlElement>("login_key_row");
+        let _ = key_row.style().set_property("display", "block");
+        let answers = self.ask(login_request(host)).await;
+        let _ = key_row.style().set_property("display", "none");
+
+        let input = self.element::<HtmlInputElement>("login_key");
+        if answers.is_some() {
+            self.key = read_file(&input).await;
+        }
+        input.set_value("");
+        answers?.pop()
+    }
+
+    /// Ask the user to accept `text`, with `accept` as the label of the button
+    pub async fn confirm(&self, title: &str, text: &str, accept: &str) -> bool {
+        let submit = self.element::<HtmlElement>("login_submit");
+        submit.set_text_content(Some(accept));
+        let request = PromptRequest {
+            title: title.to_owned(),
+            instruction: text.to_owned(),
+            prompts: Vec::new(),
+        };
+        let accepted = self.ask(request).await.is_some();
+        submit.set_text_content(Some("Login"));
+        accepted
+    }
+
+    /// Show the prompts of `request` and wait for the user to submit or cancel
+    pub async fn ask(&self, request: PromptRequest) -> Option<Vec<Zeroizing<String>>> {
+        self.element::<HtmlElement>("login_title")
+            .set_text_content(Some(&request.title));
+        self.element::<HtmlElement>("login_instruction")
+            .set_text_content(Some(&request.instruction));
+        let container = self.element::<HtmlElement>("login_prompts");
+        container.set_inner_html("");
+        let mut inputs = Vec::with_capacity(request.prompts.len());
+        for prompt in &request.prompts {
+            let row = self.document.create_element("div").unwrap();
+            let label = self.document.create_element("label").unwrap();
+            label.set_text_content(Some(&prompt.prompt));
+            let input = self
+                .document
+                .create_element("input")
+                .unwrap()
+                .dyn_into::<HtmlInputElement>()
+                .unwrap();
+            input.set_type(if prompt.echo { "text" } else { "password" });
+            input.set_autocomplete("off");
+            let _ = label.append_child(&input);
+            let _ = row.append_child(&label);
+            let _ = container.append_child(&row);
+            inputs.push(input);
+        }
+        self.show(true);
+        if let Some(input) = inputs.first() {
+            let _ = input.focus();
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let tx = Rc::new(RefCell::new(Some(tx)));
+
+        let form = self.element::<HtmlFormElement>("login_form");
+        let sender = tx.clone();
+        let submit = move |e: Event| {
+            e.prevent_default();
+            if let Some(tx) = sender.borrow_mut().take() {
+                let _ = tx.send(true);
+            }
+        };
+        let submit = Closure::wrap(Box::new(submit) as Box<dyn FnMut(_)>);
+        form.set_onsubmit(Some(submit.as_ref().unchecked_ref()));
+
+        let cancel_btn = self.element::<HtmlElement>("login_cancel");
+        let sender = tx;
+        let cancel = move || {
+            if let Some(tx) = sender.borrow_mut().take() {
+                let _ = tx.send(false);
+            }
+        };
+        let cancel = Closure::wrap(Box::new(cancel) as Box<dyn FnMut()>);
+        cancel_btn.set_onclick(Some(cancel.as_ref().unchecked_ref()));
+
+        let submitted = rx.await.unwrap_or(false);
+
+        form.set_onsubmit(None);
+        cancel_btn.set_onclick(None);
+        let answers = inputs
+            .iter()
+            .map(|input| {
+                let value = Zeroizing::new(input.value());
+                input.set_value("");
+                value
+            })
+            .collect();
+        container.set_inner_html("");
+        self.set_message("");
+        self.show(false);
+        submitted.then_some(answers)
+    }
+}
+
+#[async_trait(?Send)]
+impl AuthHandler for LoginForm {
+    async fn prompt(&mut self, request: PromptRequest) -> Option<Vec<Zeroizing<String>>> {
+        self.ask(request).await
+    }
+
+    async fn private_key(&mut self) -> Option<PrivateKey> {
+        let (name, pem) = self.key.take()?;
+        let mut passphrase: Option<Zeroizing<String>> = None;
+        loop {
+            match PrivateKey::decode(&pem, passphrase.as_deref().map(String::as_str)) {
+                Ok(key) => return Some(key),
+                Err(SshError::KeyEncrypted) | Err(SshError::BadPassphrase) => {
+                    if passphrase.is_some() {
+                        self.set_message("Incorrect passphrase");
+                    }
+                    let request = PromptRequest {
+                        title: format!("Private key {}", name),
+                        instruction: String::new(),
+                        prompts: vec![Prompt {
+                            prompt: "Passphrase:".to_owned(),
+                            echo: false,
+                        }],
+                    };
+                    passphrase = Some(self.ask(request).await?.pop()?);
+                }
+                Err(e) => {
+                    self.set_message(&format!("{}: {}", name, e));
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn banner(&mut self, message: &str) {
+        self.append_banner(message);
+    }
+
+    fn failure(&mut self, methods: &[String], partial_success: bool) {
+        if partial_success {
+            self.set_message(&format!(
+                "Further authentication required: {}",
+                methods.join(", ")
+            ));
+        } else {
+            self.set_message(&format!(
+                "Permission denied, the server allows: {}",
+                methods.join(", ")
+            ));
+        }
+    }
+}
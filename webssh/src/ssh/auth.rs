@@ -0,0 +1,456 @@
+// The user authentication protocol
+//     https://www.rfc-editor.org/rfc/rfc4252
+//     https://www.rfc-editor.org/rfc/rfc4256
+//     https://www.rfc-editor.org/rfc/rfc8308#section-3.1
+
+use super::{
+    msg::*,
+    private_key::PrivateKey,
+    wire::{SshReader, SshWriter},
+    SshError, SshResult, Transport,
+};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::info;
+use zeroize::Zeroizing;
+
+pub const SERVICE_USERAUTH: &str = "ssh-userauth";
+pub const SERVICE_CONNECTION: &str = "ssh-connection";
+
+pub const METHOD_NONE: &str = "none";
+pub const METHOD_PASSWORD: &str = "password";
+pub const METHOD_KEYBOARD_INTERACTIVE: &str = "keyboard-interactive";
+pub const METHOD_PUBLICKEY: &str = "publickey";
+
+/// Methods tried in order, as long as the server allows them
+const PREFERRED_METHODS: &[&str] = &[
+    METHOD_PUBLICKEY,
+    METHOD_KEYBOARD_INTERACTIVE,
+    METHOD_PASSWORD,
+];
+
+const EXT_SERVER_SIG_ALGS: &str = "server-sig-algs";
+
+/// A question to the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub prompt: String,
+    /// Whether the answer can be shown while typing
+    pub echo: bool,
+}
+
+/// A group of prompts which are answered together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptRequest {
+    pub title: String,
+    pub instruction: String,
+    pub prompts: Vec<Prompt>,
+}
+
+/// Interaction with the user during the authentication
+#[async_trait(?Send)]
+pub trait AuthHandler {
+    /// Answer all the `prompts` of the request
+    ///
+    /// `None` means the user gives up the current method
+    async fn prompt(&mut self, request: PromptRequest) -> Option<Vec<Zeroizing<String>>>;
+
+    /// The key to try with the publickey method, `None` to skip the method
+    ///
+    /// It is asked again after a key is rejected. The key is dropped, and so
+    /// zeroized, right after the request is signed
+    async fn private_key(&mut self) -> Option<PrivateKey> {
+        None
+    }
+
+    /// A message from the server that should be displayed before authentication
+    fn banner(&mut self, _message: &str) {}
+
+    /// The last attempt was rejected, `methods` are the ones that can continue
+    ///
+    /// `partial_success` is true if the attempt succeeded but more
+    /// authentication is required
+    fn failure(&mut self, _methods: &[String], _partial_success: bool) {}
+}
+
+// The answer of the server to an authentication request
+enum Reply {
+    Success,
+    Failure {
+        methods: Vec<String>,
+        partial_success: bool,
+    },
+}
+
+impl<S> Transport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Authenticate `user` to the server
+    ///
+    /// Publickey, keyboard-interactive and password methods are tried in
+    /// order, with the keys and questions coming from `handler`. On failure,
+    /// the error holds the methods the server still allows
+    pub async fn authenticate<H>(&mut self, user: &str, handler: &mut H) -> SshResult<()>
+    where
+        H: AuthHandler + ?Sized,
+    {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_SERVICE_REQUEST);
+        w.write_str(SERVICE_USERAUTH);
+        self.send(w.get_inner()).await?;
+        let mut sig_algs = Vec::new();
+        loop {
+            let payload = self.recv().await?;
+            match payload[0] {
+                SSH_MSG_SERVICE_ACCEPT => break,
+                SSH_MSG_EXT_INFO => sig_algs = parse_sig_algs(&payload)?,
+                other => return Err(SshError::UnexpectedMessage(other)),
+            }
+        }
+
+        // The "none" method reveals the methods that can continue
+        self.send(&userauth_request(user, METHOD_NONE).into_inner())
+            .await?;
+        let mut reply = self.auth_reply(handler).await?;
+        let mut given_up: Vec<&str> = Vec::new();
+        let mut first = true;
+        loop {
+            let (methods, partial_success) = match reply {
+                Reply::Success => {
+                    info!("Authenticated as {}", user);
+                    self.start_compression();
+                    return Ok(());
+                }
+                Reply::Failure {
+                    methods,
+                    partial_success,
+                } => (methods, partial_success),
+            };
+            if !first {
+                handler.failure(&methods, partial_success);
+            }
+            first = false;
+            if partial_success {
+                // a new stage of the chain, every method can be tried again
+                given_up.clear();
+            }
+
+            let method = PREFERRED_METHODS
+                .iter()
+                .find(|m| methods.iter().any(|s| s == *m) && !given_up.contains(m));
+            let next = match method {
+                Some(&METHOD_PUBLICKEY) => self.publickey(user, handler, &sig_algs).await?,
+                Some(&METHOD_KEYBOARD_INTERACTIVE) => {
+                    self.keyboard_interactive(user, handler).await?
+                }
+                Some(&METHOD_PASSWORD) => self.password(user, handler).await?,
+                _ => None,
+            };
+            reply = match (next, method) {
+                (Some(reply), _) => reply,
+                (None, Some(method)) => {
+                    given_up.push(method);
+                    Reply::Failure {
+                        methods,
+                        partial_success: false,
+                    }
+                }
+                (None, None) => return Err(SshError::AuthFailed(methods)),
+            };
+        }
+    }
+
+    /// Wait for the result of an authentication request
+    async fn auth_reply<H>(&mut self, handler: &mut H) -> SshResult<Reply>
+    where
+        H: AuthHandler + ?Sized,
+    {
+        loop {
+            let payload = self.recv().await?;
+            match payload[0] {
+                SSH_MSG_USERAUTH_SUCCESS => return Ok(Reply::Success),
+                SSH_MSG_USERAUTH_FAILURE => return parse_failure(&payload),
+                SSH_MSG_USERAUTH_BANNER => handler.banner(&parse_banner(&payload)?),
+                // may be sent again right before the success
+                SSH_MSG_EXT_INFO => (),
+                other => return Err(SshError::UnexpectedMessage(other)),
+            }
+        }
+    }
+
+    // Returns None if there is no more key to try
+    async fn publickey<H>(
+        &mut self,
+        user: &str,
+        handler: &mut H,
+        sig_algs: &[String],
+    ) -> SshResult<Option<Reply>>
+    where
+        H: AuthHandler + ?Sized,
+    {
+        let Some(key) = handler.private_key().await else {
+            return Ok(None);
+        };
+        // without the extension, the server is assumed to take any of them
+        let Some(algorithm) = key
+            .algorithms()
+            .iter()
+            .find(|a| sig_algs.is_empty() || sig_algs.iter().any(|s| s == *a))
+        else {
+            info!("The server does not accept {:?}", key);
+            return Ok(None);
+        };
+
+        let mut w = userauth_request(user, METHOD_PUBLICKEY);
+        w.write_bool(true);
+        w.write_str(algorithm);
+        w.write_string(&key.public_key().to_blob());
+        let mut data = SshWriter::new();
+        data.write_string(self.session_id());
+        data.write_bytes(w.get_inner());
+        let signature = key.sign(algorithm, data.get_inner())?;
+        drop(key);
+        w.write_string(&signature);
+        self.send(w.get_inner()).await?;
+        self.auth_reply(handler).await.map(Some)
+    }
+
+    // Returns None if the user gives up
+    async fn password<H>(&mut self, user: &str, handler: &mut H) -> SshResult<Option<Reply>>
+    where
+        H: AuthHandler + ?Sized,
+    {
+        let request = PromptRequest {
+            title: format!("Password authentication for {}", user),
+            instruction: String::new(),
+            prompts: vec![Prompt {
+                prompt: "Password:".to_owned(),
+                echo: false,
+            }],
+        };
+        let Some(mut answers) = handler.prompt(request).await else {
+            return Ok(None);
+        };
+        let password = answers.pop().unwrap_or_default();
+
+        let mut w = userauth_request(user, METHOD_PASSWORD);
+        w.write_bool(false);
+        w.write_str(&password);
+        self.send(&Zeroizing::new(w.into_inner())).await?;
+
+        loop {
+            let payload = self.recv().await?;
+            match payload[0] {
+                SSH_MSG_USERAUTH_SUCCESS => return Ok(Some(Reply::Success)),
+                SSH_MSG_USERAUTH_FAILURE => return parse_failure(&payload).map(Some),
+                SSH_MSG_USERAUTH_BANNER => handler.banner(&parse_banner(&payload)?),
+                SSH_MSG_EXT_INFO => (),
+                SSH_MSG_USERAUTH_PASSWD_CHANGEREQ => {
+                    let mut r = SshReader::new(&payload[1..]);
+                    let instruction = r.read_utf8()?;
+                    let request = PromptRequest {
+                        title: format!("Password of {} expired", user),
+                        instruction,
+                        prompts: vec![
+                            Prompt {
+                                prompt: "New password:".to_owned(),
+                                echo: false,
+                            },
+                            Prompt {
+                                prompt: "Retype new password:".to_owned(),
+                                echo: false,
+                            },
+                        ],
+                    };
+                    let Some(answers) = handler.prompt(request).await else {
+                        return Ok(None);
+                    };
+                    if answers.len() != 2 || answers[0] != answers[1] {
+                        handler.banner("Passwords do not match");
+                        return Ok(None);
+                    }
+                    let mut w = userauth_request(user, METHOD_PASSWORD);
+                    w.write_bool(true);
+                    w.write_str(&password);
+                    w.write_str(&answers[0]);
+                    self.send(&Zeroizing::new(w.into_inner())).await?;
+                }
+                other => return Err(SshError::UnexpectedMessage(other)),
+            }
+        }
+    }
+
+    // Returns None if the user gives up
+    async fn keyboard_interactive<H>(
+        &mut self,
+        user: &str,
+        handler: &mut H,
+    ) -> SshResult<Option<Reply>>
+    where
+        H: AuthHandler + ?Sized,
+    {
+        let mut w = userauth_request(user, METHOD_KEYBOARD_INTERACTIVE);
+        // language tag, submethods
+        w.write_str("");
+        w.write_str("");
+        self.send(w.get_inner()).await?;
+
+        loop {
+            let payload = self.recv().await?;
+            match payload[0] {
+                SSH_MSG_USERAUTH_SUCCESS => return Ok(Some(Reply::Success)),
+                SSH_MSG_USERAUTH_FAILURE => return parse_failure(&payload).map(Some),
+                SSH_MSG_USERAUTH_BANNER => handler.banner(&parse_banner(&payload)?),
+                SSH_MSG_EXT_INFO => (),
+                SSH_MSG_USERAUTH_INFO_REQUEST => {
+                    let mut request = parse_info_request(&payload)?;
+                    if request.title.is_empty() {
+                        request.title = format!("Authentication for {}", user);
+                    }
+                    let answers = if request.prompts.is_empty() {
+                        // nothing to answer, but the instruction may be worth showing
+                        if !request.instruction.is_empty() {
+                            handler.banner(&request.instruction);
+                        }
+                        Vec::new()
+                    } else {
+                        let count = request.prompts.len();
+                        match handler.prompt(request).await {
+                            Some(answers) if answers.len() == count => answers,
+                            _ => return Ok(None),
+                        }
+                    };
+                    let mut w = SshWriter::new();
+                    w.write_u8(SSH_MSG_USERAUTH_INFO_RESPONSE);
+                    w.write_u32(answers.len() as u32);
+                    for answer in &answers {
+                        w.write_str(answer);
+                    }
+                    self.send(&Zeroizing::new(w.into_inner())).await?;
+                }
+                other => return Err(SshError::UnexpectedMessage(other)),
+            }
+        }
+    }
+}
+
+fn userauth_request(user: &str, method: &str) -> SshWriter {
+    let mut w = SshWriter::new();
+    w.write_u8(SSH_MSG_USERAUTH_REQUEST);
+    w.write_str(user);
+    w.write_str(SERVICE_CONNECTION);
+    w.write_str(method);
+    w
+}
+
+fn parse_failure(payload: &[u8]) -> SshResult<Reply> {
+    let mut r = SshReader::new(&payload[1..]);
+    Ok(Reply::Failure {
+        methods: r.read_name_list()?,
+        partial_success: r.read_bool()?,
+    })
+}
+
+fn parse_banner(payload: &[u8]) -> SshResult<String> {
+    let mut r = SshReader::new(&payload[1..]);
+    r.read_utf8()
+}
+
+// The signature algorithms of SSH_MSG_EXT_INFO, empty if not announced
+fn parse_sig_algs(payload: &[u8]) -> SshResult<Vec<String>> {
+    let mut r = SshReader::new(&payload[1..]);
+    let count = r.read_u32()?;
+    for _ in 0..count {
+        let name = r.read_utf8()?;
+        let value = r.read_string()?;
+        if name == EXT_SERVER_SIG_ALGS {
+            let list = std::str::from_utf8(value).map_err(|_| SshError::Malformed)?;
+            return Ok(list.split(',').map(|s| s.to_owned()).collect());
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn parse_info_request(payload: &[u8]) -> SshResult<PromptRequest> {
+    let mut r = SshReader::new(&payload[1..]);
+    let title = r.read_utf8()?;
+    let instruction = r.read_utf8()?;
+    let _language = r.read_string()?;
+    let count = r.read_u32()?;
+    // every prompt takes at least 5 bytes
+    if count as usize > r.remain() / 5 {
+        return Err(SshError::Malformed);
+    }
+    let mut prompts = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        prompts.push(Prompt {
+            prompt: r.read_utf8()?,
+            echo: r.read_bool()?,
+        });
+    }
+    Ok(PromptRequest {
+        title,
+        instruction,
+        prompts,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_info_request() {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_USERAUTH_INFO_REQUEST);
+        w.write_str("2FA");
+        w.write_str("Answer both");
+        w.write_str("");
+        w.write_u32(2);
+        w.write_str("Password: ");
+        w.write_bool(false);
+        w.write_str("Token: ");
+        w.write_bool(true);
+        let request = parse_info_request(w.get_inner()).unwrap();
+        assert_eq!(request.title, "2FA");
+        assert_eq!(request.instruction, "Answer both");
+        assert_eq!(
+            request.prompts,
+            [
+                Prompt {
+                    prompt: "Password: ".to_owned(),
+                    echo: false
+                },
+                Prompt {
+                    prompt: "Token: ".to_owned(),
+                    echo: true
+                }
+            ]
+        );
+
+        // the count is larger than the payload
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_USERAUTH_INFO_REQUEST);
+        w.write_str("");
+        w.write_str("");
+        w.write_str("");
+        w.write_u32(u32::MAX);
+        assert!(parse_info_request(w.get_inner()).is_err());
+    }
+
+    #[test]
+    fn test_sig_algs() {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_EXT_INFO);
+        w.write_u32(2);
+        w.write_str("delay-compression");
+        w.write_str("none,zlib@openssh.com");
+        w.write_str("server-sig-algs");
+        w.write_str("ssh-ed25519,rsa-sha2-256");
+        assert_eq!(
+            parse_sig_algs(w.get_inner()).unwrap(),
+            ["ssh-ed25519", "rsa-sha2-256"]
+        );
+    }
+}
@@ -0,0 +1,439 @@
+// Packet encryption & integrity
+//     https://www.rfc-editor.org/rfc/rfc4253#section-6.3
+//     https://www.rfc-editor.org/rfc/rfc5647
+//     https://github.com/openssh/openssh-portable/blob/master/PROTOCOL.chacha20poly1305
+
+use super::{SshError, SshResult};
+use aes::{Aes128, Aes256};
+use aes_gcm::{aead::AeadInPlace, Aes128Gcm, Aes256Gcm};
+use chacha20::{
+    cipher::{KeyInit, KeyIvInit, StreamCipher, StreamCipherSeek},
+    ChaCha20Legacy,
+};
+use hmac::{Hmac, Mac as _};
+use poly1305::Poly1305;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+pub const CHACHA20_POLY1305: &str = "chacha20-poly1305@openssh.com";
+pub const AES256_GCM: &str = "aes256-gcm@openssh.com";
+pub const AES128_GCM: &str = "aes128-gcm@openssh.com";
+pub const AES256_CTR: &str = "aes256-ctr";
+pub const AES128_CTR: &str = "aes128-ctr";
+
+pub const HMAC_SHA256_ETM: &str = "hmac-sha2-256-etm@openssh.com";
+pub const HMAC_SHA512_ETM: &str = "hmac-sha2-512-etm@openssh.com";
+pub const HMAC_SHA256: &str = "hmac-sha2-256";
+pub const HMAC_SHA512: &str = "hmac-sha2-512";
+
+/// Ciphers in the order of preference, AEAD ones first
+pub const CIPHERS: &[&str] = &[
+    CHACHA20_POLY1305,
+    AES256_GCM,
+    AES128_GCM,
+    AES256_CTR,
+    AES128_CTR,
+];
+
+/// Macs in the order of preference, they are only used with the non-AEAD ciphers
+pub const MACS: &[&str] = &[HMAC_SHA256_ETM, HMAC_SHA512_ETM, HMAC_SHA256, HMAC_SHA512];
+
+/// (key length, iv length) required by the cipher
+pub fn cipher_key_len(name: &str) -> (usize, usize) {
+    match name {
+        CHACHA20_POLY1305 => (64, 0),
+        AES256_GCM => (32, 12),
+        AES128_GCM => (16, 12),
+        AES256_CTR => (32, 16),
+        AES128_CTR => (16, 16),
+        _ => (0, 0),
+    }
+}
+
+pub fn is_aead(name: &str) -> bool {
+    matches!(name, CHACHA20_POLY1305 | AES256_GCM | AES128_GCM)
+}
+
+pub fn mac_key_len(name: &str) -> usize {
+    match name {
+        HMAC_SHA256_ETM | HMAC_SHA256 => 32,
+        HMAC_SHA512_ETM | HMAC_SHA512 => 64,
+        _ => 0,
+    }
+}
+
+enum Cipher {
+    None,
+    ChaCha20Poly1305 {
+        main: Zeroizing<[u8; 32]>,
+        header: Zeroizing<[u8; 32]>,
+    },
+    Aes128Gcm {
+        aead: Box<Aes128Gcm>,
+        nonce: [u8; 12],
+    },
+    Aes256Gcm {
+        aead: Box<Aes256Gcm>,
+        nonce: [u8; 12],
+    },
+    Aes128Ctr(Box<ctr::Ctr128BE<Aes128>>),
+    Aes256Ctr(Box<ctr::Ctr128BE<Aes256>>),
+}
+
+enum Mac {
+    None,
+    HmacSha256(Zeroizing<Vec<u8>>),
+    HmacSha512(Zeroizing<Vec<u8>>),
+}
+
+/// The encryption state of one direction of the transport
+pub struct CipherState {
+    cipher: Cipher,
+    mac: Mac,
+    etm: bool,
+}
+
+impl CipherState {
+    pub fn none() -> Self {
+        Self {
+            cipher: Cipher::None,
+            mac: Mac::None,
+            etm: false,
+        }
+    }
+
+    pub fn new(cipher: &str, mac: &str, key: &[u8], iv: &[u8], mac_key: &[u8]) -> SshResult<Self> {
+        let cipher = match cipher {
+            CHACHA20_POLY1305 => {
+                // K_2 is used with the payload while K_1 only encrypts the packet length
+                let mut main = Zeroizing::new([0; 32]);
+                let mut header = Zeroizing::new([0; 32]);
+                main.copy_from_slice(&key[..32]);
+                header.copy_from_slice(&key[32..64]);
+                Cipher::ChaCha20Poly1305 { main, header }
+            }
+            AES256_GCM => Cipher::Aes256Gcm {
+                aead: Box::new(Aes256Gcm::new_from_slice(key).unwrap()),
+                nonce: iv.try_into().unwrap(),
+            },
+            AES128_GCM => Cipher::Aes128Gcm {
+                aead: Box::new(Aes128Gcm::new_from_slice(key).unwrap()),
+                nonce: iv.try_into().unwrap(),
+            },
+            AES256_CTR => {
+                Cipher::Aes256Ctr(Box::new(ctr::Ctr128BE::new_from_slices(key, iv).unwrap()))
+            }
+            AES128_CTR => {
+                Cipher::Aes128Ctr(Box::new(ctr::Ctr128BE::new_from_slices(key, iv).unwrap()))
+            }
+            _ => return Err(SshError::NoCommonAlgorithm("cipher")),
+        };
+        let (mac, etm) = match cipher {
+            Cipher::ChaCha20Poly1305 { .. }
+            | Cipher::Aes128Gcm { .. }
+            | Cipher::Aes256Gcm { .. } => (Mac::None, false),
+            _ => match mac {
+                HMAC_SHA256_ETM => (Mac::HmacSha256(Zeroizing::new(mac_key.to_vec())), true),
+                HMAC_SHA512_ETM => (Mac::HmacSha512(Zeroizing::new(mac_key.to_vec())), true),
+                HMAC_SHA256 => (Mac::HmacSha256(Zeroizing::new(mac_key.to_vec())), false),
+                HMAC_SHA512 => (Mac::HmacSha512(Zeroizing::new(mac_key.to_vec())), false),
+                _ => return Err(SshError::NoCommonAlgorithm("mac")),
+            },
+        };
+        Ok(Self { cipher, mac, etm })
+    }
+
+    pub fn block_size(&self) -> usize {
+        match self.cipher {
+            Cipher::None | Cipher::ChaCha20Poly1305 { .. } => 8,
+            _ => 16,
+        }
+    }
+
+    /// Length of the authentication tag appended to each packet
+    pub fn tag_len(&self) -> usize {
+        match (&self.cipher, &self.mac) {
+            (Cipher::ChaCha20Poly1305 { .. }, _)
+            | (Cipher::Aes128Gcm { .. }, _)
+            | (Cipher::Aes256Gcm { .. }, _) => 16,
+            (_, Mac::HmacSha256(_)) => 32,
+            (_, Mac::HmacSha512(_)) => 64,
+            _ => 0,
+        }
+    }
+
+    /// Whether the packet length is sent in the clear (or separately encrypted)
+    /// and thus excluded from the block alignment
+    pub fn length_excluded(&self) -> bool {
+        self.etm
+            || matches!(
+                self.cipher,
+                Cipher::ChaCha20Poly1305 { .. }
+                    | Cipher::Aes128Gcm { .. }
+                    | Cipher::Aes256Gcm { .. }
+            )
+    }
+
+    /// Number of bytes needed before the packet length can be told
+    pub fn first_block_len(&self) -> usize {
+        match self.cipher {
+            Cipher::Aes128Ctr(_) | Cipher::Aes256Ctr(_) if !self.etm => 16,
+            _ => 4,
+        }
+    }
+
+    /// Recover the packet length from the first bytes of a packet
+    ///
+    /// Ciphers which encrypt the length together with the payload decrypt the
+    /// first block in place, so `open` won't touch it again
+    pub fn decrypt_length(&mut self, seq: u32, first: &mut [u8]) -> u32 {
+        match &mut self.cipher {
+            Cipher::ChaCha20Poly1305 { header, .. } => {
+                let mut len = [0; 4];
+                len.copy_from_slice(&first[..4]);
+                let nonce = (seq as u64).to_be_bytes();
+                let mut chacha = ChaCha20Legacy::new(header.as_ref().into(), &nonce.into());
+                chacha.apply_keystream(&mut len);
+                u32::from_be_bytes(len)
+            }
+            Cipher::Aes128Ctr(c) if !self.etm => {
+                c.apply_keystream(first);
+                u32::from_be_bytes(first[..4].try_into().unwrap())
+            }
+            Cipher::Aes256Ctr(c) if !self.etm => {
+                c.apply_keystream(first);
+                u32::from_be_bytes(first[..4].try_into().unwrap())
+            }
+            _ => u32::from_be_bytes(first[..4].try_into().unwrap()),
+        }
+    }
+
+    /// Verify and decrypt a packet in place
+    ///
+    /// `packet` covers the packet length field to the end of the padding,
+    /// while `first_len` bytes of it have already been passed to `decrypt_length`
+    pub fn open(
+        &mut self,
+        seq: u32,
+        packet: &mut [u8],
+        first_len: usize,
+        tag: &[u8],
+    ) -> SshResult<()> {
+        match &mut self.cipher {
+            Cipher::None => Ok(()),
+            Cipher::ChaCha20Poly1305 { main, header } => {
+                let nonce = (seq as u64).to_be_bytes();
+                let mut chacha = ChaCha20Legacy::new(main.as_ref().into(), &nonce.into());
+                let mut poly_key = Zeroizing::new([0_u8; 32]);
+                chacha.apply_keystream(poly_key.as_mut());
+                let expected = Poly1305::new(poly_key.as_ref().into()).compute_unpadded(packet);
+                if !ct_eq(&expected, tag) {
+                    return Err(SshError::BadMac);
+                }
+                chacha.seek(64);
+                chacha.apply_keystream(&mut packet[4..]);
+                let mut chacha = ChaCha20Legacy::new(header.as_ref().into(), &nonce.into());
+                chacha.apply_keystream(&mut packet[..4]);
+                Ok(())
+            }
+            Cipher::Aes128Gcm { aead, nonce } => {
+                let (aad, body) = packet.split_at_mut(4);
+                aead.decrypt_in_place_detached((&*nonce).into(), aad, body, tag.into())
+                    .map_err(|_| SshError::BadMac)?;
+                increase_nonce(nonce);
+                Ok(())
+            }
+            Cipher::Aes256Gcm { aead, nonce } => {
+                let (aad, body) = packet.split_at_mut(4);
+                aead.decrypt_in_place_detached((&*nonce).into(), aad, body, tag.into())
+                    .map_err(|_| SshError::BadMac)?;
+                increase_nonce(nonce);
+                Ok(())
+            }
+            Cipher::Aes128Ctr(c) => {
+                if self.etm {
+                    self.mac.verify(seq, packet, tag)?;
+                    c.apply_keystream(&mut packet[4..]);
+                } else {
+                    c.apply_keystream(&mut packet[first_len..]);
+                    self.mac.verify(seq, packet, tag)?;
+                }
+                Ok(())
+            }
+            Cipher::Aes256Ctr(c) => {
+                if self.etm {
+                    self.mac.verify(seq, packet, tag)?;
+                    c.apply_keystream(&mut packet[4..]);
+                } else {
+                    c.apply_keystream(&mut packet[first_len..]);
+                    self.mac.verify(seq, packet, tag)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Encrypt a packet in place and append its authentication tag
+    pub fn seal(&mut self, seq: u32, packet: &mut Vec<u8>) {
+        match &mut self.cipher {
+            Cipher::None => (),
+            Cipher::ChaCha20Poly1305 { main, header } => {
+                let nonce = (seq as u64).to_be_bytes();
+                let mut chacha = ChaCha20Legacy::new(header.as_ref().into(), &nonce.into());
+                chacha.apply_keystream(&mut packet[..4]);
+                let mut chacha = ChaCha20Legacy::new(main.as_ref().into(), &nonce.into());
+                let mut poly_key = Zeroizing::new([0_u8; 32]);
+                chacha.apply_keystream(poly_key.as_mut());
+                chacha.seek(64);
+                chacha.apply_keystream(&mut packet[4..]);
+                let tag = Poly1305::new(poly_key.as_ref().into()).compute_unpadded(packet);
+                packet.extend_from_slice(&tag);
+            }
+            Cipher::Aes128Gcm { aead, nonce } => {
+                let (aad, body) = packet.split_at_mut(4);
+                let tag = aead
+                    .encrypt_in_place_detached((&*nonce).into(), aad, body)
+                    .unwrap();
+                packet.extend_from_slice(&tag);
+                increase_nonce(nonce);
+            }
+            Cipher::Aes256Gcm { aead, nonce } => {
+                let (aad, body) = packet.split_at_mut(4);
+                let tag = aead
+                    .encrypt_in_place_detached((&*nonce).into(), aad, body)
+                    .unwrap();
+                packet.extend_from_slice(&tag);
+                increase_nonce(nonce);
+            }
+            Cipher::Aes128Ctr(c) => {
+                if self.etm {
+                    c.apply_keystream(&mut packet[4..]);
+                    let tag = self.mac.compute(seq, packet);
+                    packet.extend_from_slice(&tag);
+                } else {
+                    let tag = self.mac.compute(seq, packet);
+                    c.apply_keystream(packet);
+                    packet.extend_from_slice(&tag);
+                }
+            }
+            Cipher::Aes256Ctr(c) => {
+                if self.etm {
+                    c.apply_keystream(&mut packet[4..]);
+                    let tag = self.mac.compute(seq, packet);
+                    packet.extend_from_slice(&tag);
+                } else {
+                    let tag = self.mac.compute(seq, packet);
+                    c.apply_keystream(packet);
+                    packet.extend_from_slice(&tag);
+                }
+            }
+        }
+    }
+}
+
+impl Mac {
+    fn compute(&self, seq: u32, data: &[u8]) -> Vec<u8> {
+        match self {
+            Mac::None => Vec::new(),
+            Mac::HmacSha256(key) => {
+                let mut mac = <Hmac<Sha256> as hmac::Mac>::new_from_slice(key).unwrap();
+                mac.update(&seq.to_be_bytes());
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Mac::HmacSha512(key) => {
+                let mut mac = <Hmac<Sha512> as hmac::Mac>::new_from_slice(key).unwrap();
+                mac.update(&seq.to_be_bytes());
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    fn verify(&self, seq: u32, data: &[u8], tag: &[u8]) -> SshResult<()> {
+        if ct_eq(&self.compute(seq, data), tag) {
+            Ok(())
+        } else {
+            Err(SshError::BadMac)
+        }
+    }
+}
+
+// The invocation counter is the low 64 bits of the nonce
+//     https://www.rfc-editor.org/rfc/rfc5647#section-7.1
+fn increase_nonce(nonce: &mut [u8; 12]) {
+    let counter = u64::from_be_bytes(nonce[4..].try_into().unwrap()).wrapping_add(1);
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+}
+
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip(cipher: &str, mac: &str) {
+        let (key_len, iv_len) = cipher_key_len(cipher);
+        let key = vec![0x11; key_len];
+        let iv = vec![0x22; iv_len];
+        let mac_key = vec![0x33; mac_key_len(mac)];
+        let mut sealer = CipherState::new(cipher, mac, &key, &iv, &mac_key).unwrap();
+        let mut opener = CipherState::new(cipher, mac, &key, &iv, &mac_key).unwrap();
+
+        for seq in 3..6 {
+            // 4 bytes length + 1 byte padding length + 5 bytes payload + padding
+            let plain = {
+                let mut p = vec![0, 0, 0, 28, 22, 94, 1, 2, 3, 4];
+                p.resize(32, 0xaa);
+                p
+            };
+            let mut packet = plain.clone();
+            sealer.seal(seq, &mut packet);
+            assert_ne!(&packet[4..32], &plain[4..32]);
+            let tag = packet.split_off(32);
+            assert_eq!(tag.len(), opener.tag_len());
+
+            let first = opener.first_block_len();
+            assert_eq!(opener.decrypt_length(seq, &mut packet[..first]), 28);
+            let mut tampered = packet.clone();
+            tampered[10] ^= 1;
+            assert!(CipherState::new(cipher, mac, &key, &iv, &mac_key)
+                .unwrap()
+                .open(seq, &mut tampered, first, &tag)
+                .is_err());
+            opener.open(seq, &mut packet, first, &tag).unwrap();
+            assert_eq!(packet, plain);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip(CHACHA20_POLY1305, "none");
+        roundtrip(AES256_GCM, "none");
+        roundtrip(AES128_GCM, "none");
+        roundtrip(AES256_CTR, HMAC_SHA256_ETM);
+        roundtrip(AES128_CTR, HMAC_SHA512);
+    }
+
+    #[test]
+    fn test_chacha20_poly1305() {
+        // key = 0x00..0x3f, seq = 7, packet: length 12, padding 4, payload "ping" + 3 zeros
+        let key: Vec<u8> = (0..64).collect();
+        let mut state = CipherState::new(CHACHA20_POLY1305, "none", &key, &[], &[]).unwrap();
+        let mut packet = vec![0, 0, 0, 12, 4, 0x70, 0x69, 0x6e, 0x67, 0, 0, 0, 0, 0, 0, 0];
+        state.seal(7, &mut packet);
+        assert_eq!(
+            packet,
+            [
+                0xa3, 0x9a, 0xfc, 0xa6, 0x2c, 0x36, 0x7c, 0x2d, 0x29, 0x83, 0x2a, 0x5e, 0x6c, 0x6d,
+                0xbb, 0xf0, 0xe6, 0x06, 0x26, 0x38, 0x70, 0xdf, 0xb5, 0x79, 0x69, 0xf4, 0x5a, 0x22,
+                0x17, 0x45, 0x00, 0x6c
+            ]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+// The zlib compression of the payloads, delayed until the user is
+// authenticated
+//     https://github.com/openssh/openssh-portable/blob/master/PROTOCOL
+
+use super::{SshError, SshResult};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+pub const ZLIB_OPENSSH: &str = "zlib@openssh.com";
+
+const CHUNK: usize = 4096;
+
+/// The deflate stream of the payloads sent, flushed after each of them
+pub struct Compressor(Compress);
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self(Compress::new(Compression::default(), true))
+    }
+
+    pub fn compress(&mut self, payload: &[u8]) -> SshResult<Vec<u8>> {
+        let mut out = Vec::with_capacity(payload.len() + CHUNK);
+        let start = self.0.total_in();
+        loop {
+            let consumed = (self.0.total_in() - start) as usize;
+            if out.len() == out.capacity() {
+                out.reserve(CHUNK);
+            }
+            self.0
+                .compress_vec(&payload[consumed..], &mut out, FlushCompress::Partial)
+                .map_err(|e| SshError::General(e.to_string()))?;
+            // the flush is complete once there is room left
+            if (self.0.total_in() - start) as usize == payload.len() && out.len() < out.capacity() {
+                return Ok(out);
+            }
+        }
+    }
+}
+
+/// The inflate stream of the payloads received
+pub struct Decompressor(Decompress);
+
+impl Decompressor {
+    pub fn new() -> Self {
+        Self(Decompress::new(true))
+    }
+
+    /// Inflate a payload, which may not be larger than `limit`
+    pub fn decompress(&mut self, payload: &[u8], limit: usize) -> SshResult<Vec<u8>> {
+        let mut out = Vec::with_capacity(payload.len() * 2 + CHUNK);
+        let start = self.0.total_in();
+        loop {
+            let consumed = (self.0.total_in() - start) as usize;
+            if out.len() == out.capacity() {
+                out.reserve(CHUNK);
+            }
+            let before = (consumed, out.len());
+            let status = self
+                .0
+                .decompress_vec(&payload[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|_| SshError::Malformed)?;
+            if out.len() > limit {
+                return Err(SshError::Malformed);
+            }
+            let consumed = (self.0.total_in() - start) as usize;
+            if status == Status::StreamEnd
+                || (consumed == payload.len() && out.len() < out.capacity())
+            {
+                return Ok(out);
+            }
+            if (consumed, out.len()) == before {
+                return Err(SshError::Malformed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_zlib() {
+        let mut compressor = Compressor::new();
+        let mut decompressor = Decompressor::new();
+        let big: Vec<u8> = (0..100_000u32).map(|i| (i % 7) as u8).collect();
+        // the dictionary is kept from one payload to the next
+        for payload in [&b"hello hello hello"[..], b"", &big, b"hello hello hello"] {
+            let compressed = compressor.compress(payload).unwrap();
+            assert_eq!(
+                decompressor.decompress(&compressed, 1 << 20).unwrap(),
+                payload
+            );
+        }
+        let compressed = compressor.compress(&big).unwrap();
+        assert!(compressed.len() < big.len() / 10);
+        assert!(decompressor.decompress(&compressed, 1000).is_err());
+    }
+}
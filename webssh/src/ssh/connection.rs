@@ -0,0 +1,486 @@
+// The connection protocol
+//     https://www.rfc-editor.org/rfc/rfc4254
+
+use super::{
+    msg::*,
+    wire::{SshReader, SshWriter},
+    SshError, SshResult, Transport,
+};
+use std::collections::{HashMap, VecDeque};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::{info, trace, warn};
+
+/// The window we grant to the server for each channel
+pub const WINDOW_SIZE: u32 = 2 * 1024 * 1024;
+/// The maximum payload of a data message we accept
+pub const MAX_PACKET_SIZE: u32 = 32 * 1024;
+
+pub const CHANNEL_SESSION: &str = "session";
+pub const CHANNEL_DIRECT_TCPIP: &str = "direct-tcpip";
+/// A global request the server does not know, only to get a reply
+pub const KEEPALIVE: &str = "keepalive@openssh.com";
+
+/// Something happened to a channel, identified by its local id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelEvent {
+    /// The server accepted to open the channel
+    Opened(u32),
+    /// The server refused to open the channel, with the reason
+    OpenFailed(u32, String),
+    Data(u32, Vec<u8>),
+    /// Data of another stream, `1` is stderr
+    ExtendedData(u32, u32, Vec<u8>),
+    /// The server granted more window, the data queued to the channel is
+    /// sent with the next flush
+    WindowAdjust(u32),
+    /// The answer to a request sent with `want_reply`
+    RequestReply(u32, bool),
+    ExitStatus(u32, u32),
+    ExitSignal(u32, String),
+    /// The server will not send more data
+    Eof(u32),
+    /// The channel is closed by both sides and its id is freed
+    Closed(u32),
+}
+
+// A channel request not sent yet as the channel is not confirmed
+struct PendingRequest {
+    kind: String,
+    want_reply: bool,
+    data: Vec<u8>,
+}
+
+struct Channel {
+    remote_id: Option<u32>,
+    remote_window: u32,
+    remote_max_packet: u32,
+    local_window: u32,
+    // data waiting for the remote window
+    pending: VecDeque<u8>,
+    requests: Vec<PendingRequest>,
+    eof: bool,
+    eof_sent: bool,
+    close_sent: bool,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            remote_id: None,
+            remote_window: 0,
+            remote_max_packet: 0,
+            local_window: WINDOW_SIZE,
+            pending: VecDeque::new(),
+            requests: Vec::new(),
+            eof: false,
+            eof_sent: false,
+            close_sent: false,
+        }
+    }
+}
+
+/// Channels multiplexed over an authenticated transport
+///
+/// Messages to the server are queued by the channel operations and written
+/// by [Connection::flush], so that [Connection::recv] is the only await point
+/// that reads, and it is safe to be cancelled, e.g. in a `tokio::select!`
+pub struct Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    transport: Transport<S>,
+    channels: HashMap<u32, Channel>,
+    next_id: u32,
+    outbox: VecDeque<Vec<u8>>,
+    // keepalives sent and not answered yet
+    keepalives: u32,
+}
+
+impl<S> Connection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(transport: Transport<S>) -> Self {
+        Self {
+            transport,
+            channels: HashMap::new(),
+            next_id: 0,
+            outbox: VecDeque::new(),
+            keepalives: 0,
+        }
+    }
+
+    pub fn transport(&self) -> &Transport<S> {
+        &self.transport
+    }
+
+    /// Ask the server for a reply, so that the connection is not idle and
+    /// a dead one is noticed
+    pub fn keepalive(&mut self) {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_GLOBAL_REQUEST);
+        w.write_str(KEEPALIVE);
+        w.write_bool(true);
+        self.outbox.push_back(w.into_inner());
+        self.keepalives += 1;
+    }
+
+    /// The keepalives the server has not replied to
+    pub fn unanswered_keepalives(&self) -> u32 {
+        self.keepalives
+    }
+
+    /// Whether the channel `id` is open, or being opened
+    pub fn is_open(&self, id: u32) -> bool {
+        self.channels.contains_key(&id)
+    }
+
+    /// Open a session channel, the local id is returned
+    ///
+    /// Requests to the channel can be queued before it is confirmed
+    pub fn open_session(&mut self) -> u32 {
+        self.open_channel(CHANNEL_SESSION, &[])
+    }
+
+    /// Open a channel to `host:port`, connected by the server
+    ///
+    /// The data of the channel is what the server reads from, and writes to,
+    /// its tcp connection
+    pub fn open_direct_tcpip(&mut self, host: &str, port: u16) -> u32 {
+        let mut w = SshWriter::new();
+        w.write_str(host);
+        w.write_u32(port as u32);
+        // the originator, there is no local socket
+        w.write_str("127.0.0.1");
+        w.write_u32(0);
+        self.open_channel(CHANNEL_DIRECT_TCPIP, w.get_inner())
+    }
+
+    /// Open a channel of `kind` with the type specific `data`
+    pub fn open_channel(&mut self, kind: &str, data: &[u8]) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.channels.insert(id, Channel::new());
+
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_CHANNEL_OPEN);
+        w.write_str(kind);
+        w.write_u32(id);
+        w.write_u32(WINDOW_SIZE);
+        w.write_u32(MAX_PACKET_SIZE);
+        w.write_bytes(data);
+        self.outbox.push_back(w.into_inner());
+        id
+    }
+
+    /// Send a channel request of `kind` with the type specific `data`
+    pub fn request(&mut self, id: u32, kind: &str, want_reply: bool, data: &[u8]) {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return;
+        };
+        match channel.remote_id {
+            Some(remote_id) => {
+                self.outbox
+                    .push_back(encode_request(remote_id, kind, want_reply, data));
+            }
+            None => channel.requests.push(PendingRequest {
+                kind: kind.to_owned(),
+                want_reply,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    /// Request a pseudo terminal of `cols` x `rows`
+    pub fn request_pty(&mut self, id: u32, term: &str, cols: u32, rows: u32) {
+        let mut w = SshWriter::new();
+        w.write_str(term);
+        w.write_u32(cols);
+        w.write_u32(rows);
+        // width & height in pixels
+        w.write_u32(0);
+        w.write_u32(0);
+        // encoded terminal modes, none
+        w.write_string(&[0]);
+        self.request(id, "pty-req", false, w.get_inner());
+    }
+
+    /// Tell the new size of the terminal, after the pty is allocated
+    pub fn window_change(&mut self, id: u32, cols: u32, rows: u32) {
+        let mut w = SshWriter::new();
+        w.write_u32(cols);
+        w.write_u32(rows);
+        w.write_u32(0);
+        w.write_u32(0);
+        self.request(id, "window-change", false, w.get_inner());
+    }
+
+    pub fn request_shell(&mut self, id: u32) {
+        self.request(id, "shell", true, &[]);
+    }
+
+    /// Queue `data` to the channel, it is sent as the window of the server allows
+    pub fn data(&mut self, id: u32, data: &[u8]) {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return;
+        };
+        if channel.eof_sent || channel.close_sent {
+            return;
+        }
+        channel.pending.extend(data);
+        self.send_pending(id);
+    }
+
+    /// Tell the server no more data will be sent to the channel
+    pub fn eof(&mut self, id: u32) {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return;
+        };
+        channel.eof = true;
+        self.send_pending(id);
+    }
+
+    pub fn close(&mut self, id: u32) {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return;
+        };
+        if channel.close_sent {
+            return;
+        }
+        channel.close_sent = true;
+        channel.pending.clear();
+        if let Some(remote_id) = channel.remote_id {
+            self.outbox
+                .push_back(encode_channel_msg(SSH_MSG_CHANNEL_CLOSE, remote_id));
+        }
+    }
+
+    /// Write the queued messages to the server
+    pub async fn flush(&mut self) -> SshResult<()> {
+        while let Some(payload) = self.outbox.pop_front() {
+            self.transport.send(&payload).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn disconnect(&mut self) -> SshResult<()> {
+        self.flush().await?;
+        self.transport
+            .disconnect(SSH_DISCONNECT_BY_APPLICATION, "bye")
+            .await
+    }
+
+    // Send as much pending data as the window allows
+    fn send_pending(&mut self, id: u32) {
+        let Some(channel) = self.channels.get_mut(&id) else {
+            return;
+        };
+        let Some(remote_id) = channel.remote_id else {
+            return;
+        };
+        while !channel.pending.is_empty() && channel.remote_window > 0 {
+            let len = channel
+                .pending
+                .len()
+                .min(channel.remote_window as usize)
+                .min(channel.remote_max_packet as usize);
+            let mut w = SshWriter::new();
+            w.write_u8(SSH_MSG_CHANNEL_DATA);
+            w.write_u32(remote_id);
+            w.write_u32(len as u32);
+            let chunk: Vec<u8> = channel.pending.drain(..len).collect();
+            w.write_bytes(&chunk);
+            self.outbox.push_back(w.into_inner());
+            channel.remote_window -= len as u32;
+        }
+        if channel.pending.is_empty() && channel.eof && !channel.eof_sent {
+            channel.eof_sent = true;
+            self.outbox
+                .push_back(encode_channel_msg(SSH_MSG_CHANNEL_EOF, remote_id));
+        }
+    }
+
+    /// Receive the next event of any channel
+    pub async fn recv(&mut self) -> SshResult<ChannelEvent> {
+        loop {
+            let payload = self.transport.recv().await?;
+            if let Some(event) = self.handle(&payload)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn channel_mut(&mut self, id: u32) -> SshResult<&mut Channel> {
+        self.channels
+            .get_mut(&id)
+            .ok_or_else(|| SshError::General(format!("No such channel {}", id)))
+    }
+
+    fn handle(&mut self, payload: &[u8]) -> SshResult<Option<ChannelEvent>> {
+        let mut r = SshReader::new(&payload[1..]);
+        let event = match payload[0] {
+            SSH_MSG_GLOBAL_REQUEST => {
+                let name = r.read_utf8()?;
+                trace!("Global request {}", name);
+                if r.read_bool()? {
+                    self.outbox.push_back(vec![SSH_MSG_REQUEST_FAILURE]);
+                }
+                None
+            }
+            // only the keepalives are sent, and answered in order
+            SSH_MSG_REQUEST_SUCCESS | SSH_MSG_REQUEST_FAILURE => {
+                self.keepalives = self.keepalives.saturating_sub(1);
+                None
+            }
+            SSH_MSG_CHANNEL_OPEN => {
+                // no channel is opened by the server
+                let kind = r.read_utf8()?;
+                let sender = r.read_u32()?;
+                info!("Reject channel {} opened by the server", kind);
+                let mut w = SshWriter::new();
+                w.write_u8(SSH_MSG_CHANNEL_OPEN_FAILURE);
+                w.write_u32(sender);
+                w.write_u32(SSH_OPEN_ADMINISTRATIVELY_PROHIBITED);
+                w.write_str("not supported");
+                w.write_str("");
+                self.outbox.push_back(w.into_inner());
+                None
+            }
+            SSH_MSG_CHANNEL_OPEN_CONFIRMATION => {
+                let id = r.read_u32()?;
+                let channel = self.channel_mut(id)?;
+                let remote_id = r.read_u32()?;
+                channel.remote_id = Some(remote_id);
+                channel.remote_window = r.read_u32()?;
+                channel.remote_max_packet = r.read_u32()?.max(1);
+                let requests = std::mem::take(&mut channel.requests);
+                let close = channel.close_sent;
+                for request in requests {
+                    self.outbox.push_back(encode_request(
+                        remote_id,
+                        &request.kind,
+                        request.want_reply,
+                        &request.data,
+                    ));
+                }
+                if close {
+                    self.outbox
+                        .push_back(encode_channel_msg(SSH_MSG_CHANNEL_CLOSE, remote_id));
+                }
+                self.send_pending(id);
+                Some(ChannelEvent::Opened(id))
+            }
+            SSH_MSG_CHANNEL_OPEN_FAILURE => {
+                let id = r.read_u32()?;
+                let code = r.read_u32()?;
+                let desc = r.read_utf8()?;
+                self.channels.remove(&id);
+                Some(ChannelEvent::OpenFailed(id, format!("{} ({})", desc, code)))
+            }
+            SSH_MSG_CHANNEL_WINDOW_ADJUST => {
+                let id = r.read_u32()?;
+                let add = r.read_u32()?;
+                let channel = self.channel_mut(id)?;
+                channel.remote_window = channel.remote_window.saturating_add(add);
+                self.send_pending(id);
+                Some(ChannelEvent::WindowAdjust(id))
+            }
+            SSH_MSG_CHANNEL_DATA | SSH_MSG_CHANNEL_EXTENDED_DATA => {
+                let id = r.read_u32()?;
+                let code = if payload[0] == SSH_MSG_CHANNEL_EXTENDED_DATA {
+                    Some(r.read_u32()?)
+                } else {
+                    None
+                };
+                let data = r.read_string()?.to_vec();
+                self.consume_window(id, data.len() as u32)?;
+                Some(match code {
+                    Some(code) => ChannelEvent::ExtendedData(id, code, data),
+                    None => ChannelEvent::Data(id, data),
+                })
+            }
+            SSH_MSG_CHANNEL_EOF => Some(ChannelEvent::Eof(r.read_u32()?)),
+            SSH_MSG_CHANNEL_CLOSE => {
+                let id = r.read_u32()?;
+                let channel = self
+                    .channels
+                    .remove(&id)
+                    .ok_or_else(|| SshError::General(format!("No such channel {}", id)))?;
+                if !channel.close_sent {
+                    if let Some(remote_id) = channel.remote_id {
+                        self.outbox
+                            .push_back(encode_channel_msg(SSH_MSG_CHANNEL_CLOSE, remote_id));
+                    }
+                }
+                Some(ChannelEvent::Closed(id))
+            }
+            SSH_MSG_CHANNEL_REQUEST => {
+                let id = r.read_u32()?;
+                let kind = r.read_utf8()?;
+                let want_reply = r.read_bool()?;
+                let event = match kind.as_str() {
+                    "exit-status" => Some(ChannelEvent::ExitStatus(id, r.read_u32()?)),
+                    "exit-signal" => Some(ChannelEvent::ExitSignal(id, r.read_utf8()?)),
+                    _ => {
+                        trace!("Channel request {}", kind);
+                        None
+                    }
+                };
+                if want_reply {
+                    let remote_id = self.channel_mut(id)?.remote_id.unwrap_or_default();
+                    self.outbox
+                        .push_back(encode_channel_msg(SSH_MSG_CHANNEL_FAILURE, remote_id));
+                }
+                event
+            }
+            SSH_MSG_CHANNEL_SUCCESS => Some(ChannelEvent::RequestReply(r.read_u32()?, true)),
+            SSH_MSG_CHANNEL_FAILURE => Some(ChannelEvent::RequestReply(r.read_u32()?, false)),
+            other => {
+                warn!("Unexpected message {}", other);
+                None
+            }
+        };
+        Ok(event)
+    }
+
+    // Account the received data and grant more window if half of it is used
+    fn consume_window(&mut self, id: u32, len: u32) -> SshResult<()> {
+        let channel = self.channel_mut(id)?;
+        if len > channel.local_window {
+            return Err(SshError::General(format!(
+                "Channel {} window exceeded by the server",
+                id
+            )));
+        }
+        channel.local_window -= len;
+        if channel.local_window < WINDOW_SIZE / 2 {
+            if let Some(remote_id) = channel.remote_id {
+                let add = WINDOW_SIZE - channel.local_window;
+                channel.local_window = WINDOW_SIZE;
+                let mut w = SshWriter::new();
+                w.write_u8(SSH_MSG_CHANNEL_WINDOW_ADJUST);
+                w.write_u32(remote_id);
+                w.write_u32(add);
+                self.outbox.push_back(w.into_inner());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_channel_msg(msg: u8, remote_id: u32) -> Vec<u8> {
+    let mut w = SshWriter::new();
+    w.write_u8(msg);
+    w.write_u32(remote_id);
+    w.into_inner()
+}
+
+fn encode_request(remote_id: u32, kind: &str, want_reply: bool, data: &[u8]) -> Vec<u8> {
+    let mut w = SshWriter::new();
+    w.write_u8(SSH_MSG_CHANNEL_REQUEST);
+    w.write_u32(remote_id);
+    w.write_str(kind);
+    w.write_bool(want_reply);
+    w.write_bytes(data);
+    w.into_inner()
+}
@@ -0,0 +1,109 @@
+use super::{compress, kex, key, transport::TransportConfig, SshResult, Transport};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Connection Builder to setup a ssh client
+pub struct SshConnector<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream: S,
+    config: TransportConfig,
+}
+
+impl<S> SshConnector<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// To new a ssh client configuration with stream `S`
+    ///
+    /// `S` should implement async I/O methods, it can be a websocket stream
+    /// or a tcp stream, when testing natively
+    ///
+    /// ```no_run
+    /// use tokio::net::TcpStream;
+    /// use webssh::ssh::SshConnector;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tcp = TcpStream::connect("127.0.0.1:22").await.unwrap();
+    ///     let transport = SshConnector::new(tcp).connect().await.unwrap();
+    ///     println!("Connected to {}", transport.server_id());
+    /// }
+    /// ```
+    ///
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            config: TransportConfig::default(),
+        }
+    }
+
+    /// The identification string sent to the server, `SSH-2.0-webssh_0.1` by default
+    ///
+    pub fn set_client_id(mut self, client_id: &str) -> Self {
+        self.config.client_id = client_id.to_owned();
+        self
+    }
+
+    /// Offer the host key algorithms of `key_types` first
+    ///
+    /// Like OpenSSH does for the key types it already knows for the host, so
+    /// that the server proves the key which can be verified
+    ///
+    pub fn prefer_host_keys(mut self, key_types: &[String]) -> Self {
+        self.config
+            .host_key
+            .sort_by_key(|algorithm| !key_types.iter().any(|t| t == key::key_type_of(algorithm)));
+        self
+    }
+
+    /// Exchange the keys again after `bytes` in either direction, or after
+    /// `interval`, 1 GiB and one hour by default
+    ///
+    pub fn set_rekey_limit(mut self, bytes: u64, interval: Duration) -> Self {
+        self.config.rekey_bytes = bytes;
+        self.config.rekey_interval = interval;
+        self
+    }
+
+    /// Offer the zlib compression, which starts once the user is authenticated
+    ///
+    pub fn set_compression(mut self, enabled: bool) -> Self {
+        self.config.compression = if enabled {
+            vec![compress::ZLIB_OPENSSH, kex::COMPRESSION_NONE]
+        } else {
+            vec![kex::COMPRESSION_NONE]
+        };
+        self
+    }
+
+    /// Exchange the version and keys with the server
+    ///
+    /// The returned transport has been encrypted with the negotiated algorithms
+    /// and the server has proved the ownership of its host key
+    ///
+    pub async fn connect(self) -> SshResult<Transport<S>> {
+        Transport::connect(self.stream, self.config).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prefer_host_keys() {
+        let (stream, _) = tokio::io::duplex(64);
+        let connector = SshConnector::new(stream).prefer_host_keys(&[key::SSH_RSA.to_owned()]);
+        assert_eq!(
+            connector.config.host_key,
+            [
+                key::RSA_SHA2_512,
+                key::RSA_SHA2_256,
+                key::SSH_ED25519,
+                key::ECDSA_SHA2_NISTP256
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use thiserror::Error;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SshError {
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The server sent an invalid version string: {0}")]
+    InvalidVersion(String),
+    #[error("Malformed ssh packet")]
+    Malformed,
+    #[error("Packet authentication failed")]
+    BadMac,
+    #[error("Unexpected ssh message {0}")]
+    UnexpectedMessage(u8),
+    #[error("No common {0} algorithm")]
+    NoCommonAlgorithm(&'static str),
+    #[error("Unsupported key type: {0}")]
+    UnsupportedKey(String),
+    #[error("Invalid private key: {0}")]
+    InvalidKey(String),
+    #[error("The private key is encrypted, a passphrase is required")]
+    KeyEncrypted,
+    #[error("Incorrect passphrase for the private key")]
+    BadPassphrase,
+    #[error("Host key signature verification failed")]
+    BadSignature,
+    #[error("Invalid key exchange value")]
+    InvalidKex,
+    #[error("Disconnected by the server ({0}): {1}")]
+    Disconnect(u32, String),
+    #[error("Authentication failed, the server allows: {}", .0.join(", "))]
+    AuthFailed(Vec<String>),
+    #[error("Channel {0} open failed: {1}")]
+    ChannelOpenFailed(u32, String),
+    #[error("Ssh Error with message: {0}")]
+    General(String),
+}
+
+pub type SshResult<T> = Result<T, SshError>;
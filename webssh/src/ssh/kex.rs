@@ -0,0 +1,327 @@
+// Key exchange
+//     https://www.rfc-editor.org/rfc/rfc4253#section-7
+//     https://www.rfc-editor.org/rfc/rfc8731
+
+use super::{
+    cipher,
+    msg::*,
+    wire::{SshReader, SshWriter},
+    SshError, SshResult,
+};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+pub const CURVE25519_SHA256: &str = "curve25519-sha256";
+pub const CURVE25519_SHA256_LIBSSH: &str = "curve25519-sha256@libssh.org";
+
+// Strict key exchange, the mitigation of the "Terrapin" attack
+//     https://github.com/openssh/openssh-portable/blob/master/PROTOCOL
+pub const KEX_STRICT_CLIENT: &str = "kex-strict-c-v00@openssh.com";
+pub const KEX_STRICT_SERVER: &str = "kex-strict-s-v00@openssh.com";
+
+// Asks the server for SSH_MSG_EXT_INFO, which tells the signature algorithms
+// it accepts for user authentication
+//     https://www.rfc-editor.org/rfc/rfc8308
+pub const EXT_INFO_CLIENT: &str = "ext-info-c";
+
+pub const KEX_ALGORITHMS: &[&str] = &[CURVE25519_SHA256, CURVE25519_SHA256_LIBSSH];
+
+pub const COMPRESSION_NONE: &str = "none";
+
+#[derive(Debug, Clone)]
+pub struct KexInit {
+    pub cookie: [u8; 16],
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub cipher_c2s: Vec<String>,
+    pub cipher_s2c: Vec<String>,
+    pub mac_c2s: Vec<String>,
+    pub mac_s2c: Vec<String>,
+    pub comp_c2s: Vec<String>,
+    pub comp_s2c: Vec<String>,
+    pub first_kex_follows: bool,
+}
+
+fn to_list(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+impl KexInit {
+    pub fn client(kex: &[&str], host_key: &[&str], compression: &[&str]) -> Self {
+        let mut cookie = [0; 16];
+        getrandom::getrandom(&mut cookie).unwrap();
+        Self {
+            cookie,
+            kex: to_list(kex),
+            host_key: to_list(host_key),
+            cipher_c2s: to_list(cipher::CIPHERS),
+            cipher_s2c: to_list(cipher::CIPHERS),
+            mac_c2s: to_list(cipher::MACS),
+            mac_s2c: to_list(cipher::MACS),
+            comp_c2s: to_list(compression),
+            comp_s2c: to_list(compression),
+            first_kex_follows: false,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_KEXINIT);
+        w.write_bytes(&self.cookie);
+        for list in [
+            &self.kex,
+            &self.host_key,
+            &self.cipher_c2s,
+            &self.cipher_s2c,
+            &self.mac_c2s,
+            &self.mac_s2c,
+            &self.comp_c2s,
+            &self.comp_s2c,
+        ] {
+            w.write_str(&list.join(","));
+        }
+        // languages
+        w.write_name_list(&[]);
+        w.write_name_list(&[]);
+        w.write_bool(self.first_kex_follows);
+        // reserved
+        w.write_u32(0);
+        w.into_inner()
+    }
+
+    pub fn decode(payload: &[u8]) -> SshResult<Self> {
+        let mut r = SshReader::new(payload);
+        if r.read_u8()? != SSH_MSG_KEXINIT {
+            return Err(SshError::Malformed);
+        }
+        let cookie = r.read_bytes(16)?.try_into().unwrap();
+        let init = Self {
+            cookie,
+            kex: r.read_name_list()?,
+            host_key: r.read_name_list()?,
+            cipher_c2s: r.read_name_list()?,
+            cipher_s2c: r.read_name_list()?,
+            mac_c2s: r.read_name_list()?,
+            mac_s2c: r.read_name_list()?,
+            comp_c2s: r.read_name_list()?,
+            comp_s2c: r.read_name_list()?,
+            first_kex_follows: {
+                // languages are ignored
+                let _ = r.read_name_list()?;
+                let _ = r.read_name_list()?;
+                r.read_bool()?
+            },
+        };
+        Ok(init)
+    }
+}
+
+/// Algorithms agreed by both sides
+#[derive(Debug, Clone)]
+pub struct Algorithms {
+    pub kex: String,
+    pub host_key: String,
+    pub cipher_c2s: String,
+    pub cipher_s2c: String,
+    pub mac_c2s: String,
+    pub mac_s2c: String,
+    pub comp_c2s: String,
+    pub comp_s2c: String,
+}
+
+// The chosen algorithm MUST be the first algorithm on the client's name-list
+// that is also on the server's name-list.
+fn choose(what: &'static str, client: &[String], server: &[String]) -> SshResult<String> {
+    client
+        .iter()
+        .find(|c| server.contains(c))
+        .cloned()
+        .ok_or(SshError::NoCommonAlgorithm(what))
+}
+
+pub fn negotiate(client: &KexInit, server: &KexInit) -> SshResult<Algorithms> {
+    let cipher_c2s = choose("cipher", &client.cipher_c2s, &server.cipher_c2s)?;
+    let cipher_s2c = choose("cipher", &client.cipher_s2c, &server.cipher_s2c)?;
+    // The mac of an AEAD cipher is implied by the cipher itself
+    let mac_c2s = if cipher::is_aead(&cipher_c2s) {
+        String::new()
+    } else {
+        choose("mac", &client.mac_c2s, &server.mac_c2s)?
+    };
+    let mac_s2c = if cipher::is_aead(&cipher_s2c) {
+        String::new()
+    } else {
+        choose("mac", &client.mac_s2c, &server.mac_s2c)?
+    };
+    Ok(Algorithms {
+        kex: choose("kex", &client.kex, &server.kex)?,
+        host_key: choose("host key", &client.host_key, &server.host_key)?,
+        cipher_c2s,
+        cipher_s2c,
+        mac_c2s,
+        mac_s2c,
+        comp_c2s: choose("compression", &client.comp_c2s, &server.comp_c2s)?,
+        comp_s2c: choose("compression", &client.comp_s2c, &server.comp_s2c)?,
+    })
+}
+
+/// Whether the server's guessed kex packet should be ignored
+///
+/// The guess is right only if both the preferred kex and host key algorithms match
+pub fn guess_is_wrong(client: &KexInit, server: &KexInit) -> bool {
+    server.first_kex_follows
+        && (client.kex.first() != server.kex.first()
+            || client.host_key.first() != server.host_key.first())
+}
+
+pub struct Curve25519 {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl Curve25519 {
+    pub fn new() -> Self {
+        let mut seed = Zeroizing::new([0; 32]);
+        getrandom::getrandom(seed.as_mut()).unwrap();
+        let secret = StaticSecret::from(*seed);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.public.as_bytes()
+    }
+
+    /// The shared secret K, as an unsigned big-endian number
+    pub fn shared_secret(&self, server_public: &[u8]) -> SshResult<Zeroizing<Vec<u8>>> {
+        let server_public: [u8; 32] = server_public.try_into().map_err(|_| SshError::InvalidKex)?;
+        let shared = self.secret.diffie_hellman(&PublicKey::from(server_public));
+        // Clients MUST check whether the computed shared secret K is the all-zero value
+        if !shared.was_contributory() {
+            return Err(SshError::InvalidKex);
+        }
+        Ok(Zeroizing::new(shared.as_bytes().to_vec()))
+    }
+}
+
+/// The exchange hash H
+pub struct ExchangeHash<'a> {
+    pub client_id: &'a [u8],
+    pub server_id: &'a [u8],
+    pub client_kexinit: &'a [u8],
+    pub server_kexinit: &'a [u8],
+    pub host_key: &'a [u8],
+    pub client_public: &'a [u8],
+    pub server_public: &'a [u8],
+    pub shared_secret: &'a [u8],
+}
+
+impl ExchangeHash<'_> {
+    pub fn digest(&self) -> Vec<u8> {
+        let mut w = SshWriter::new();
+        w.write_string(self.client_id);
+        w.write_string(self.server_id);
+        w.write_string(self.client_kexinit);
+        w.write_string(self.server_kexinit);
+        w.write_string(self.host_key);
+        w.write_string(self.client_public);
+        w.write_string(self.server_public);
+        w.write_mpint(self.shared_secret);
+        Sha256::digest(w.get_inner()).to_vec()
+    }
+}
+
+/// Derive a key of `len` bytes for the usage `letter` ('A' to 'F')
+///
+/// HASH(K || H || letter || session_id), extended with HASH(K || H || K1 ...) as needed
+pub fn derive_key(
+    shared_secret: &[u8],
+    exchange_hash: &[u8],
+    letter: u8,
+    session_id: &[u8],
+    len: usize,
+) -> Zeroizing<Vec<u8>> {
+    let mut k = SshWriter::new();
+    k.write_mpint(shared_secret);
+    let k = Zeroizing::new(k.into_inner());
+
+    let mut hasher = Sha256::new();
+    hasher.update(&k);
+    hasher.update(exchange_hash);
+    hasher.update([letter]);
+    hasher.update(session_id);
+    let mut key = Zeroizing::new(hasher.finalize().to_vec());
+    while key.len() < len {
+        let mut hasher = Sha256::new();
+        hasher.update(&k);
+        hasher.update(exchange_hash);
+        hasher.update(&key);
+        key.extend_from_slice(&hasher.finalize());
+    }
+    key.truncate(len);
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::key;
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let client = KexInit::client(
+            KEX_ALGORITHMS,
+            key::HOST_KEY_ALGORITHMS,
+            &[COMPRESSION_NONE],
+        );
+        let mut server = KexInit::decode(&client.encode()).unwrap();
+        server.kex = to_list(&[CURVE25519_SHA256_LIBSSH, "diffie-hellman-group14-sha256"]);
+        server.cipher_c2s = to_list(&[cipher::AES128_CTR, cipher::CHACHA20_POLY1305]);
+        server.cipher_s2c = to_list(&[cipher::AES128_CTR]);
+        server.mac_c2s = to_list(&["hmac-sha1"]);
+        server.mac_s2c = to_list(&[cipher::HMAC_SHA512, cipher::HMAC_SHA256]);
+
+        let algs = negotiate(&client, &server).unwrap();
+        assert_eq!(algs.kex, CURVE25519_SHA256_LIBSSH);
+        assert_eq!(algs.cipher_c2s, cipher::CHACHA20_POLY1305);
+        assert_eq!(algs.mac_c2s, "");
+        assert_eq!(algs.cipher_s2c, cipher::AES128_CTR);
+        assert_eq!(algs.mac_s2c, cipher::HMAC_SHA256);
+
+        server.host_key = to_list(&["ssh-dss"]);
+        assert!(matches!(
+            negotiate(&client, &server),
+            Err(SshError::NoCommonAlgorithm("host key"))
+        ));
+    }
+
+    #[test]
+    fn test_curve25519() {
+        let alice = Curve25519::new();
+        let bob = Curve25519::new();
+        assert_eq!(
+            *alice.shared_secret(bob.public_key()).unwrap(),
+            *bob.shared_secret(alice.public_key()).unwrap()
+        );
+        // low order point
+        assert!(alice.shared_secret(&[0; 32]).is_err());
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let key = derive_key(&[0x80, 1, 2], b"hash", b'C', b"session", 64);
+        assert_eq!(key.len(), 64);
+        // HASH(mpint(K) || H || 'C' || session_id)
+        let mut first = Sha256::new();
+        first.update([0, 0, 0, 4, 0, 0x80, 1, 2]);
+        first.update(b"hashCsession");
+        let first = first.finalize();
+        assert_eq!(&key[..32], first.as_slice());
+        let mut second = Sha256::new();
+        second.update([0, 0, 0, 4, 0, 0x80, 1, 2]);
+        second.update(b"hash");
+        second.update(first);
+        assert_eq!(&key[32..], second.finalize().as_slice());
+    }
+}
@@ -0,0 +1,168 @@
+// Public key formats & signatures
+//     https://www.rfc-editor.org/rfc/rfc4253#section-6.6
+//     https://www.rfc-editor.org/rfc/rfc5656#section-3
+//     https://www.rfc-editor.org/rfc/rfc8332
+//     https://www.rfc-editor.org/rfc/rfc8709
+
+use super::{
+    wire::{SshReader, SshWriter},
+    SshError, SshResult,
+};
+use base64ct::{Base64, Base64Unpadded, Encoding};
+use p256::ecdsa::signature::Verifier;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha512};
+
+pub const SSH_ED25519: &str = "ssh-ed25519";
+pub const ECDSA_SHA2_NISTP256: &str = "ecdsa-sha2-nistp256";
+pub const RSA_SHA2_512: &str = "rsa-sha2-512";
+pub const RSA_SHA2_256: &str = "rsa-sha2-256";
+pub const SSH_RSA: &str = "ssh-rsa";
+
+pub const HOST_KEY_ALGORITHMS: &[&str] =
+    &[SSH_ED25519, ECDSA_SHA2_NISTP256, RSA_SHA2_512, RSA_SHA2_256];
+
+/// The type of the keys which sign with the host key `algorithm`
+pub fn key_type_of(algorithm: &str) -> &str {
+    match algorithm {
+        RSA_SHA2_512 | RSA_SHA2_256 => SSH_RSA,
+        algorithm => algorithm,
+    }
+}
+
+const NISTP256: &str = "nistp256";
+const RSA_MAX_BITS: usize = 16384;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    EcdsaP256(p256::ecdsa::VerifyingKey),
+    Rsa(RsaPublicKey),
+}
+
+impl PublicKey {
+    pub fn from_blob(blob: &[u8]) -> SshResult<Self> {
+        let mut r = SshReader::new(blob);
+        let key_type = r.read_utf8()?;
+        match key_type.as_str() {
+            SSH_ED25519 => {
+                let key: [u8; 32] = r
+                    .read_string()?
+                    .try_into()
+                    .map_err(|_| SshError::Malformed)?;
+                let key = ed25519_dalek::VerifyingKey::from_bytes(&key)
+                    .map_err(|_| SshError::Malformed)?;
+                Ok(PublicKey::Ed25519(key))
+            }
+            ECDSA_SHA2_NISTP256 => {
+                if r.read_utf8()? != NISTP256 {
+                    return Err(SshError::Malformed);
+                }
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(r.read_string()?)
+                    .map_err(|_| SshError::Malformed)?;
+                Ok(PublicKey::EcdsaP256(key))
+            }
+            SSH_RSA => {
+                let e = BigUint::from_bytes_be(r.read_mpint()?);
+                let n = BigUint::from_bytes_be(r.read_mpint()?);
+                let key = RsaPublicKey::new_with_max_size(n, e, RSA_MAX_BITS)
+                    .map_err(|_| SshError::Malformed)?;
+                Ok(PublicKey::Rsa(key))
+            }
+            _ => Err(SshError::UnsupportedKey(key_type)),
+        }
+    }
+
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut w = SshWriter::new();
+        w.write_str(self.key_type());
+        match self {
+            PublicKey::Ed25519(key) => w.write_string(key.as_bytes()),
+            PublicKey::EcdsaP256(key) => {
+                w.write_str(NISTP256);
+                w.write_string(key.to_encoded_point(false).as_bytes());
+            }
+            PublicKey::Rsa(key) => {
+                use rsa::traits::PublicKeyParts;
+                w.write_mpint(&key.e().to_bytes_be());
+                w.write_mpint(&key.n().to_bytes_be());
+            }
+        }
+        w.into_inner()
+    }
+
+    /// The key type as it is encoded in the blob
+    pub fn key_type(&self) -> &'static str {
+        match self {
+            PublicKey::Ed25519(_) => SSH_ED25519,
+            PublicKey::EcdsaP256(_) => ECDSA_SHA2_NISTP256,
+            PublicKey::Rsa(_) => SSH_RSA,
+        }
+    }
+
+    /// The SHA256 fingerprint, as shown by `ssh-keygen -l`
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.to_blob())
+    }
+
+    /// The `type base64` form of the OpenSSH public key files
+    pub fn to_openssh(&self) -> String {
+        format!(
+            "{} {}",
+            self.key_type(),
+            Base64::encode_string(&self.to_blob())
+        )
+    }
+
+    /// Verify an encoded signature `string algorithm || string signature` of `data`
+    ///
+    /// The signature algorithm must be `algorithm`, which is the one negotiated
+    pub fn verify(&self, algorithm: &str, data: &[u8], signature: &[u8]) -> SshResult<()> {
+        let mut r = SshReader::new(signature);
+        if r.read_utf8()? != algorithm {
+            return Err(SshError::BadSignature);
+        }
+        let sig = r.read_string()?;
+        let ok = match (self, algorithm) {
+            (PublicKey::Ed25519(key), SSH_ED25519) => {
+                let sig = ed25519_dalek::Signature::from_slice(sig)
+                    .map_err(|_| SshError::BadSignature)?;
+                key.verify_strict(data, &sig).is_ok()
+            }
+            (PublicKey::EcdsaP256(key), ECDSA_SHA2_NISTP256) => {
+                let mut r = SshReader::new(sig);
+                let (r, s) = (r.read_mpint()?, r.read_mpint()?);
+                if r.len() > 32 || s.len() > 32 {
+                    return Err(SshError::BadSignature);
+                }
+                let mut rb = [0; 32];
+                let mut sb = [0; 32];
+                rb[32 - r.len()..].copy_from_slice(r);
+                sb[32 - s.len()..].copy_from_slice(s);
+                let sig = p256::ecdsa::Signature::from_scalars(rb, sb)
+                    .map_err(|_| SshError::BadSignature)?;
+                key.verify(data, &sig).is_ok()
+            }
+            (PublicKey::Rsa(key), RSA_SHA2_256) => key
+                .verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(data), sig)
+                .is_ok(),
+            (PublicKey::Rsa(key), RSA_SHA2_512) => key
+                .verify(Pkcs1v15Sign::new::<Sha512>(), &Sha512::digest(data), sig)
+                .is_ok(),
+            _ => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(SshError::BadSignature)
+        }
+    }
+}
+
+/// The SHA256 fingerprint of a public key blob
+pub fn fingerprint(blob: &[u8]) -> String {
+    format!(
+        "SHA256:{}",
+        Base64Unpadded::encode_string(&Sha256::digest(blob))
+    )
+}
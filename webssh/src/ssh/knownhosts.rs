@@ -0,0 +1,426 @@
+// The known_hosts file of OpenSSH
+//     https://man.openbsd.org/sshd.8#SSH_KNOWN_HOSTS_FILE_FORMAT
+//
+// Lines which are not understood are kept as they are, so that an imported
+// file is exported again without loss
+
+use super::key::{fingerprint, PublicKey};
+use base64ct::{Base64, Encoding};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::fmt;
+
+const HASH_MAGIC: &str = "|1|";
+const MARKER_REVOKED: &str = "@revoked";
+const DEFAULT_PORT: u16 = 22;
+
+/// The result of looking up a host key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostKeyStatus {
+    /// The key is known for the host
+    Trusted,
+    /// No key is known for the host
+    Unknown,
+    /// Another key is known for the host, of the same type if there is one
+    Changed {
+        key_type: String,
+        fingerprint: String,
+    },
+    /// The key is marked as revoked
+    Revoked,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    marker: Option<String>,
+    hosts: String,
+    key_type: String,
+    blob: Vec<u8>,
+    comment: String,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let mut first = fields.next()?;
+        let marker = if first.starts_with('@') {
+            let marker = first.to_owned();
+            first = fields.next()?;
+            Some(marker)
+        } else {
+            None
+        };
+        let key_type = fields.next()?.to_owned();
+        let blob = Base64::decode_vec(fields.next()?).ok()?;
+        Some(Self {
+            marker,
+            hosts: first.to_owned(),
+            key_type,
+            blob,
+            comment: fields.collect::<Vec<_>>().join(" "),
+        })
+    }
+
+    // Negated patterns win over the others
+    fn matches(&self, host: &str) -> bool {
+        let mut matched = false;
+        for pattern in self.hosts.split(',') {
+            if let Some(hashed) = pattern.strip_prefix(HASH_MAGIC) {
+                matched |= hash_matches(hashed, host);
+            } else if let Some(negated) = pattern.strip_prefix('!') {
+                if wildcard_matches(negated, host) {
+                    return false;
+                }
+            } else {
+                matched |= wildcard_matches(pattern, host);
+            }
+        }
+        matched
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(marker) = &self.marker {
+            write!(f, "{} ", marker)?;
+        }
+        write!(
+            f,
+            "{} {} {}",
+            self.hosts,
+            self.key_type,
+            Base64::encode_string(&self.blob)
+        )?;
+        if !self.comment.is_empty() {
+            write!(f, " {}", self.comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Line {
+    Entry(Entry),
+    Other(String),
+}
+
+/// The known host keys, in the OpenSSH known_hosts format
+#[derive(Debug, Clone, Default)]
+pub struct KnownHosts {
+    lines: Vec<Line>,
+}
+
+impl KnownHosts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the content of a known_hosts file
+    pub fn parse(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    return Line::Other(line.to_owned());
+                }
+                match Entry::parse(trimmed) {
+                    Some(entry) => Line::Entry(entry),
+                    None => Line::Other(line.to_owned()),
+                }
+            })
+            .collect();
+        Self { lines }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(entry) => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// The number of host keys
+    pub fn len(&self) -> usize {
+        self.entries().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add the entries of `other` which are not known yet, returns how many
+    pub fn merge(&mut self, other: KnownHosts) -> usize {
+        let mut added = 0;
+        for line in other.lines {
+            if let Line::Entry(entry) = line {
+                let known = self.entries().any(|e| {
+                    e.hosts == entry.hosts && e.blob == entry.blob && e.marker == entry.marker
+                });
+                if !known {
+                    self.lines.push(Line::Entry(entry));
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    /// The types of the keys trusted for `host`, in the order of the file
+    pub fn key_types(&self, host: &str) -> Vec<String> {
+        let mut key_types: Vec<String> = Vec::new();
+        for entry in self
+            .entries()
+            .filter(|e| e.marker.is_none() && e.matches(host))
+        {
+            if !key_types.contains(&entry.key_type) {
+                key_types.push(entry.key_type.clone());
+            }
+        }
+        key_types
+    }
+
+    /// Look up the key of `host`, which is in the form of [host_name]
+    ///
+    /// A key is only unknown if no key at all is trusted for the host, so that
+    /// a server cannot pass off a key of another type as a new host
+    pub fn check(&self, host: &str, key: &PublicKey) -> HostKeyStatus {
+        let blob = key.to_blob();
+        let mut trusted = false;
+        let mut changed: Option<&Entry> = None;
+        for entry in self.entries().filter(|e| e.matches(host)) {
+            match entry.marker.as_deref() {
+                Some(MARKER_REVOKED) if entry.blob == blob => return HostKeyStatus::Revoked,
+                Some(_) => (),
+                None if entry.blob == blob => trusted = true,
+                None => {
+                    if changed.is_none_or(|c| {
+                        c.key_type != key.key_type() && entry.key_type == key.key_type()
+                    }) {
+                        changed = Some(entry);
+                    }
+                }
+            }
+        }
+        match changed {
+            _ if trusted => HostKeyStatus::Trusted,
+            Some(entry) => HostKeyStatus::Changed {
+                key_type: entry.key_type.clone(),
+                fingerprint: fingerprint(&entry.blob),
+            },
+            None => HostKeyStatus::Unknown,
+        }
+    }
+
+    /// Remove the trusted keys of `host`, returns how many
+    ///
+    /// Entries of patterns which also match other hosts are kept
+    pub fn remove(&mut self, host: &str) -> usize {
+        let before = self.lines.len();
+        self.lines.retain(|line| match line {
+            Line::Entry(entry) => {
+                let only_host = entry.hosts == host
+                    || (entry.hosts.starts_with(HASH_MAGIC)
+                        && !entry.hosts.contains(',')
+                        && entry.matches(host));
+                entry.marker.is_some() || !only_host
+            }
+            Line::Other(_) => true,
+        });
+        before - self.lines.len()
+    }
+
+    /// Trust `key` for `host`
+    pub fn add(&mut self, host: &str, key: &PublicKey) {
+        self.lines.push(Line::Entry(Entry {
+            marker: None,
+            hosts: host.to_owned(),
+            key_type: key.key_type().to_owned(),
+            blob: key.to_blob(),
+            comment: String::new(),
+        }));
+    }
+}
+
+impl fmt::Display for KnownHosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Entry(entry) => writeln!(f, "{}", entry)?,
+                Line::Other(text) => writeln!(f, "{}", text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The host and the port of `host:port`, the port is 22 if not given
+pub fn host_port(target: &str) -> (&str, u16) {
+    let (host, port) = match target.rsplit_once(':') {
+        // a bare IPv6 address has more than one colon
+        Some((host, port)) if !host.contains(':') || host.starts_with('[') => {
+            (host.trim_start_matches('[').trim_end_matches(']'), port)
+        }
+        _ => (target, ""),
+    };
+    (host, port.parse().unwrap_or(DEFAULT_PORT))
+}
+
+/// The name of `host:port` in known_hosts, `[host]:port` unless the port is 22
+pub fn host_name(target: &str) -> String {
+    match host_port(target) {
+        (host, port) if port != DEFAULT_PORT => format!("[{}]:{}", host, port),
+        (host, _) => host.to_owned(),
+    }
+}
+
+// |1|base64(salt)|base64(hmac-sha1(salt, host))
+fn hash_matches(hashed: &str, host: &str) -> bool {
+    let Some((salt, hash)) = hashed.split_once('|') else {
+        return false;
+    };
+    let (Ok(salt), Ok(hash)) = (Base64::decode_vec(salt), Base64::decode_vec(hash)) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha1>::new_from_slice(&salt) else {
+        return false;
+    };
+    mac.update(host.as_bytes());
+    mac.verify_slice(&hash).is_ok()
+}
+
+// `*` matches any characters and `?` exactly one, case insensitive
+fn wildcard_matches(pattern: &str, host: &str) -> bool {
+    fn matches(p: &[u8], h: &[u8]) -> bool {
+        match (p.first(), h.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => matches(&p[1..], h) || (!h.is_empty() && matches(p, &h[1..])),
+            (Some(b'?'), Some(_)) => matches(&p[1..], &h[1..]),
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => matches(&p[1..], &h[1..]),
+            _ => false,
+        }
+    }
+    matches(pattern.as_bytes(), host.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::key::{ECDSA_SHA2_NISTP256, SSH_ED25519};
+    use super::*;
+
+    const ED25519: &str =
+        "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJMbjzZEmWfced5xYR3yxB663TOyJbdxdWV+RG0oV/b1";
+    const ECDSA: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBKO1NCGTPRlW+qQ2pKj5UXZDHz5RPG/Awwn+qYIkqiPuQmuj8fUXeShdX01j83II8+iS5YhxunWUEM7qw68ctOo=";
+
+    fn key(line: &str) -> PublicKey {
+        let blob = Base64::decode_vec(line.split(' ').nth(1).unwrap()).unwrap();
+        PublicKey::from_blob(&blob).unwrap()
+    }
+
+    #[test]
+    fn test_host_name() {
+        assert_eq!(host_name("example.com"), "example.com");
+        assert_eq!(host_name("example.com:22"), "example.com");
+        assert_eq!(host_name("10.0.0.1:2222"), "[10.0.0.1]:2222");
+        assert_eq!(host_name("[::1]:2222"), "[::1]:2222");
+        assert_eq!(host_name("::1"), "::1");
+        assert_eq!(host_port("[::1]:2222"), ("::1", 2222));
+        assert_eq!(host_port("example.com"), ("example.com", 22));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        // ssh-keygen -l
+        assert_eq!(
+            key(ED25519).fingerprint(),
+            "SHA256:ez341mkDfzlTgVhRbqDEjB32b+AOFUYQ5ouBLauatXg"
+        );
+        assert_eq!(key(ED25519).to_openssh(), ED25519);
+    }
+
+    #[test]
+    fn test_check() {
+        let text = format!(
+            "# comment\n\
+             |1|EPpM3P3BHVvxKO9m/F1sTpiDdjA=|xBoHODKIFpFiKWqCXUztJAPoXWs= {ED25519} ed@test\n\
+             *.example.org,!bad.example.org {ECDSA}\n\
+             @revoked [revoked.example.org]:2222 {ED25519}\n\
+             garbage\n"
+        );
+        let mut known = KnownHosts::parse(&text);
+        assert_eq!(known.len(), 3);
+        assert_eq!(known.to_string(), text);
+
+        let ed = key(ED25519);
+        let ec = key(ECDSA);
+        assert_eq!(known.check("example.com", &ed), HostKeyStatus::Trusted);
+        assert_eq!(known.check("EXAMPLE.COM", &ed), HostKeyStatus::Unknown);
+        assert_eq!(
+            known.check("example.com", &ec),
+            HostKeyStatus::Changed {
+                key_type: SSH_ED25519.to_owned(),
+                fingerprint: ed.fingerprint()
+            }
+        );
+        assert_eq!(known.key_types("example.com"), [SSH_ED25519]);
+        assert!(known.key_types("[revoked.example.org]:2222").is_empty());
+        assert_eq!(known.check("a.example.org", &ec), HostKeyStatus::Trusted);
+        assert_eq!(known.check("bad.example.org", &ec), HostKeyStatus::Unknown);
+        assert_eq!(
+            known.check("[revoked.example.org]:2222", &ed),
+            HostKeyStatus::Revoked
+        );
+        assert_eq!(known.remove("a.example.org"), 0);
+        assert_eq!(known.remove("[revoked.example.org]:2222"), 0);
+        assert_eq!(known.remove("example.com"), 1);
+        assert_eq!(known.check("example.com", &ed), HostKeyStatus::Unknown);
+
+        let mut known = KnownHosts::new();
+        known.add("[host]:2222", &ec);
+        assert_eq!(known.check("[host]:2222", &ec), HostKeyStatus::Trusted);
+        assert_eq!(known.check("host", &ec), HostKeyStatus::Unknown);
+        let mut other = KnownHosts::new();
+        other.add("[host]:2222", &ec);
+        other.add("[host]:2222", &ed);
+        assert_eq!(known.merge(other), 1);
+        assert_eq!(known.len(), 2);
+        assert_eq!(known.remove("[host]:2222"), 2);
+        assert!(known.is_empty());
+    }
+
+    #[test]
+    fn test_changed() {
+        let mut known = KnownHosts::new();
+        known.add("host", &key(ECDSA));
+        let other = p256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+        let other = PublicKey::EcdsaP256(*other.verifying_key());
+        assert_eq!(
+            known.check("host", &other),
+            HostKeyStatus::Changed {
+                key_type: ECDSA_SHA2_NISTP256.to_owned(),
+                fingerprint: key(ECDSA).fingerprint()
+            }
+        );
+
+        // the key of the same type is the one reported
+        known.add("host", &key(ED25519));
+        assert_eq!(known.key_types("host"), [ECDSA_SHA2_NISTP256, SSH_ED25519]);
+        assert_eq!(
+            known.check("host", &other),
+            HostKeyStatus::Changed {
+                key_type: ECDSA_SHA2_NISTP256.to_owned(),
+                fingerprint: key(ECDSA).fingerprint()
+            }
+        );
+        known.remove("host");
+        known.add("host", &key(ED25519));
+        known.add("host", &key(ECDSA));
+        assert_eq!(
+            known.check("host", &other),
+            HostKeyStatus::Changed {
+                key_type: ECDSA_SHA2_NISTP256.to_owned(),
+                fingerprint: key(ECDSA).fingerprint()
+            }
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! An async implementation of the ssh client side protocol
+//!
+//! The protocol is not bound to the browser, so that it can be tested with
+//! a tcp stream against a local OpenSSH server
+
+mod auth;
+mod cipher;
+mod compress;
+mod connection;
+mod connector;
+mod error;
+mod kex;
+pub mod key;
+pub mod knownhosts;
+pub mod msg;
+pub mod private_key;
+pub mod sftp;
+mod transport;
+mod tunnel;
+pub mod wire;
+
+pub use auth::{AuthHandler, Prompt, PromptRequest};
+pub use connection::{ChannelEvent, Connection};
+pub use connector::SshConnector;
+pub use error::*;
+pub use kex::Algorithms;
+pub use transport::Transport;
+pub use tunnel::tunnel;
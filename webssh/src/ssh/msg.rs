@@ -0,0 +1,61 @@
+// Message numbers
+//     https://www.rfc-editor.org/rfc/rfc4250#section-4.1
+
+// Transport layer generic
+pub const SSH_MSG_DISCONNECT: u8 = 1;
+pub const SSH_MSG_IGNORE: u8 = 2;
+pub const SSH_MSG_UNIMPLEMENTED: u8 = 3;
+pub const SSH_MSG_DEBUG: u8 = 4;
+pub const SSH_MSG_SERVICE_REQUEST: u8 = 5;
+pub const SSH_MSG_SERVICE_ACCEPT: u8 = 6;
+pub const SSH_MSG_EXT_INFO: u8 = 7;
+
+// Algorithm negotiation
+pub const SSH_MSG_KEXINIT: u8 = 20;
+pub const SSH_MSG_NEWKEYS: u8 = 21;
+
+// Key exchange method specific
+pub const SSH_MSG_KEX_ECDH_INIT: u8 = 30;
+pub const SSH_MSG_KEX_ECDH_REPLY: u8 = 31;
+
+// User authentication generic
+pub const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
+pub const SSH_MSG_USERAUTH_FAILURE: u8 = 51;
+pub const SSH_MSG_USERAUTH_SUCCESS: u8 = 52;
+pub const SSH_MSG_USERAUTH_BANNER: u8 = 53;
+
+// User authentication method specific, the same numbers are reused
+pub const SSH_MSG_USERAUTH_PASSWD_CHANGEREQ: u8 = 60;
+pub const SSH_MSG_USERAUTH_INFO_REQUEST: u8 = 60;
+pub const SSH_MSG_USERAUTH_INFO_RESPONSE: u8 = 61;
+
+// Connection protocol generic
+pub const SSH_MSG_GLOBAL_REQUEST: u8 = 80;
+pub const SSH_MSG_REQUEST_SUCCESS: u8 = 81;
+pub const SSH_MSG_REQUEST_FAILURE: u8 = 82;
+
+// Channel related messages
+pub const SSH_MSG_CHANNEL_OPEN: u8 = 90;
+pub const SSH_MSG_CHANNEL_OPEN_CONFIRMATION: u8 = 91;
+pub const SSH_MSG_CHANNEL_OPEN_FAILURE: u8 = 92;
+pub const SSH_MSG_CHANNEL_WINDOW_ADJUST: u8 = 93;
+pub const SSH_MSG_CHANNEL_DATA: u8 = 94;
+pub const SSH_MSG_CHANNEL_EXTENDED_DATA: u8 = 95;
+pub const SSH_MSG_CHANNEL_EOF: u8 = 96;
+pub const SSH_MSG_CHANNEL_CLOSE: u8 = 97;
+pub const SSH_MSG_CHANNEL_REQUEST: u8 = 98;
+pub const SSH_MSG_CHANNEL_SUCCESS: u8 = 99;
+pub const SSH_MSG_CHANNEL_FAILURE: u8 = 100;
+
+// Disconnection reason codes
+//     https://www.rfc-editor.org/rfc/rfc4250#section-4.2.2
+pub const SSH_DISCONNECT_PROTOCOL_ERROR: u32 = 2;
+pub const SSH_DISCONNECT_KEY_EXCHANGE_FAILED: u32 = 3;
+pub const SSH_DISCONNECT_MAC_ERROR: u32 = 5;
+pub const SSH_DISCONNECT_HOST_KEY_NOT_VERIFIABLE: u32 = 9;
+pub const SSH_DISCONNECT_BY_APPLICATION: u32 = 11;
+pub const SSH_DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE: u32 = 14;
+
+// Channel open failure reason codes
+//     https://www.rfc-editor.org/rfc/rfc4250#section-4.3
+pub const SSH_OPEN_ADMINISTRATIVELY_PROHIBITED: u32 = 1;
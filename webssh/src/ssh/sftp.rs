@@ -0,0 +1,591 @@
+// The SSH file transfer protocol, version 3
+//     https://datatracker.ietf.org/doc/html/draft-ietf-secsh-filexfer-02
+//
+// The client runs over the data of a session channel which requested the
+// `sftp` subsystem. It only encodes the requests and decodes the replies,
+// the caller moves the bytes between it and the channel
+
+use super::{
+    wire::{SshReader, SshWriter},
+    SshError, SshResult,
+};
+use std::collections::VecDeque;
+
+pub const SUBSYSTEM: &str = "sftp";
+pub const VERSION: u32 = 3;
+
+pub const SSH_FXP_INIT: u8 = 1;
+pub const SSH_FXP_VERSION: u8 = 2;
+pub const SSH_FXP_OPEN: u8 = 3;
+pub const SSH_FXP_CLOSE: u8 = 4;
+pub const SSH_FXP_READ: u8 = 5;
+pub const SSH_FXP_WRITE: u8 = 6;
+pub const SSH_FXP_OPENDIR: u8 = 11;
+pub const SSH_FXP_READDIR: u8 = 12;
+pub const SSH_FXP_REMOVE: u8 = 13;
+pub const SSH_FXP_MKDIR: u8 = 14;
+pub const SSH_FXP_RMDIR: u8 = 15;
+pub const SSH_FXP_REALPATH: u8 = 16;
+pub const SSH_FXP_STAT: u8 = 17;
+pub const SSH_FXP_RENAME: u8 = 18;
+pub const SSH_FXP_STATUS: u8 = 101;
+pub const SSH_FXP_HANDLE: u8 = 102;
+pub const SSH_FXP_DATA: u8 = 103;
+pub const SSH_FXP_NAME: u8 = 104;
+pub const SSH_FXP_ATTRS: u8 = 105;
+
+pub const SSH_FX_OK: u32 = 0;
+pub const SSH_FX_EOF: u32 = 1;
+pub const SSH_FX_NO_SUCH_FILE: u32 = 2;
+pub const SSH_FX_PERMISSION_DENIED: u32 = 3;
+pub const SSH_FX_FAILURE: u32 = 4;
+pub const SSH_FX_BAD_MESSAGE: u32 = 5;
+pub const SSH_FX_OP_UNSUPPORTED: u32 = 8;
+
+pub const SSH_FILEXFER_ATTR_SIZE: u32 = 0x1;
+pub const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x2;
+pub const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x4;
+pub const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x8;
+pub const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+pub const SSH_FXF_READ: u32 = 0x1;
+pub const SSH_FXF_WRITE: u32 = 0x2;
+pub const SSH_FXF_APPEND: u32 = 0x4;
+pub const SSH_FXF_CREAT: u32 = 0x8;
+pub const SSH_FXF_TRUNC: u32 = 0x10;
+pub const SSH_FXF_EXCL: u32 = 0x20;
+
+/// The largest packet accepted from the server, a bit more than the 256k of
+/// data a read may return
+const MAX_PACKET: usize = 256 * 1024 + 1024;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+/// The attributes of a file, each one is optional
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttrs {
+    pub size: Option<u64>,
+    pub uid_gid: Option<(u32, u32)>,
+    pub permissions: Option<u32>,
+    /// (atime, mtime) in seconds since the epoch
+    pub times: Option<(u32, u32)>,
+}
+
+impl FileAttrs {
+    pub fn is_dir(&self) -> bool {
+        self.permissions.is_some_and(|p| p & S_IFMT == S_IFDIR)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.permissions.is_some_and(|p| p & S_IFMT == S_IFLNK)
+    }
+
+    fn read(r: &mut SshReader) -> SshResult<Self> {
+        let flags = r.read_u32()?;
+        let mut attrs = Self::default();
+        if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+            attrs.size = Some(r.read_u64()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+            attrs.uid_gid = Some((r.read_u32()?, r.read_u32()?));
+        }
+        if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(r.read_u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            attrs.times = Some((r.read_u32()?, r.read_u32()?));
+        }
+        if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            for _ in 0..r.read_u32()? {
+                r.read_string()?;
+                r.read_string()?;
+            }
+        }
+        Ok(attrs)
+    }
+
+    fn write(&self, w: &mut SshWriter) {
+        let mut flags = 0;
+        if self.size.is_some() {
+            flags |= SSH_FILEXFER_ATTR_SIZE;
+        }
+        if self.uid_gid.is_some() {
+            flags |= SSH_FILEXFER_ATTR_UIDGID;
+        }
+        if self.permissions.is_some() {
+            flags |= SSH_FILEXFER_ATTR_PERMISSIONS;
+        }
+        if self.times.is_some() {
+            flags |= SSH_FILEXFER_ATTR_ACMODTIME;
+        }
+        w.write_u32(flags);
+        if let Some(size) = self.size {
+            w.write_u64(size);
+        }
+        if let Some((uid, gid)) = self.uid_gid {
+            w.write_u32(uid);
+            w.write_u32(gid);
+        }
+        if let Some(permissions) = self.permissions {
+            w.write_u32(permissions);
+        }
+        if let Some((atime, mtime)) = self.times {
+            w.write_u32(atime);
+            w.write_u32(mtime);
+        }
+    }
+}
+
+/// An entry of a directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub filename: String,
+    /// The `ls -l` like line of the server
+    pub longname: String,
+    pub attrs: FileAttrs,
+}
+
+/// A reply of the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// The version the server speaks, the answer to the init
+    Version(u32),
+    Status {
+        id: u32,
+        code: u32,
+        message: String,
+    },
+    Handle {
+        id: u32,
+        handle: Vec<u8>,
+    },
+    Data {
+        id: u32,
+        data: Vec<u8>,
+    },
+    Name {
+        id: u32,
+        entries: Vec<DirEntry>,
+    },
+    Attrs {
+        id: u32,
+        attrs: FileAttrs,
+    },
+}
+
+impl Reply {
+    /// The id of the request answered
+    pub fn id(&self) -> Option<u32> {
+        match self {
+            Reply::Version(_) => None,
+            Reply::Status { id, .. }
+            | Reply::Handle { id, .. }
+            | Reply::Data { id, .. }
+            | Reply::Name { id, .. }
+            | Reply::Attrs { id, .. } => Some(*id),
+        }
+    }
+
+    fn decode(packet: &[u8]) -> SshResult<Self> {
+        let mut r = SshReader::new(&packet[1..]);
+        if packet[0] == SSH_FXP_VERSION {
+            // the extensions are not used
+            return Ok(Reply::Version(r.read_u32()?));
+        }
+        let id = r.read_u32()?;
+        let reply = match packet[0] {
+            SSH_FXP_STATUS => {
+                let code = r.read_u32()?;
+                // some old servers leave out the message
+                let message = if r.remain() > 0 {
+                    r.read_utf8()?
+                } else {
+                    String::new()
+                };
+                Reply::Status { id, code, message }
+            }
+            SSH_FXP_HANDLE => Reply::Handle {
+                id,
+                handle: r.read_string()?.to_vec(),
+            },
+            SSH_FXP_DATA => Reply::Data {
+                id,
+                data: r.read_string()?.to_vec(),
+            },
+            SSH_FXP_NAME => {
+                let count = r.read_u32()?;
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    let filename = String::from_utf8_lossy(r.read_string()?).into_owned();
+                    let longname = String::from_utf8_lossy(r.read_string()?).into_owned();
+                    let attrs = FileAttrs::read(&mut r)?;
+                    entries.push(DirEntry {
+                        filename,
+                        longname,
+                        attrs,
+                    });
+                }
+                Reply::Name { id, entries }
+            }
+            SSH_FXP_ATTRS => Reply::Attrs {
+                id,
+                attrs: FileAttrs::read(&mut r)?,
+            },
+            other => return Err(SshError::UnexpectedMessage(other)),
+        };
+        Ok(reply)
+    }
+}
+
+/// A readable message of a status code
+pub fn status_message(code: u32) -> &'static str {
+    match code {
+        SSH_FX_OK => "Success",
+        SSH_FX_EOF => "End of file",
+        SSH_FX_NO_SUCH_FILE => "No such file",
+        SSH_FX_PERMISSION_DENIED => "Permission denied",
+        SSH_FX_BAD_MESSAGE => "Bad message",
+        SSH_FX_OP_UNSUPPORTED => "Operation unsupported",
+        _ => "Failure",
+    }
+}
+
+/// The sftp client side
+///
+/// The requests are queued to be sent with [Sftp::take_output] and return
+/// their id, which is found again in the [Reply]
+#[derive(Default)]
+pub struct Sftp {
+    next_id: u32,
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl Sftp {
+    /// Start the session, the server answers with [Reply::Version]
+    pub fn new() -> Self {
+        let mut sftp = Self::default();
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_FXP_INIT);
+        w.write_u32(VERSION);
+        sftp.push(w);
+        sftp
+    }
+
+    fn push(&mut self, packet: SshWriter) {
+        self.output
+            .extend_from_slice(&(packet.len() as u32).to_be_bytes());
+        self.output.extend_from_slice(packet.get_inner());
+    }
+
+    // A request of `kind` with its id, the arguments are up to the caller
+    fn request(&mut self, kind: u8, args: impl FnOnce(&mut SshWriter)) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let mut w = SshWriter::new();
+        w.write_u8(kind);
+        w.write_u32(id);
+        args(&mut w);
+        self.push(w);
+        id
+    }
+
+    /// The bytes to be sent to the channel
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Add the bytes received from the channel
+    pub fn feed(&mut self, data: &[u8]) {
+        self.input.extend(data);
+    }
+
+    /// The next complete reply received, if any
+    pub fn next_reply(&mut self) -> SshResult<Option<Reply>> {
+        if self.input.len() < 4 {
+            return Ok(None);
+        }
+        let len = self
+            .input
+            .iter()
+            .take(4)
+            .fold(0, |n, b| n << 8 | *b as usize);
+        if len == 0 || len > MAX_PACKET {
+            return Err(SshError::Malformed);
+        }
+        if self.input.len() < 4 + len {
+            return Ok(None);
+        }
+        let packet: Vec<u8> = self.input.drain(..4 + len).skip(4).collect();
+        Reply::decode(&packet).map(Some)
+    }
+
+    pub fn open(&mut self, path: &str, flags: u32, attrs: &FileAttrs) -> u32 {
+        self.request(SSH_FXP_OPEN, |w| {
+            w.write_str(path);
+            w.write_u32(flags);
+            attrs.write(w);
+        })
+    }
+
+    pub fn close(&mut self, handle: &[u8]) -> u32 {
+        self.request(SSH_FXP_CLOSE, |w| w.write_string(handle))
+    }
+
+    /// Read up to `len` bytes at `offset`, the end of file is a status
+    pub fn read(&mut self, handle: &[u8], offset: u64, len: u32) -> u32 {
+        self.request(SSH_FXP_READ, |w| {
+            w.write_string(handle);
+            w.write_u64(offset);
+            w.write_u32(len);
+        })
+    }
+
+    pub fn write(&mut self, handle: &[u8], offset: u64, data: &[u8]) -> u32 {
+        self.request(SSH_FXP_WRITE, |w| {
+            w.write_string(handle);
+            w.write_u64(offset);
+            w.write_string(data);
+        })
+    }
+
+    pub fn opendir(&mut self, path: &str) -> u32 {
+        self.request(SSH_FXP_OPENDIR, |w| w.write_str(path))
+    }
+
+    /// The next entries of the directory, the end is a status
+    pub fn readdir(&mut self, handle: &[u8]) -> u32 {
+        self.request(SSH_FXP_READDIR, |w| w.write_string(handle))
+    }
+
+    pub fn remove(&mut self, path: &str) -> u32 {
+        self.request(SSH_FXP_REMOVE, |w| w.write_str(path))
+    }
+
+    pub fn mkdir(&mut self, path: &str, attrs: &FileAttrs) -> u32 {
+        self.request(SSH_FXP_MKDIR, |w| {
+            w.write_str(path);
+            attrs.write(w);
+        })
+    }
+
+    pub fn rmdir(&mut self, path: &str) -> u32 {
+        self.request(SSH_FXP_RMDIR, |w| w.write_str(path))
+    }
+
+    /// The absolute path of `path`, answered by a name with one entry
+    pub fn realpath(&mut self, path: &str) -> u32 {
+        self.request(SSH_FXP_REALPATH, |w| w.write_str(path))
+    }
+
+    /// The attributes of `path`, following symbolic links
+    pub fn stat(&mut self, path: &str) -> u32 {
+        self.request(SSH_FXP_STAT, |w| w.write_str(path))
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> u32 {
+        self.request(SSH_FXP_RENAME, |w| {
+            w.write_str(from);
+            w.write_str(to);
+        })
+    }
+}
+
+/// `name` in the directory `dir`
+pub fn join(dir: &str, name: &str) -> String {
+    if name.starts_with('/') {
+        name.to_owned()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// The parent directory of the absolute `path`
+pub fn parent(path: &str) -> String {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_owned(),
+        Some((dir, _)) => dir.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A packet of the server
+    fn packet(kind: u8, body: impl FnOnce(&mut SshWriter)) -> Vec<u8> {
+        let mut w = SshWriter::new();
+        w.write_u8(kind);
+        body(&mut w);
+        let mut out = (w.len() as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(w.get_inner());
+        out
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut sftp = Sftp::new();
+        assert_eq!(sftp.take_output(), [0, 0, 0, 5, SSH_FXP_INIT, 0, 0, 0, 3]);
+        assert_eq!(sftp.opendir("/tmp"), 0);
+        assert_eq!(
+            sftp.take_output(),
+            [
+                0,
+                0,
+                0,
+                13,
+                SSH_FXP_OPENDIR,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                4,
+                b'/',
+                b't',
+                b'm',
+                b'p'
+            ]
+        );
+        let attrs = FileAttrs {
+            permissions: Some(0o755),
+            ..Default::default()
+        };
+        assert_eq!(sftp.open("a", SSH_FXF_READ, &attrs), 1);
+        assert_eq!(
+            sftp.take_output(),
+            [
+                0,
+                0,
+                0,
+                22,
+                SSH_FXP_OPEN,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                1,
+                b'a',
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                4,
+                0,
+                0,
+                1,
+                0o355
+            ]
+        );
+        assert_eq!(sftp.read(b"h", 1 << 32, 5), 2);
+        assert_eq!(
+            sftp.take_output(),
+            [
+                0,
+                0,
+                0,
+                22,
+                SSH_FXP_READ,
+                0,
+                0,
+                0,
+                2,
+                0,
+                0,
+                0,
+                1,
+                b'h',
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                5
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replies() {
+        let mut sftp = Sftp::new();
+        let mut input = packet(SSH_FXP_VERSION, |w| {
+            w.write_u32(3);
+            w.write_str("posix-rename@openssh.com");
+            w.write_str("1");
+        });
+        input.extend(packet(SSH_FXP_NAME, |w| {
+            w.write_u32(7);
+            w.write_u32(2);
+            w.write_str("dir");
+            w.write_str("drwxr-xr-x 2 root root 40 Jan 1 00:00 dir");
+            w.write_u32(SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_PERMISSIONS);
+            w.write_u64(40);
+            w.write_u32(0o40755);
+            w.write_str("file");
+            w.write_str("");
+            w.write_u32(SSH_FILEXFER_ATTR_EXTENDED);
+            w.write_u32(1);
+            w.write_str("name");
+            w.write_str("value");
+        }));
+        input.extend(packet(SSH_FXP_STATUS, |w| {
+            w.write_u32(8);
+            w.write_u32(SSH_FX_EOF);
+        }));
+
+        // the replies may arrive in pieces
+        let (first, rest) = input.split_at(50);
+        sftp.feed(first);
+        assert_eq!(sftp.next_reply().unwrap(), Some(Reply::Version(3)));
+        assert_eq!(sftp.next_reply().unwrap(), None);
+        sftp.feed(rest);
+        let Some(Reply::Name { id: 7, entries }) = sftp.next_reply().unwrap() else {
+            panic!()
+        };
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].attrs.is_dir());
+        assert_eq!(entries[0].attrs.size, Some(40));
+        assert_eq!(entries[1].filename, "file");
+        assert_eq!(entries[1].attrs, FileAttrs::default());
+        assert_eq!(
+            sftp.next_reply().unwrap(),
+            Some(Reply::Status {
+                id: 8,
+                code: SSH_FX_EOF,
+                message: String::new()
+            })
+        );
+        assert_eq!(sftp.next_reply().unwrap(), None);
+
+        sftp.feed(&packet(SSH_FXP_INIT, |w| w.write_u32(0)));
+        assert!(sftp.next_reply().is_err());
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(join("/home/user", "a"), "/home/user/a");
+        assert_eq!(join("/", "a"), "/a");
+        assert_eq!(join("/home", "/tmp"), "/tmp");
+        assert_eq!(parent("/home/user"), "/home");
+        assert_eq!(parent("/home/"), "/");
+        assert_eq!(parent("/"), "/");
+    }
+}
@@ -0,0 +1,677 @@
+// The transport layer protocol
+//     https://www.rfc-editor.org/rfc/rfc4253
+
+use super::{
+    cipher::{self, CipherState},
+    compress::{self, Compressor, Decompressor},
+    kex::{self, Algorithms, Curve25519, ExchangeHash, KexInit},
+    key::{self, PublicKey},
+    msg::*,
+    wire::{SshReader, SshWriter},
+    SshError, SshResult,
+};
+use fluvio_wasm_timer::Instant;
+use std::{collections::VecDeque, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, trace};
+
+pub const CLIENT_ID: &str = "SSH-2.0-webssh_0.1";
+
+// All implementations MUST be able to process packets with an uncompressed
+// payload length of 32768 bytes or less and a total packet size of 35000
+// bytes or less
+const MAX_PACKET_LEN: usize = 256 * 1024;
+const MAX_VERSION_LINE: usize = 255;
+const MAX_BANNER_LINES: usize = 1024;
+const READ_CHUNK: usize = 16 * 1024;
+
+// It is RECOMMENDED that the keys be changed after each gigabyte of
+// transmitted data or after each hour of connection time
+pub const REKEY_BYTES: u64 = 1 << 30;
+pub const REKEY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Options of the transport, set through [super::SshConnector]
+#[derive(Debug, Clone)]
+pub struct TransportConfig {
+    pub client_id: String,
+    pub kex: Vec<&'static str>,
+    pub host_key: Vec<&'static str>,
+    pub compression: Vec<&'static str>,
+    /// The keys are exchanged again after this many bytes in either direction
+    pub rekey_bytes: u64,
+    /// or after this time
+    pub rekey_interval: Duration,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            client_id: CLIENT_ID.to_owned(),
+            kex: kex::KEX_ALGORITHMS.to_vec(),
+            host_key: key::HOST_KEY_ALGORITHMS.to_vec(),
+            compression: vec![kex::COMPRESSION_NONE],
+            rekey_bytes: REKEY_BYTES,
+            rekey_interval: REKEY_INTERVAL,
+        }
+    }
+}
+
+// A key exchange in progress, it moves on as the packets of the server come
+enum Kex {
+    Idle,
+    // our KEXINIT is sent
+    InitSent {
+        init: KexInit,
+        client_kexinit: Vec<u8>,
+    },
+    // our ECDH public key is sent
+    EcdhSent {
+        client_kexinit: Vec<u8>,
+        server_kexinit: Vec<u8>,
+        algorithms: Algorithms,
+        ecdh: Curve25519,
+    },
+    // our NEWKEYS is sent, the keys of the server change with its NEWKEYS
+    NewKeysSent {
+        incoming: CipherState,
+        algorithms: Algorithms,
+    },
+}
+
+// Once a party has sent a SSH_MSG_KEXINIT message for key exchange or
+// re-exchange, until it has sent a SSH_MSG_NEWKEYS message, it MUST NOT send
+// any messages other than transport layer generic messages (1 to 19) (but
+// SSH_MSG_SERVICE_REQUEST and SSH_MSG_SERVICE_ACCEPT MUST NOT be sent),
+// algorithm negotiation messages (20 to 29) and key exchange method messages
+// (30 to 49)
+fn allowed_in_kex(msg: u8) -> bool {
+    msg < SSH_MSG_SERVICE_REQUEST || (SSH_MSG_KEXINIT..50).contains(&msg)
+}
+
+struct Direction {
+    state: CipherState,
+    seq: u32,
+}
+
+impl Direction {
+    fn new() -> Self {
+        Self {
+            state: CipherState::none(),
+            seq: 0,
+        }
+    }
+}
+
+pub struct Transport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream: S,
+    config: TransportConfig,
+    server_id: String,
+    // bytes read from the stream but not consumed yet
+    rbuf: Vec<u8>,
+    // total length of the incoming packet, once its first block was decrypted
+    rpending: Option<usize>,
+    incoming: Direction,
+    outgoing: Direction,
+    // packets encrypted but not written yet
+    wbuf: Vec<u8>,
+    session_id: Option<Vec<u8>>,
+    host_key: Option<PublicKey>,
+    algorithms: Option<Algorithms>,
+    strict_kex: bool,
+    kex: Kex,
+    // the next kex packet of the server is a wrong guess
+    skip_guess: bool,
+    // messages of the upper layers sent during a key exchange
+    held: VecDeque<Vec<u8>>,
+    // since the last key exchange
+    sent_bytes: u64,
+    recv_bytes: u64,
+    kex_time: Instant,
+    compressor: Option<Compressor>,
+    decompressor: Option<Decompressor>,
+}
+
+impl<S> Transport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub(super) async fn connect(stream: S, config: TransportConfig) -> SshResult<Self> {
+        let mut transport = Self {
+            stream,
+            config,
+            server_id: String::new(),
+            rbuf: Vec::with_capacity(READ_CHUNK),
+            rpending: None,
+            incoming: Direction::new(),
+            outgoing: Direction::new(),
+            wbuf: Vec::new(),
+            session_id: None,
+            host_key: None,
+            algorithms: None,
+            strict_kex: false,
+            kex: Kex::Idle,
+            skip_guess: false,
+            held: VecDeque::new(),
+            sent_bytes: 0,
+            recv_bytes: 0,
+            kex_time: Instant::now(),
+            compressor: None,
+            decompressor: None,
+        };
+        transport.version_exchange().await?;
+        transport.start_kex();
+        // nothing but the key exchange is expected until it is done
+        while transport.algorithms.is_none() {
+            transport.write_buffered().await?;
+            let payload = transport.recv_packet().await?;
+            if let Some(payload) = transport.dispatch(payload)? {
+                return Err(SshError::UnexpectedMessage(payload[0]));
+            }
+        }
+        Ok(transport)
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.config.client_id
+    }
+
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+
+    /// The exchange hash of the first key exchange
+    pub fn session_id(&self) -> &[u8] {
+        self.session_id.as_deref().unwrap_or_default()
+    }
+
+    /// The host key presented (and proved) by the server in the last key exchange
+    pub fn host_key(&self) -> Option<&PublicKey> {
+        self.host_key.as_ref()
+    }
+
+    pub fn algorithms(&self) -> Option<&Algorithms> {
+        self.algorithms.as_ref()
+    }
+
+    /// Whether a key exchange is in progress
+    pub fn is_rekeying(&self) -> bool {
+        !matches!(self.kex, Kex::Idle)
+    }
+
+    /// Start the delayed compression, once the user is authenticated
+    pub(super) fn start_compression(&mut self) {
+        let Some(algorithms) = &self.algorithms else {
+            return;
+        };
+        if algorithms.comp_c2s == compress::ZLIB_OPENSSH && self.compressor.is_none() {
+            info!("Compression started");
+            self.compressor = Some(Compressor::new());
+        }
+        if algorithms.comp_s2c == compress::ZLIB_OPENSSH && self.decompressor.is_none() {
+            self.decompressor = Some(Decompressor::new());
+        }
+    }
+
+    // When the connection has been established, both sides MUST send an
+    // identification string.
+    //     SSH-protoversion-softwareversion SP comments CR LF
+    // The server MAY send other lines of data before sending the version string.
+    async fn version_exchange(&mut self) -> SshResult<()> {
+        let hello = format!("{}\r\n", self.config.client_id);
+        self.stream.write_all(hello.as_bytes()).await?;
+        self.stream.flush().await?;
+
+        for _ in 0..MAX_BANNER_LINES {
+            let line = loop {
+                if let Some(end) = self.rbuf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = self.rbuf.drain(..=end).collect();
+                    break line;
+                }
+                if self.rbuf.len() > MAX_VERSION_LINE {
+                    return Err(SshError::InvalidVersion(
+                        String::from_utf8_lossy(&self.rbuf).into_owned(),
+                    ));
+                }
+                self.fill_buf().await?;
+            };
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.starts_with("SSH-") {
+                if !line.starts_with("SSH-2.0-") && !line.starts_with("SSH-1.99-") {
+                    return Err(SshError::InvalidVersion(line.to_owned()));
+                }
+                info!("Server version {}", line);
+                self.server_id = line.to_owned();
+                return Ok(());
+            }
+            trace!("Banner: {}", line);
+        }
+        Err(SshError::InvalidVersion(
+            "too many lines before the version".to_owned(),
+        ))
+    }
+
+    async fn fill_buf(&mut self) -> SshResult<()> {
+        let mut buf = [0; READ_CHUNK];
+        let n = self.stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        self.rbuf.extend_from_slice(&buf[..n]);
+        Ok(())
+    }
+
+    /// Decode a packet from the received bytes if it is complete
+    fn try_decode(&mut self) -> SshResult<Option<Vec<u8>>> {
+        let first_len = self.incoming.state.first_block_len();
+        let tag_len = self.incoming.state.tag_len();
+        let total = match self.rpending {
+            Some(total) => total,
+            None => {
+                if self.rbuf.len() < first_len {
+                    return Ok(None);
+                }
+                let seq = self.incoming.seq;
+                let len = self
+                    .incoming
+                    .state
+                    .decrypt_length(seq, &mut self.rbuf[..first_len])
+                    as usize;
+                if len > MAX_PACKET_LEN || len + 4 < first_len {
+                    return Err(SshError::Malformed);
+                }
+                let total = 4 + len + tag_len;
+                self.rpending = Some(total);
+                total
+            }
+        };
+        if self.rbuf.len() < total {
+            return Ok(None);
+        }
+        self.rpending = None;
+        self.recv_bytes += total as u64;
+
+        let mut packet: Vec<u8> = self.rbuf.drain(..total).collect();
+        let tag = packet.split_off(total - tag_len);
+        let seq = self.incoming.seq;
+        self.incoming
+            .state
+            .open(seq, &mut packet, first_len, &tag)?;
+        self.incoming.seq = seq.wrapping_add(1);
+
+        let padding = packet[4] as usize;
+        if padding < 4 || 5 + padding > packet.len() {
+            return Err(SshError::Malformed);
+        }
+        packet.truncate(packet.len() - padding);
+        packet.drain(..5);
+        if let Some(decompressor) = &mut self.decompressor {
+            packet = decompressor.decompress(&packet, MAX_PACKET_LEN)?;
+        }
+        if packet.is_empty() {
+            return Err(SshError::Malformed);
+        }
+        Ok(Some(packet))
+    }
+
+    /// Read the next packet payload, whatever the message is
+    ///
+    /// This is cancel safe, the partially received packet is kept in the buffer
+    async fn recv_packet(&mut self) -> SshResult<Vec<u8>> {
+        loop {
+            if let Some(payload) = self.try_decode()? {
+                trace!("Recv msg {}, {} bytes", payload[0], payload.len());
+                return Ok(payload);
+            }
+            self.fill_buf().await?;
+        }
+    }
+
+    // Encrypt a message to be written
+    fn queue(&mut self, payload: &[u8]) -> SshResult<()> {
+        let compressed;
+        let payload = match &mut self.compressor {
+            Some(compressor) => {
+                compressed = compressor.compress(payload)?;
+                &compressed
+            }
+            None => payload,
+        };
+        let state = &mut self.outgoing.state;
+        let block = state.block_size();
+        let aligned = if state.length_excluded() {
+            1 + payload.len()
+        } else {
+            5 + payload.len()
+        };
+        // There MUST be at least four bytes of padding.
+        let mut padding = block - aligned % block;
+        if padding < 4 {
+            padding += block;
+        }
+
+        let mut packet = Vec::with_capacity(5 + payload.len() + padding + state.tag_len());
+        packet.extend_from_slice(&((1 + payload.len() + padding) as u32).to_be_bytes());
+        packet.push(padding as u8);
+        packet.extend_from_slice(payload);
+        let mut random = [0; 32];
+        getrandom::getrandom(&mut random[..padding]).unwrap();
+        packet.extend_from_slice(&random[..padding]);
+
+        let seq = self.outgoing.seq;
+        state.seal(seq, &mut packet);
+        self.outgoing.seq = seq.wrapping_add(1);
+        self.sent_bytes += packet.len() as u64;
+        self.wbuf.extend_from_slice(&packet);
+        Ok(())
+    }
+
+    // Write the encrypted packets
+    //
+    // This is cancel safe, what is not written yet is kept in the buffer
+    async fn write_buffered(&mut self) -> SshResult<()> {
+        while !self.wbuf.is_empty() {
+            let n = self.stream.write(&self.wbuf).await?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            self.wbuf.drain(..n);
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Send a message
+    ///
+    /// The messages of the upper layers are held while the keys are being
+    /// exchanged, and sent with the new keys
+    pub async fn send(&mut self, payload: &[u8]) -> SshResult<()> {
+        trace!("Send msg {}, {} bytes", payload[0], payload.len());
+        if self.rekey_due() {
+            self.start_kex();
+        }
+        match self.kex {
+            Kex::InitSent { .. } | Kex::EcdhSent { .. } if !allowed_in_kex(payload[0]) => {
+                self.held.push_back(payload.to_vec());
+            }
+            _ => self.queue(payload)?,
+        }
+        self.write_buffered().await
+    }
+
+    /// Receive the next message for the upper layers
+    ///
+    /// Transport messages (ignore, debug, disconnect, key exchange...) are
+    /// handled here. This is cancel safe, a key exchange goes on with the
+    /// next call
+    pub async fn recv(&mut self) -> SshResult<Vec<u8>> {
+        loop {
+            if self.rekey_due() {
+                self.start_kex();
+            }
+            self.write_buffered().await?;
+            let payload = self.recv_packet().await?;
+            if let Some(payload) = self.dispatch(payload)? {
+                return Ok(payload);
+            }
+        }
+    }
+
+    // Handle a transport message, the others are returned
+    fn dispatch(&mut self, payload: Vec<u8>) -> SshResult<Option<Vec<u8>>> {
+        // only the messages of the key exchange are accepted in the first
+        // one, but those allowed anywhere if it is not strict
+        let first_kex = self.algorithms.is_none();
+        match payload[0] {
+            SSH_MSG_DISCONNECT => Err(Self::parse_disconnect(&payload)),
+            SSH_MSG_IGNORE | SSH_MSG_DEBUG | SSH_MSG_UNIMPLEMENTED
+                if first_kex && self.strict_kex =>
+            {
+                Err(SshError::UnexpectedMessage(payload[0]))
+            }
+            SSH_MSG_IGNORE | SSH_MSG_UNIMPLEMENTED => Ok(None),
+            SSH_MSG_DEBUG => {
+                let mut r = SshReader::new(&payload[1..]);
+                let _display = r.read_bool()?;
+                info!("Server debug message: {}", r.read_utf8()?);
+                Ok(None)
+            }
+            SSH_MSG_KEXINIT..50 => {
+                self.kex_step(payload)?;
+                Ok(None)
+            }
+            msg if first_kex => Err(SshError::UnexpectedMessage(msg)),
+            _ => Ok(Some(payload)),
+        }
+    }
+
+    fn parse_disconnect(payload: &[u8]) -> SshError {
+        let mut r = SshReader::new(&payload[1..]);
+        match (r.read_u32(), r.read_utf8()) {
+            (Ok(code), Ok(desc)) => SshError::Disconnect(code, desc),
+            _ => SshError::Malformed,
+        }
+    }
+
+    /// Tell the server that we're going to close the connection
+    pub async fn disconnect(&mut self, reason: u32, description: &str) -> SshResult<()> {
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_DISCONNECT);
+        w.write_u32(reason);
+        w.write_str(description);
+        w.write_str("");
+        self.send(w.get_inner()).await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    fn rekey_due(&self) -> bool {
+        matches!(self.kex, Kex::Idle)
+            && self.algorithms.is_some()
+            && (self.sent_bytes.max(self.recv_bytes) >= self.config.rekey_bytes
+                || self.kex_time.elapsed() >= self.config.rekey_interval)
+    }
+
+    /// Start a key exchange, unless one is in progress
+    ///
+    /// The first one happens right after the version exchange, later ones
+    /// start when a limit is reached, or when the server sends its KEXINIT
+    pub fn start_kex(&mut self) {
+        if !matches!(self.kex, Kex::Idle) {
+            return;
+        }
+        if let Err(e) = self.send_kexinit() {
+            // only the compression may fail, the next send fails as well
+            info!("Cannot start a key exchange: {}", e);
+        }
+    }
+
+    fn send_kexinit(&mut self) -> SshResult<()> {
+        let mut kex_list = self.config.kex.clone();
+        if self.session_id.is_none() {
+            kex_list.push(kex::EXT_INFO_CLIENT);
+            kex_list.push(kex::KEX_STRICT_CLIENT);
+        }
+        let init = KexInit::client(&kex_list, &self.config.host_key, &self.config.compression);
+        let client_kexinit = init.encode();
+        self.queue(&client_kexinit)?;
+        self.kex = Kex::InitSent {
+            init,
+            client_kexinit,
+        };
+        Ok(())
+    }
+
+    // Move the key exchange on with a packet of the server
+    fn kex_step(&mut self, payload: Vec<u8>) -> SshResult<()> {
+        if self.skip_guess && payload[0] != SSH_MSG_KEXINIT && payload[0] != SSH_MSG_NEWKEYS {
+            self.skip_guess = false;
+            return Ok(());
+        }
+        if let (Kex::Idle, SSH_MSG_KEXINIT) = (&self.kex, payload[0]) {
+            self.send_kexinit()?;
+        }
+        match (std::mem::replace(&mut self.kex, Kex::Idle), payload[0]) {
+            (
+                Kex::InitSent {
+                    init,
+                    client_kexinit,
+                },
+                SSH_MSG_KEXINIT,
+            ) => self.server_kexinit(init, client_kexinit, payload),
+            (
+                Kex::EcdhSent {
+                    client_kexinit,
+                    server_kexinit,
+                    algorithms,
+                    ecdh,
+                },
+                SSH_MSG_KEX_ECDH_REPLY,
+            ) => self.ecdh_reply(client_kexinit, server_kexinit, algorithms, ecdh, &payload),
+            (
+                Kex::NewKeysSent {
+                    incoming,
+                    algorithms,
+                },
+                SSH_MSG_NEWKEYS,
+            ) => {
+                self.incoming.state = incoming;
+                if self.strict_kex {
+                    self.incoming.seq = 0;
+                }
+                self.algorithms = Some(algorithms);
+                self.sent_bytes = 0;
+                self.recv_bytes = 0;
+                self.kex_time = Instant::now();
+                info!("Key exchange done");
+                Ok(())
+            }
+            (_, msg) => Err(SshError::UnexpectedMessage(msg)),
+        }
+    }
+
+    fn server_kexinit(
+        &mut self,
+        client_init: KexInit,
+        client_kexinit: Vec<u8>,
+        server_kexinit: Vec<u8>,
+    ) -> SshResult<()> {
+        let first_kex = self.session_id.is_none();
+        let server_init = KexInit::decode(&server_kexinit)?;
+        if first_kex && server_init.kex.iter().any(|k| k == kex::KEX_STRICT_SERVER) {
+            // KEXINIT must be the very first packet from the server
+            if self.incoming.seq != 1 {
+                return Err(SshError::General(
+                    "Strict kex violated by the server".to_owned(),
+                ));
+            }
+            info!("Strict kex enabled");
+            self.strict_kex = true;
+        }
+
+        let algorithms = kex::negotiate(&client_init, &server_init)?;
+        // the pseudo algorithms are not real key exchange methods
+        if !kex::KEX_ALGORITHMS.contains(&algorithms.kex.as_str()) {
+            return Err(SshError::NoCommonAlgorithm("kex"));
+        }
+        info!("Negotiated algorithms {:?}", algorithms);
+        self.skip_guess = kex::guess_is_wrong(&client_init, &server_init);
+
+        // Curve25519 key exchange
+        //     https://www.rfc-editor.org/rfc/rfc5656#section-4
+        let ecdh = Curve25519::new();
+        let mut w = SshWriter::new();
+        w.write_u8(SSH_MSG_KEX_ECDH_INIT);
+        w.write_string(ecdh.public_key());
+        self.queue(w.get_inner())?;
+        self.kex = Kex::EcdhSent {
+            client_kexinit,
+            server_kexinit,
+            algorithms,
+            ecdh,
+        };
+        Ok(())
+    }
+
+    fn ecdh_reply(
+        &mut self,
+        client_kexinit: Vec<u8>,
+        server_kexinit: Vec<u8>,
+        algorithms: Algorithms,
+        ecdh: Curve25519,
+        reply: &[u8],
+    ) -> SshResult<()> {
+        let mut r = SshReader::new(&reply[1..]);
+        let host_key_blob = r.read_string()?;
+        let server_public = r.read_string()?;
+        let signature = r.read_string()?;
+
+        let shared_secret = ecdh.shared_secret(server_public)?;
+        let exchange_hash = ExchangeHash {
+            client_id: self.config.client_id.as_bytes(),
+            server_id: self.server_id.as_bytes(),
+            client_kexinit: &client_kexinit,
+            server_kexinit: &server_kexinit,
+            host_key: host_key_blob,
+            client_public: ecdh.public_key(),
+            server_public,
+            shared_secret: &shared_secret,
+        }
+        .digest();
+
+        let host_key = PublicKey::from_blob(host_key_blob)?;
+        host_key.verify(&algorithms.host_key, &exchange_hash, signature)?;
+        if let Some(old) = &self.host_key {
+            // the host key is not supposed to change during a session
+            if *old != host_key {
+                return Err(SshError::General(
+                    "Host key changed during a rekey".to_owned(),
+                ));
+            }
+        }
+        self.host_key = Some(host_key);
+
+        let session_id = self
+            .session_id
+            .get_or_insert_with(|| exchange_hash.clone())
+            .clone();
+
+        // Key exchange ends by each side sending an SSH_MSG_NEWKEYS message.
+        self.queue(&[SSH_MSG_NEWKEYS])?;
+        let derive =
+            |letter, len| kex::derive_key(&shared_secret, &exchange_hash, letter, &session_id, len);
+        let (key_len, iv_len) = cipher::cipher_key_len(&algorithms.cipher_c2s);
+        let mac_len = cipher::mac_key_len(&algorithms.mac_c2s);
+        self.outgoing.state = CipherState::new(
+            &algorithms.cipher_c2s,
+            &algorithms.mac_c2s,
+            &derive(b'C', key_len),
+            &derive(b'A', iv_len),
+            &derive(b'E', mac_len),
+        )?;
+        if self.strict_kex {
+            self.outgoing.seq = 0;
+        }
+        let (key_len, iv_len) = cipher::cipher_key_len(&algorithms.cipher_s2c);
+        let mac_len = cipher::mac_key_len(&algorithms.mac_s2c);
+        let incoming = CipherState::new(
+            &algorithms.cipher_s2c,
+            &algorithms.mac_s2c,
+            &derive(b'D', key_len),
+            &derive(b'B', iv_len),
+            &derive(b'F', mac_len),
+        )?;
+        self.kex = Kex::NewKeysSent {
+            incoming,
+            algorithms,
+        };
+        // what the upper layers sent meanwhile goes with the new keys
+        while let Some(payload) = self.held.pop_front() {
+            self.queue(&payload)?;
+        }
+        Ok(())
+    }
+}
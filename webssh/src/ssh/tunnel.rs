@@ -0,0 +1,92 @@
+// A stream to a host behind the server, for a jump host
+//     https://www.rfc-editor.org/rfc/rfc4254#section-7.2
+
+use super::{connection::MAX_PACKET_SIZE, ChannelEvent, Connection, SshError, SshResult};
+use std::future::Future;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tracing::{info, trace};
+
+// Data of the channel not read from the stream yet, the connection is not
+// read any further above it, so the window of the channel is not adjusted
+const MAX_BUFFERED: usize = 256 * 1024;
+
+/// Open a `direct-tcpip` channel from the server to `host:port`
+///
+/// The returned stream carries the data of the channel, so that another
+/// transport can run on it. The returned future moves the data between the
+/// stream and the channel and has to be polled as long as the stream is
+/// used, it ends when either side is closed and disconnects from the server
+pub async fn tunnel<S>(
+    mut conn: Connection<S>,
+    host: &str,
+    port: u16,
+) -> SshResult<(DuplexStream, impl Future<Output = SshResult<()>>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let id = conn.open_direct_tcpip(host, port);
+    loop {
+        conn.flush().await?;
+        match conn.recv().await? {
+            ChannelEvent::Opened(channel) if channel == id => break,
+            ChannelEvent::OpenFailed(channel, reason) if channel == id => {
+                let _ = conn.disconnect().await;
+                return Err(SshError::ChannelOpenFailed(id, reason));
+            }
+            event => trace!("{:?}", event),
+        }
+    }
+    info!("Tunnel to {}:{} opened", host, port);
+    let (stream, end) = tokio::io::duplex(MAX_PACKET_SIZE as usize);
+    Ok((stream, pump(conn, id, end)))
+}
+
+async fn pump<S>(mut conn: Connection<S>, id: u32, end: DuplexStream) -> SshResult<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut reader, mut writer) = tokio::io::split(end);
+    let mut buf = vec![0; MAX_PACKET_SIZE as usize];
+    // received from the channel, not written to the stream yet
+    let mut inbound = Vec::new();
+    let mut eof = false;
+    let result = loop {
+        if let Err(e) = conn.flush().await {
+            break Err(e);
+        }
+        if eof && inbound.is_empty() {
+            let _ = writer.shutdown().await;
+            break Ok(());
+        }
+        tokio::select! {
+            event = conn.recv(), if !eof && inbound.len() < MAX_BUFFERED => match event {
+                Ok(ChannelEvent::Data(channel, data)) if channel == id => {
+                    inbound.extend_from_slice(&data)
+                }
+                Ok(ChannelEvent::Eof(channel) | ChannelEvent::Closed(channel)) if channel == id => {
+                    eof = true
+                }
+                Ok(event) => trace!("{:?}", event),
+                Err(e) => break Err(e),
+            },
+            read = reader.read(&mut buf) => match read {
+                Ok(n) if n > 0 => conn.data(id, &buf[..n]),
+                // the stream is dropped, by the transport running on it
+                _ => {
+                    conn.eof(id);
+                    conn.close(id);
+                    break Ok(());
+                }
+            },
+            written = writer.write(&inbound), if !inbound.is_empty() => match written {
+                Ok(n) => {
+                    inbound.drain(..n);
+                }
+                Err(_) => break Ok(()),
+            },
+        }
+    };
+    info!("Tunnel closed");
+    let _ = conn.disconnect().await;
+    result
+}
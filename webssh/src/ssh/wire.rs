@@ -0,0 +1,193 @@
+// Data type representations used in the ssh protocols
+//     https://www.rfc-editor.org/rfc/rfc4251#section-5
+
+use super::{SshError, SshResult};
+
+pub struct SshReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SshReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn remain(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> SshResult<&'a [u8]> {
+        if self.remain() < n {
+            return Err(SshError::Malformed);
+        }
+        let out = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    pub fn read_u8(&mut self) -> SshResult<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> SshResult<bool> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u32(&mut self) -> SshResult<u32> {
+        let buf = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes(buf.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> SshResult<u64> {
+        let buf = self.read_bytes(8)?;
+        Ok(u64::from_be_bytes(buf.try_into().unwrap()))
+    }
+
+    pub fn read_string(&mut self) -> SshResult<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    pub fn read_utf8(&mut self) -> SshResult<String> {
+        let buf = self.read_string()?;
+        String::from_utf8(buf.to_vec()).map_err(|_| SshError::Malformed)
+    }
+
+    pub fn read_name_list(&mut self) -> SshResult<Vec<String>> {
+        let list = self.read_utf8()?;
+        if list.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(list.split(',').map(|s| s.to_owned()).collect())
+    }
+
+    /// Returns the magnitude of a non-negative mpint, with the leading zeros stripped
+    pub fn read_mpint(&mut self) -> SshResult<&'a [u8]> {
+        let buf = self.read_string()?;
+        if !buf.is_empty() && buf[0] & 0x80 != 0 {
+            // negative numbers are never used by the algorithms we support
+            return Err(SshError::Malformed);
+        }
+        let leading = buf.iter().take_while(|b| **b == 0).count();
+        Ok(&buf[leading..])
+    }
+
+    pub fn read_to_end(&mut self) -> &'a [u8] {
+        let out = &self.buf[self.pos..];
+        self.pos = self.buf.len();
+        out
+    }
+}
+
+#[derive(Default)]
+pub struct SshWriter {
+    buf: Vec<u8>,
+}
+
+impl SshWriter {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(256),
+        }
+    }
+
+    pub fn write_u8(&mut self, b: u8) {
+        self.buf.push(b);
+    }
+
+    pub fn write_bool(&mut self, b: bool) {
+        self.buf.push(b as u8);
+    }
+
+    pub fn write_u32(&mut self, b: u32) {
+        self.buf.extend_from_slice(&b.to_be_bytes());
+    }
+
+    pub fn write_u64(&mut self, b: u64) {
+        self.buf.extend_from_slice(&b.to_be_bytes());
+    }
+
+    pub fn write_bytes(&mut self, s: &[u8]) {
+        self.buf.extend_from_slice(s);
+    }
+
+    pub fn write_string(&mut self, s: &[u8]) {
+        self.write_u32(s.len() as u32);
+        self.buf.extend_from_slice(s);
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_string(s.as_bytes());
+    }
+
+    pub fn write_name_list(&mut self, list: &[&str]) {
+        self.write_str(&list.join(","));
+    }
+
+    /// Writes an unsigned big-endian magnitude as a non-negative mpint
+    pub fn write_mpint(&mut self, n: &[u8]) {
+        let leading = n.iter().take_while(|b| **b == 0).count();
+        let n = &n[leading..];
+        if !n.is_empty() && n[0] & 0x80 != 0 {
+            self.write_u32(n.len() as u32 + 1);
+            self.buf.push(0);
+            self.buf.extend_from_slice(n);
+        } else {
+            self.write_string(n);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn get_inner(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mpint() {
+        // examples from rfc4251 section 5
+        let mut w = SshWriter::new();
+        w.write_mpint(&[]);
+        w.write_mpint(&[0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3, 0x32, 0xa7]);
+        w.write_mpint(&[0x00, 0x80]);
+        assert_eq!(
+            w.get_inner(),
+            &[
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x09, 0xa3, 0x78, 0xf9, 0xb2, 0xe3,
+                0x32, 0xa7, 0x00, 0x00, 0x00, 0x02, 0x00, 0x80
+            ]
+        );
+
+        let mut r = SshReader::new(w.get_inner());
+        assert!(r.read_mpint().unwrap().is_empty());
+        assert_eq!(r.read_mpint().unwrap().len(), 8);
+        assert_eq!(r.read_mpint().unwrap(), &[0x80]);
+        assert!(r.read_u8().is_err());
+    }
+
+    #[test]
+    fn test_name_list() {
+        let mut w = SshWriter::new();
+        w.write_name_list(&[]);
+        w.write_name_list(&["zlib", "none"]);
+        let mut r = SshReader::new(w.get_inner());
+        assert!(r.read_name_list().unwrap().is_empty());
+        assert_eq!(r.read_name_list().unwrap(), vec!["zlib", "none"]);
+    }
+}
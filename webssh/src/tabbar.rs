@@ -0,0 +1,94 @@
+use crate::canvas::CanvasEvent;
+
+use tokio::sync::mpsc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement, MouseEvent};
+
+/// Input from the tab bar, the tabs are identified by their channel
+#[derive(Debug, Clone)]
+pub enum TabEvent {
+    /// Open another shell over the same connection
+    New,
+    Select(u32),
+    Close(u32),
+}
+
+fn element<T: JsCast>(id: &str) -> T {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<T>()
+        .unwrap()
+}
+
+fn send(sender: &mpsc::Sender<CanvasEvent>, event: TabEvent) {
+    let sender = sender.clone();
+    futures::executor::block_on(async move {
+        let _ = sender.send(CanvasEvent::Tab(event)).await;
+    });
+}
+
+/// Bind the controls of the tab bar
+pub fn bind(sender: mpsc::Sender<CanvasEvent>) {
+    let s = sender.clone();
+    let new = move |_: MouseEvent| send(&s, TabEvent::New);
+    let new = Closure::wrap(Box::new(new) as Box<dyn FnMut(_)>);
+    element::<HtmlElement>("tabs_new").set_onclick(Some(new.as_ref().unchecked_ref()));
+    new.forget();
+
+    // the tabs are rendered again and again, listen on their parent
+    let click = move |e: MouseEvent| {
+        let Some(target) = e.target().and_then(|t| t.dyn_into::<Element>().ok()) else {
+            return;
+        };
+        let Some(id) = target
+            .closest("[data-tab]")
+            .ok()
+            .flatten()
+            .and_then(|tab| tab.get_attribute("data-tab"))
+            .and_then(|id| id.parse().ok())
+        else {
+            return;
+        };
+        if target.has_attribute("data-close") {
+            send(&sender, TabEvent::Close(id));
+        } else {
+            send(&sender, TabEvent::Select(id));
+        }
+    };
+    let click = Closure::wrap(Box::new(click) as Box<dyn FnMut(_)>);
+    element::<HtmlElement>("tabs_list").set_onclick(Some(click.as_ref().unchecked_ref()));
+    click.forget();
+}
+
+/// Show the tabs as (channel, title), with the `active` one highlighted
+pub fn render(tabs: &[(u32, &str)], active: u32) {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let list = element::<Element>("tabs_list");
+    list.set_inner_html("");
+    for (id, title) in tabs {
+        let tab = document.create_element("span").unwrap();
+        let _ = tab.set_attribute("data-tab", &id.to_string());
+        let _ = tab.set_attribute(
+            "class",
+            if *id == active {
+                "tab tab_active"
+            } else {
+                "tab"
+            },
+        );
+        let name = document.create_element("span").unwrap();
+        name.set_text_content(Some(title));
+        let _ = tab.append_child(&name);
+        let close = document.create_element("span").unwrap();
+        let _ = close.set_attribute("data-close", "");
+        let _ = close.set_attribute("class", "tab_close");
+        close.set_text_content(Some("\u{2715}"));
+        let _ = tab.append_child(&close);
+        let _ = list.append_child(&tab);
+    }
+}
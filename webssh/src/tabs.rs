@@ -0,0 +1,180 @@
+use crate::cast::Recorder;
+use crate::terminal::Terminal;
+
+/// A shell on its own session channel
+pub struct Tab {
+    pub channel: u32,
+    /// The title set by the application, or the name of the tab
+    pub title: String,
+    name: String,
+    pub term: Terminal,
+    /// Set while the session is recorded
+    pub recorder: Option<Recorder>,
+}
+
+/// The shells sharing one connection, one of them is shown
+pub struct Tabs {
+    tabs: Vec<Tab>,
+    active: usize,
+    // for the titles, never reused
+    opened: usize,
+    changed: bool,
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tabs {
+    pub fn new() -> Self {
+        Self {
+            tabs: Vec::new(),
+            active: 0,
+            opened: 0,
+            changed: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    /// Add the shell of `channel` as the active tab
+    pub fn add(&mut self, channel: u32, term: Terminal) {
+        self.opened += 1;
+        let name = format!("Shell {}", self.opened);
+        self.tabs.push(Tab {
+            channel,
+            title: name.clone(),
+            name,
+            term,
+            recorder: None,
+        });
+        self.active = self.tabs.len() - 1;
+        self.changed = true;
+    }
+
+    pub fn get_mut(&mut self, channel: u32) -> Option<&mut Tab> {
+        self.tabs.iter_mut().find(|tab| tab.channel == channel)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Tab> {
+        self.tabs.iter_mut()
+    }
+
+    /// The shown tab, there must be one
+    pub fn active(&mut self) -> &mut Tab {
+        &mut self.tabs[self.active]
+    }
+
+    pub fn active_channel(&self) -> Option<u32> {
+        self.tabs.get(self.active).map(|tab| tab.channel)
+    }
+
+    /// Show the tab of `channel`, true if another tab was shown before
+    pub fn select(&mut self, channel: u32) -> bool {
+        match self.tabs.iter().position(|tab| tab.channel == channel) {
+            Some(index) if index != self.active => {
+                self.active = index;
+                self.changed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the tab of `channel`, the one on its left is shown if it was
+    /// the active one
+    pub fn remove(&mut self, channel: u32) -> Option<Tab> {
+        let index = self.tabs.iter().position(|tab| tab.channel == channel)?;
+        let tab = self.tabs.remove(index);
+        if index < self.active || (index == self.active && self.active > 0) {
+            self.active -= 1;
+        }
+        self.changed = true;
+        Some(tab)
+    }
+
+    /// Set the title of the tab of `channel`, back to its name if empty
+    pub fn set_title(&mut self, channel: u32, title: String) {
+        if let Some(tab) = self.get_mut(channel) {
+            tab.title = if title.is_empty() {
+                tab.name.clone()
+            } else {
+                title
+            };
+            self.changed = true;
+        }
+    }
+
+    /// Whether the tabs were added, removed or switched since the last call
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// (channel, title) of the tabs, in order
+    pub fn titles(&self) -> Vec<(u32, &str)> {
+        self.tabs
+            .iter()
+            .map(|tab| (tab.channel, tab.title.as_str()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tabs() {
+        let mut tabs = Tabs::new();
+        assert!(tabs.is_empty());
+        assert_eq!(tabs.active_channel(), None);
+        for channel in [0, 3, 5] {
+            tabs.add(channel, Terminal::new(10, 4));
+        }
+        assert_eq!(tabs.active_channel(), Some(5));
+        assert!(tabs.take_changed());
+        assert!(!tabs.take_changed());
+        assert_eq!(
+            tabs.titles(),
+            vec![(0, "Shell 1"), (3, "Shell 2"), (5, "Shell 3")]
+        );
+
+        assert!(tabs.select(3));
+        assert!(tabs.take_changed());
+        assert!(!tabs.select(3));
+        assert!(!tabs.take_changed());
+        assert!(!tabs.select(7));
+        assert_eq!(tabs.active().channel, 3);
+
+        tabs.set_title(3, "vim".to_owned());
+        assert!(tabs.take_changed());
+        assert_eq!(tabs.active().title, "vim");
+        tabs.set_title(3, String::new());
+        assert_eq!(tabs.active().title, "Shell 2");
+
+        // a tab on the left goes, the active one stays
+        assert_eq!(tabs.remove(0).unwrap().channel, 0);
+        assert_eq!(tabs.active_channel(), Some(3));
+        assert!(tabs.remove(0).is_none());
+
+        // the active one goes, its left one is shown
+        tabs.add(6, Terminal::new(10, 4));
+        assert_eq!(tabs.titles()[2], (6, "Shell 4"));
+        tabs.select(5);
+        tabs.remove(5);
+        assert_eq!(tabs.active_channel(), Some(3));
+        tabs.remove(3);
+        assert_eq!(tabs.active_channel(), Some(6));
+        tabs.remove(6);
+        assert!(tabs.is_empty());
+        assert_eq!(tabs.len(), 0);
+    }
+}
@@ -0,0 +1,405 @@
+//! Test suite against a local OpenSSH server.
+//!
+//! Ignored by default, run it with
+//! `WEBSSH_TEST_SERVER=127.0.0.1:22 WEBSSH_TEST_USER=user WEBSSH_TEST_PASSWORD=pass cargo test -- --ignored`
+//!
+//! The sftp test works in `WEBSSH_TEST_SFTP_DIR`, a directory which is
+//! created and removed on the server.
+//!
+//! The jump test reaches `WEBSSH_TEST_JUMP_TARGET` through the server, the
+//! server itself by default, and logs in there with the same credentials
+//!
+//! The publickey test also needs `WEBSSH_TEST_KEY=~/.ssh/id_ed25519`, and
+//! `WEBSSH_TEST_PASSPHRASE` if the key is encrypted
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use webssh::files::{FileSession, TransferState};
+use webssh::ssh::{
+    self, msg, private_key::PrivateKey, sftp, AuthHandler, ChannelEvent, Connection, PromptRequest,
+    SshConnector, SshError, Transport,
+};
+use zeroize::Zeroizing;
+
+fn env(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_owned())
+}
+
+/// Answers password prompts with the password, others with the one time code
+struct Answers {
+    password: String,
+    otp: String,
+    failures: usize,
+    key: Option<Vec<u8>>,
+}
+
+#[async_trait(?Send)]
+impl AuthHandler for Answers {
+    async fn prompt(&mut self, request: PromptRequest) -> Option<Vec<Zeroizing<String>>> {
+        if self.failures > 2 {
+            return None;
+        }
+        let answers = request
+            .prompts
+            .iter()
+            .map(|p| {
+                if p.prompt.to_lowercase().contains("password") {
+                    Zeroizing::new(self.password.clone())
+                } else {
+                    Zeroizing::new(self.otp.clone())
+                }
+            })
+            .collect();
+        Some(answers)
+    }
+
+    async fn private_key(&mut self) -> Option<PrivateKey> {
+        let pem = self.key.take()?;
+        let passphrase = std::env::var("WEBSSH_TEST_PASSPHRASE").ok();
+        Some(PrivateKey::decode(&pem, passphrase.as_deref()).unwrap())
+    }
+
+    fn failure(&mut self, _methods: &[String], partial_success: bool) {
+        if !partial_success {
+            self.failures += 1;
+        }
+    }
+}
+
+async fn tcp() -> TcpStream {
+    TcpStream::connect(env("WEBSSH_TEST_SERVER", "127.0.0.1:22"))
+        .await
+        .unwrap()
+}
+
+async fn connect() -> Transport<TcpStream> {
+    SshConnector::new(tcp().await).connect().await.unwrap()
+}
+
+async fn authenticate<S>(transport: &mut Transport<S>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut answers = Answers {
+        password: env("WEBSSH_TEST_PASSWORD", ""),
+        otp: env("WEBSSH_TEST_OTP", ""),
+        failures: 0,
+        key: None,
+    };
+    transport
+        .authenticate(&env("WEBSSH_TEST_USER", "root"), &mut answers)
+        .await
+        .unwrap();
+}
+
+async fn login() -> Connection<TcpStream> {
+    let mut transport = connect().await;
+    authenticate(&mut transport).await;
+    Connection::new(transport)
+}
+
+#[tokio::test]
+#[ignore]
+async fn handshake() {
+    let mut transport = connect().await;
+    assert!(transport.server_id().starts_with("SSH-2.0-"));
+    assert_eq!(transport.session_id().len(), 32);
+    assert!(transport.host_key().is_some());
+
+    let mut w = webssh::ssh::wire::SshWriter::new();
+    w.write_u8(msg::SSH_MSG_SERVICE_REQUEST);
+    w.write_str("ssh-userauth");
+    transport.send(w.get_inner()).await.unwrap();
+    let mut reply = transport.recv().await.unwrap();
+    if reply[0] == msg::SSH_MSG_EXT_INFO {
+        reply = transport.recv().await.unwrap();
+    }
+    assert_eq!(reply[0], msg::SSH_MSG_SERVICE_ACCEPT);
+
+    transport
+        .disconnect(msg::SSH_DISCONNECT_BY_APPLICATION, "bye")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn wrong_password() {
+    let mut transport = connect().await;
+    let mut answers = Answers {
+        password: "wrong password".to_owned(),
+        otp: "wrong code".to_owned(),
+        failures: 0,
+        key: None,
+    };
+    let result = transport
+        .authenticate(&env("WEBSSH_TEST_USER", "root"), &mut answers)
+        .await;
+    match result {
+        Err(SshError::AuthFailed(methods)) => assert!(!methods.is_empty()),
+        Err(SshError::Disconnect(..)) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn publickey() {
+    let mut transport = connect().await;
+    let mut answers = Answers {
+        password: "wrong password".to_owned(),
+        otp: "wrong code".to_owned(),
+        failures: 0,
+        key: Some(std::fs::read(env("WEBSSH_TEST_KEY", "")).unwrap()),
+    };
+    transport
+        .authenticate(&env("WEBSSH_TEST_USER", "root"), &mut answers)
+        .await
+        .unwrap();
+    assert_eq!(answers.failures, 0);
+}
+
+// Run `command`, its output and exit status are returned
+async fn run_command<S>(conn: &mut Connection<S>, command: &str) -> (Vec<u8>, Option<u32>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let channel = conn.open_session();
+    let mut w = webssh::ssh::wire::SshWriter::new();
+    w.write_str(command);
+    conn.request(channel, "exec", true, w.get_inner());
+
+    let mut output = Vec::new();
+    let mut status = None;
+    loop {
+        conn.flush().await.unwrap();
+        match conn.recv().await.unwrap() {
+            ChannelEvent::Data(id, data) if id == channel => output.extend_from_slice(&data),
+            ChannelEvent::ExitStatus(id, code) if id == channel => status = Some(code),
+            ChannelEvent::Closed(id) if id == channel => break,
+            _ => (),
+        }
+    }
+    (output, status)
+}
+
+#[tokio::test]
+#[ignore]
+async fn exec() {
+    let mut conn = login().await;
+    let (output, status) = run_command(&mut conn, "echo webssh").await;
+    assert_eq!(String::from_utf8_lossy(&output).trim(), "webssh");
+    assert_eq!(status, Some(0));
+    conn.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn rekey() {
+    let mut transport = SshConnector::new(tcp().await)
+        .set_rekey_limit(64 * 1024, Duration::from_secs(3600))
+        .connect()
+        .await
+        .unwrap();
+    let session_id = transport.session_id().to_vec();
+    authenticate(&mut transport).await;
+    let mut conn = Connection::new(transport);
+
+    // the keys change many times on the way, the session goes on
+    let (output, status) = run_command(&mut conn, "head -c 1000000 /dev/zero").await;
+    assert_eq!(output.len(), 1_000_000);
+    assert_eq!(status, Some(0));
+    let (output, _) = run_command(&mut conn, "echo webssh").await;
+    assert_eq!(String::from_utf8_lossy(&output).trim(), "webssh");
+    assert_eq!(conn.transport().session_id(), session_id);
+
+    // the keepalive is answered, even if the request is unknown
+    conn.keepalive();
+    assert_eq!(conn.unanswered_keepalives(), 1);
+    run_command(&mut conn, "echo webssh").await;
+    assert_eq!(conn.unanswered_keepalives(), 0);
+    conn.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn compression() {
+    let mut transport = SshConnector::new(tcp().await)
+        .set_compression(true)
+        .connect()
+        .await
+        .unwrap();
+    let algorithms = transport.algorithms().unwrap();
+    assert_eq!(algorithms.comp_c2s, "zlib@openssh.com");
+    assert_eq!(algorithms.comp_s2c, "zlib@openssh.com");
+    authenticate(&mut transport).await;
+    let mut conn = Connection::new(transport);
+    let (output, status) = run_command(&mut conn, "head -c 1000000 /dev/zero").await;
+    assert_eq!(output.len(), 1_000_000);
+    assert_eq!(status, Some(0));
+    let (output, _) = run_command(&mut conn, "echo webssh").await;
+    assert_eq!(String::from_utf8_lossy(&output).trim(), "webssh");
+    conn.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn jump() {
+    let target = env(
+        "WEBSSH_TEST_JUMP_TARGET",
+        &env("WEBSSH_TEST_SERVER", "127.0.0.1:22"),
+    );
+    let (host, port) = target.rsplit_once(':').unwrap();
+    let (stream, pump) = ssh::tunnel(login().await, host, port.parse().unwrap())
+        .await
+        .unwrap();
+    let pump = tokio::spawn(pump);
+
+    let mut transport = SshConnector::new(stream).connect().await.unwrap();
+    assert!(transport.host_key().is_some());
+    authenticate(&mut transport).await;
+    let mut conn = Connection::new(transport);
+    let channel = conn.open_session();
+    conn.request_pty(channel, "xterm", 80, 24);
+    conn.request_shell(channel);
+    conn.data(channel, b"echo through the jump host\n");
+    expect_data(&mut conn, channel, "through the jump host").await;
+    conn.disconnect().await.unwrap();
+    drop(conn);
+    pump.await.unwrap().unwrap();
+
+    // the target is refused by the server
+    let refused = ssh::tunnel(login().await, "127.0.0.1", 1).await;
+    assert!(matches!(refused, Err(SshError::ChannelOpenFailed(..))));
+}
+
+// Receive until `channel` has sent `expected`, the other channels are
+// left alone
+async fn expect_data<S>(conn: &mut Connection<S>, channel: u32, expected: &str)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut output = Vec::new();
+    while !String::from_utf8_lossy(&output).contains(expected) {
+        conn.flush().await.unwrap();
+        match conn.recv().await.unwrap() {
+            ChannelEvent::Data(id, data) if id == channel => output.extend_from_slice(&data),
+            ChannelEvent::RequestReply(_, accepted) => assert!(accepted),
+            ChannelEvent::Closed(id) => assert_ne!(id, channel),
+            _ => (),
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn channels() {
+    let mut conn = login().await;
+    let first = conn.open_session();
+    let second = conn.open_session();
+    assert_ne!(first, second);
+    for channel in [first, second] {
+        conn.request_pty(channel, "xterm", 80, 24);
+        conn.request_shell(channel);
+    }
+    conn.data(first, b"echo first\n");
+    conn.data(second, b"echo second\n");
+    expect_data(&mut conn, first, "first").await;
+    expect_data(&mut conn, second, "second").await;
+
+    // closing a channel leaves the others and the connection alone
+    conn.close(first);
+    loop {
+        conn.flush().await.unwrap();
+        if conn.recv().await.unwrap() == ChannelEvent::Closed(first) {
+            break;
+        }
+    }
+    assert!(!conn.is_open(first));
+    assert!(conn.is_open(second));
+    conn.data(second, b"echo still here\n");
+    expect_data(&mut conn, second, "still here").await;
+    conn.disconnect().await.unwrap();
+}
+
+// Run the file session until `done` holds
+async fn sftp_until(
+    conn: &mut Connection<TcpStream>,
+    channel: u32,
+    session: &mut FileSession,
+    done: impl Fn(&FileSession) -> bool,
+) {
+    while !done(session) {
+        let output = session.take_output();
+        conn.data(channel, &output);
+        conn.flush().await.unwrap();
+        match conn.recv().await.unwrap() {
+            ChannelEvent::Data(_, data) => session.feed(&data).unwrap(),
+            ChannelEvent::RequestReply(_, accepted) => assert!(accepted),
+            ChannelEvent::Opened(_) | ChannelEvent::WindowAdjust(_) => (),
+            event => panic!("{:?}", event),
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn sftp() {
+    let mut conn = login().await;
+    let channel = conn.open_session();
+    let mut w = webssh::ssh::wire::SshWriter::new();
+    w.write_str(sftp::SUBSYSTEM);
+    conn.request(channel, "subsystem", true, w.get_inner());
+
+    let mut session = FileSession::new();
+    sftp_until(&mut conn, channel, &mut session, |s| !s.cwd.is_empty()).await;
+    let dir = env("WEBSSH_TEST_SFTP_DIR", "webssh-test");
+    session.mkdir(&dir);
+    sftp_until(&mut conn, channel, &mut session, |s| {
+        s.entries
+            .iter()
+            .any(|e| e.filename == dir && e.attrs.is_dir())
+    })
+    .await;
+    session.cd(&dir);
+    sftp_until(&mut conn, channel, &mut session, |s| s.cwd.ends_with(&dir)).await;
+
+    let content: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let upload = session.upload("a", content.clone());
+    sftp_until(&mut conn, channel, &mut session, |s| {
+        s.transfers[&upload].state != TransferState::Running && s.entries.len() == 1
+    })
+    .await;
+    assert_eq!(session.transfers[&upload].state, TransferState::Done);
+    assert_eq!(session.entries[0].attrs.size, Some(content.len() as u64));
+
+    session.rename("a", "b");
+    sftp_until(&mut conn, channel, &mut session, |s| {
+        s.entries.iter().any(|e| e.filename == "b")
+    })
+    .await;
+    let download = session.download("b", None);
+    sftp_until(&mut conn, channel, &mut session, |s| {
+        s.transfers[&download].state != TransferState::Running
+    })
+    .await;
+    let downloads = session.take_downloads();
+    assert_eq!(downloads, [("b".to_owned(), content)]);
+
+    session.remove("b", false);
+    sftp_until(&mut conn, channel, &mut session, |s| s.entries.is_empty()).await;
+    session.up();
+    sftp_until(&mut conn, channel, &mut session, |s| !s.cwd.ends_with(&dir)).await;
+    session.remove(&dir, true);
+    sftp_until(&mut conn, channel, &mut session, |s| {
+        !s.entries.iter().any(|e| e.filename == dir)
+    })
+    .await;
+    conn.disconnect().await.unwrap();
+}
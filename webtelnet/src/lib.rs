@@ -0,0 +1,279 @@
+pub mod telnet;
+mod utils;
+
+use telnet::Telnet;
+use terminal::{
+    input::{self, KeyInput, MouseAction, MouseInput},
+    ClipboardRequest, MouseTracking, Terminal, DEFAULT_SCROLLBACK,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+use tracing_wasm::WASMLayerConfigBuilder;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use webterm::canvas::{self, CanvasEvent, Options};
+use ws_stream_wasm::WsMeta;
+
+pub use webterm::terminal;
+
+type CanvasUtils = canvas::CanvasUtils<CanvasEvent>;
+
+// Sent in turn when the server asks for the terminal type
+const TERMINAL_TYPES: [&str; 3] = ["XTERM-256COLOR", "XTERM", "VT100"];
+const READ_SIZE: usize = 16 * 1024;
+
+const CANVAS: Options<CanvasEvent> = Options {
+    canvas_id: "telnet-canvas",
+    find: None,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    fn alert(s: &str);
+    pub fn setClipBoard(s: String);
+    pub fn getClipBoard() -> String;
+}
+
+fn set_status(msg: &str) {
+    let status_bar = web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("telnet_status")
+        .unwrap();
+    status_bar.set_text_content(Some(msg));
+}
+
+fn set_title(title: &str) {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .set_title(title);
+}
+
+// Open a hyperlink of the terminal, unless it could run in the page
+fn open_link(uri: &str) {
+    const SCHEMES: [&str; 4] = ["http:", "https:", "ftp:", "mailto:"];
+    if !SCHEMES
+        .iter()
+        .any(|scheme| uri.to_ascii_lowercase().starts_with(scheme))
+    {
+        info!("Link not opened: {}", uri);
+        return;
+    }
+    let _ = web_sys::window()
+        .unwrap()
+        .open_with_url_and_target_and_features(uri, "_blank", "noopener");
+}
+
+fn start_websocket() -> Result<(), JsValue> {
+    // connect
+    let url = format!(
+        "{scheme}://{host}/websockify",
+        scheme = if web_sys::window()
+            .unwrap()
+            .location()
+            .protocol()?
+            .starts_with("https")
+        {
+            "wss"
+        } else {
+            "ws"
+        },
+        host = web_sys::window().unwrap().location().host()?
+    );
+
+    spawn_local(async move {
+        let (event_sender, event_receiver) = tokio::sync::mpsc::channel(4096);
+        let mut canvas = CanvasUtils::new(CANVAS, event_sender);
+        let (cols, rows) = canvas.fit();
+        canvas.init(cols, rows);
+        let mut term = Terminal::new(cols, rows);
+        term.set_scrollback(
+            utils::query_param("scrollback")
+                .and_then(|lines| lines.parse().ok())
+                .unwrap_or(DEFAULT_SCROLLBACK),
+        );
+        term.feed(format!("Connecting to {}\r\n", url).as_bytes());
+        canvas.render(&mut term);
+
+        // start websocket
+        let (_ws, wsio) = match WsMeta::connect(url, vec!["binary"]).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                let msg = format!("connect error {}", e);
+                error!(msg);
+                alert(&msg);
+                return;
+            }
+        };
+        set_status("Connected");
+        session(wsio.into_io(), term, canvas, event_receiver).await;
+    });
+
+    Ok(())
+}
+
+// The terminal on `stream`, until it is closed
+async fn session<S>(
+    mut stream: S,
+    mut term: Terminal,
+    mut canvas: CanvasUtils,
+    mut event_receiver: mpsc::Receiver<CanvasEvent>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (cols, rows) = (term.screen().cols(), term.screen().rows());
+    let mut telnet = Telnet::new(&TERMINAL_TYPES, cols as u16, rows as u16);
+    canvas.focus();
+
+    let mut buf = vec![0; READ_SIZE];
+    let reason = loop {
+        let output = telnet.take_output();
+        if !output.is_empty() {
+            if let Err(e) = stream.write_all(&output).await {
+                break e.to_string();
+            }
+        }
+        tokio::select! {
+            read = stream.read(&mut buf) => match read {
+                Ok(0) => break "Connection closed".to_owned(),
+                Ok(n) => {
+                    let data = telnet.feed(&buf[..n]);
+                    term.feed(&data);
+                    // the answers of the terminal to the queries of the host
+                    telnet.send(&term.take_output());
+                    clipboard(&mut term);
+                    if let Some(title) = term.take_title() {
+                        set_title(&title);
+                    }
+                }
+                Err(e) => break e.to_string(),
+            },
+            Some(event) = event_receiver.recv() => match event {
+                CanvasEvent::Key(key) => {
+                    if !scroll_key(&mut term, &key) {
+                        if let Some(bytes) = input::encode(&key, term.screen().modes()) {
+                            term.scroll_to_bottom();
+                            send_input(&mut telnet, &mut term, &bytes);
+                        }
+                    }
+                }
+                CanvasEvent::Mouse(input) => mouse(&mut telnet, &mut term, &input),
+                CanvasEvent::Paste(text) => {
+                    let bytes = input::paste(&text, term.screen().modes());
+                    term.scroll_to_bottom();
+                    send_input(&mut telnet, &mut term, &bytes);
+                }
+                CanvasEvent::Resize(cols, rows) => {
+                    canvas.init(cols, rows);
+                    term.resize(cols, rows);
+                    telnet.resize(cols as u16, rows as u16);
+                    term.screen_mut().grid_mut().mark_dirty();
+                }
+            }
+        }
+        canvas.render(&mut term);
+    };
+    info!("Disconnected: {}", reason);
+    set_status(&format!("Disconnected: {}", reason));
+    term.feed(format!("\r\n{}\r\n", reason).as_bytes());
+    canvas.render(&mut term);
+    let _ = stream.shutdown().await;
+}
+
+// Send what the user typed, echoed here unless the server does it
+fn send_input(telnet: &mut Telnet, term: &mut Terminal, bytes: &[u8]) {
+    telnet.send(bytes);
+    if !telnet.remote_echo() {
+        term.feed(&local_echo(bytes));
+    }
+}
+
+// What is shown of the input in local echo, the keys sent as escape
+// sequences are not
+fn local_echo(bytes: &[u8]) -> Vec<u8> {
+    if bytes.first() == Some(&0x1b) {
+        return Vec::new();
+    }
+    let mut echo = Vec::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'\r' => echo.extend_from_slice(b"\r\n"),
+            0x08 | 0x7f => echo.extend_from_slice(b"\x08 \x08"),
+            b if b < 0x20 => (),
+            b => echo.push(b),
+        }
+    }
+    echo
+}
+
+// Shift+PageUp/PageDown scroll by pages, Shift+Home/End to the ends
+fn scroll_key(term: &mut Terminal, key: &KeyInput) -> bool {
+    if !key.shift || key.ctrl || key.alt {
+        return false;
+    }
+    let page = term.screen().rows() as isize - 1;
+    match key.key.as_str() {
+        "PageUp" => term.scroll(page.max(1)),
+        "PageDown" => term.scroll(-page.max(1)),
+        "Home" => term.scroll(isize::MAX),
+        "End" => term.scroll_to_bottom(),
+        _ => return false,
+    }
+    true
+}
+
+// The OSC 52 requests of the host, it may not read the clipboard
+fn clipboard(term: &mut Terminal) {
+    for request in term.take_clipboard() {
+        match request {
+            ClipboardRequest::Set(text) => setClipBoard(text),
+            ClipboardRequest::Query(_) => info!("Clipboard read refused"),
+        }
+    }
+}
+
+// Ctrl+click opens a hyperlink. The mouse goes to the application which
+// tracks it, unless Shift is held. Otherwise the left button selects, and the
+// selection is copied to the clipboard, and the wheel scrolls back
+fn mouse(telnet: &mut Telnet, term: &mut Terminal, mouse: &MouseInput) {
+    if mouse.action == MouseAction::Press && mouse.button == 0 && mouse.ctrl {
+        if let Some(uri) = term.link_at(mouse.row, mouse.col) {
+            open_link(uri);
+            return;
+        }
+    }
+    let modes = *term.screen().modes();
+    // the rows shown are not the ones of the application when scrolled back
+    if modes.mouse != MouseTracking::Off && !mouse.shift && term.view().offset() == 0 {
+        if let Some(bytes) = input::encode_mouse(mouse, &modes) {
+            telnet.send(&bytes);
+        }
+        return;
+    }
+    match mouse.action {
+        MouseAction::Press if mouse.button == 0 => term.select_start(mouse.row, mouse.col),
+        MouseAction::Move if mouse.buttons & 1 != 0 => term.select_to(mouse.row, mouse.col),
+        MouseAction::Release if mouse.button == 0 => {
+            if let Some(text) = term.selection_text() {
+                setClipBoard(text);
+            }
+        }
+        MouseAction::Wheel(lines) => term.scroll(lines),
+        _ => (),
+    }
+}
+
+#[wasm_bindgen(start)]
+pub fn run_app() -> Result<(), JsValue> {
+    utils::set_panic_hook();
+    tracing_wasm::set_as_global_default_with_config(
+        WASMLayerConfigBuilder::new()
+            .set_max_level(tracing::Level::INFO)
+            .build(),
+    );
+    start_websocket()
+}
@@ -0,0 +1,399 @@
+// The telnet protocol and its option negotiation
+//     https://www.rfc-editor.org/rfc/rfc854
+//     https://www.rfc-editor.org/rfc/rfc855
+//
+// Nothing here depends on the browser, the bytes from the server are fed in
+// and the data for the terminal comes out, the replies are queued
+
+use tracing::{info, trace};
+
+// Commands
+pub const SE: u8 = 240;
+pub const NOP: u8 = 241;
+pub const SB: u8 = 250;
+pub const WILL: u8 = 251;
+pub const WONT: u8 = 252;
+pub const DO: u8 = 253;
+pub const DONT: u8 = 254;
+pub const IAC: u8 = 255;
+
+// Options
+//     https://www.iana.org/assignments/telnet-options
+pub const BINARY: u8 = 0;
+pub const ECHO: u8 = 1;
+pub const SGA: u8 = 3;
+pub const TTYPE: u8 = 24;
+pub const NAWS: u8 = 31;
+
+// The subnegotiation of TTYPE
+//     https://www.rfc-editor.org/rfc/rfc1091
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+// What the server may do, and what we do
+const REMOTE_OPTIONS: [u8; 3] = [BINARY, ECHO, SGA];
+const LOCAL_OPTIONS: [u8; 4] = [BINARY, SGA, TTYPE, NAWS];
+
+// A subnegotiation longer than that is not one we know
+const MAX_SUBNEGOTIATION: usize = 1024;
+
+// The state of an option on one side, a request we sent is not answered
+#[derive(Debug, Clone, Copy, Default)]
+struct Opt {
+    enabled: bool,
+    asked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Data,
+    // the CR of the server, the NUL after it is dropped
+    Cr,
+    Iac,
+    // WILL, WONT, DO or DONT, waiting for the option
+    Negotiate(u8),
+    Sb,
+    SbIac,
+}
+
+pub struct Telnet {
+    state: State,
+    // the options of the server, and ours
+    remote: [Opt; 256],
+    local: [Opt; 256],
+    subnegotiation: Vec<u8>,
+    // the names sent for TTYPE, the last one is repeated
+    terminal_types: Vec<String>,
+    terminal_type: usize,
+    size: (u16, u16),
+    output: Vec<u8>,
+}
+
+impl Telnet {
+    /// Start as the terminal `terminal_types` (most specific first) of
+    /// (cols, rows), the options we want are asked for at once
+    pub fn new(terminal_types: &[&str], cols: u16, rows: u16) -> Self {
+        let mut telnet = Self {
+            state: State::Data,
+            remote: [Opt::default(); 256],
+            local: [Opt::default(); 256],
+            subnegotiation: Vec::new(),
+            terminal_types: terminal_types.iter().map(|t| t.to_string()).collect(),
+            terminal_type: 0,
+            size: (cols, rows),
+            output: Vec::new(),
+        };
+        telnet.ask_remote(SGA);
+        telnet.ask_local(TTYPE);
+        telnet.ask_local(NAWS);
+        telnet
+    }
+
+    /// Whether the server echoes what is typed, otherwise we do
+    pub fn remote_echo(&self) -> bool {
+        self.remote[ECHO as usize].enabled
+    }
+
+    /// Whether the server sends 8 bit data, as opposed to NVT ASCII
+    pub fn remote_binary(&self) -> bool {
+        self.remote[BINARY as usize].enabled
+    }
+
+    /// Whether we send 8 bit data, as opposed to NVT ASCII
+    pub fn local_binary(&self) -> bool {
+        self.local[BINARY as usize].enabled
+    }
+
+    /// Take the bytes from the server, the data among them is returned
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            self.state = match (self.state, b) {
+                (State::Data | State::Cr, IAC) => State::Iac,
+                // a bare CR is sent as CR NUL
+                (State::Cr, 0) if !self.remote_binary() => State::Data,
+                (State::Data | State::Cr, b'\r') => {
+                    data.push(b);
+                    State::Cr
+                }
+                (State::Data | State::Cr, b) => {
+                    data.push(b);
+                    State::Data
+                }
+                (State::Iac, IAC) => {
+                    data.push(IAC);
+                    State::Data
+                }
+                (State::Iac, WILL | WONT | DO | DONT) => State::Negotiate(b),
+                (State::Iac, SB) => {
+                    self.subnegotiation.clear();
+                    State::Sb
+                }
+                // NOP, GA, the data mark and the like mean nothing to us
+                (State::Iac, command) => {
+                    trace!("Telnet command {}", command);
+                    State::Data
+                }
+                (State::Negotiate(command), option) => {
+                    self.negotiate(command, option);
+                    State::Data
+                }
+                (State::Sb, IAC) => State::SbIac,
+                (State::Sb, b) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION {
+                        self.subnegotiation.push(b);
+                    }
+                    State::Sb
+                }
+                (State::SbIac, SE) => {
+                    self.subnegotiate();
+                    State::Data
+                }
+                (State::SbIac, IAC) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION {
+                        self.subnegotiation.push(IAC);
+                    }
+                    State::Sb
+                }
+                // not a valid end, the subnegotiation is given up
+                (State::SbIac, _) => State::Data,
+            };
+        }
+        data
+    }
+
+    /// Queue what the user typed, IAC is doubled and a bare CR is followed
+    /// by NUL unless we send binary data
+    pub fn send(&mut self, data: &[u8]) {
+        let binary = self.local_binary();
+        for (i, &b) in data.iter().enumerate() {
+            self.output.push(b);
+            if b == IAC {
+                self.output.push(IAC);
+            } else if b == b'\r' && !binary && data.get(i + 1) != Some(&b'\n') {
+                self.output.push(0);
+            }
+        }
+    }
+
+    /// The terminal is (cols, rows) now, told to the server if it asked
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        if self.size != (cols, rows) {
+            self.size = (cols, rows);
+            if self.local[NAWS as usize].enabled {
+                self.send_size();
+            }
+        }
+    }
+
+    /// The bytes to send to the server
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    fn command(&mut self, command: u8, option: u8) {
+        trace!("Telnet sent {} {}", command, option);
+        self.output.extend_from_slice(&[IAC, command, option]);
+    }
+
+    fn ask_remote(&mut self, option: u8) {
+        self.remote[option as usize].asked = true;
+        self.command(DO, option);
+    }
+
+    fn ask_local(&mut self, option: u8) {
+        self.local[option as usize].asked = true;
+        self.command(WILL, option);
+    }
+
+    // A reply is only sent when the state of the option changes, the
+    // answers to our requests are not replied
+    //     https://www.rfc-editor.org/rfc/rfc854#page-3
+    fn negotiate(&mut self, command: u8, option: u8) {
+        trace!("Telnet received {} {}", command, option);
+        let (opt, supported, accept, refuse) = match command {
+            WILL | WONT => (
+                &mut self.remote[option as usize],
+                REMOTE_OPTIONS.contains(&option),
+                DO,
+                DONT,
+            ),
+            _ => (
+                &mut self.local[option as usize],
+                LOCAL_OPTIONS.contains(&option),
+                WILL,
+                WONT,
+            ),
+        };
+        let asked = std::mem::take(&mut opt.asked);
+        let reply = match command {
+            WILL | DO if opt.enabled => None,
+            WILL | DO if asked || supported => {
+                opt.enabled = true;
+                (!asked).then_some(accept)
+            }
+            WILL | DO => Some(refuse),
+            _ if opt.enabled => {
+                opt.enabled = false;
+                (!asked).then_some(refuse)
+            }
+            _ => None,
+        };
+        if let Some(reply) = reply {
+            self.command(reply, option);
+        }
+        if command == DO && option == NAWS && self.local[NAWS as usize].enabled {
+            self.send_size();
+        }
+        if matches!(command, WILL | WONT) && option == ECHO {
+            info!("Remote echo {}", self.remote_echo());
+        }
+    }
+
+    fn subnegotiate(&mut self) {
+        match self.subnegotiation.as_slice() {
+            [TTYPE, TTYPE_SEND] if self.local[TTYPE as usize].enabled => {
+                let name = match self.terminal_types.get(self.terminal_type) {
+                    Some(name) => {
+                        self.terminal_type += 1;
+                        name.clone()
+                    }
+                    None => self.terminal_types.last().cloned().unwrap_or_default(),
+                };
+                let mut reply = vec![TTYPE, TTYPE_IS];
+                reply.extend_from_slice(name.as_bytes());
+                self.subnegotiation_reply(&reply);
+            }
+            other => trace!("Telnet subnegotiation {:?}", other),
+        }
+    }
+
+    // The window size
+    //     https://www.rfc-editor.org/rfc/rfc1073
+    fn send_size(&mut self) {
+        let (cols, rows) = self.size;
+        let mut reply = vec![NAWS];
+        reply.extend_from_slice(&cols.to_be_bytes());
+        reply.extend_from_slice(&rows.to_be_bytes());
+        self.subnegotiation_reply(&reply);
+    }
+
+    fn subnegotiation_reply(&mut self, payload: &[u8]) {
+        self.output.extend_from_slice(&[IAC, SB]);
+        for &b in payload {
+            self.output.push(b);
+            if b == IAC {
+                self.output.push(IAC);
+            }
+        }
+        self.output.extend_from_slice(&[IAC, SE]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiation() {
+        let mut telnet = Telnet::new(&["XTERM-256COLOR", "VT100"], 80, 24);
+        assert_eq!(
+            telnet.take_output(),
+            [IAC, DO, SGA, IAC, WILL, TTYPE, IAC, WILL, NAWS]
+        );
+
+        // the answers to our requests are not replied, the size follows
+        assert!(telnet
+            .feed(&[IAC, WILL, SGA, IAC, DO, TTYPE, IAC, DO, NAWS])
+            .is_empty());
+        assert_eq!(telnet.take_output(), [IAC, SB, NAWS, 0, 80, 0, 24, IAC, SE]);
+
+        // the server echoes, asked again it is not replied
+        assert!(!telnet.remote_echo());
+        telnet.feed(&[IAC, WILL, ECHO]);
+        assert_eq!(telnet.take_output(), [IAC, DO, ECHO]);
+        assert!(telnet.remote_echo());
+        telnet.feed(&[IAC, WILL, ECHO]);
+        assert!(telnet.take_output().is_empty());
+        telnet.feed(&[IAC, WONT, ECHO]);
+        assert_eq!(telnet.take_output(), [IAC, DONT, ECHO]);
+        assert!(!telnet.remote_echo());
+
+        // unknown options are refused, refusing them again is not replied
+        telnet.feed(&[IAC, DO, 39, IAC, WILL, 5]);
+        assert_eq!(telnet.take_output(), [IAC, WONT, 39, IAC, DONT, 5]);
+        telnet.feed(&[IAC, DONT, 39, IAC, WONT, 5]);
+        assert!(telnet.take_output().is_empty());
+
+        // binary both ways
+        telnet.feed(&[IAC, DO, BINARY, IAC, WILL, BINARY]);
+        assert_eq!(telnet.take_output(), [IAC, WILL, BINARY, IAC, DO, BINARY]);
+        assert!(telnet.local_binary() && telnet.remote_binary());
+
+        // the terminal types in turn, the last one is repeated
+        let send = [IAC, SB, TTYPE, TTYPE_SEND, IAC, SE];
+        for name in ["XTERM-256COLOR", "VT100", "VT100"] {
+            telnet.feed(&send);
+            let mut expected = vec![IAC, SB, TTYPE, TTYPE_IS];
+            expected.extend_from_slice(name.as_bytes());
+            expected.extend_from_slice(&[IAC, SE]);
+            assert_eq!(telnet.take_output(), expected);
+        }
+
+        // the size is sent again when it changes only, IAC is doubled
+        telnet.resize(80, 24);
+        assert!(telnet.take_output().is_empty());
+        telnet.resize(255, 30);
+        assert_eq!(
+            telnet.take_output(),
+            [IAC, SB, NAWS, 0, IAC, IAC, 0, 30, IAC, SE]
+        );
+    }
+
+    #[test]
+    fn test_refused() {
+        let mut telnet = Telnet::new(&["XTERM"], 80, 24);
+        telnet.take_output();
+        telnet.feed(&[IAC, DONT, NAWS, IAC, WONT, SGA]);
+        assert!(telnet.take_output().is_empty());
+        // no size is sent for a refused NAWS
+        telnet.resize(100, 40);
+        assert!(telnet.take_output().is_empty());
+        // asked by the server later on
+        telnet.feed(&[IAC, DO, NAWS]);
+        assert_eq!(
+            telnet.take_output(),
+            [IAC, WILL, NAWS, IAC, SB, NAWS, 0, 100, 0, 40, IAC, SE]
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        let mut telnet = Telnet::new(&["XTERM"], 80, 24);
+        telnet.take_output();
+        // the commands are stripped, even split across reads
+        assert_eq!(telnet.feed(b"login:"), b"login:");
+        assert_eq!(telnet.feed(&[b'a', IAC]), b"a");
+        assert_eq!(telnet.feed(&[NOP, b'b', IAC]), b"b");
+        assert_eq!(telnet.feed(&[IAC, b'c', IAC, SB, TTYPE]), [IAC, b'c']);
+        assert_eq!(telnet.feed(&[7, IAC, IAC, IAC, SE, b'd']), b"d");
+        assert_eq!(telnet.feed(&[IAC, DO]), b"");
+        assert_eq!(telnet.feed(&[ECHO, b'e']), b"e");
+        // we do not echo
+        assert_eq!(telnet.take_output(), [IAC, WONT, ECHO]);
+
+        // CR NUL is a bare CR, CR LF is kept
+        assert_eq!(telnet.feed(b"\r\0x\r\ny\r"), b"\rx\r\ny\r");
+        assert_eq!(telnet.feed(b"\0z"), b"z");
+
+        telnet.send(&[b'a', IAC, b'\r', b'\r', b'\n']);
+        assert_eq!(
+            telnet.take_output(),
+            [b'a', IAC, IAC, b'\r', 0, b'\r', b'\n']
+        );
+        telnet.feed(&[IAC, DO, BINARY]);
+        telnet.take_output();
+        telnet.send(b"\r");
+        assert_eq!(telnet.take_output(), b"\r");
+    }
+}
@@ -0,0 +1,545 @@
+use crate::terminal::{
+    grid::{self, palette, Attr, Color, Flags},
+    input::{KeyInput, MouseAction, MouseInput},
+    Highlight, Terminal,
+};
+
+use std::{cell::Cell, rc::Rc};
+use tokio::sync::mpsc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement, KeyboardEvent, MouseEvent,
+    ResizeObserver, WheelEvent,
+};
+
+const FONT_SIZE: f64 = 15.0;
+const FONT_FAMILY: &str = "Menlo, Consolas, \"DejaVu Sans Mono\", monospace";
+const LINE_HEIGHT: f64 = 1.2;
+
+const DEFAULT_FG: (u8, u8, u8) = (0xe5, 0xe5, 0xe5);
+const DEFAULT_BG: (u8, u8, u8) = (0x00, 0x00, 0x00);
+const MATCH_BG: (u8, u8, u8) = (0xe5, 0xe5, 0x00);
+const CURRENT_MATCH_BG: (u8, u8, u8) = (0xff, 0x80, 0x00);
+
+#[wasm_bindgen]
+extern "C" {
+    fn getClipBoard() -> String;
+}
+
+fn css((r, g, b): (u8, u8, u8)) -> String {
+    format!("rgb({},{},{})", r, g, b)
+}
+
+/// Input from the page to the session
+#[derive(Debug, Clone)]
+pub enum CanvasEvent {
+    Key(KeyInput),
+    Mouse(MouseInput),
+    /// Text pasted from the clipboard panel
+    Paste(String),
+    /// The page fits a terminal of (cols, rows) now
+    Resize(usize, usize),
+}
+
+/// What differs between the pages the terminal is on, the events of the
+/// canvas are sent as `E`, along with those of the rest of the page
+pub struct Options<E> {
+    /// The id of the canvas, in an element of id `canvas`
+    pub canvas_id: &'static str,
+    /// Opens the find bar on Ctrl+Shift+F, if the page has one
+    pub find: Option<fn(&mpsc::Sender<E>)>,
+}
+
+struct Canvas<E> {
+    container: HtmlElement,
+    canvas: HtmlCanvasElement,
+    ctx: CanvasRenderingContext2d,
+    char_width: f64,
+    char_height: f64,
+    // where the cursor was drawn last time
+    cursor: Cell<Option<(usize, usize)>>,
+    // the size in cells
+    size: Cell<(usize, usize)>,
+    // the wheel delta not scrolled yet, in lines
+    wheel: Cell<f64>,
+    // the cell of the last mouse event, moves within a cell are not sent
+    mouse_cell: Cell<(usize, usize)>,
+    output: mpsc::Sender<E>,
+    find: Option<fn(&mpsc::Sender<E>)>,
+}
+
+impl<E: From<CanvasEvent> + 'static> Canvas<E> {
+    fn new(options: Options<E>, sender: mpsc::Sender<E>) -> Self {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let container = document
+            .get_element_by_id("canvas")
+            .unwrap()
+            .dyn_into::<HtmlElement>()
+            .unwrap();
+        let canvas = document.get_element_by_id(options.canvas_id).unwrap();
+        let canvas: HtmlCanvasElement = canvas
+            .dyn_into::<HtmlCanvasElement>()
+            .map_err(|_| ())
+            .unwrap();
+        let ctx = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        ctx.set_font(&Self::font(false, false));
+        let char_width = ctx.measure_text("W").unwrap().width().ceil();
+        let char_height = (FONT_SIZE * LINE_HEIGHT).ceil();
+        Self {
+            container,
+            canvas,
+            ctx,
+            char_width,
+            char_height,
+            cursor: Cell::new(None),
+            size: Cell::new((0, 0)),
+            wheel: Cell::new(0.0),
+            mouse_cell: Cell::new((usize::MAX, usize::MAX)),
+            output: sender,
+            find: options.find,
+        }
+    }
+
+    fn font(bold: bool, italic: bool) -> String {
+        format!(
+            "{}{}{}px {}",
+            if italic { "italic " } else { "" },
+            if bold { "bold " } else { "" },
+            FONT_SIZE,
+            FONT_FAMILY
+        )
+    }
+
+    /// How many cells fit in the container, down to the bottom of the page
+    fn fit(&self) -> (usize, usize) {
+        let window = web_sys::window().unwrap();
+        let width = self.container.client_width() as f64;
+        let height = window.inner_height().unwrap().as_f64().unwrap()
+            - self.canvas.get_bounding_client_rect().top();
+        (
+            ((width / self.char_width) as usize).max(1),
+            ((height / self.char_height) as usize).max(1),
+        )
+    }
+
+    fn set_size(&self, cols: usize, rows: usize) {
+        self.size.set((cols, rows));
+        self.canvas
+            .set_width((cols as f64 * self.char_width) as u32);
+        self.canvas
+            .set_height((rows as f64 * self.char_height) as u32);
+        // the context is reset with the size
+        self.ctx.set_text_baseline("middle");
+        self.ctx.set_fill_style_str(&css(DEFAULT_BG));
+        self.ctx.fill_rect(
+            0.0,
+            0.0,
+            self.canvas.width() as f64,
+            self.canvas.height() as f64,
+        );
+        self.cursor.set(None);
+    }
+
+    fn bind(self: &Rc<Self>) {
+        let sender = self.output.clone();
+        let find = self.find;
+        let key_down = move |e: KeyboardEvent| {
+            // leave the shortcuts of the browser alone
+            if e.meta_key() {
+                return;
+            }
+            e.prevent_default();
+            e.stop_propagation();
+            if let Some(find) = find.filter(|_| e.ctrl_key() && e.shift_key() && e.code() == "KeyF")
+            {
+                find(&sender);
+                return;
+            }
+            let sender = sender.clone();
+            let input = KeyInput {
+                key: e.key(),
+                code: e.code(),
+                ctrl: e.ctrl_key(),
+                alt: e.alt_key(),
+                shift: e.shift_key(),
+                meta: e.meta_key(),
+            };
+            futures::executor::block_on(async move {
+                let _ = sender.send(CanvasEvent::Key(input).into()).await;
+            });
+        };
+
+        let handler = Box::new(key_down) as Box<dyn FnMut(_)>;
+
+        let cb = Closure::wrap(handler);
+
+        self.canvas
+            .add_event_listener_with_callback("keydown", cb.as_ref().unchecked_ref())
+            .unwrap();
+        cb.forget();
+
+        self.bind_mouse();
+        self.bind_paste();
+    }
+
+    fn bind_paste(self: &Rc<Self>) {
+        let this = self.clone();
+        let paste = move || {
+            this.send(CanvasEvent::Paste(getClipBoard()));
+            this.focus();
+        };
+
+        let handler = Box::new(paste) as Box<dyn FnMut()>;
+
+        let cb = Closure::wrap(handler);
+
+        let document = web_sys::window().unwrap().document().unwrap();
+        if let Some(button) = document.get_element_by_id("clipboardsend") {
+            button
+                .add_event_listener_with_callback("click", cb.as_ref().unchecked_ref())
+                .unwrap();
+        }
+        cb.forget();
+    }
+
+    fn mouse_input(&self, action: MouseAction, e: &MouseEvent) -> MouseInput {
+        let (row, col) = self.cell(e);
+        MouseInput {
+            action,
+            button: e.button() as u16,
+            buttons: e.buttons(),
+            row,
+            col,
+            ctrl: e.ctrl_key(),
+            alt: e.alt_key(),
+            shift: e.shift_key(),
+        }
+    }
+
+    // The cell under the mouse
+    fn cell(&self, e: &MouseEvent) -> (usize, usize) {
+        let rect = self.canvas.get_bounding_client_rect();
+        let (cols, rows) = self.size.get();
+        let x = (e.client_x() as f64 - rect.left()).max(0.0) / self.char_width;
+        let y = (e.client_y() as f64 - rect.top()).max(0.0) / self.char_height;
+        (
+            (y as usize).min(rows.saturating_sub(1)),
+            (x as usize).min(cols.saturating_sub(1)),
+        )
+    }
+
+    fn send(&self, event: CanvasEvent) {
+        let sender = self.output.clone();
+        futures::executor::block_on(async move {
+            let _ = sender.send(event.into()).await;
+        });
+    }
+
+    fn bind_mouse(self: &Rc<Self>) {
+        // a release outside of the canvas still ends a drag
+        for (event, action) in [
+            ("mousedown", MouseAction::Press),
+            ("mousemove", MouseAction::Move),
+            ("mouseup", MouseAction::Release),
+        ] {
+            let this = self.clone();
+            let mouse = move |e: MouseEvent| {
+                if action == MouseAction::Press {
+                    this.focus();
+                    // no text of the page is selected by a drag
+                    e.prevent_default();
+                }
+                let input = this.mouse_input(action, &e);
+                let cell = (input.row, input.col);
+                if action == MouseAction::Move && cell == this.mouse_cell.replace(cell) {
+                    return;
+                }
+                this.send(CanvasEvent::Mouse(input));
+            };
+
+            let handler = Box::new(mouse) as Box<dyn FnMut(_)>;
+
+            let cb = Closure::wrap(handler);
+
+            if action == MouseAction::Release {
+                web_sys::window()
+                    .unwrap()
+                    .add_event_listener_with_callback(event, cb.as_ref().unchecked_ref())
+                    .unwrap();
+            } else {
+                self.canvas
+                    .add_event_listener_with_callback(event, cb.as_ref().unchecked_ref())
+                    .unwrap();
+            }
+            cb.forget();
+        }
+
+        let this = self.clone();
+        let wheel = move |e: WheelEvent| {
+            e.prevent_default();
+            let lines = match e.delta_mode() {
+                WheelEvent::DOM_DELTA_PIXEL => e.delta_y() / this.char_height,
+                WheelEvent::DOM_DELTA_LINE => e.delta_y(),
+                _ => e.delta_y() * this.size.get().1 as f64,
+            };
+            // touchpads send many small deltas
+            let lines = this.wheel.get() + lines;
+            let whole = lines.trunc();
+            this.wheel.set(lines - whole);
+            if whole != 0.0 {
+                let input = this.mouse_input(MouseAction::Wheel(-whole as isize), &e);
+                this.send(CanvasEvent::Mouse(input));
+            }
+        };
+
+        let handler = Box::new(wheel) as Box<dyn FnMut(_)>;
+
+        let cb = Closure::wrap(handler);
+
+        self.canvas
+            .add_event_listener_with_callback("wheel", cb.as_ref().unchecked_ref())
+            .unwrap();
+        cb.forget();
+    }
+
+    // Both the window and the container may change their size
+    fn bind_resize(self: &Rc<Self>) {
+        let this = self.clone();
+        let resize = move || {
+            let size = this.fit();
+            if size != this.size.get() {
+                // only reported once per new size
+                this.size.set(size);
+                let sender = this.output.clone();
+                futures::executor::block_on(async move {
+                    let _ = sender
+                        .send(CanvasEvent::Resize(size.0, size.1).into())
+                        .await;
+                });
+            }
+        };
+
+        let handler = Box::new(resize) as Box<dyn FnMut()>;
+
+        let cb = Closure::wrap(handler);
+
+        web_sys::window()
+            .unwrap()
+            .add_event_listener_with_callback("resize", cb.as_ref().unchecked_ref())
+            .unwrap();
+        if let Ok(observer) = ResizeObserver::new(cb.as_ref().unchecked_ref()) {
+            observer.observe(&self.container);
+        }
+        cb.forget();
+    }
+
+    fn focus(&self) {
+        let _ = self.canvas.focus();
+    }
+
+    fn colors(attr: &Attr, reverse_video: bool, highlight: Option<Highlight>) -> (String, String) {
+        let (default_fg, default_bg) = if reverse_video {
+            (DEFAULT_BG, DEFAULT_FG)
+        } else {
+            (DEFAULT_FG, DEFAULT_BG)
+        };
+        let mut fg = match attr.fg {
+            Color::Default => default_fg,
+            // bold is rendered as bright for the ANSI colours
+            Color::Indexed(i) if i < 8 && attr.flags.contains(Flags::BOLD) => palette(i + 8),
+            Color::Indexed(i) => palette(i),
+            Color::Rgb(r, g, b) => (r, g, b),
+        };
+        let mut bg = match attr.bg {
+            Color::Default => default_bg,
+            Color::Indexed(i) => palette(i),
+            Color::Rgb(r, g, b) => (r, g, b),
+        };
+        if attr.flags.contains(Flags::INVERSE) != (highlight == Some(Highlight::Selection)) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        match highlight {
+            Some(Highlight::Match) => (fg, bg) = (DEFAULT_BG, MATCH_BG),
+            Some(Highlight::CurrentMatch) => (fg, bg) = (DEFAULT_BG, CURRENT_MATCH_BG),
+            _ => (),
+        }
+        if attr.flags.contains(Flags::FAINT) {
+            fg = (
+                fg.0 / 2 + bg.0 / 2,
+                fg.1 / 2 + bg.1 / 2,
+                fg.2 / 2 + bg.2 / 2,
+            );
+        }
+        if attr.flags.contains(Flags::HIDDEN) {
+            fg = bg;
+        }
+        (css(fg), css(bg))
+    }
+
+    fn draw_cell(
+        &self,
+        y: usize,
+        x: usize,
+        cell: &grid::Cell,
+        attr: &Attr,
+        reverse_video: bool,
+        highlight: Option<Highlight>,
+    ) {
+        let (fg, bg) = Self::colors(attr, reverse_video, highlight);
+        let (px, py) = (x as f64 * self.char_width, y as f64 * self.char_height);
+        // a wide character covers its right half as well
+        let width = self.char_width * cell.width.max(1) as f64;
+        self.ctx.set_fill_style_str(&bg);
+        self.ctx.fill_rect(px, py, width, self.char_height);
+        self.ctx.set_fill_style_str(&fg);
+        if cell.c != ' ' && !cell.is_spacer() {
+            self.ctx.set_font(&Self::font(
+                attr.flags.contains(Flags::BOLD),
+                attr.flags.contains(Flags::ITALIC),
+            ));
+            let _ = self.ctx.fill_text_with_max_width(
+                &cell.text(),
+                px,
+                py + self.char_height / 2.0,
+                width,
+            );
+        }
+        if attr.flags.contains(Flags::UNDERLINE) || attr.link != 0 {
+            self.ctx
+                .fill_rect(px, py + self.char_height - 2.0, width, 1.0);
+        }
+        if attr.flags.contains(Flags::STRIKE) {
+            self.ctx
+                .fill_rect(px, py + self.char_height / 2.0, width, 1.0);
+        }
+    }
+
+    fn draw_row(&self, term: &Terminal, y: usize) {
+        let reverse_video = term.screen().modes().reverse_video;
+        let highlights = term.highlights(y);
+        let cells = &term.visible_row(y).cells;
+        for (x, cell) in cells.iter().enumerate() {
+            // drawn with the wide character on its left
+            if cell.is_spacer() && x > 0 && cells[x - 1].width == 2 {
+                continue;
+            }
+            let wide = (cell.width == 2) as usize;
+            // the last highlight wins, the matches over the selection
+            let highlight = highlights
+                .iter()
+                .rev()
+                .find(|(start, end, _)| *start <= x + wide && x < *end)
+                .map(|(_, _, highlight)| *highlight);
+            self.draw_cell(y, x, cell, &cell.attr, reverse_video, highlight);
+        }
+    }
+
+    fn draw_cursor(&self, term: &Terminal) {
+        let screen = term.screen();
+        let (y, mut x) = screen.cursor();
+        let cells = &screen.grid().row(y).cells;
+        if cells[x].is_spacer() && x > 0 && cells[x - 1].width == 2 {
+            x -= 1;
+        }
+        let cell = cells[x];
+        let (fg, _) = Self::colors(&cell.attr, screen.modes().reverse_video, None);
+        let (px, py) = (x as f64 * self.char_width, y as f64 * self.char_height);
+        match screen.cursor_style() {
+            // underline
+            3 | 4 => {
+                self.ctx.set_fill_style_str(&fg);
+                self.ctx
+                    .fill_rect(px, py + self.char_height - 2.0, self.char_width, 2.0);
+            }
+            // bar
+            5 | 6 => {
+                self.ctx.set_fill_style_str(&fg);
+                self.ctx.fill_rect(px, py, 2.0, self.char_height);
+            }
+            // block
+            _ => {
+                let mut attr = cell.attr;
+                if attr.flags.contains(Flags::INVERSE) {
+                    attr.flags.remove(Flags::INVERSE)
+                } else {
+                    attr.flags.insert(Flags::INVERSE)
+                }
+                self.draw_cell(y, x, &cell, &attr, screen.modes().reverse_video, None);
+            }
+        }
+        self.cursor.set(Some((y, x)));
+    }
+
+    fn render(&self, term: &mut Terminal) {
+        let mut dirty = term.screen_mut().grid_mut().take_dirty();
+        // the rows are not where the grid has them when scrolled back, and
+        // the highlights move with the text
+        let scrolled = term.view().offset() > 0 || term.view().has_highlights();
+        if term.take_view_changed() || (scrolled && !dirty.is_empty()) {
+            dirty = (0..term.screen().rows()).collect();
+        }
+        // the cell under the old cursor has to be restored
+        if let Some((y, _)) = self.cursor.take() {
+            if y < term.screen().rows() && !dirty.contains(&y) {
+                dirty.push(y);
+            }
+        }
+        for y in dirty {
+            self.draw_row(term, y);
+        }
+        if term.screen().modes().show_cursor && term.view().offset() == 0 {
+            self.draw_cursor(term);
+        }
+    }
+}
+
+pub struct CanvasUtils<E> {
+    inner: Rc<Canvas<E>>,
+    bind: bool,
+}
+
+impl<E> Clone for CanvasUtils<E> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            bind: self.bind,
+        }
+    }
+}
+
+impl<E: From<CanvasEvent> + 'static> CanvasUtils<E> {
+    pub fn new(options: Options<E>, sender: mpsc::Sender<E>) -> Self {
+        Self {
+            inner: Rc::new(Canvas::new(options, sender)),
+            bind: false,
+        }
+    }
+
+    /// The terminal size (cols, rows) fitting the page
+    pub fn fit(&self) -> (usize, usize) {
+        self.inner.as_ref().fit()
+    }
+
+    pub fn init(&mut self, cols: usize, rows: usize) {
+        self.inner.as_ref().set_size(cols, rows);
+        if !self.bind {
+            self.inner.bind();
+            self.inner.bind_resize();
+            self.bind = true;
+        }
+    }
+
+    pub fn focus(&self) {
+        self.inner.as_ref().focus();
+    }
+
+    pub fn render(&self, term: &mut Terminal) {
+        self.inner.as_ref().render(term);
+    }
+}
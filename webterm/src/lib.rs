@@ -0,0 +1,5 @@
+//! The xterm compatible terminal of webssh and webtelnet, and the canvas it
+//! is drawn to
+
+pub mod canvas;
+pub mod terminal;
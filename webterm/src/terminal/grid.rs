@@ -0,0 +1,581 @@
+// The screen model, a grid of cells
+//
+// Row 0 is the top of the screen, column 0 is the left. A wide character
+// takes two cells, the second one is left empty with a width of 0
+
+use std::{cell::RefCell, collections::HashMap};
+
+// The grapheme clusters of more than one character, a char and its
+// combining marks or an emoji sequence, shared by all the terminals. The
+// later ones only show their first character
+const MAX_CLUSTERS: usize = 1 << 16;
+
+#[derive(Default)]
+struct Clusters {
+    strings: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+thread_local! {
+    static CLUSTERS: RefCell<Clusters> = RefCell::default();
+}
+
+// The id of the cluster `s`, 0 if there are too many
+fn intern(s: &str) -> u32 {
+    CLUSTERS.with(|clusters| {
+        let mut clusters = clusters.borrow_mut();
+        if let Some(id) = clusters.ids.get(s) {
+            return *id;
+        }
+        if clusters.strings.len() >= MAX_CLUSTERS {
+            return 0;
+        }
+        clusters.strings.push(s.to_owned());
+        let id = clusters.strings.len() as u32;
+        clusters.ids.insert(s.to_owned(), id);
+        id
+    })
+}
+
+/// Colour of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    #[default]
+    Default,
+    /// The 256 colours palette, 0 ~ 15 are the ANSI colours
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// The default xterm palette of the 16 ANSI colours
+const ANSI_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// Resolve an entry of the 256 colours palette to rgb
+pub fn palette(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        // 6x6x6 colour cube
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = index - 16;
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        // grayscale ramp
+        232..=255 => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+/// Rendition flags set by SGR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags(u16);
+
+impl Flags {
+    pub const BOLD: Flags = Flags(1);
+    pub const FAINT: Flags = Flags(1 << 1);
+    pub const ITALIC: Flags = Flags(1 << 2);
+    pub const UNDERLINE: Flags = Flags(1 << 3);
+    pub const BLINK: Flags = Flags(1 << 4);
+    pub const INVERSE: Flags = Flags(1 << 5);
+    pub const HIDDEN: Flags = Flags(1 << 6);
+    pub const STRIKE: Flags = Flags(1 << 7);
+
+    pub fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Flags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Flags) {
+        self.0 &= !other.0;
+    }
+}
+
+/// Graphic rendition of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attr {
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+    /// The hyperlink of the cell, see [Screen::link](super::Screen::link),
+    /// 0 if none
+    pub link: u32,
+}
+
+impl Attr {
+    /// The attribute used to erase cells, only the background is kept
+    pub fn erased(&self) -> Self {
+        Self {
+            bg: self.bg,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// The first character of the cell
+    pub c: char,
+    pub attr: Attr,
+    /// 2 for a wide character, 0 for the cell on its right
+    pub width: u8,
+    // the whole cluster when there are more characters, 0 if none
+    cluster: u32,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self::blank(Attr::default())
+    }
+}
+
+impl Cell {
+    pub fn blank(attr: Attr) -> Self {
+        Self {
+            c: ' ',
+            attr,
+            width: 1,
+            cluster: 0,
+        }
+    }
+
+    pub fn new(c: char, attr: Attr, width: u8) -> Self {
+        Self {
+            c,
+            attr,
+            width,
+            cluster: 0,
+        }
+    }
+
+    /// The right half of a wide character
+    pub fn is_spacer(&self) -> bool {
+        self.width == 0
+    }
+
+    /// Append `c` to the characters of the cell, like a combining mark
+    pub fn combine(&mut self, c: char) {
+        let mut text = self.text();
+        text.push(c);
+        let id = intern(&text);
+        if id != 0 {
+            self.cluster = id;
+        }
+    }
+
+    /// Append the characters of the cell to `text`
+    pub fn push_to(&self, text: &mut String) {
+        if self.cluster == 0 {
+            text.push(self.c);
+            return;
+        }
+        CLUSTERS.with(|clusters| {
+            let clusters = clusters.borrow();
+            match clusters.strings.get(self.cluster as usize - 1) {
+                Some(s) => text.push_str(s),
+                None => text.push(self.c),
+            }
+        });
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.push_to(&mut text);
+        text
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// The line continues on the next row because of auto wrapping
+    pub wrapped: bool,
+    dirty: bool,
+}
+
+impl Row {
+    pub fn new(cols: usize, attr: Attr) -> Self {
+        Self {
+            cells: vec![Cell::blank(attr); cols],
+            wrapped: false,
+            dirty: true,
+        }
+    }
+
+    /// The text of the row without the trailing blanks
+    pub fn text(&self) -> String {
+        self.text_range(0, self.cells.len()).trim_end().to_owned()
+    }
+
+    /// The text of the cells `from..to`, with the whole wide character if
+    /// `from` is its right half
+    pub fn text_range(&self, from: usize, to: usize) -> String {
+        let to = to.min(self.cells.len());
+        let from = match self.cells.get(from) {
+            Some(cell) if cell.is_spacer() => from.saturating_sub(1),
+            _ => from,
+        };
+        let mut text = String::new();
+        for cell in &self.cells[from.min(to)..to] {
+            if !cell.is_spacer() {
+                cell.push_to(&mut text);
+            }
+        }
+        text
+    }
+
+    /// The text of the row, with the column of each of its bytes and one
+    /// more for its end
+    pub fn text_columns(&self) -> (String, Vec<usize>) {
+        let mut text = String::new();
+        let mut columns = Vec::new();
+        for (x, cell) in self.cells.iter().enumerate() {
+            if !cell.is_spacer() {
+                cell.push_to(&mut text);
+                columns.resize(text.len(), x);
+            }
+        }
+        columns.push(self.cells.len());
+        (text, columns)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn erase(&mut self, from: usize, to: usize, attr: Attr) {
+        let to = to.min(self.cells.len());
+        if from < to {
+            self.cells[from..to].fill(Cell::blank(attr));
+        }
+        self.dirty = true;
+    }
+}
+
+fn is_blank(cells: &[Cell]) -> bool {
+    cells.iter().all(|c| *c == Cell::default())
+}
+
+#[derive(Debug, Clone)]
+pub struct Grid {
+    cols: usize,
+    rows: Vec<Row>,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows: (0..rows).map(|_| Row::new(cols, Attr::default())).collect(),
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn row(&self, y: usize) -> &Row {
+        &self.rows[y]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut Row {
+        let row = &mut self.rows[y];
+        row.dirty = true;
+        row
+    }
+
+    pub fn cell_mut(&mut self, y: usize, x: usize) -> &mut Cell {
+        &mut self.row_mut(y).cells[x]
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.rows.iter_mut().for_each(|r| r.dirty = true);
+    }
+
+    /// Indices of the rows changed since the last call
+    pub fn take_dirty(&mut self) -> Vec<usize> {
+        self.rows
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(y, row)| std::mem::take(&mut row.dirty).then_some(y))
+            .collect()
+    }
+
+    /// Scroll the rows `top..=bottom` up by `n`, blank rows appear at the bottom
+    pub fn scroll_up(&mut self, top: usize, bottom: usize, n: usize, attr: Attr) {
+        let n = n.min(bottom + 1 - top);
+        self.rows[top..=bottom].rotate_left(n);
+        for row in &mut self.rows[bottom + 1 - n..=bottom] {
+            *row = Row::new(self.cols, attr);
+        }
+        self.rows[top..=bottom]
+            .iter_mut()
+            .for_each(|r| r.dirty = true);
+    }
+
+    /// Scroll the rows `top..=bottom` down by `n`, blank rows appear at the top
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, n: usize, attr: Attr) {
+        let n = n.min(bottom + 1 - top);
+        self.rows[top..=bottom].rotate_right(n);
+        for row in &mut self.rows[top..top + n] {
+            *row = Row::new(self.cols, attr);
+        }
+        self.rows[top..=bottom]
+            .iter_mut()
+            .for_each(|r| r.dirty = true);
+    }
+
+    /// Erase the cells `from..to` of row `y`
+    pub fn erase(&mut self, y: usize, from: usize, to: usize, attr: Attr) {
+        self.rows[y].erase(from, to, attr);
+    }
+
+    /// Erase the whole rows `from..to`
+    pub fn erase_rows(&mut self, from: usize, to: usize, attr: Attr) {
+        for row in &mut self.rows[from..to] {
+            *row = Row::new(self.cols, attr);
+        }
+    }
+
+    /// Blank the other half of the wide character at (y, x), if any, before
+    /// (y, x) is written
+    pub fn clear_wide(&mut self, y: usize, x: usize) {
+        let row = self.row_mut(y);
+        let cell = row.cells[x];
+        let other = match cell.width {
+            0 => x.checked_sub(1),
+            2 => Some(x + 1).filter(|x| *x < row.cells.len()),
+            _ => None,
+        };
+        if let Some(other) = other {
+            row.cells[other] = Cell::blank(cell.attr);
+            row.cells[x] = Cell::blank(cell.attr);
+        }
+    }
+
+    /// Insert `n` blank cells at (y, x), cells pushed off the right edge are lost
+    pub fn insert_cells(&mut self, y: usize, x: usize, n: usize, attr: Attr) {
+        let cols = self.cols;
+        let row = self.row_mut(y);
+        let n = n.min(cols - x);
+        row.cells[x..].rotate_right(n);
+        row.cells[x..x + n].fill(Cell::blank(attr));
+    }
+
+    /// Delete `n` cells at (y, x), blank cells appear at the right edge
+    pub fn delete_cells(&mut self, y: usize, x: usize, n: usize, attr: Attr) {
+        let cols = self.cols;
+        let row = self.row_mut(y);
+        let n = n.min(cols - x);
+        row.cells[x..].rotate_left(n);
+        row.cells[cols - n..].fill(Cell::blank(attr));
+    }
+
+    /// Change the size without reflowing, for the alternate screen
+    ///
+    /// Rows are removed from the top only as far as needed to keep the row
+    /// `keep` on screen, the others from the bottom. Returns how many rows
+    /// were removed from the top
+    pub fn resize(&mut self, cols: usize, rows: usize, keep: usize) -> usize {
+        for row in &mut self.rows {
+            row.cells.resize(cols, Cell::default());
+            row.wrapped = false;
+        }
+        self.cols = cols;
+        let top = (keep + 1).saturating_sub(rows).min(self.rows.len());
+        self.rows.drain(..top);
+        self.rows
+            .resize_with(rows, || Row::new(cols, Attr::default()));
+        self.mark_dirty();
+        top
+    }
+
+    /// Change the size, rewrapping the lines which were wrapped automatically
+    ///
+    /// `cursor` is followed to its new position, which is returned along with
+    /// the rows pushed off the top
+    pub fn reflow(
+        &mut self,
+        cols: usize,
+        rows: usize,
+        cursor: (usize, usize),
+    ) -> (Vec<Row>, (usize, usize)) {
+        // join the rows into logical lines, with the offset of the cursor
+        let mut lines: Vec<Vec<Cell>> = Vec::new();
+        let mut line = Vec::new();
+        let mut cursor_at = (0, 0);
+        for (y, row) in self.rows.drain(..).enumerate() {
+            if y == cursor.0 {
+                cursor_at = (lines.len(), line.len() + cursor.1);
+            }
+            line.extend(row.cells);
+            if !row.wrapped {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        // the blank lines below the cursor are not worth keeping
+        while lines.len() > cursor_at.0 + 1 && lines.last().is_some_and(|l| is_blank(l)) {
+            lines.pop();
+        }
+
+        let mut new_rows = Vec::new();
+        let mut new_cursor = (0, 0);
+        for (i, mut line) in lines.into_iter().enumerate() {
+            let mut len = line.len();
+            while len > 0 && line[len - 1] == Cell::default() {
+                len -= 1;
+            }
+            let cursor_col = (i == cursor_at.0).then_some(cursor_at.1);
+            if let Some(col) = cursor_col {
+                len = len.max(col + 1);
+            }
+            line.resize(len.max(1), Cell::default());
+            // a wide character is not split over two rows
+            let mut cells = Vec::with_capacity(cols);
+            for (x, cell) in line.into_iter().enumerate() {
+                if cells.len() == cols || (cell.width == 2 && cells.len() + 1 == cols) {
+                    cells.resize(cols, Cell::default());
+                    new_rows.push(Row {
+                        cells: std::mem::replace(&mut cells, Vec::with_capacity(cols)),
+                        wrapped: true,
+                        dirty: true,
+                    });
+                }
+                if cursor_col == Some(x) {
+                    new_cursor = (new_rows.len(), cells.len());
+                }
+                cells.push(cell);
+            }
+            cells.resize(cols, Cell::default());
+            new_rows.push(Row {
+                cells,
+                wrapped: false,
+                dirty: true,
+            });
+        }
+
+        // remove from the top first, as long as the cursor stays on screen
+        let top = new_rows.len().saturating_sub(rows).min(new_cursor.0);
+        let removed: Vec<Row> = new_rows.drain(..top).collect();
+        new_rows.truncate(rows);
+        new_rows.resize_with(rows, || Row::new(cols, Attr::default()));
+        self.rows = new_rows;
+        self.cols = cols;
+        (removed, (new_cursor.0 - top, new_cursor.1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grid_of(lines: &[&str]) -> Grid {
+        let mut grid = Grid::new(4, lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                grid.cell_mut(y, x).c = c;
+            }
+        }
+        grid
+    }
+
+    fn texts(grid: &Grid) -> Vec<String> {
+        (0..grid.rows()).map(|y| grid.row(y).text()).collect()
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(palette(1), (0xcd, 0, 0));
+        assert_eq!(palette(16), (0, 0, 0));
+        assert_eq!(palette(196), (0xff, 0, 0));
+        assert_eq!(palette(231), (0xff, 0xff, 0xff));
+        assert_eq!(palette(232), (8, 8, 8));
+        assert_eq!(palette(255), (0xee, 0xee, 0xee));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut grid = grid_of(&["a", "b", "c", "d"]);
+        grid.scroll_up(1, 2, 1, Attr::default());
+        assert_eq!(texts(&grid), ["a", "c", "", "d"]);
+        grid.scroll_down(0, 3, 2, Attr::default());
+        assert_eq!(texts(&grid), ["", "", "a", "c"]);
+        grid.take_dirty();
+        grid.scroll_up(0, 3, 9, Attr::default());
+        assert_eq!(texts(&grid), ["", "", "", ""]);
+        assert_eq!(grid.take_dirty(), [0, 1, 2, 3]);
+        assert!(grid.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn test_insert_delete() {
+        let mut grid = grid_of(&["abcd"]);
+        grid.insert_cells(0, 1, 2, Attr::default());
+        assert_eq!(grid.row(0).text(), "a  b");
+        grid.delete_cells(0, 0, 1, Attr::default());
+        assert_eq!(grid.row(0).text(), "  b");
+        grid.erase(0, 2, 4, Attr::default());
+        assert_eq!(grid.row(0).text(), "");
+    }
+
+    #[test]
+    fn test_reflow() {
+        let mut grid = grid_of(&["abcd", "ef", "gh", ""]);
+        grid.rows[0].wrapped = true;
+        let (removed, cursor) = grid.reflow(3, 4, (2, 2));
+        assert!(removed.is_empty());
+        assert_eq!(texts(&grid), ["abc", "def", "gh", ""]);
+        assert_eq!(cursor, (2, 2));
+        assert!(grid.row(0).wrapped && !grid.row(1).wrapped);
+
+        let (removed, cursor) = grid.reflow(6, 1, cursor);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].text(), "abcdef");
+        assert_eq!(texts(&grid), ["gh"]);
+        assert_eq!(cursor, (0, 2));
+
+        // the cursor stays on screen when shrinking
+        let mut grid = grid_of(&["a", "b", "c", "d"]);
+        let (removed, cursor) = grid.reflow(4, 2, (0, 1));
+        assert!(removed.is_empty());
+        assert_eq!(texts(&grid), ["a", "b"]);
+        assert_eq!(cursor, (0, 1));
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut grid = grid_of(&["abcd", "efgh", "ijkl"]);
+        assert_eq!(grid.resize(2, 2, 2), 1);
+        assert_eq!(texts(&grid), ["ef", "ij"]);
+        assert_eq!(grid.resize(3, 3, 0), 0);
+        assert_eq!(texts(&grid), ["ef", "ij", ""]);
+    }
+}
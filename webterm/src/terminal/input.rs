@@ -0,0 +1,363 @@
+// Keyboard and mouse input to the byte sequences a xterm sends
+//     https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-PC-Style-Function-Keys
+//     https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Mouse-Tracking
+
+use super::{Modes, MouseTracking};
+use base64ct::{Base64, Encoding};
+
+/// A key press, as the fields of a browser `KeyboardEvent`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyInput {
+    pub key: String,
+    pub code: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+/// What happened to the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press,
+    Release,
+    Move,
+    /// The wheel is turned by lines, up if positive
+    Wheel(isize),
+}
+
+/// A mouse event on the cell (row, col) of the view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MouseInput {
+    pub action: MouseAction,
+    /// The button of a press or release, 0 left, 1 middle & 2 right
+    pub button: u16,
+    /// The buttons held down, as `MouseEvent.buttons`
+    pub buttons: u16,
+    pub row: usize,
+    pub col: usize,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyInput {
+    // the modifier parameter of the function keys, 1 means none
+    fn modifier(&self) -> u8 {
+        1 + self.shift as u8 + 2 * self.alt as u8 + 4 * self.ctrl as u8
+    }
+}
+
+// Control characters typed with ctrl
+fn ctrl_char(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '-' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+/// Encode a key press, `None` if the key produces nothing
+pub fn encode(input: &KeyInput, modes: &Modes) -> Option<Vec<u8>> {
+    let mut chars = input.key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // a printable character
+        let mut bytes = Vec::new();
+        if input.alt {
+            bytes.push(0x1b);
+        }
+        match ctrl_char(c) {
+            Some(b) if input.ctrl => bytes.push(b),
+            _ => {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        return Some(bytes);
+    }
+
+    let modifier = input.modifier();
+    // CSI 1 ; modifier final, or SS3 final in the application mode
+    let cursor = |c: u8| -> Vec<u8> {
+        if modifier > 1 {
+            format!("\x1b[1;{}{}", modifier, c as char).into_bytes()
+        } else if modes.app_cursor {
+            vec![0x1b, b'O', c]
+        } else {
+            vec![0x1b, b'[', c]
+        }
+    };
+    // CSI number ; modifier ~
+    let tilde = |n: u8| -> Vec<u8> {
+        if modifier > 1 {
+            format!("\x1b[{};{}~", n, modifier).into_bytes()
+        } else {
+            format!("\x1b[{}~", n).into_bytes()
+        }
+    };
+    // SS3 final, with CSI 1 ; modifier final when modified
+    let function = |c: u8| -> Vec<u8> {
+        if modifier > 1 {
+            format!("\x1b[1;{}{}", modifier, c as char).into_bytes()
+        } else {
+            vec![0x1b, b'O', c]
+        }
+    };
+    let alt_prefixed = |b: u8| -> Vec<u8> {
+        if input.alt {
+            vec![0x1b, b]
+        } else {
+            vec![b]
+        }
+    };
+
+    let bytes = match input.key.as_str() {
+        "Enter" => {
+            if modes.newline {
+                b"\r\n".to_vec()
+            } else {
+                alt_prefixed(b'\r')
+            }
+        }
+        "Backspace" => alt_prefixed(if input.ctrl { 0x08 } else { 0x7f }),
+        "Tab" if input.shift => b"\x1b[Z".to_vec(),
+        "Tab" => alt_prefixed(b'\t'),
+        "Escape" => alt_prefixed(0x1b),
+        "ArrowUp" => cursor(b'A'),
+        "ArrowDown" => cursor(b'B'),
+        "ArrowRight" => cursor(b'C'),
+        "ArrowLeft" => cursor(b'D'),
+        "Home" => cursor(b'H'),
+        "End" => cursor(b'F'),
+        "Insert" => tilde(2),
+        "Delete" => tilde(3),
+        "PageUp" => tilde(5),
+        "PageDown" => tilde(6),
+        "F1" => function(b'P'),
+        "F2" => function(b'Q'),
+        "F3" => function(b'R'),
+        "F4" => function(b'S'),
+        "F5" => tilde(15),
+        "F6" => tilde(17),
+        "F7" => tilde(18),
+        "F8" => tilde(19),
+        "F9" => tilde(20),
+        "F10" => tilde(21),
+        "F11" => tilde(23),
+        "F12" => tilde(24),
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+// The legacy encoding adds 32 to the coordinates in a byte
+const MAX_LEGACY_POS: usize = 255 - 32;
+// The wheel events sent at most for one event of the browser
+const MAX_WHEEL_REPORTS: usize = 10;
+
+/// Encode a mouse event for the application, `None` if it is not reported
+/// in the tracking mode
+pub fn encode_mouse(input: &MouseInput, modes: &Modes) -> Option<Vec<u8>> {
+    let held = |buttons: u16| match buttons {
+        b if b & 1 != 0 => Some(0),
+        b if b & 4 != 0 => Some(1),
+        b if b & 2 != 0 => Some(2),
+        _ => None,
+    };
+    let (code, count) = match (input.action, modes.mouse) {
+        (_, MouseTracking::Off) => return None,
+        (MouseAction::Press | MouseAction::Release, _) if input.button > 2 => return None,
+        (MouseAction::Press, _) => (input.button, 1),
+        // the legacy encoding does not tell which button is released
+        (MouseAction::Release, _) if !modes.mouse_sgr => (3, 1),
+        (MouseAction::Release, _) => (input.button, 1),
+        (MouseAction::Move, MouseTracking::Click) => return None,
+        (MouseAction::Move, MouseTracking::Drag) => (held(input.buttons)? + 32, 1),
+        (MouseAction::Move, MouseTracking::Motion) => (held(input.buttons).unwrap_or(3) + 32, 1),
+        (MouseAction::Wheel(0), _) => return None,
+        (MouseAction::Wheel(lines), _) => (
+            if lines > 0 { 64 } else { 65 },
+            lines.unsigned_abs().min(MAX_WHEEL_REPORTS),
+        ),
+    };
+    let code = code + 4 * input.shift as u16 + 8 * input.alt as u16 + 16 * input.ctrl as u16;
+    let (x, y) = (input.col + 1, input.row + 1);
+    let report = if modes.mouse_sgr {
+        let last = if input.action == MouseAction::Release {
+            'm'
+        } else {
+            'M'
+        };
+        format!("\x1b[<{};{};{}{}", code, x, y, last).into_bytes()
+    } else {
+        if x > MAX_LEGACY_POS || y > MAX_LEGACY_POS {
+            return None;
+        }
+        vec![
+            0x1b,
+            b'[',
+            b'M',
+            32 + code as u8,
+            32 + x as u8,
+            32 + y as u8,
+        ]
+    };
+    Some(report.repeat(count))
+}
+
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+
+/// Encode pasted text, the lines end with CR as if typed, and the text is
+/// bracketed if the application asks so
+pub fn paste(text: &str, modes: &Modes) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if modes.bracketed_paste {
+        // the text must not end the paste early
+        let text = text.replace(PASTE_END, "");
+        format!("{}{}{}", PASTE_START, text, PASTE_END).into_bytes()
+    } else {
+        text.into_bytes()
+    }
+}
+
+/// The answer to an OSC 52 query, with the clipboard `text`
+pub fn clipboard_reply(selection: &str, text: &str) -> Vec<u8> {
+    format!(
+        "\x1b]52;{};{}\x1b\\",
+        selection,
+        Base64::encode_string(text.as_bytes())
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: &str) -> KeyInput {
+        KeyInput {
+            key: key.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let modes = Modes::default();
+        assert_eq!(encode(&key("a"), &modes).unwrap(), b"a");
+        assert_eq!(encode(&key("中"), &modes).unwrap(), "中".as_bytes());
+        let ctrl_c = KeyInput {
+            ctrl: true,
+            ..key("c")
+        };
+        assert_eq!(encode(&ctrl_c, &modes).unwrap(), [3]);
+        let alt_b = KeyInput {
+            alt: true,
+            ..key("b")
+        };
+        assert_eq!(encode(&alt_b, &modes).unwrap(), b"\x1bb");
+        assert_eq!(encode(&key("Enter"), &modes).unwrap(), b"\r");
+        assert_eq!(encode(&key("Backspace"), &modes).unwrap(), [0x7f]);
+        assert_eq!(encode(&key("ArrowUp"), &modes).unwrap(), b"\x1b[A");
+        assert_eq!(encode(&key("F5"), &modes).unwrap(), b"\x1b[15~");
+        assert_eq!(encode(&key("Shift"), &modes), None);
+
+        let ctrl_right = KeyInput {
+            ctrl: true,
+            ..key("ArrowRight")
+        };
+        assert_eq!(encode(&ctrl_right, &modes).unwrap(), b"\x1b[1;5C");
+        let shift_delete = KeyInput {
+            shift: true,
+            ..key("Delete")
+        };
+        assert_eq!(encode(&shift_delete, &modes).unwrap(), b"\x1b[3;2~");
+
+        let modes = Modes {
+            app_cursor: true,
+            ..Default::default()
+        };
+        assert_eq!(encode(&key("ArrowLeft"), &modes).unwrap(), b"\x1bOD");
+        assert_eq!(encode(&key("F1"), &modes).unwrap(), b"\x1bOP");
+    }
+
+    fn mouse(action: MouseAction, button: u16, buttons: u16) -> MouseInput {
+        MouseInput {
+            action,
+            button,
+            buttons,
+            row: 2,
+            col: 4,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    #[test]
+    fn test_encode_mouse() {
+        let press = mouse(MouseAction::Press, 0, 1);
+        let release = mouse(MouseAction::Release, 2, 0);
+        let drag = mouse(MouseAction::Move, 0, 1);
+        let hover = mouse(MouseAction::Move, 0, 0);
+        let wheel = mouse(MouseAction::Wheel(-2), 0, 0);
+
+        let mut modes = Modes::default();
+        assert_eq!(encode_mouse(&press, &modes), None);
+
+        modes.mouse = MouseTracking::Click;
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[M %#");
+        assert_eq!(encode_mouse(&release, &modes).unwrap(), b"\x1b[M#%#");
+        assert_eq!(encode_mouse(&drag, &modes), None);
+        assert_eq!(encode_mouse(&wheel, &modes).unwrap(), b"\x1b[Ma%#\x1b[Ma%#");
+        let far = MouseInput {
+            col: 300,
+            ..press.clone()
+        };
+        assert_eq!(encode_mouse(&far, &modes), None);
+
+        modes.mouse_sgr = true;
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[<0;5;3M");
+        assert_eq!(encode_mouse(&release, &modes).unwrap(), b"\x1b[<2;5;3m");
+        assert_eq!(encode_mouse(&far, &modes).unwrap(), b"\x1b[<0;301;3M");
+        let ctrl_shift = MouseInput {
+            ctrl: true,
+            shift: true,
+            ..press.clone()
+        };
+        assert_eq!(encode_mouse(&ctrl_shift, &modes).unwrap(), b"\x1b[<20;5;3M");
+        let back = mouse(MouseAction::Press, 3, 8);
+        assert_eq!(encode_mouse(&back, &modes), None);
+
+        modes.mouse = MouseTracking::Drag;
+        assert_eq!(encode_mouse(&drag, &modes).unwrap(), b"\x1b[<32;5;3M");
+        assert_eq!(encode_mouse(&hover, &modes), None);
+        modes.mouse = MouseTracking::Motion;
+        assert_eq!(encode_mouse(&hover, &modes).unwrap(), b"\x1b[<35;5;3M");
+        let right_drag = mouse(MouseAction::Move, 0, 2);
+        assert_eq!(encode_mouse(&right_drag, &modes).unwrap(), b"\x1b[<34;5;3M");
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut modes = Modes::default();
+        assert_eq!(paste("ls\nrm -rf x\r\n", &modes), b"ls\rrm -rf x\r");
+        modes.bracketed_paste = true;
+        assert_eq!(paste("a\nb", &modes), b"\x1b[200~a\rb\x1b[201~");
+        assert_eq!(paste("a\x1b[201~\nb", &modes), b"\x1b[200~a\rb\x1b[201~");
+    }
+
+    #[test]
+    fn test_clipboard_reply() {
+        assert_eq!(clipboard_reply("c", "hello"), b"\x1b]52;c;aGVsbG8=\x1b\\");
+        assert_eq!(clipboard_reply("", ""), b"\x1b]52;;\x1b\\");
+    }
+}
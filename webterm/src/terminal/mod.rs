@@ -0,0 +1,491 @@
+//! A xterm compatible terminal emulator
+//!
+//! The byte stream from the host is fed to the parser, whose actions are
+//! applied to the screen model. Nothing here depends on the browser, the
+//! canvas only reads the grid back to draw it
+
+pub mod grid;
+pub mod input;
+pub mod parser;
+mod screen;
+mod view;
+
+pub use screen::{ClipboardRequest, Modes, MouseTracking, Screen, DEFAULT_SCROLLBACK};
+pub use view::{Highlight, View};
+
+use grid::Row;
+use parser::Parser;
+
+pub struct Terminal {
+    parser: Parser,
+    screen: Screen,
+    view: View,
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            screen: Screen::new(cols, rows),
+            view: View::default(),
+        }
+    }
+
+    // The number of the line above the screen
+    fn history_end(&self) -> usize {
+        self.screen.line_end() - self.screen.rows()
+    }
+
+    /// Process the output of the host
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let end = self.history_end();
+        self.parser.advance(&mut self.screen, bytes);
+        self.view
+            .history_changed(&self.screen, self.history_end() - end);
+    }
+
+    /// Resize the screen, see [Screen::resize]
+    ///
+    /// The lines are rewrapped, so the selection is lost
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let end = self.history_end();
+        self.screen.resize(cols, rows);
+        self.view.clear_selection();
+        self.view
+            .history_changed(&self.screen, self.history_end() - end);
+    }
+
+    /// Keep at most `lines` in the scrollback
+    pub fn set_scrollback(&mut self, lines: usize) {
+        self.screen.set_scrollback_limit(lines);
+        self.view.history_changed(&self.screen, 0);
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    /// The row shown at `y`, which is in the scrollback when scrolled back
+    pub fn visible_row(&self, y: usize) -> &Row {
+        self.view.row(&self.screen, y)
+    }
+
+    /// The hyperlink of the cell shown at (y, x)
+    pub fn link_at(&self, y: usize, x: usize) -> Option<&str> {
+        let cell = self.visible_row(y).cells.get(x)?;
+        self.screen.link(cell.attr.link)
+    }
+
+    /// The window title set by the host since the last call
+    pub fn take_title(&mut self) -> Option<String> {
+        self.screen.take_title()
+    }
+
+    /// The OSC 52 requests of the host since the last call
+    pub fn take_clipboard(&mut self) -> Vec<ClipboardRequest> {
+        self.screen.take_clipboard()
+    }
+
+    /// The highlights of the row shown at `y`, see [View::highlights]
+    pub fn highlights(&self, y: usize) -> Vec<(usize, usize, Highlight)> {
+        self.view.highlights(&self.screen, y)
+    }
+
+    /// Whether the view has changed since the last call, so that everything
+    /// has to be redrawn
+    pub fn take_view_changed(&mut self) -> bool {
+        self.view.take_changed()
+    }
+
+    /// Scroll back by `lines`, or forward if negative
+    pub fn scroll(&mut self, lines: isize) {
+        self.view.scroll(&self.screen, lines);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.view.scroll_to_bottom();
+    }
+
+    /// Start a selection at the cell (y, x) of the view
+    pub fn select_start(&mut self, y: usize, x: usize) {
+        self.view.select_start(&self.screen, y, x);
+    }
+
+    /// Extend the selection to the cell (y, x) of the view
+    pub fn select_to(&mut self, y: usize, x: usize) {
+        self.view.select_to(&self.screen, y, x);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.view.clear_selection();
+    }
+
+    pub fn selection_text(&self) -> Option<String> {
+        self.view.selection_text(&self.screen)
+    }
+
+    /// Search the scrollback and the screen, see [View::search]
+    pub fn search(
+        &mut self,
+        pattern: &str,
+        regex: bool,
+        case_sensitive: bool,
+    ) -> Result<bool, regex::Error> {
+        self.view
+            .search(&self.screen, pattern, regex, case_sensitive)
+    }
+
+    /// Move to the next match, upwards if `backward`
+    pub fn find(&mut self, backward: bool) -> bool {
+        self.view.find(&self.screen, backward)
+    }
+
+    pub fn close_search(&mut self) {
+        self.view.close_search();
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
+    /// Replies to be sent back to the host, e.g. the cursor position report
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.screen.take_output()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{grid::*, *};
+
+    fn run(cols: usize, rows: usize, bytes: &[u8]) -> Terminal {
+        let mut term = Terminal::new(cols, rows);
+        term.feed(bytes);
+        term
+    }
+
+    fn lines(term: &Terminal) -> Vec<String> {
+        let grid = term.screen().grid();
+        (0..grid.rows()).map(|y| grid.row(y).text()).collect()
+    }
+
+    #[test]
+    fn test_wrap() {
+        let term = run(4, 3, b"abcdef\r\nxy");
+        assert_eq!(lines(&term), ["abcd", "ef", "xy"]);
+        assert!(term.screen().grid().row(0).wrapped);
+        assert!(!term.screen().grid().row(1).wrapped);
+        assert_eq!(term.screen().cursor(), (2, 2));
+
+        // the cursor stays on the last column until the next print
+        let term = run(4, 2, b"abcd");
+        assert_eq!(term.screen().cursor(), (0, 3));
+        let term = run(4, 2, b"abcd\r\n");
+        assert_eq!(lines(&term), ["abcd", ""]);
+
+        // scroll at the bottom, no auto wrap
+        let term = run(4, 2, b"1\r\n2\r\n3\x1b[?7labcdef");
+        assert_eq!(lines(&term), ["2", "3abf"]);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let term = run(10, 5, b"\x1b[3;4Hx\x1b[2Ay\x1b[10Cz\x1b[Hw\x1b[5Gv");
+        assert_eq!(lines(&term), ["w   v    z", "", "   x", "", ""]);
+        let mut term = run(10, 5, b"\x1b[2;3H\x1b[6n\x1b[c");
+        assert_eq!(term.take_output(), b"\x1b[2;3R\x1b[?62;22c");
+        // save & restore
+        let term = run(10, 5, b"\x1b[2;2H\x1b7\x1b[5;5H\x1b8a");
+        assert_eq!(lines(&term)[1], " a");
+        // tabs
+        let term = run(20, 1, b"a\tb\x1b[2Ic\x1b[Zd");
+        assert_eq!(lines(&term), ["a       b       d  c"]);
+    }
+
+    #[test]
+    fn test_erase() {
+        let screen = b"aaaa\r\nbbbb\r\ncccc";
+        let mut bytes = screen.to_vec();
+        bytes.extend_from_slice(b"\x1b[2;2H\x1b[K");
+        assert_eq!(lines(&run(4, 3, &bytes)), ["aaaa", "b", "cccc"]);
+        let mut bytes = screen.to_vec();
+        bytes.extend_from_slice(b"\x1b[2;2H\x1b[1J");
+        assert_eq!(lines(&run(4, 3, &bytes)), ["", "  bb", "cccc"]);
+        let mut bytes = screen.to_vec();
+        bytes.extend_from_slice(b"\x1b[2;3H\x1b[J");
+        assert_eq!(lines(&run(4, 3, &bytes)), ["aaaa", "bb", ""]);
+        let mut bytes = screen.to_vec();
+        bytes.extend_from_slice(b"\x1b[1;2H\x1b[2P\x1b[2;2H\x1b[@\x1b[3;1H\x1b[2X");
+        assert_eq!(lines(&run(4, 3, &bytes)), ["aa", "b bb", "  cc"]);
+
+        // background colour erase
+        let term = run(4, 1, b"\x1b[41m\x1b[2J");
+        let cell = term.screen().grid().row(0).cells[3];
+        assert_eq!(cell.attr.bg, Color::Indexed(1));
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let bytes = b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[4;1H\n\nx";
+        assert_eq!(lines(&run(4, 5, bytes)), ["1", "4", "", "x", "5"]);
+        // reverse index at the top margin
+        let bytes = b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[2;1H\x1bMy";
+        assert_eq!(lines(&run(4, 5, bytes)), ["1", "y", "2", "3", "5"]);
+        // insert & delete lines inside the region
+        let bytes = b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[3;1H\x1b[L";
+        assert_eq!(lines(&run(4, 5, bytes)), ["1", "2", "", "3", "5"]);
+        let bytes = b"1\r\n2\r\n3\r\n4\r\n5\x1b[2;4r\x1b[2;1H\x1b[2M";
+        assert_eq!(lines(&run(4, 5, bytes)), ["1", "4", "", "", "5"]);
+        // origin mode
+        let bytes = b"\x1b[2;4r\x1b[?6h\x1b[1;1Ho\x1b[9;1Hp";
+        assert_eq!(lines(&run(4, 5, bytes)), ["", "o", "", "p", ""]);
+    }
+
+    #[test]
+    fn test_alternate_screen() {
+        let mut term = run(4, 2, b"ab\x1b[?1049h\x1b[Hxy");
+        assert!(term.screen().is_alternate());
+        assert_eq!(lines(&term), ["xy", ""]);
+        term.feed(b"\x1b[?1049l");
+        assert!(!term.screen().is_alternate());
+        assert_eq!(lines(&term), ["ab", ""]);
+        assert_eq!(term.screen().cursor(), (0, 2));
+        // the alternate screen is cleared when entered again
+        term.feed(b"\x1b[?1049h");
+        assert_eq!(lines(&term), ["", ""]);
+    }
+
+    #[test]
+    fn test_sgr() {
+        let term = run(
+            8,
+            1,
+            b"\x1b[1;31ma\x1b[0;38;5;200;48;2;1;2;3mb\x1b[38:2::4:5:6;7mc\x1b[22;27;93;104md\x1b[me",
+        );
+        let cells = &term.screen().grid().row(0).cells;
+        assert_eq!(cells[0].attr.fg, Color::Indexed(1));
+        assert!(cells[0].attr.flags.contains(Flags::BOLD));
+        assert_eq!(cells[1].attr.fg, Color::Indexed(200));
+        assert_eq!(cells[1].attr.bg, Color::Rgb(1, 2, 3));
+        assert!(!cells[1].attr.flags.contains(Flags::BOLD));
+        assert_eq!(cells[2].attr.fg, Color::Rgb(4, 5, 6));
+        assert!(cells[2].attr.flags.contains(Flags::INVERSE));
+        assert_eq!(cells[3].attr.fg, Color::Indexed(11));
+        assert_eq!(cells[3].attr.bg, Color::Indexed(12));
+        assert_eq!(cells[3].attr.flags, Flags::default());
+        assert_eq!(cells[4].attr, Attr::default());
+    }
+
+    #[test]
+    fn test_charset() {
+        let term = run(8, 1, b"\x1b(0lqk\x1b(Bq\x0e\x1b)0q\x0fq");
+        assert_eq!(lines(&term), ["┌─┐q─q"]);
+    }
+
+    #[test]
+    fn test_modes() {
+        let term = run(8, 2, b"\x1b[?1h\x1b=\x1b[?25l\x1b[?2004h\x1b[4hab\x1b[1Gc");
+        let modes = term.screen().modes();
+        assert!(modes.app_cursor && modes.app_keypad && modes.bracketed_paste);
+        assert!(!modes.show_cursor);
+        assert_eq!(lines(&term), ["cab", ""]);
+        let term = run(8, 2, b"\x1b[20ha\nb\x1bc");
+        assert_eq!(lines(&term), ["", ""]);
+        assert_eq!(term.screen().cursor(), (0, 0));
+        assert!(!term.screen().modes().newline);
+
+        let mut term = run(8, 2, b"\x1b[?1000h\x1b[?1006h");
+        assert_eq!(term.screen().modes().mouse, MouseTracking::Click);
+        assert!(term.screen().modes().mouse_sgr);
+        term.feed(b"\x1b[?1003h");
+        assert_eq!(term.screen().modes().mouse, MouseTracking::Motion);
+        term.feed(b"\x1b[?1002h");
+        assert_eq!(term.screen().modes().mouse, MouseTracking::Drag);
+        term.feed(b"\x1b[?1002l\x1b[?1006l");
+        assert_eq!(term.screen().modes().mouse, MouseTracking::Off);
+        assert!(!term.screen().modes().mouse_sgr);
+    }
+
+    #[test]
+    fn test_osc() {
+        let mut term = run(8, 2, b"\x1b]0;vim a;b\x07\x1b]2;top\x1b\\");
+        assert_eq!(term.take_title().as_deref(), Some("top"));
+        assert_eq!(term.take_title(), None);
+
+        // "hello" and "?" over both terminators
+        term.feed(b"\x1b]52;c;aGVsbG8=\x07\x1b]52;c;?\x1b\\\x1b]52;c;!!\x07");
+        assert_eq!(
+            term.take_clipboard(),
+            [
+                ClipboardRequest::Set("hello".to_owned()),
+                ClipboardRequest::Query("c".to_owned())
+            ]
+        );
+        assert!(term.take_clipboard().is_empty());
+
+        // the link survives SGR, and is ended by an empty URI
+        term.feed(b"a\x1b]8;id=1;http://x/?a=1;b\x1b\\b\x1b[1;0mc\x1b]8;;\x1b\\d");
+        assert_eq!(term.link_at(0, 0), None);
+        assert_eq!(term.link_at(0, 1), Some("http://x/?a=1;b"));
+        assert_eq!(term.link_at(0, 2), Some("http://x/?a=1;b"));
+        assert_eq!(term.link_at(0, 3), None);
+        assert_eq!(term.link_at(0, 9), None);
+        term.feed(b"\x1b]8;;http://y\x07e\x1b]8;;http://x/?a=1;b\x07f");
+        assert_eq!(term.link_at(0, 4), Some("http://y"));
+        assert_eq!(term.screen().grid().row(0).cells[5].attr.link, 1);
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut term = run(6, 3, b"abcdefgh\r\n$ ");
+        term.resize(4, 4);
+        assert_eq!(lines(&term), ["abcd", "efgh", "$", ""]);
+        assert_eq!(term.screen().cursor(), (2, 2));
+        term.feed(b"xyz");
+        assert_eq!(lines(&term), ["abcd", "efgh", "$ xy", "z"]);
+
+        term.resize(10, 2);
+        assert_eq!(lines(&term), ["abcdefgh", "$ xyz"]);
+        assert_eq!(term.screen().cursor(), (1, 5));
+
+        // the alternate screen is cut, the primary one is still rewrapped
+        term.feed(b"\x1b[?1049h\x1b[2;4HA");
+        term.resize(5, 2);
+        assert_eq!(lines(&term), ["", "   A"]);
+        term.feed(b"\x1b[?1049l");
+        assert_eq!(lines(&term), ["$ xyz", ""]);
+        assert_eq!(term.screen().cursor(), (1, 0));
+    }
+
+    fn visible(term: &Terminal) -> Vec<String> {
+        (0..term.screen().rows())
+            .map(|y| term.visible_row(y).text())
+            .collect()
+    }
+
+    #[test]
+    fn test_scrollback() {
+        let mut term = run(4, 2, b"1\r\n2\r\n3\r\n4");
+        assert_eq!(term.screen().history_len(), 2);
+        term.scroll(1);
+        assert_eq!(visible(&term), ["2", "3"]);
+        // the view stays on the same lines while the output goes on
+        term.feed(b"\r\n5");
+        assert_eq!(visible(&term), ["2", "3"]);
+        term.scroll(10);
+        assert_eq!(visible(&term), ["1", "2"]);
+        term.scroll(-10);
+        assert_eq!(visible(&term), ["4", "5"]);
+
+        // the limit drops the oldest lines
+        term.set_scrollback(2);
+        term.scroll(10);
+        assert_eq!(visible(&term), ["2", "3"]);
+        // no scrollback on the alternate screen nor inside a scroll region
+        term.feed(b"\x1b[?1049h\r\n\r\n\r\n");
+        assert_eq!(term.screen().history_len(), 0);
+        assert_eq!(visible(&term), ["", ""]);
+        term.feed(b"\x1b[?1049l\x1b[2;2r\x1b[2;1H\n\n");
+        assert_eq!(term.screen().history_len(), 2);
+        // ED 3 clears it
+        term.feed(b"\x1b[r\x1b[3J");
+        assert_eq!(term.screen().history_len(), 0);
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut term = run(6, 3, b"abcdefgh\r\nxy  z");
+        term.select_start(0, 2);
+        assert_eq!(term.selection_text(), None);
+        term.select_to(2, 4);
+        // wrapped lines are joined, trailing blanks are trimmed
+        assert_eq!(term.selection_text().unwrap(), "cdefgh\nxy  z");
+        term.select_to(0, 0);
+        assert_eq!(term.selection_text().unwrap(), "abc");
+        assert_eq!(term.highlights(0), [(0, 3, Highlight::Selection)]);
+        // the selection follows the text
+        term.feed(b"\r\n\r\n");
+        assert_eq!(term.selection_text().unwrap(), "abc");
+        term.clear_selection();
+        assert_eq!(term.selection_text(), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut term = run(8, 2, b"foo 1\r\nbar\r\nFoo 2\r\nfoo 3");
+        assert_eq!(term.search("foo", false, false), Ok(true));
+        assert_eq!(term.highlights(1), [(0, 3, Highlight::CurrentMatch)]);
+        assert!(term.find(true));
+        assert_eq!(term.highlights(0), [(0, 3, Highlight::CurrentMatch)]);
+        // the match in the scrollback is scrolled into view
+        assert!(term.find(true));
+        assert_eq!(visible(&term)[0], "foo 1");
+        assert!(!term.find(true));
+        assert!(term.find(false));
+        assert_eq!(term.highlights(0), [(0, 3, Highlight::CurrentMatch)]);
+
+        assert_eq!(term.search("foo", false, true), Ok(true));
+        assert!(term.find(true));
+        assert!(!term.find(true));
+        assert_eq!(term.search(r"o \d", true, false), Ok(true));
+        assert_eq!(term.highlights(1), [(2, 5, Highlight::CurrentMatch)]);
+        assert_eq!(term.search("a.", false, false), Ok(false));
+        assert!(term.search("(", true, false).is_err());
+        term.close_search();
+        assert!(term.highlights(1).is_empty());
+    }
+
+    #[test]
+    fn test_unicode() {
+        // wide characters take two cells and move the cursor by two
+        let term = run(6, 2, "a中文b".as_bytes());
+        assert_eq!(lines(&term), ["a中文b", ""]);
+        assert_eq!(term.screen().cursor(), (0, 6 - 1));
+        let row = term.screen().grid().row(0);
+        assert_eq!(row.cells[1].width, 2);
+        assert!(row.cells[2].is_spacer());
+
+        // one that does not fit on the last column goes to the next line
+        let term = run(4, 2, "abc中".as_bytes());
+        assert_eq!(lines(&term), ["abc", "中"]);
+        assert!(term.screen().grid().row(0).wrapped);
+        assert_eq!(term.screen().cursor(), (1, 2));
+
+        // combining marks and emoji sequences stay in one cell
+        let term = run(8, 1, "e\u{301}x👩\u{200d}💻!".as_bytes());
+        assert_eq!(lines(&term), ["e\u{301}x👩\u{200d}💻!"]);
+        assert_eq!(term.screen().cursor(), (0, 5));
+        let row = term.screen().grid().row(0);
+        assert_eq!(row.cells[0].text(), "e\u{301}");
+        assert_eq!(row.cells[2].text(), "👩\u{200d}💻");
+        // the emoji presentation of a narrow symbol is wide
+        let term = run(4, 1, "\u{2764}\u{fe0f}a".as_bytes());
+        assert_eq!(lines(&term), ["\u{2764}\u{fe0f}a"]);
+        assert_eq!(term.screen().cursor(), (0, 3));
+        // a mark after a wide character, and one with nothing before it
+        let term = run(4, 1, "\u{301}中\u{301}".as_bytes());
+        assert_eq!(term.screen().grid().row(0).cells[0].text(), "中\u{301}");
+
+        // overwriting half of a wide character blanks the other half
+        let term = run(6, 1, "中文\x1b[1Gx\x1b[4Gy".as_bytes());
+        assert_eq!(lines(&term), ["x  y"]);
+
+        // rewrapped without splitting them
+        let mut term = run(6, 2, "ab中文".as_bytes());
+        term.resize(5, 3);
+        assert_eq!(lines(&term), ["ab中", "文", ""]);
+
+        // selected and found by their columns
+        let mut term = run(8, 1, "中文 abc".as_bytes());
+        term.select_start(0, 3);
+        term.select_to(0, 6);
+        assert_eq!(term.selection_text().as_deref(), Some("文 ab"));
+        term.clear_selection();
+        assert_eq!(term.search("abc", false, false), Ok(true));
+        assert_eq!(term.highlights(0), [(5, 8, Highlight::CurrentMatch)]);
+    }
+}
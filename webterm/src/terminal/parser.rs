@@ -0,0 +1,410 @@
+// Escape sequence parser
+//
+// A state machine following the DEC ANSI parser by Paul Williams
+//     https://vt100.net/emu/dec_ansi_parser
+// The input is decoded as UTF-8 in the ground state, C1 controls are not recognized
+
+const MAX_PARAMS: usize = 32;
+const MAX_INTERMEDIATES: usize = 2;
+const MAX_OSC_LEN: usize = 1 << 20;
+
+/// Parameters of a control sequence
+///
+/// Each parameter is a group of sub parameters separated by `:`,
+/// an omitted parameter is `0`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Params {
+    params: Vec<Vec<u16>>,
+}
+
+impl Params {
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        self.params.iter().map(|p| p.as_slice())
+    }
+
+    /// The `i`th parameter, `default` if omitted or zero
+    pub fn get(&self, i: usize, default: u16) -> u16 {
+        match self.params.get(i).and_then(|p| p.first()) {
+            Some(0) | None => default,
+            Some(&v) => v,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.params.clear();
+    }
+
+    fn push_digit(&mut self, digit: u8) {
+        if self.params.is_empty() {
+            self.params.push(vec![0]);
+        }
+        let last = self.params.last_mut().unwrap().last_mut().unwrap();
+        *last = last
+            .saturating_mul(10)
+            .saturating_add((digit - b'0') as u16);
+    }
+
+    fn next_param(&mut self) {
+        if self.params.is_empty() {
+            self.params.push(vec![0]);
+        }
+        if self.params.len() < MAX_PARAMS {
+            self.params.push(vec![0]);
+        }
+    }
+
+    fn next_subparam(&mut self) {
+        if self.params.is_empty() {
+            self.params.push(vec![0]);
+        }
+        self.params.last_mut().unwrap().push(0);
+    }
+}
+
+/// Actions emitted by the parser
+pub trait Perform {
+    /// Draw a printable character
+    fn print(&mut self, c: char);
+
+    /// Execute a C0 control
+    fn execute(&mut self, byte: u8);
+
+    /// A control sequence `CSI [private] params [intermediates] action` is finished
+    ///
+    /// The private marker `<=>?` is reported as the first of `intermediates`
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char);
+
+    /// An escape sequence `ESC [intermediates] byte` is finished
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8);
+
+    /// An operating system command is finished, params are separated by `;`
+    fn osc_dispatch(&mut self, params: &[&[u8]]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    OscString,
+    // DCS, SOS, PM and APC strings are consumed without any action
+    IgnoreString,
+}
+
+pub struct Parser {
+    state: State,
+    params: Params,
+    intermediates: Vec<u8>,
+    ignoring: bool,
+    osc: Vec<u8>,
+    // pending UTF-8 sequence
+    utf8_code: u32,
+    utf8_remain: u8,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Params::default(),
+            intermediates: Vec::new(),
+            ignoring: false,
+            osc: Vec::new(),
+            utf8_code: 0,
+            utf8_remain: 0,
+        }
+    }
+
+    pub fn advance<P: Perform>(&mut self, performer: &mut P, bytes: &[u8]) {
+        for &byte in bytes {
+            self.advance_byte(performer, byte);
+        }
+    }
+
+    fn advance_byte<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.state == State::Ground && (byte >= 0x80 || self.utf8_remain > 0) {
+            self.advance_utf8(performer, byte);
+            return;
+        }
+
+        // transitions from anywhere
+        match byte {
+            0x18 | 0x1a => {
+                performer.execute(byte);
+                self.state = State::Ground;
+                return;
+            }
+            0x1b => {
+                if self.state == State::OscString {
+                    self.dispatch_osc(performer);
+                }
+                self.clear();
+                self.state = State::Escape;
+                return;
+            }
+            _ => (),
+        }
+
+        match self.state {
+            State::Ground => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x7f => (),
+                _ => performer.print(byte as char),
+            },
+            State::Escape => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x20..=0x2f => {
+                    self.collect(byte);
+                    self.state = State::EscapeIntermediate;
+                }
+                b'[' => self.state = State::CsiEntry,
+                b']' => {
+                    self.osc.clear();
+                    self.state = State::OscString;
+                }
+                b'P' | b'X' | b'^' | b'_' => self.state = State::IgnoreString,
+                0x30..=0x7e => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = State::Ground;
+                }
+                _ => (),
+            },
+            State::EscapeIntermediate => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x20..=0x2f => self.collect(byte),
+                0x30..=0x7e => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = State::Ground;
+                }
+                _ => (),
+            },
+            State::CsiEntry => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                b'<'..=b'?' => {
+                    self.collect(byte);
+                    self.state = State::CsiParam;
+                }
+                _ => {
+                    self.state = State::CsiParam;
+                    self.csi_param(performer, byte);
+                }
+            },
+            State::CsiParam => self.csi_param(performer, byte),
+            State::CsiIntermediate => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x20..=0x2f => self.collect(byte),
+                0x30..=0x3f => self.state = State::CsiIgnore,
+                0x40..=0x7e => self.dispatch_csi(performer, byte),
+                _ => (),
+            },
+            State::CsiIgnore => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x40..=0x7e => self.state = State::Ground,
+                _ => (),
+            },
+            State::OscString => match byte {
+                // BEL is accepted as the terminator as well as ST
+                0x07 => {
+                    self.dispatch_osc(performer);
+                    self.state = State::Ground;
+                }
+                0x00..=0x1f => (),
+                _ => {
+                    if self.osc.len() < MAX_OSC_LEN {
+                        self.osc.push(byte)
+                    }
+                }
+            },
+            State::IgnoreString => (),
+        }
+    }
+
+    fn csi_param<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            b'0'..=b'9' => self.params.push_digit(byte),
+            b';' => self.params.next_param(),
+            b':' => self.params.next_subparam(),
+            0x20..=0x2f => {
+                self.collect(byte);
+                self.state = State::CsiIntermediate;
+            }
+            0x3c..=0x3f => self.state = State::CsiIgnore,
+            0x40..=0x7e => self.dispatch_csi(performer, byte),
+            _ => (),
+        }
+    }
+
+    fn advance_utf8<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.utf8_remain > 0 {
+            if byte & 0xc0 == 0x80 {
+                self.utf8_code = (self.utf8_code << 6) | (byte & 0x3f) as u32;
+                self.utf8_remain -= 1;
+                if self.utf8_remain == 0 {
+                    performer.print(char::from_u32(self.utf8_code).unwrap_or('\u{fffd}'));
+                }
+                return;
+            }
+            // the sequence is interrupted, start over with this byte
+            self.utf8_remain = 0;
+            performer.print('\u{fffd}');
+            self.advance_byte(performer, byte);
+            return;
+        }
+        let (code, remain) = match byte {
+            0xc2..=0xdf => (byte & 0x1f, 1),
+            0xe0..=0xef => (byte & 0x0f, 2),
+            0xf0..=0xf4 => (byte & 0x07, 3),
+            _ => {
+                performer.print('\u{fffd}');
+                return;
+            }
+        };
+        self.utf8_code = code as u32;
+        self.utf8_remain = remain;
+    }
+
+    fn collect(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(byte);
+        } else {
+            self.ignoring = true;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.params.clear();
+        self.intermediates.clear();
+        self.ignoring = false;
+    }
+
+    fn dispatch_csi<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if !self.ignoring {
+            performer.csi_dispatch(&self.params, &self.intermediates, byte as char);
+        }
+        self.state = State::Ground;
+    }
+
+    fn dispatch_osc<P: Perform>(&mut self, performer: &mut P) {
+        let params: Vec<&[u8]> = self.osc.split(|&b| b == b';').collect();
+        performer.osc_dispatch(&params);
+        self.osc.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Perform for Recorder {
+        fn print(&mut self, c: char) {
+            self.0.push(format!("print {}", c));
+        }
+
+        fn execute(&mut self, byte: u8) {
+            self.0.push(format!("execute {:02x}", byte));
+        }
+
+        fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char) {
+            self.0.push(format!(
+                "csi {:?} {} {}",
+                params.iter().collect::<Vec<_>>(),
+                String::from_utf8_lossy(intermediates),
+                action
+            ));
+        }
+
+        fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+            self.0.push(format!(
+                "esc {} {}",
+                String::from_utf8_lossy(intermediates),
+                byte as char
+            ));
+        }
+
+        fn osc_dispatch(&mut self, params: &[&[u8]]) {
+            let params: Vec<_> = params.iter().map(|p| String::from_utf8_lossy(p)).collect();
+            self.0.push(format!("osc {:?}", params));
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Vec<String> {
+        let mut recorder = Recorder::default();
+        Parser::new().advance(&mut recorder, bytes);
+        recorder.0
+    }
+
+    #[test]
+    fn test_csi() {
+        assert_eq!(
+            parse(b"\x1b[1;31mA\x1b[?1049h\x1b[38:2::1:2:3m\x1b[ q\x1b[m"),
+            [
+                "csi [[1], [31]]  m",
+                "print A",
+                "csi [[1049]] ? h",
+                "csi [[38, 2, 0, 1, 2, 3]]  m",
+                "csi []   q",
+                "csi []  m",
+            ]
+        );
+        // C0 controls are executed in the middle of a sequence
+        assert_eq!(parse(b"\x1b[2\n;3H"), ["execute 0a", "csi [[2], [3]]  H"]);
+        // CAN aborts the sequence
+        assert_eq!(parse(b"\x1b[2\x18H"), ["execute 18", "print H"]);
+    }
+
+    #[test]
+    fn test_esc_osc() {
+        assert_eq!(
+            parse(b"\x1b(0\x1b7\x1b]0;title\x07\x1b]2;a;b\x1b\\x"),
+            [
+                "esc ( 0",
+                "esc  7",
+                "osc [\"0\", \"title\"]",
+                "osc [\"2\", \"a\", \"b\"]",
+                "esc  \\",
+                "print x",
+            ]
+        );
+        // DCS is ignored
+        assert_eq!(parse(b"\x1bPq#0;1\x1b\\y"), ["esc  \\", "print y"]);
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(
+            parse("中é😀".as_bytes()),
+            ["print 中", "print é", "print 😀"]
+        );
+        // split sequences, invalid bytes
+        let mut recorder = Recorder::default();
+        let mut parser = Parser::new();
+        parser.advance(&mut recorder, &[0xe4, 0xb8]);
+        parser.advance(&mut recorder, &[0xad, 0xff, 0xe4, b'a']);
+        assert_eq!(
+            recorder.0,
+            ["print 中", "print \u{fffd}", "print \u{fffd}", "print a"]
+        );
+    }
+}
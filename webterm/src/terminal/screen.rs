@@ -0,0 +1,1032 @@
+// The xterm behaviour on top of the grid
+//     https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
+
+use super::{
+    grid::{Attr, Cell, Color, Flags, Grid, Row},
+    parser::{Params, Perform},
+};
+use base64ct::{Base64, Encoding};
+use std::collections::VecDeque;
+use tracing::trace;
+use unicode_width::UnicodeWidthChar;
+
+/// Lines kept in the scrollback by default
+pub const DEFAULT_SCROLLBACK: usize = 10_000;
+
+// The hyperlinks remembered, the later ones are not shown as links
+const MAX_LINKS: usize = 4096;
+const MAX_TITLE_LEN: usize = 1024;
+
+// Joins the emoji on both sides into one
+const ZWJ: char = '\u{200d}';
+// Asks for the emoji presentation of the character before it, two cells wide
+const VS16: char = '\u{fe0f}';
+
+/// An OSC 52 request of the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    /// Copy the text to the clipboard
+    Set(String),
+    /// Report the clipboard, for the selection parameter given
+    Query(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Charset {
+    #[default]
+    Ascii,
+    // DEC special graphics, the line drawing characters
+    DecGraphics,
+}
+
+impl Charset {
+    fn map(&self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::DecGraphics => match c {
+                '`' => '◆',
+                'a' => '▒',
+                'f' => '°',
+                'g' => '±',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    row: usize,
+    col: usize,
+    attr: Attr,
+    // the last column has been written, the next print wraps
+    wrap_next: bool,
+    origin: bool,
+    charsets: [Charset; 2],
+    // G0 or G1 is in use
+    shift: usize,
+}
+
+/// The mouse events reported to the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// 1000, presses and releases
+    Click,
+    /// 1002, motion too while a button is held
+    Drag,
+    /// 1003, all motion
+    Motion,
+}
+
+/// Terminal modes set by SM/RM and DECSET/DECRST
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modes {
+    /// DECCKM, cursor keys send application sequences
+    pub app_cursor: bool,
+    /// DECKPAM, keypad sends application sequences
+    pub app_keypad: bool,
+    /// DECAWM
+    pub autowrap: bool,
+    /// DECTCEM
+    pub show_cursor: bool,
+    /// DECSCNM
+    pub reverse_video: bool,
+    /// IRM
+    pub insert: bool,
+    /// LNM, line feed implies carriage return
+    pub newline: bool,
+    pub bracketed_paste: bool,
+    pub mouse: MouseTracking,
+    /// 1006, the mouse is reported as `CSI < b ; x ; y M`
+    pub mouse_sgr: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            app_cursor: false,
+            app_keypad: false,
+            autowrap: true,
+            show_cursor: true,
+            reverse_video: false,
+            insert: false,
+            newline: false,
+            bracketed_paste: false,
+            mouse: MouseTracking::Off,
+            mouse_sgr: false,
+        }
+    }
+}
+
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    primary: Grid,
+    alternate: Grid,
+    alt_active: bool,
+    cursor: Cursor,
+    saved: Cursor,
+    // saved cursor of the primary screen when 1049 is set
+    saved_primary: Cursor,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    tabs: Vec<bool>,
+    modes: Modes,
+    cursor_style: u16,
+    last_char: Option<char>,
+    // replies to the host, like the cursor position report
+    output: Vec<u8>,
+    bell: bool,
+    // the rows scrolled off the top of the primary screen, the oldest first
+    scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    // how many rows have been dropped from the scrollback, so that the line
+    // numbers stay the same while the history goes on
+    dropped: usize,
+    // set by OSC 0 or 2, until it is taken
+    title: Option<String>,
+    clipboard: Vec<ClipboardRequest>,
+    // the URIs of OSC 8, a link id is the index + 1
+    links: Vec<String>,
+}
+
+fn default_tabs(cols: usize) -> Vec<bool> {
+    (0..cols).map(|x| x % 8 == 0).collect()
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Self {
+            cols,
+            rows,
+            primary: Grid::new(cols, rows),
+            alternate: Grid::new(cols, rows),
+            alt_active: false,
+            cursor: Cursor::default(),
+            saved: Cursor::default(),
+            saved_primary: Cursor::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            tabs: default_tabs(cols),
+            modes: Modes::default(),
+            cursor_style: 0,
+            last_char: None,
+            output: Vec::new(),
+            bell: false,
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK,
+            dropped: 0,
+            title: None,
+            clipboard: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn grid(&self) -> &Grid {
+        if self.alt_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        if self.alt_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    pub fn is_alternate(&self) -> bool {
+        self.alt_active
+    }
+
+    /// The cursor position as (row, col)
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor.row, self.cursor.col)
+    }
+
+    /// DECSCUSR, 0 ~ 6
+    pub fn cursor_style(&self) -> u16 {
+        self.cursor_style
+    }
+
+    pub fn modes(&self) -> &Modes {
+        &self.modes
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    /// The window title set since the last call
+    pub fn take_title(&mut self) -> Option<String> {
+        self.title.take()
+    }
+
+    /// The clipboard requests since the last call
+    pub fn take_clipboard(&mut self) -> Vec<ClipboardRequest> {
+        std::mem::take(&mut self.clipboard)
+    }
+
+    /// The URI of the hyperlink `id` of a cell
+    pub fn link(&self, id: u32) -> Option<&str> {
+        let index = (id as usize).checked_sub(1)?;
+        self.links.get(index).map(String::as_str)
+    }
+
+    /// Keep at most `limit` lines in the scrollback
+    pub fn set_scrollback_limit(&mut self, limit: usize) {
+        self.scrollback_limit = limit;
+        while self.scrollback.len() > limit {
+            self.scrollback.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// Lines of the scrollback which can be scrolled back to
+    pub fn history_len(&self) -> usize {
+        if self.alt_active {
+            0
+        } else {
+            self.scrollback.len()
+        }
+    }
+
+    /// The number of the line below the last one
+    ///
+    /// Lines are numbered from the first one that entered the scrollback,
+    /// the bottom `rows` of them are on the screen
+    pub fn line_end(&self) -> usize {
+        self.dropped + self.scrollback.len() + self.rows
+    }
+
+    /// The number of the first line still available
+    pub fn line_start(&self) -> usize {
+        self.line_end() - self.rows - self.history_len()
+    }
+
+    /// The line numbered `line`, in the scrollback or on the screen
+    pub fn line(&self, line: usize) -> Option<&Row> {
+        let index = line.checked_sub(self.line_start())?;
+        let history = self.history_len();
+        if index < history {
+            self.scrollback.get(self.scrollback.len() - history + index)
+        } else if index - history < self.rows {
+            Some(self.grid().row(index - history))
+        } else {
+            None
+        }
+    }
+
+    fn push_history(&mut self, row: Row) {
+        if self.scrollback_limit == 0 {
+            self.dropped += 1;
+            return;
+        }
+        self.scrollback.push_back(row);
+        if self.scrollback.len() > self.scrollback_limit {
+            self.scrollback.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    fn clear_history(&mut self) {
+        self.dropped += self.scrollback.len();
+        self.scrollback.clear();
+    }
+
+    /// Change the size, the lines of the primary screen are rewrapped
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        if (cols, rows) == (self.cols, self.rows) {
+            return;
+        }
+        let primary_cursor = if self.alt_active {
+            &mut self.saved_primary
+        } else {
+            &mut self.cursor
+        };
+        let (removed, (row, col)) =
+            self.primary
+                .reflow(cols, rows, (primary_cursor.row, primary_cursor.col));
+        primary_cursor.row = row;
+        primary_cursor.col = col;
+        primary_cursor.wrap_next = false;
+        for row in removed {
+            self.push_history(row);
+        }
+        if self.alt_active {
+            let top = self.alternate.resize(cols, rows, self.cursor.row);
+            self.cursor.row -= top;
+        } else {
+            self.alternate.resize(cols, rows, 0);
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        for cursor in [&mut self.cursor, &mut self.saved, &mut self.saved_primary] {
+            cursor.row = cursor.row.min(rows - 1);
+            cursor.col = cursor.col.min(cols - 1);
+        }
+        self.cursor.wrap_next = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        let mut tabs = default_tabs(cols);
+        let kept = self.tabs.len().min(cols);
+        tabs[..kept].copy_from_slice(&self.tabs[..kept]);
+        self.tabs = tabs;
+    }
+
+    fn reset(&mut self) {
+        let output = std::mem::take(&mut self.output);
+        let scrollback = std::mem::take(&mut self.scrollback);
+        let (limit, dropped) = (self.scrollback_limit, self.dropped);
+        *self = Self::new(self.cols, self.rows);
+        self.output = output;
+        self.scrollback = scrollback;
+        self.scrollback_limit = limit;
+        self.dropped = dropped;
+    }
+
+    // The cell written last, where a combining mark goes
+    fn last_cell(&self) -> Option<(usize, usize)> {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let col = if self.cursor.wrap_next {
+            col
+        } else {
+            col.checked_sub(1)?
+        };
+        if self.grid().row(row).cells[col].is_spacer() {
+            Some((row, col.checked_sub(1)?))
+        } else {
+            Some((row, col))
+        }
+    }
+
+    // Make the character just written at (row, col) wide, if it is not on
+    // the last column
+    fn widen(&mut self, row: usize, col: usize) {
+        if self.cursor.wrap_next || col + 1 != self.cursor.col {
+            return;
+        }
+        let grid = self.grid_mut();
+        grid.clear_wide(row, col + 1);
+        let cell = grid.cell_mut(row, col);
+        cell.width = 2;
+        let attr = cell.attr;
+        *grid.cell_mut(row, col + 1) = Cell::new(' ', attr, 0);
+        if col + 2 < self.cols {
+            self.cursor.col = col + 2;
+        } else {
+            self.cursor.wrap_next = true;
+        }
+    }
+
+    fn erase_attr(&self) -> Attr {
+        self.cursor.attr.erased()
+    }
+
+    // ---- cursor movement
+
+    fn goto(&mut self, row: usize, col: usize) {
+        let (top, bottom) = if self.cursor.origin {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.cursor.row = (row + top).clamp(top, bottom);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.wrap_next = false;
+    }
+
+    // the origin mode independent position
+    fn goto_absolute(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.rows - 1);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.wrap_next = false;
+    }
+
+    fn move_up(&mut self, n: usize) {
+        // the cursor stops at the top margin if it starts inside the region
+        let top = if self.cursor.row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        };
+        self.cursor.row = self.cursor.row.saturating_sub(n).max(top);
+        self.cursor.wrap_next = false;
+    }
+
+    fn move_down(&mut self, n: usize) {
+        let bottom = if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows - 1
+        };
+        self.cursor.row = (self.cursor.row + n).min(bottom);
+        self.cursor.wrap_next = false;
+    }
+
+    fn move_forward(&mut self, n: usize) {
+        self.cursor.col = (self.cursor.col + n).min(self.cols - 1);
+        self.cursor.wrap_next = false;
+    }
+
+    fn move_backward(&mut self, n: usize) {
+        self.cursor.col = self.cursor.col.saturating_sub(n);
+        self.cursor.wrap_next = false;
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.wrap_next = false;
+    }
+
+    /// IND, move down and scroll up at the bottom margin
+    fn index(&mut self) {
+        if self.cursor.row == self.scroll_bottom {
+            let (top, bottom, attr) = (self.scroll_top, self.scroll_bottom, self.erase_attr());
+            if top == 0 && !self.alt_active {
+                self.push_history(self.primary.row(0).clone());
+            }
+            self.grid_mut().scroll_up(top, bottom, 1, attr);
+        } else if self.cursor.row < self.rows - 1 {
+            self.cursor.row += 1;
+        }
+        self.cursor.wrap_next = false;
+    }
+
+    /// RI, move up and scroll down at the top margin
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.scroll_top {
+            let (top, bottom, attr) = (self.scroll_top, self.scroll_bottom, self.erase_attr());
+            self.grid_mut().scroll_down(top, bottom, 1, attr);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+        self.cursor.wrap_next = false;
+    }
+
+    fn linefeed(&mut self) {
+        self.index();
+        if self.modes.newline {
+            self.carriage_return();
+        }
+    }
+
+    fn tab_forward(&mut self, n: usize) {
+        for _ in 0..n {
+            let next = (self.cursor.col + 1..self.cols).find(|&x| self.tabs[x]);
+            self.cursor.col = next.unwrap_or(self.cols - 1);
+        }
+        self.cursor.wrap_next = false;
+    }
+
+    fn tab_backward(&mut self, n: usize) {
+        for _ in 0..n {
+            let prev = (0..self.cursor.col).rev().find(|&x| self.tabs[x]);
+            self.cursor.col = prev.unwrap_or(0);
+        }
+        self.cursor.wrap_next = false;
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = self.cursor;
+    }
+
+    fn restore_cursor(&mut self) {
+        self.cursor = self.saved;
+        self.cursor.row = self.cursor.row.min(self.rows - 1);
+        self.cursor.col = self.cursor.col.min(self.cols - 1);
+    }
+
+    // ---- editing
+
+    fn erase_display(&mut self, mode: u16) {
+        let (row, col, attr) = (self.cursor.row, self.cursor.col, self.erase_attr());
+        let (rows, cols) = (self.rows, self.cols);
+        let grid = self.grid_mut();
+        match mode {
+            0 => {
+                grid.erase(row, col, cols, attr);
+                grid.erase_rows(row + 1, rows, attr);
+            }
+            1 => {
+                grid.erase_rows(0, row, attr);
+                grid.erase(row, 0, col + 1, attr);
+            }
+            2 => grid.erase_rows(0, rows, attr),
+            // the saved lines only
+            3 => self.clear_history(),
+            _ => (),
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let (row, col, attr) = (self.cursor.row, self.cursor.col, self.erase_attr());
+        let cols = self.cols;
+        let grid = self.grid_mut();
+        match mode {
+            0 => grid.erase(row, col, cols, attr),
+            1 => grid.erase(row, 0, col + 1, attr),
+            2 => grid.erase(row, 0, cols, attr),
+            _ => (),
+        }
+        if mode != 1 {
+            grid.row_mut(row).wrapped = false;
+        }
+    }
+
+    fn insert_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let (bottom, attr) = (self.scroll_bottom, self.erase_attr());
+        self.grid_mut().scroll_down(row, bottom, n, attr);
+        self.carriage_return();
+    }
+
+    fn delete_lines(&mut self, n: usize) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        let (bottom, attr) = (self.scroll_bottom, self.erase_attr());
+        self.grid_mut().scroll_up(row, bottom, n, attr);
+        self.carriage_return();
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows);
+        if top + 1 < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom - 1;
+        } else if top == 0 && bottom == 0 {
+            self.scroll_top = 0;
+            self.scroll_bottom = self.rows - 1;
+        } else {
+            return;
+        }
+        self.goto(0, 0);
+    }
+
+    fn switch_screen(&mut self, alternate: bool, clear: bool) {
+        if self.alt_active == alternate {
+            return;
+        }
+        self.alt_active = alternate;
+        if alternate && clear {
+            let (rows, attr) = (self.rows, self.erase_attr());
+            self.alternate.erase_rows(0, rows, attr);
+        }
+        self.grid_mut().mark_dirty();
+    }
+
+    fn set_mode(&mut self, params: &Params, private: bool, on: bool) {
+        for param in params.iter() {
+            let mode = param[0];
+            match (private, mode) {
+                (false, 4) => self.modes.insert = on,
+                (false, 20) => self.modes.newline = on,
+                (true, 1) => self.modes.app_cursor = on,
+                (true, 5) => {
+                    self.modes.reverse_video = on;
+                    self.grid_mut().mark_dirty();
+                }
+                (true, 6) => {
+                    self.cursor.origin = on;
+                    self.goto(0, 0);
+                }
+                (true, 7) => self.modes.autowrap = on,
+                (true, 25) => self.modes.show_cursor = on,
+                (true, 47) => self.switch_screen(on, false),
+                (true, 1047) => {
+                    if !on && self.alt_active {
+                        let (rows, attr) = (self.rows, self.erase_attr());
+                        self.alternate.erase_rows(0, rows, attr);
+                    }
+                    self.switch_screen(on, false);
+                }
+                (true, 1048) => {
+                    if on {
+                        self.save_cursor()
+                    } else {
+                        self.restore_cursor()
+                    }
+                }
+                (true, 1049) => {
+                    if on {
+                        if !self.alt_active {
+                            self.saved_primary = self.cursor;
+                        }
+                        self.switch_screen(true, true);
+                    } else if self.alt_active {
+                        self.switch_screen(false, false);
+                        self.cursor = self.saved_primary;
+                    }
+                }
+                (true, 1000) | (true, 1002) | (true, 1003) => {
+                    self.modes.mouse = match (on, mode) {
+                        (false, _) => MouseTracking::Off,
+                        (true, 1000) => MouseTracking::Click,
+                        (true, 1002) => MouseTracking::Drag,
+                        _ => MouseTracking::Motion,
+                    }
+                }
+                (true, 1006) => self.modes.mouse_sgr = on,
+                (true, 2004) => self.modes.bracketed_paste = on,
+                _ => trace!("Unsupported mode {} {}", mode, on),
+            }
+        }
+    }
+
+    // OSC 8, the cells printed next link to `uri`, none if empty
+    fn set_link(&mut self, uri: &str) {
+        self.cursor.attr.link = if uri.is_empty() {
+            0
+        } else if let Some(index) = self.links.iter().position(|link| link == uri) {
+            index as u32 + 1
+        } else if self.links.len() < MAX_LINKS {
+            self.links.push(uri.to_owned());
+            self.links.len() as u32
+        } else {
+            0
+        };
+    }
+
+    fn set_sgr(&mut self, params: &Params) {
+        let attr = &mut self.cursor.attr;
+        // the hyperlink is not part of the rendition
+        let reset = Attr {
+            link: attr.link,
+            ..Default::default()
+        };
+        if params.is_empty() {
+            *attr = reset;
+            return;
+        }
+        let mut iter = params.iter();
+        while let Some(param) = iter.next() {
+            match param[0] {
+                0 => *attr = reset,
+                1 => attr.flags.insert(Flags::BOLD),
+                2 => attr.flags.insert(Flags::FAINT),
+                3 => attr.flags.insert(Flags::ITALIC),
+                4 => attr.flags.insert(Flags::UNDERLINE),
+                5 | 6 => attr.flags.insert(Flags::BLINK),
+                7 => attr.flags.insert(Flags::INVERSE),
+                8 => attr.flags.insert(Flags::HIDDEN),
+                9 => attr.flags.insert(Flags::STRIKE),
+                21 => attr.flags.insert(Flags::UNDERLINE),
+                22 => {
+                    attr.flags.remove(Flags::BOLD);
+                    attr.flags.remove(Flags::FAINT);
+                }
+                23 => attr.flags.remove(Flags::ITALIC),
+                24 => attr.flags.remove(Flags::UNDERLINE),
+                25 => attr.flags.remove(Flags::BLINK),
+                27 => attr.flags.remove(Flags::INVERSE),
+                28 => attr.flags.remove(Flags::HIDDEN),
+                29 => attr.flags.remove(Flags::STRIKE),
+                c @ 30..=37 => attr.fg = Color::Indexed((c - 30) as u8),
+                38 => attr.fg = extended_color(param, &mut iter).unwrap_or(attr.fg),
+                39 => attr.fg = Color::Default,
+                c @ 40..=47 => attr.bg = Color::Indexed((c - 40) as u8),
+                48 => attr.bg = extended_color(param, &mut iter).unwrap_or(attr.bg),
+                49 => attr.bg = Color::Default,
+                c @ 90..=97 => attr.fg = Color::Indexed((c - 90 + 8) as u8),
+                c @ 100..=107 => attr.bg = Color::Indexed((c - 100 + 8) as u8),
+                c => trace!("Unsupported SGR {}", c),
+            }
+        }
+    }
+
+    fn report(&mut self, params: &Params, private: bool) {
+        match params.get(0, 0) {
+            // operating status OK
+            5 => self.output.extend_from_slice(b"\x1b[0n"),
+            // cursor position report
+            6 => {
+                let row = if self.cursor.origin {
+                    self.cursor.row - self.scroll_top
+                } else {
+                    self.cursor.row
+                };
+                let reply = if private {
+                    format!("\x1b[?{};{}R", row + 1, self.cursor.col + 1)
+                } else {
+                    format!("\x1b[{};{}R", row + 1, self.cursor.col + 1)
+                };
+                self.output.extend_from_slice(reply.as_bytes());
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Parse the colour of SGR 38/48, both `38;5;n` and `38:5:n` forms are accepted
+fn extended_color<'a>(param: &[u16], iter: &mut impl Iterator<Item = &'a [u16]>) -> Option<Color> {
+    let args: Vec<u16> = if param.len() > 1 {
+        // colon separated, `38:2:<colorspace>:r:g:b` or `38:2:r:g:b`
+        match param[1] {
+            2 if param.len() >= 6 => vec![2, param[3], param[4], param[5]],
+            _ => param[1..].to_vec(),
+        }
+    } else {
+        match iter.next()?.first()? {
+            5 => vec![5, *iter.next()?.first()?],
+            2 => {
+                let mut args = vec![2];
+                for _ in 0..3 {
+                    args.push(*iter.next()?.first()?);
+                }
+                args
+            }
+            _ => return None,
+        }
+    };
+    match args.as_slice() {
+        [5, index, ..] => Some(Color::Indexed(*index as u8)),
+        [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        let c = self.cursor.charsets[self.cursor.shift].map(c);
+        let width = match c.width() {
+            Some(width) => width.min(2),
+            None => return,
+        };
+        // a combining mark or what follows a zero width joiner goes with the
+        // character before it
+        if let Some((row, col)) = self.last_cell() {
+            let cell = self.grid().row(row).cells[col];
+            if width == 0 || cell.text().ends_with(ZWJ) {
+                self.grid_mut().row_mut(row).cells[col].combine(c);
+                if c == VS16 && cell.width == 1 {
+                    self.widen(row, col);
+                }
+                return;
+            }
+        }
+        if width == 0 {
+            return;
+        }
+        let width = width.min(self.cols);
+        if self.cursor.wrap_next && self.modes.autowrap
+            || width == 2 && self.cursor.col + 1 == self.cols && self.modes.autowrap
+        {
+            let row = self.cursor.row;
+            self.grid_mut().row_mut(row).wrapped = true;
+            self.index();
+            self.carriage_return();
+        }
+        let (row, cols, attr) = (self.cursor.row, self.cols, self.cursor.attr);
+        // without autowrap, a wide character is not cut at the right margin
+        let col = self.cursor.col.min(cols - width);
+        let insert = self.modes.insert;
+        let grid = self.grid_mut();
+        if insert {
+            grid.insert_cells(row, col, width, attr);
+        }
+        for x in col..col + width {
+            grid.clear_wide(row, x);
+        }
+        *grid.cell_mut(row, col) = Cell::new(c, attr, width as u8);
+        if width == 2 {
+            *grid.cell_mut(row, col + 1) = Cell::new(' ', attr, 0);
+        }
+        if col + width < cols {
+            self.cursor.col = col + width;
+        } else {
+            self.cursor.col = col + width - 1;
+            self.cursor.wrap_next = true;
+        }
+        self.last_char = Some(c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.bell = true,
+            0x08 => self.move_backward(1),
+            0x09 => self.tab_forward(1),
+            0x0a..=0x0c => self.linefeed(),
+            0x0d => self.carriage_return(),
+            0x0e => self.cursor.shift = 1,
+            0x0f => self.cursor.shift = 0,
+            _ => (),
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        let n = |i| params.get(i, 1) as usize;
+        match (intermediates, action) {
+            ([], '@') => {
+                let (row, col, attr) = (self.cursor.row, self.cursor.col, self.erase_attr());
+                self.grid_mut().insert_cells(row, col, n(0), attr);
+            }
+            ([], 'A') => self.move_up(n(0)),
+            ([], 'B') | ([], 'e') => self.move_down(n(0)),
+            ([], 'C') | ([], 'a') => self.move_forward(n(0)),
+            ([], 'D') => self.move_backward(n(0)),
+            ([], 'E') => {
+                self.move_down(n(0));
+                self.carriage_return();
+            }
+            ([], 'F') => {
+                self.move_up(n(0));
+                self.carriage_return();
+            }
+            ([], 'G') | ([], '`') => {
+                let row = self.cursor.row;
+                self.goto_absolute(row, n(0) - 1);
+            }
+            ([], 'H') | ([], 'f') => self.goto(n(0) - 1, n(1) - 1),
+            ([], 'I') => self.tab_forward(n(0)),
+            ([], 'J') | ([b'?'], 'J') => self.erase_display(params.get(0, 0)),
+            ([], 'K') | ([b'?'], 'K') => self.erase_line(params.get(0, 0)),
+            ([], 'L') => self.insert_lines(n(0)),
+            ([], 'M') => self.delete_lines(n(0)),
+            ([], 'P') => {
+                let (row, col, attr) = (self.cursor.row, self.cursor.col, self.erase_attr());
+                self.grid_mut().delete_cells(row, col, n(0), attr);
+            }
+            ([], 'S') => {
+                let (top, bottom, attr) = (self.scroll_top, self.scroll_bottom, self.erase_attr());
+                self.grid_mut().scroll_up(top, bottom, n(0), attr);
+            }
+            ([], 'T') => {
+                let (top, bottom, attr) = (self.scroll_top, self.scroll_bottom, self.erase_attr());
+                self.grid_mut().scroll_down(top, bottom, n(0), attr);
+            }
+            ([], 'X') => {
+                let (row, col, attr) = (self.cursor.row, self.cursor.col, self.erase_attr());
+                self.grid_mut().erase(row, col, col + n(0), attr);
+            }
+            ([], 'Z') => self.tab_backward(n(0)),
+            ([], 'b') => {
+                if let Some(c) = self.last_char {
+                    for _ in 0..n(0).min(self.cols * self.rows) {
+                        self.print(c);
+                    }
+                }
+            }
+            // primary device attributes, VT220 with ANSI colour
+            ([], 'c') => self.output.extend_from_slice(b"\x1b[?62;22c"),
+            // secondary device attributes
+            ([b'>'], 'c') => self.output.extend_from_slice(b"\x1b[>0;10;1c"),
+            ([], 'd') => {
+                let col = self.cursor.col;
+                self.goto_absolute(n(0) - 1, col);
+                if self.cursor.origin {
+                    self.cursor.row = self.cursor.row.clamp(self.scroll_top, self.scroll_bottom);
+                }
+            }
+            ([], 'g') => match params.get(0, 0) {
+                0 => self.tabs[self.cursor.col] = false,
+                3 => self.tabs.fill(false),
+                _ => (),
+            },
+            ([], 'h') => self.set_mode(params, false, true),
+            ([], 'l') => self.set_mode(params, false, false),
+            ([b'?'], 'h') => self.set_mode(params, true, true),
+            ([b'?'], 'l') => self.set_mode(params, true, false),
+            ([], 'm') => self.set_sgr(params),
+            ([], 'n') => self.report(params, false),
+            ([b'?'], 'n') => self.report(params, true),
+            ([], 'r') => {
+                let top = params.get(0, 1) as usize - 1;
+                let bottom = params.get(1, self.rows as u16) as usize;
+                self.set_scroll_region(top, bottom);
+            }
+            ([], 's') => self.save_cursor(),
+            ([], 'u') => self.restore_cursor(),
+            ([b' '], 'q') => self.cursor_style = params.get(0, 0),
+            // DECSTR, soft reset
+            ([b'!'], 'p') => {
+                self.modes = Modes::default();
+                self.cursor.attr = Attr::default();
+                self.cursor.origin = false;
+                self.cursor.charsets = Default::default();
+                self.cursor.shift = 0;
+                self.scroll_top = 0;
+                self.scroll_bottom = self.rows - 1;
+                self.saved = Cursor::default();
+            }
+            _ => trace!(
+                "Unsupported CSI {:?} {:?} {}",
+                params,
+                String::from_utf8_lossy(intermediates),
+                action
+            ),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.index(),
+            ([], b'E') => {
+                self.index();
+                self.carriage_return();
+            }
+            ([], b'H') => self.tabs[self.cursor.col] = true,
+            ([], b'M') => self.reverse_index(),
+            ([], b'c') => self.reset(),
+            ([], b'=') => self.modes.app_keypad = true,
+            ([], b'>') => self.modes.app_keypad = false,
+            ([b'('], c) | ([b')'], c) => {
+                let g = (intermediates[0] == b')') as usize;
+                self.cursor.charsets[g] = if c == b'0' {
+                    Charset::DecGraphics
+                } else {
+                    Charset::Ascii
+                };
+            }
+            // DECALN, fill the screen with E
+            ([b'#'], b'8') => {
+                let (rows, cols) = (self.rows, self.cols);
+                let grid = self.grid_mut();
+                for y in 0..rows {
+                    for x in 0..cols {
+                        *grid.cell_mut(y, x) = Cell::new('E', Attr::default(), 1);
+                    }
+                }
+                self.goto_absolute(0, 0);
+            }
+            _ => trace!(
+                "Unsupported ESC {:?} {}",
+                String::from_utf8_lossy(intermediates),
+                byte as char
+            ),
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]]) {
+        // the text may contain the separator
+        let text = |params: &[&[u8]]| String::from_utf8_lossy(&params.join(&b';')).into_owned();
+        match params {
+            [b"0" | b"2", title @ ..] => {
+                self.title = Some(text(title).chars().take(MAX_TITLE_LEN).collect());
+            }
+            // icon name
+            [b"1", ..] => (),
+            [b"8", _, uri @ ..] => self.set_link(&text(uri)),
+            [b"52", selection, data] => {
+                let selection = String::from_utf8_lossy(selection).into_owned();
+                if *data == b"?" {
+                    self.clipboard.push(ClipboardRequest::Query(selection));
+                } else if let Ok(data) = Base64::decode_vec(&String::from_utf8_lossy(data)) {
+                    self.clipboard.push(ClipboardRequest::Set(
+                        String::from_utf8_lossy(&data).into_owned(),
+                    ));
+                }
+            }
+            _ => trace!("Unsupported OSC {:?}", params.first()),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+// What is shown of the terminal: the scroll position in the history, the
+// selection and the search matches
+//
+// Positions are (line, col) with the line numbers of [Screen::line], so
+// that they stick to the text while it scrolls
+
+use super::{grid::Row, Screen};
+use regex::{Regex, RegexBuilder};
+
+/// How a cell is highlighted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Selection,
+    Match,
+    CurrentMatch,
+}
+
+/// A match of the search, the columns `start..end` of `line`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Match {
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Default)]
+pub struct View {
+    // lines scrolled back from the bottom
+    offset: usize,
+    // the anchor and the moving end
+    selection: Option<((usize, usize), (usize, usize))>,
+    search: Option<Regex>,
+    current: Option<Match>,
+    changed: bool,
+}
+
+// The columns matched by `regex` in `row`
+fn find_matches(regex: &Regex, row: &Row) -> Vec<(usize, usize)> {
+    let (text, columns) = row.text_columns();
+    regex
+        .find_iter(&text)
+        .filter(|m| !m.is_empty())
+        .map(|m| (columns[m.start()], columns[m.end()]))
+        .collect()
+}
+
+impl View {
+    /// Lines scrolled back, 0 when the bottom is shown
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether everything has to be redrawn
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// The line shown at the top
+    pub fn top(&self, screen: &Screen) -> usize {
+        screen.line_end() - screen.rows() - self.offset
+    }
+
+    /// The row shown at `y`
+    pub fn row<'a>(&self, screen: &'a Screen, y: usize) -> &'a Row {
+        screen
+            .line(self.top(screen) + y)
+            .unwrap_or_else(|| screen.grid().row(y))
+    }
+
+    /// Scroll back by `lines`, or forward if negative
+    pub fn scroll(&mut self, screen: &Screen, lines: isize) {
+        let offset = self
+            .offset
+            .saturating_add_signed(lines)
+            .min(screen.history_len());
+        if offset != self.offset {
+            self.offset = offset;
+            self.changed = true;
+        }
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        if self.offset != 0 {
+            self.offset = 0;
+            self.changed = true;
+        }
+    }
+
+    // Make `line` visible, around the middle of the screen
+    fn reveal(&mut self, screen: &Screen, line: usize) {
+        let top = self.top(screen);
+        if line >= top && line < top + screen.rows() {
+            return;
+        }
+        let bottom = (line + screen.rows() / 2 + 1).min(screen.line_end());
+        self.offset = (screen.line_end() - bottom).min(screen.history_len());
+        self.changed = true;
+    }
+
+    /// `lines` entered the scrollback, the view keeps showing the same lines
+    /// unless it is at the bottom. The history may have been cleared as well
+    pub fn history_changed(&mut self, screen: &Screen, lines: usize) {
+        if self.offset > 0 {
+            self.offset = (self.offset + lines).min(screen.history_len());
+            self.changed = true;
+        }
+        let start = screen.line_start();
+        if self.selection.is_some_and(|(a, b)| a.0.min(b.0) < start) {
+            self.selection = None;
+            self.changed = true;
+        }
+        if self.current.is_some_and(|m| m.line < start) {
+            self.current = None;
+        }
+    }
+
+    // ---- selection
+
+    /// Start a selection at the cell (y, x) of the view
+    pub fn select_start(&mut self, screen: &Screen, y: usize, x: usize) {
+        let point = (self.top(screen) + y, x);
+        self.selection = Some((point, point));
+        self.changed = true;
+    }
+
+    /// Move the end of the selection to the cell (y, x) of the view
+    pub fn select_to(&mut self, screen: &Screen, y: usize, x: usize) {
+        let point = (self.top(screen) + y, x);
+        if let Some((_, end)) = &mut self.selection {
+            if *end != point {
+                *end = point;
+                self.changed = true;
+            }
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() {
+            self.changed = true;
+        }
+    }
+
+    // The selected cells, from the first to the last one included
+    fn selection_range(&self) -> Option<((usize, usize), (usize, usize))> {
+        let (a, b) = self.selection?;
+        if a == b {
+            return None;
+        }
+        Some(if a <= b { (a, b) } else { (b, a) })
+    }
+
+    /// The selected text, wrapped lines are joined
+    pub fn selection_text(&self, screen: &Screen) -> Option<String> {
+        let (start, end) = self.selection_range()?;
+        let mut text = String::new();
+        for line in start.0..=end.0 {
+            let Some(row) = screen.line(line) else {
+                continue;
+            };
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 {
+                end.1 + 1
+            } else {
+                row.cells.len()
+            };
+            let part = row.text_range(from, to);
+            if row.wrapped && line != end.0 {
+                text.push_str(&part);
+            } else {
+                text.push_str(part.trim_end());
+                if line != end.0 {
+                    text.push('\n');
+                }
+            }
+        }
+        Some(text)
+    }
+
+    // ---- search
+
+    /// Search `pattern` from the bottom up, returns whether it is found
+    ///
+    /// The pattern is a regular expression if `regex` is set, literal text
+    /// otherwise
+    pub fn search(
+        &mut self,
+        screen: &Screen,
+        pattern: &str,
+        regex: bool,
+        case_sensitive: bool,
+    ) -> Result<bool, regex::Error> {
+        self.changed = true;
+        self.current = None;
+        if pattern.is_empty() {
+            self.search = None;
+            return Ok(false);
+        }
+        let pattern = if regex {
+            pattern.to_owned()
+        } else {
+            regex::escape(pattern)
+        };
+        self.search = Some(
+            RegexBuilder::new(&pattern)
+                .case_insensitive(!case_sensitive)
+                .build()?,
+        );
+        Ok(self.find(screen, true))
+    }
+
+    /// Move to the next match, upwards if `backward`, returns whether any
+    pub fn find(&mut self, screen: &Screen, backward: bool) -> bool {
+        let Some(regex) = &self.search else {
+            return false;
+        };
+        let (start, end) = (screen.line_start(), screen.line_end());
+        let found = if backward {
+            let (from, col) = match self.current {
+                Some(m) => (m.line, m.start),
+                None => (end - 1, usize::MAX),
+            };
+            (start..=from).rev().find_map(|line| {
+                let matches = find_matches(regex, screen.line(line)?);
+                let limit = if line == from { col } else { usize::MAX };
+                let (s, e) = matches.into_iter().rev().find(|m| m.0 < limit)?;
+                Some(Match {
+                    line,
+                    start: s,
+                    end: e,
+                })
+            })
+        } else {
+            let Some(current) = self.current else {
+                return false;
+            };
+            (current.line..end).find_map(|line| {
+                let matches = find_matches(regex, screen.line(line)?);
+                let (s, e) = matches
+                    .into_iter()
+                    .find(|m| line != current.line || m.0 > current.start)?;
+                Some(Match {
+                    line,
+                    start: s,
+                    end: e,
+                })
+            })
+        };
+        match found {
+            Some(m) => {
+                self.current = Some(m);
+                self.reveal(screen, m.line);
+                self.changed = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn close_search(&mut self) {
+        if self.search.take().is_some() {
+            self.current = None;
+            self.changed = true;
+        }
+    }
+
+    /// Whether the rows have to be drawn through [View::highlights]
+    pub fn has_highlights(&self) -> bool {
+        self.search.is_some() || self.selection_range().is_some()
+    }
+
+    /// The highlighted columns `start..end` of the row at `y`
+    pub fn highlights(&self, screen: &Screen, y: usize) -> Vec<(usize, usize, Highlight)> {
+        let line = self.top(screen) + y;
+        let mut out = Vec::new();
+        if let Some(((l0, c0), (l1, c1))) = self.selection_range() {
+            if (l0..=l1).contains(&line) {
+                let from = if line == l0 { c0 } else { 0 };
+                let to = if line == l1 { c1 + 1 } else { usize::MAX };
+                out.push((from, to, Highlight::Selection));
+            }
+        }
+        if let Some(regex) = &self.search {
+            let row = self.row(screen, y);
+            for (start, end) in find_matches(regex, row) {
+                let current = self
+                    .current
+                    .is_some_and(|m| m.line == line && m.start == start);
+                let kind = if current {
+                    Highlight::CurrentMatch
+                } else {
+                    Highlight::Match
+                };
+                out.push((start, end, kind));
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,14 @@
+//! The helpers shared by the pages of the clients
+
+use web_sys::UrlSearchParams;
+
+/// The non empty parameter `name` of the query string of the page
+pub fn query_param(name: &str) -> Option<String> {
+    web_sys::window()?
+        .location()
+        .search()
+        .ok()
+        .and_then(|search| UrlSearchParams::new_with_str(&search).ok())
+        .and_then(|params| params.get(name))
+        .filter(|value| !value.is_empty())
+}
@@ -1,28 +1,92 @@
 // use crate::input::{X11Event, KeyEventType, MouseEventType};
 // use rdp::core::event::BitmapEvent;
 use crate::{
-    x11cursor::MouseUtils,
+    framebuffer::Framebuffer,
+    vnc::{Rect, X11Event},
+    x11cursor::{MouseUtils, Wheel},
     x11keyboard::{self, KeyboardUtils},
 };
 
-use std::rc::Rc;
+use js_sys::{Array, Uint8Array};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
 use tokio::sync::mpsc;
-use vnc::{Rect, X11Event};
+use tracing::error;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{Clamped, JsCast};
+use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    CanvasRenderingContext2d, HtmlButtonElement, HtmlCanvasElement, HtmlImageElement,
-    KeyboardEvent, MouseEvent,
+    Blob, BlobPropertyBag, CanvasRenderingContext2d, Event, HtmlButtonElement, HtmlCanvasElement,
+    HtmlInputElement, ImageBitmap, KeyboardEvent, MouseEvent, WheelEvent,
 };
 
+/// How the desktop is fitted into the page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// One to one, scrolled when it is larger
+    Off,
+    /// Scaled to fit the page
+    Scale,
+    /// The server resizes the desktop to the page
+    Remote,
+}
+
+impl Scaling {
+    pub fn from_param(param: Option<&str>) -> Self {
+        match param {
+            Some("off") => Scaling::Off,
+            Some("remote") => Scaling::Remote,
+            _ => Scaling::Scale,
+        }
+    }
+}
+
+// The milliseconds without resizing the window before the desktop is
+// resized, so that the server does not resize on each step of a drag
+const RESIZE_DELAY: i32 = 200;
+
+// A canvas out of the page
+struct Scratch {
+    canvas: HtmlCanvasElement,
+    ctx: CanvasRenderingContext2d,
+}
+
+impl Scratch {
+    fn new() -> Self {
+        let canvas = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<HtmlCanvasElement>()
+            .unwrap();
+        let ctx = canvas
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        Self { canvas, ctx }
+    }
+}
+
 struct Canvas {
     canvas: HtmlCanvasElement,
     ctx: CanvasRenderingContext2d,
-    output: mpsc::Sender<X11Event>,
+    output: mpsc::UnboundedSender<X11Event>,
+    scaling: Scaling,
+    framebuffer: RefCell<Framebuffer>,
+    scratch: Scratch,
+    // the text both clipboards had last, not sent again on focus
+    clipboard: Rc<RefCell<String>>,
 }
 
 impl Canvas {
-    fn new(sender: mpsc::Sender<X11Event>) -> Self {
+    fn new(sender: mpsc::UnboundedSender<X11Event>, scaling: Scaling) -> Self {
         let document = web_sys::window().unwrap().document().unwrap();
         let canvas = document.get_element_by_id("vnc-canvas").unwrap();
         let canvas: HtmlCanvasElement = canvas
@@ -39,6 +103,10 @@ impl Canvas {
             canvas,
             ctx,
             output: sender,
+            scaling,
+            framebuffer: RefCell::new(Framebuffer::new()),
+            scratch: Scratch::new(),
+            clipboard: Rc::new(RefCell::new(String::new())),
         }
     }
 
@@ -46,23 +114,68 @@ impl Canvas {
         // set hight & width
         self.canvas.set_height(height);
         self.canvas.set_width(width);
-        self.ctx.rect(0_f64, 0_f64, width as f64, height as f64);
-        self.ctx.fill();
+        self.framebuffer
+            .borrow_mut()
+            .resize(width as u16, height as u16);
+        fit(&self.canvas, self.scaling);
     }
 
     fn bind(&self) {
+        // the page has been resized
+        let canvas = self.canvas.clone();
+        let scaling = self.scaling;
+        let sender = self.output.clone();
+        let timer = Rc::new(std::cell::Cell::new(0));
+        let window_resize = move |_: Event| match scaling {
+            Scaling::Off => (),
+            Scaling::Scale => fit(&canvas, scaling),
+            Scaling::Remote => {
+                let window = web_sys::window().unwrap();
+                window.clear_timeout_with_handle(timer.get());
+                let sender = sender.clone();
+                let resize = Closure::once_into_js(move || request_resize(&sender));
+                timer.set(
+                    window
+                        .set_timeout_with_callback_and_timeout_and_arguments_0(
+                            resize.unchecked_ref(),
+                            RESIZE_DELAY,
+                        )
+                        .unwrap(),
+                );
+            }
+        };
+
+        let handler = Box::new(window_resize) as Box<dyn FnMut(_)>;
+
+        let cb = Closure::wrap(handler);
+
+        web_sys::window()
+            .unwrap()
+            .add_event_listener_with_callback("resize", cb.as_ref().unchecked_ref())
+            .unwrap();
+        cb.forget();
+        if scaling == Scaling::Remote {
+            request_resize(&self.output);
+        }
+
+        // the keysyms of the keys held, by their physical key, so that a key
+        // is released with the keysym it was pressed with, whatever the
+        // modifiers became meanwhile
+        let pressed: Rc<RefCell<HashMap<String, u32>>> = Rc::new(RefCell::new(HashMap::new()));
         let sender = self.output.clone();
+        let pressed_down = pressed.clone();
         let key_down = move |e: KeyboardEvent| {
-            let sender = sender.clone();
             e.prevent_default();
             e.stop_propagation();
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::KeyEvent(
-                        (KeyboardUtils::get_keysym(e), true).into(),
-                    ))
-                    .await;
-            });
+            let (keysym, scancode) = KeyboardUtils::get_key(&e);
+            if keysym.is_none() && scancode == 0 {
+                return;
+            }
+            let keysym = *pressed_down
+                .borrow_mut()
+                .entry(key_id(&e))
+                .or_insert(keysym.unwrap_or(0));
+            let _ = sender.send(X11Event::KeyEvent((keysym, scancode, true).into()));
         };
 
         let handler = Box::new(key_down) as Box<dyn FnMut(_)>;
@@ -76,16 +189,15 @@ impl Canvas {
 
         let sender = self.output.clone();
         let key_up = move |e: KeyboardEvent| {
-            let sender = sender.clone();
             e.prevent_default();
             e.stop_propagation();
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::KeyEvent(
-                        (KeyboardUtils::get_keysym(e), false).into(),
-                    ))
-                    .await;
-            });
+            let (keysym, scancode) = KeyboardUtils::get_key(&e);
+            let keysym = match pressed.borrow_mut().remove(&key_id(&e)) {
+                Some(keysym) => keysym,
+                None if keysym.is_none() && scancode == 0 => return,
+                None => keysym.unwrap_or(0),
+            };
+            let _ = sender.send(X11Event::KeyEvent((keysym, scancode, false).into()));
         };
 
         let handler = Box::new(key_up) as Box<dyn FnMut(_)>;
@@ -108,29 +220,14 @@ impl Canvas {
             .map_err(|_| ())
             .unwrap();
         let ctrl_alt_del = move || {
-            let sender = sender.clone();
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::KeyEvent((x11keyboard::XK_Control_L, true).into()))
-                    .await;
-                let _ = sender
-                    .send(X11Event::KeyEvent((x11keyboard::XK_Alt_L, true).into()))
-                    .await;
-                let _ = sender
-                    .send(X11Event::KeyEvent((x11keyboard::XK_Delete, true).into()))
-                    .await;
-                let _ = sender
-                    .send(X11Event::KeyEvent((x11keyboard::XK_Delete, false).into()))
-                    .await;
-                let _ = sender
-                    .send(X11Event::KeyEvent((x11keyboard::XK_Alt_L, false).into()))
-                    .await;
-                let _ = sender
-                    .send(X11Event::KeyEvent(
-                        (x11keyboard::XK_Control_L, false).into(),
-                    ))
-                    .await;
-            });
+            let _ = sender.send(X11Event::KeyEvent((x11keyboard::XK_Control_L, true).into()));
+            let _ = sender.send(X11Event::KeyEvent((x11keyboard::XK_Alt_L, true).into()));
+            let _ = sender.send(X11Event::KeyEvent((x11keyboard::XK_Delete, true).into()));
+            let _ = sender.send(X11Event::KeyEvent((x11keyboard::XK_Delete, false).into()));
+            let _ = sender.send(X11Event::KeyEvent((x11keyboard::XK_Alt_L, false).into()));
+            let _ = sender.send(X11Event::KeyEvent(
+                (x11keyboard::XK_Control_L, false).into(),
+            ));
         };
         let handler = Box::new(ctrl_alt_del) as Box<dyn FnMut()>;
 
@@ -139,25 +236,62 @@ impl Canvas {
         ctrl_alt_del_btn.set_onclick(Some(cb.as_ref().unchecked_ref()));
         cb.forget();
 
-        // On a conventional mouse, buttons 1, 2, and 3 correspond to the left,
-        // middle, and right buttons on the mouse.  On a wheel mouse, each step
-        // of the wheel upwards is represented by a press and release of button
-        // 4, and each step downwards is represented by a press and release of
-        // button 5.
+        // the text of the clipboard panel goes to the server when sent, or
+        // when the canvas is focused if it is to be kept in sync
+        let document = web_sys::window().unwrap().document().unwrap();
+        let sender = self.output.clone();
+        let clipboard = self.clipboard.clone();
+        let clipboard_send = move || {
+            let text = crate::getClipBoard();
+            *clipboard.borrow_mut() = text.clone();
+            let _ = sender.send(X11Event::CopyText(text));
+        };
+        let handler = Box::new(clipboard_send) as Box<dyn FnMut()>;
+
+        let cb = Closure::wrap(handler);
+
+        document
+            .get_element_by_id("clipboardsend")
+            .unwrap()
+            .dyn_into::<HtmlButtonElement>()
+            .map_err(|_| ())
+            .unwrap()
+            .set_onclick(Some(cb.as_ref().unchecked_ref()));
+        cb.forget();
+
+        let sync = document
+            .get_element_by_id("clipboardsync")
+            .unwrap()
+            .dyn_into::<HtmlInputElement>()
+            .map_err(|_| ())
+            .unwrap();
+        let sender = self.output.clone();
+        let clipboard = self.clipboard.clone();
+        let focus = move |_: Event| {
+            let text = crate::getClipBoard();
+            if !sync.checked() || *clipboard.borrow() == text {
+                return;
+            }
+            *clipboard.borrow_mut() = text.clone();
+            let _ = sender.send(X11Event::CopyText(text));
+        };
+
+        let handler = Box::new(focus) as Box<dyn FnMut(_)>;
+
+        let cb = Closure::wrap(handler);
+
+        self.canvas
+            .add_event_listener_with_callback("focus", cb.as_ref().unchecked_ref())
+            .unwrap();
+        cb.forget();
 
-        // to do:
-        // calculate relation position
         let sender = self.output.clone();
+        let canvas = self.canvas.clone();
         let mouse_move = move |e: MouseEvent| {
-            let sender = sender.clone();
             e.prevent_default();
             e.stop_propagation();
-            let (x, y, mask) = MouseUtils::get_mouse_sym(e);
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::PointerEvent((x, y, mask).into()))
-                    .await;
-            });
+            let (x, y, mask) = MouseUtils::get_mouse_sym(e, &canvas);
+            let _ = sender.send(X11Event::PointerEvent((x, y, mask).into()));
         };
 
         let handler = Box::new(mouse_move) as Box<dyn FnMut(_)>;
@@ -170,16 +304,12 @@ impl Canvas {
         cb.forget();
 
         let sender = self.output.clone();
+        let canvas = self.canvas.clone();
         let mouse_down = move |e: MouseEvent| {
-            let sender = sender.clone();
             // e.prevent_default();
             e.stop_propagation();
-            let (x, y, mask) = MouseUtils::get_mouse_sym(e);
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::PointerEvent((x, y, mask).into()))
-                    .await;
-            });
+            let (x, y, mask) = MouseUtils::get_mouse_sym(e, &canvas);
+            let _ = sender.send(X11Event::PointerEvent((x, y, mask).into()));
         };
 
         let handler = Box::new(mouse_down) as Box<dyn FnMut(_)>;
@@ -192,16 +322,12 @@ impl Canvas {
         cb.forget();
 
         let sender = self.output.clone();
+        let canvas = self.canvas.clone();
         let mouse_up = move |e: MouseEvent| {
-            let sender = sender.clone();
             e.prevent_default();
             e.stop_propagation();
-            let (x, y, mask) = MouseUtils::get_mouse_sym(e);
-            futures::executor::block_on(async move {
-                let _ = sender
-                    .send(X11Event::PointerEvent((x, y, mask).into()))
-                    .await;
-            });
+            let (x, y, mask) = MouseUtils::get_mouse_sym(e, &canvas);
+            let _ = sender.send(X11Event::PointerEvent((x, y, mask).into()));
         };
 
         let handler = Box::new(mouse_up) as Box<dyn FnMut(_)>;
@@ -213,6 +339,32 @@ impl Canvas {
             .unwrap();
         cb.forget();
 
+        // On a wheel mouse, each step of the wheel upwards is represented by a
+        // press and release of button 4, and each step downwards is
+        // represented by a press and release of button 5.
+        let sender = self.output.clone();
+        let canvas = self.canvas.clone();
+        let mut wheel = Wheel::default();
+        let mouse_wheel = move |e: WheelEvent| {
+            e.prevent_default();
+            e.stop_propagation();
+            let steps = wheel.steps(e.delta_x(), e.delta_y(), e.delta_mode());
+            let (x, y, mask) = MouseUtils::get_mouse_sym(e.into(), &canvas);
+            for button in steps {
+                let _ = sender.send(X11Event::PointerEvent((x, y, mask | button).into()));
+                let _ = sender.send(X11Event::PointerEvent((x, y, mask).into()));
+            }
+        };
+
+        let handler = Box::new(mouse_wheel) as Box<dyn FnMut(_)>;
+
+        let cb = Closure::wrap(handler);
+
+        self.canvas
+            .add_event_listener_with_callback("wheel", cb.as_ref().unchecked_ref())
+            .unwrap();
+        cb.forget();
+
         let get_context_menu = move |e: MouseEvent| {
             e.prevent_default();
             e.stop_propagation();
@@ -228,83 +380,160 @@ impl Canvas {
         cb.forget();
     }
 
-    fn draw(&self, rect: Rect, mut data: Vec<u8>) {
-        let mut idx = 0;
-        while idx < data.len() {
-            data[idx + 3] = 255;
-            idx += 4;
-        }
-        let data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
-            Clamped(&data),
-            rect.width as u32,
-            rect.height as u32,
-        );
-
-        let data = data.unwrap();
-        let _ = self.ctx.put_image_data(&data, rect.x as f64, rect.y as f64);
+    fn draw(&self, rect: Rect, data: Vec<u8>) {
+        self.framebuffer.borrow_mut().put(rect, &data);
     }
 
     fn copy(&self, dst: Rect, src: Rect) {
-        //copy
-        let _ = self
+        self.framebuffer.borrow_mut().copy(dst, src);
+    }
+
+    // Draw what has changed, once per animation frame
+    fn render(&self) {
+        for (rect, data) in self.framebuffer.borrow_mut().take_dirty() {
+            let data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+                Clamped(&data),
+                rect.width as u32,
+                rect.height as u32,
+            )
+            .unwrap();
+            let _ = self.ctx.put_image_data(&data, rect.x as f64, rect.y as f64);
+        }
+    }
+
+    // The pixels of a decoded JPEG go through a canvas of its size, to be
+    // put to the desktop in the order of the updates
+    fn put_bitmap(&self, rect: Rect, bitmap: ImageBitmap) {
+        let scratch = &self.scratch;
+        scratch.canvas.set_width(rect.width as u32);
+        scratch.canvas.set_height(rect.height as u32);
+        let _ = scratch
             .ctx
-            .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                &self.canvas,
-                src.x as f64,
-                src.y as f64,
-                dst.width as f64,
-                dst.height as f64,
-                dst.x as f64,
-                dst.y as f64,
-                dst.width as f64,
-                dst.height as f64,
-            );
+            .draw_image_with_image_bitmap(&bitmap, 0_f64, 0_f64);
+        bitmap.close();
+        if let Ok(data) =
+            scratch
+                .ctx
+                .get_image_data(0_f64, 0_f64, rect.width as f64, rect.height as f64)
+        {
+            self.draw(rect, data.data().0);
+        }
     }
 
-    fn jpeg(&self, rect: Rect, data: Vec<u8>) {
-        let image = HtmlImageElement::new().unwrap();
-        let base64 = crate::utils::base64_encode(&data);
-        image.set_src(&format!(
-            "data:image/jpeg;base64,{}",
-            std::str::from_utf8(&base64).unwrap()
-        ));
-        let _ = self.ctx.draw_image_with_html_image_element_and_dw_and_dh(
-            &image,
-            rect.x as f64,
-            rect.y as f64,
-            rect.width as f64,
-            rect.height as f64,
+    // The cursor of the server is the one of the canvas, so that it moves
+    // along with the mouse without waiting for the server
+    fn set_cursor(&self, hotspot: Rect, data: Vec<u8>) {
+        let style = self.canvas.style();
+        if hotspot.width == 0 || hotspot.height == 0 {
+            let _ = style.set_property("cursor", "none");
+            return;
+        }
+        let cursor = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("canvas")
+            .unwrap()
+            .dyn_into::<HtmlCanvasElement>()
+            .unwrap();
+        cursor.set_width(hotspot.width as u32);
+        cursor.set_height(hotspot.height as u32);
+        let ctx = cursor
+            .get_context("2d")
+            .unwrap()
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+        let data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&data),
+            hotspot.width as u32,
+            hotspot.height as u32,
+        )
+        .unwrap();
+        let _ = ctx.put_image_data(&data, 0_f64, 0_f64);
+
+        // the hotspot has to be in the image, or the whole value is ignored
+        let _ = style.set_property(
+            "cursor",
+            &format!(
+                "url({}) {} {}, default",
+                cursor.to_data_url().unwrap(),
+                hotspot.x.min(hotspot.width - 1),
+                hotspot.y.min(hotspot.height - 1)
+            ),
         );
     }
 
     fn close(&self) {
-        self.ctx.fill();
+        self.ctx.fill_rect(
+            0_f64,
+            0_f64,
+            self.canvas.width() as f64,
+            self.canvas.height() as f64,
+        );
     }
 }
 
-pub struct CanvasUtils {
-    inner: Rc<Canvas>,
-    bind: bool,
+// The element of the page the desktop is shown in
+fn container() -> web_sys::Element {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("canvas")
+        .unwrap()
 }
 
-impl Clone for CanvasUtils {
-    fn clone(&self) -> Self {
-        Self {
-            inner: self.inner.clone(),
-            bind: self.bind,
-        }
+// Size the canvas on the page, its pixels stay the ones of the desktop
+fn fit(canvas: &HtmlCanvasElement, scaling: Scaling) {
+    let style = canvas.style();
+    if scaling != Scaling::Scale {
+        let _ = style.remove_property("width");
+        let _ = style.remove_property("height");
+        return;
     }
+    let container = container();
+    let (width, height) = (canvas.width().max(1) as f64, canvas.height().max(1) as f64);
+    let scale =
+        (container.client_width() as f64 / width).min(container.client_height() as f64 / height);
+    let _ = style.set_property("width", &format!("{}px", (width * scale).floor()));
+    let _ = style.set_property("height", &format!("{}px", (height * scale).floor()));
+}
+
+// Ask the server for a desktop of the size of the page
+fn request_resize(sender: &mpsc::UnboundedSender<X11Event>) {
+    let container = container();
+    let width = container.client_width().clamp(1, u16::MAX as i32) as u16;
+    let height = container.client_height().clamp(1, u16::MAX as i32) as u16;
+    let _ = sender.send(X11Event::Resize((width, height).into()));
+}
+
+// An update waiting for a JPEG before it
+enum Pending {
+    Draw(Rect, Vec<u8>),
+    Copy(Rect, Rect),
+    Jpeg(Rect, JsFuture),
+}
+
+pub struct CanvasUtils {
+    inner: Rc<Canvas>,
+    bind: bool,
+    // the updates from the first JPEG still decoding
+    pending: VecDeque<Pending>,
 }
 
 impl CanvasUtils {
-    pub fn new(sender: mpsc::Sender<X11Event>) -> Self {
+    pub fn new(sender: mpsc::UnboundedSender<X11Event>, scaling: Scaling) -> Self {
         Self {
-            inner: Rc::new(Canvas::new(sender)),
+            inner: Rc::new(Canvas::new(sender, scaling)),
             bind: false,
+            pending: VecDeque::new(),
         }
     }
 
     pub fn init(&mut self, width: u32, height: u32) {
+        // the server redraws the whole desktop after it is resized
+        self.pending.clear();
         self.inner.as_ref().set_resolution(width, height);
         if !self.bind {
             self.inner.as_ref().bind();
@@ -312,19 +541,93 @@ impl CanvasUtils {
         }
     }
 
-    pub fn draw(&self, rect: Rect, data: Vec<u8>) {
-        self.inner.as_ref().draw(rect, data);
+    pub fn draw(&mut self, rect: Rect, data: Vec<u8>) {
+        if self.pending.is_empty() {
+            self.inner.as_ref().draw(rect, data);
+        } else {
+            self.pending.push_back(Pending::Draw(rect, data));
+        }
     }
 
-    pub fn copy(&self, dst: Rect, src: Rect) {
-        self.inner.as_ref().copy(dst, src);
+    pub fn copy(&mut self, dst: Rect, src: Rect) {
+        if self.pending.is_empty() {
+            self.inner.as_ref().copy(dst, src);
+        } else {
+            self.pending.push_back(Pending::Copy(dst, src));
+        }
     }
 
-    pub fn jpeg(&self, rect: Rect, data: Vec<u8>) {
-        self.inner.as_ref().jpeg(rect, data);
+    /// Start decoding a JPEG, the updates after it wait for it
+    pub fn jpeg(&mut self, rect: Rect, data: Vec<u8>) {
+        let bytes = Array::of1(&Uint8Array::from(&data[..]));
+        let options = BlobPropertyBag::new();
+        options.set_type("image/jpeg");
+        let blob = Blob::new_with_u8_array_sequence_and_options(&bytes, &options).unwrap();
+        let bitmap = web_sys::window()
+            .unwrap()
+            .create_image_bitmap_with_blob(&blob)
+            .unwrap();
+        self.pending
+            .push_back(Pending::Jpeg(rect, JsFuture::from(bitmap)));
+    }
+
+    pub fn decoding(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Wait for the first JPEG, then put it and the updates after it up to
+    /// the next JPEG
+    ///
+    /// It is cancel safe, the JPEG stays first until it is decoded
+    pub async fn decoded(&mut self) {
+        let bitmap = match self.pending.front_mut() {
+            Some(Pending::Jpeg(_, bitmap)) => bitmap.await,
+            _ => return futures::future::pending().await,
+        };
+        if let Some(Pending::Jpeg(rect, _)) = self.pending.pop_front() {
+            match bitmap {
+                Ok(bitmap) => self
+                    .inner
+                    .as_ref()
+                    .put_bitmap(rect, bitmap.unchecked_into()),
+                Err(e) => error!("Invalid jpeg: {:?}", e),
+            }
+        }
+        while let Some(pending) = self.pending.pop_front() {
+            match pending {
+                Pending::Draw(rect, data) => self.inner.as_ref().draw(rect, data),
+                Pending::Copy(dst, src) => self.inner.as_ref().copy(dst, src),
+                jpeg => {
+                    self.pending.push_front(jpeg);
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn render(&self) {
+        self.inner.as_ref().render();
+    }
+
+    pub fn set_cursor(&self, hotspot: Rect, data: Vec<u8>) {
+        self.inner.as_ref().set_cursor(hotspot, data);
+    }
+
+    /// The text the server put to the clipboard
+    pub fn set_clipboard(&self, text: &str) {
+        *self.inner.as_ref().clipboard.borrow_mut() = text.to_owned();
     }
 
     pub fn close(&self) {
         self.inner.as_ref().close()
     }
 }
+
+// The physical key of a key event, or what it typed if the browser does not
+// tell
+fn key_id(e: &KeyboardEvent) -> String {
+    match e.code() {
+        code if code.is_empty() => e.key(),
+        code => code,
+    }
+}
@@ -0,0 +1,186 @@
+// The RGBA pixels of the desktop, the canvas is only drawn from the parts
+// which have changed at each animation frame
+
+use crate::vnc::Rect;
+
+// More dirty rects are merged into one
+const MAX_DIRTY: usize = 16;
+
+pub struct Framebuffer {
+    width: u16,
+    height: u16,
+    data: Vec<u8>,
+    dirty: Vec<Rect>,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            data: Vec::new(),
+            dirty: Vec::new(),
+        }
+    }
+
+    /// A black desktop of this size
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        self.data = [0, 0, 0, 255].repeat(width as usize * height as usize);
+        self.dirty.clear();
+        self.mark(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+    }
+
+    /// Put the pixels of `rect`, which are cut to the desktop
+    pub fn put(&mut self, rect: Rect, data: &[u8]) {
+        let clipped = self.clip(rect);
+        let stride = rect.width as usize * 4;
+        let row = clipped.width as usize * 4;
+        for y in 0..clipped.height as usize {
+            let from = y * stride;
+            let to = self.offset(clipped.x, clipped.y + y as u16);
+            self.data[to..to + row].copy_from_slice(&data[from..from + row]);
+        }
+        self.mark(clipped);
+    }
+
+    /// Copy the pixels of `src` to the position of `dst`, in the order of
+    /// the other updates
+    pub fn copy(&mut self, dst: Rect, src: Rect) {
+        let src = self.clip(src);
+        let dst = self.clip(Rect {
+            width: src.width,
+            height: src.height,
+            ..dst
+        });
+        let row = dst.width as usize * 4;
+        // the rects may overlap, the rows are not overwritten before copied
+        let rows: Box<dyn Iterator<Item = u16>> = if dst.y > src.y {
+            Box::new((0..dst.height).rev())
+        } else {
+            Box::new(0..dst.height)
+        };
+        for y in rows {
+            let from = self.offset(src.x, src.y + y);
+            let to = self.offset(dst.x, dst.y + y);
+            self.data.copy_within(from..from + row, to);
+        }
+        self.mark(dst);
+    }
+
+    /// The rects changed since the last call, and the pixels of each one
+    pub fn take_dirty(&mut self) -> Vec<(Rect, Vec<u8>)> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty
+            .into_iter()
+            .map(|rect| {
+                let row = rect.width as usize * 4;
+                let mut data = Vec::with_capacity(row * rect.height as usize);
+                for y in rect.y..rect.y + rect.height {
+                    let from = self.offset(rect.x, y);
+                    data.extend_from_slice(&self.data[from..from + row]);
+                }
+                (rect, data)
+            })
+            .collect()
+    }
+
+    fn offset(&self, x: u16, y: u16) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    fn clip(&self, rect: Rect) -> Rect {
+        let x = rect.x.min(self.width);
+        let y = rect.y.min(self.height);
+        Rect {
+            x,
+            y,
+            width: rect.width.min(self.width - x),
+            height: rect.height.min(self.height - y),
+        }
+    }
+
+    fn mark(&mut self, rect: Rect) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.dirty.push(rect);
+        if self.dirty.len() > MAX_DIRTY {
+            let bounds = self.dirty.iter().fold(rect, |bounds, rect| {
+                let x = bounds.x.min(rect.x);
+                let y = bounds.y.min(rect.y);
+                Rect {
+                    x,
+                    y,
+                    width: (bounds.x + bounds.width).max(rect.x + rect.width) - x,
+                    height: (bounds.y + bounds.height).max(rect.y + rect.height) - y,
+                }
+            });
+            self.dirty = vec![bounds];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rect(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // the red of each pixel
+    fn reds(fb: &Framebuffer) -> Vec<u8> {
+        fb.data.chunks(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn test_put() {
+        let mut fb = Framebuffer::new();
+        fb.resize(3, 2);
+        assert_eq!(
+            fb.take_dirty(),
+            [(rect(0, 0, 3, 2), [0, 0, 0, 255].repeat(6))]
+        );
+
+        // cut to the desktop
+        fb.put(rect(2, 1, 2, 1), &[1, 0, 0, 255, 2, 0, 0, 255]);
+        assert_eq!(reds(&fb), [0, 0, 0, 0, 0, 1]);
+        assert_eq!(fb.take_dirty(), [(rect(2, 1, 1, 1), vec![1, 0, 0, 255])]);
+        assert!(fb.take_dirty().is_empty());
+
+        for x in 0..=MAX_DIRTY as u16 {
+            fb.put(rect(x % 3, 0, 1, 1), &[9, 9, 9, 255]);
+        }
+        let dirty = fb.take_dirty();
+        assert_eq!(dirty.len(), 1);
+        assert_eq!(dirty[0].0, rect(0, 0, 3, 1));
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut fb = Framebuffer::new();
+        fb.resize(3, 3);
+        let data: Vec<u8> = (1..=9).flat_map(|red| [red, 0, 0, 255]).collect();
+        fb.put(rect(0, 0, 3, 3), &data);
+
+        // down and right, over itself
+        fb.copy(rect(1, 1, 2, 2), rect(0, 0, 2, 2));
+        assert_eq!(reds(&fb), [1, 2, 3, 4, 1, 2, 7, 4, 5]);
+        // then a raw rect which was sent after it
+        fb.put(rect(0, 0, 1, 1), &[0, 0, 0, 255]);
+        fb.copy(rect(0, 1, 3, 2), rect(0, 0, 3, 2));
+        assert_eq!(reds(&fb), [0, 2, 3, 0, 2, 3, 4, 1, 2]);
+    }
+}
@@ -1,28 +1,47 @@
 mod canvas;
+mod framebuffer;
+mod status;
 mod utils;
+pub mod vnc;
 mod x11cursor;
 mod x11keyboard;
 
-use ::vnc::{client::connector::VncConnector, PixelFormat, VncEncoding, VncEvent, X11Event};
-use canvas::CanvasUtils;
-use futures::StreamExt;
+use canvas::{CanvasUtils, Scaling};
+use futures::future::OptionFuture;
+use status::{choose, set_status};
+use tokio::sync::oneshot;
 use tracing::{error, info};
 use tracing_wasm::WASMLayerConfigBuilder;
+use vnc::{msg, VncConnector, VncEncoding, VncEvent, VncVersion, X11Event};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 use ws_stream_wasm::WsMeta;
 
 #[wasm_bindgen]
 extern "C" {
-    fn alert(s: &str);
     pub fn setClipBoard(s: String);
     pub fn getClipBoard() -> String;
     fn prompt(msg: &str) -> String;
 }
 
+// Let the user pick one of the security types of the server, the ones we
+// cannot do are shown disabled
+async fn choose_security(types: Vec<u8>) -> u8 {
+    let options: Vec<(String, bool)> = types
+        .iter()
+        .map(|&security| {
+            (
+                msg::security_name(security),
+                msg::SUPPORTED_SECURITY.contains(&security),
+            )
+        })
+        .collect();
+    types[choose("Security type: ", &options).await]
+}
+
 fn run() -> Result<(), JsValue> {
     spawn_local(async move {
-        let vnc = loop {
+        let mut vnc = loop {
             // connect
             let url = format!(
                 "{scheme}://{host}/websockify",
@@ -41,42 +60,68 @@ fn run() -> Result<(), JsValue> {
             );
 
             // start websocket
-            let (_ws, wsio) = WsMeta::connect(url, vec!["binary"]).await.unwrap();
+            set_status(&format!("Connecting to {}", url));
+            let wsio = match WsMeta::connect(url, vec!["binary"]).await {
+                Ok((_ws, wsio)) => wsio,
+                Err(e) => {
+                    error!("connect error {}", e);
+                    choose(
+                        &format!("Connect error: {} ", e),
+                        &[("Reconnect".to_owned(), true)],
+                    )
+                    .await;
+                    continue;
+                }
+            };
 
             // vnc connect
             let vnc = VncConnector::new(wsio.into_io())
                 .set_auth_method(async move { Ok(prompt("Input your password")) })
+                .set_security_chooser(|types| async move { Ok(choose_security(types).await) })
                 .add_encoding(VncEncoding::Tight)
                 .add_encoding(VncEncoding::Zrle)
                 .add_encoding(VncEncoding::CopyRect)
                 .add_encoding(VncEncoding::Raw)
-                // .add_encoding(VncEncoding::CursorPseudo)
+                .add_encoding(VncEncoding::CursorPseudo)
                 .add_encoding(VncEncoding::DesktopSizePseudo)
+                .add_encoding(VncEncoding::ExtendedClipboardPseudo)
+                .add_encoding(VncEncoding::ExtendedDesktopSizePseudo)
+                .add_encoding(VncEncoding::QemuExtendedKeyEventPseudo)
                 .allow_shared(true)
-                .set_pixel_format(PixelFormat::rgba())
-                .set_version(vnc::VncVersion::RFB33)
-                .build()
-                .unwrap()
-                .try_start()
+                .set_version(VncVersion::RFB38)
+                .connect()
                 .await;
 
-            if vnc.is_err() {
-                if let Err(e) = vnc {
-                    let msg = format!("connect error {:?}\nRelog in please", e);
-                    alert(&msg);
-                    continue;
+            match vnc {
+                Ok(vnc) => break vnc,
+                // the reasons of the server are shown, 3.8 gives them
+                Err(e) => {
+                    error!("connect error {}", e);
+                    choose(
+                        &format!("Connect error: {} ", e),
+                        &[("Reconnect".to_owned(), true)],
+                    )
+                    .await;
                 }
             }
-            break vnc;
         };
+        set_status(&format!("Connected to {}", vnc.name()));
 
-        let vnc = vnc.unwrap().finish().unwrap();
+        let (x11_events_sender, mut x11_events_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-        let (x11_events_sender, mut x11_events_receiver) = tokio::sync::mpsc::channel(4096);
+        let mut canvas = CanvasUtils::new(
+            x11_events_sender.clone(),
+            Scaling::from_param(utils::query_param("resize").as_deref()),
+        );
 
-        let mut canvas = CanvasUtils::new(x11_events_sender.clone());
-
-        fn hande_vnc_event(event: VncEvent, canvas: &mut CanvasUtils) {
+        // the update is shown and the next one is asked for at the animation
+        // frame after its end, so that the server sends no more than can be
+        // shown
+        fn hande_vnc_event(
+            event: VncEvent,
+            canvas: &mut CanvasUtils,
+            frame: &mut Option<oneshot::Receiver<()>>,
+        ) {
             match event {
                 VncEvent::SetResolution(screen) => {
                     info!("Resize {:?}", screen);
@@ -88,7 +133,6 @@ fn run() -> Result<(), JsValue> {
                 VncEvent::Bell => {
                     //ignore
                 }
-                VncEvent::SetPixelFormat(_) => unreachable!(),
                 VncEvent::Copy(dst, src) => {
                     canvas.copy(dst, src);
                 }
@@ -96,40 +140,41 @@ fn run() -> Result<(), JsValue> {
                     canvas.jpeg(rect, data);
                 }
                 VncEvent::SetCursor(rect, data) => {
-                    if rect.width != 0 {
-                        canvas.draw(rect, data)
-                    }
+                    canvas.set_cursor(rect, data);
                 }
                 VncEvent::Text(string) => {
+                    canvas.set_clipboard(&string);
                     setClipBoard(string);
                 }
-                VncEvent::Error(msg) => {
-                    error!(msg);
-                    alert(&msg);
-                    panic!()
+                VncEvent::UpdateEnd => {
+                    *frame = Some(utils::animation_frame());
                 }
-                _ => unreachable!(),
             }
         }
 
         spawn_local(async move {
-            let mut interval =
-                fluvio_wasm_timer::Interval::new(std::time::Duration::from_millis(1));
+            let mut frame = None;
             loop {
-                match vnc.poll_event().await {
-                    Ok(Some(e)) => hande_vnc_event(e, &mut canvas),
-                    Ok(None) => {
-                        let _ = interval.next().await;
-                        let _ = vnc.input(X11Event::Refresh).await;
-                    }
-                    Err(e) => {
-                        alert(&e.to_string());
-                        break;
+                let decoding = canvas.decoding();
+                let input = tokio::select! {
+                    event = vnc.recv() => event.map(|e| hande_vnc_event(e, &mut canvas, &mut frame)),
+                    Some(x11event) = x11_events_receiver.recv() => vnc.input(x11event),
+                    _ = canvas.decoded(), if decoding => Ok(()),
+                    Some(_) = OptionFuture::from(frame.as_mut()), if frame.is_some() => {
+                        frame = None;
+                        canvas.render();
+                        vnc.input(X11Event::Refresh)
                     }
-                }
-
-                while let Ok(x11event) = x11_events_receiver.try_recv() {
-                    let _ = vnc.input(x11event).await;
+                };
+                // the answers to the server go along with the input
+                let input = match input {
+                    Ok(()) => vnc.flush().await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = input {
+                    error!("{}", e);
+                    set_status(&format!("Disconnected: {}", e));
+                    break;
                 }
             }
             canvas.close();
@@ -0,0 +1,60 @@
+// The status bar above the desktop, which also asks the questions of the
+// connection
+
+use std::{cell::RefCell, rc::Rc};
+use tokio::sync::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlButtonElement;
+
+fn status_bar() -> web_sys::Element {
+    web_sys::window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id("vnc_status")
+        .unwrap()
+}
+
+pub fn set_status(msg: &str) {
+    status_bar().set_text_content(Some(msg));
+}
+
+/// Show `msg` with a button of each option, the disabled ones cannot be
+/// clicked, until one is clicked
+pub async fn choose(msg: &str, options: &[(String, bool)]) -> usize {
+    let document = web_sys::window().unwrap().document().unwrap();
+    let status_bar = status_bar();
+    status_bar.set_text_content(Some(msg));
+
+    let (sender, receiver) = oneshot::channel();
+    let sender = Rc::new(RefCell::new(Some(sender)));
+    for (i, (name, enabled)) in options.iter().enumerate() {
+        let button = document
+            .create_element("button")
+            .unwrap()
+            .dyn_into::<HtmlButtonElement>()
+            .map_err(|_| ())
+            .unwrap();
+        button.set_text_content(Some(name));
+        button.set_disabled(!enabled);
+
+        let sender = sender.clone();
+        let click = move || {
+            if let Some(sender) = sender.borrow_mut().take() {
+                let _ = sender.send(i);
+            }
+        };
+        let handler = Box::new(click) as Box<dyn FnMut()>;
+
+        let cb = Closure::wrap(handler);
+
+        button.set_onclick(Some(cb.as_ref().unchecked_ref()));
+        cb.forget();
+        let _ = status_bar.append_child(&button);
+    }
+
+    let choice = receiver.await.unwrap_or_default();
+    status_bar.set_text_content(None);
+    choice
+}
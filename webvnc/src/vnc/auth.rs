@@ -0,0 +1,41 @@
+// The VNC Authentication
+//     https://www.rfc-editor.org/rfc/rfc6143#section-7.2.2
+
+use super::des;
+
+/// The challenge encrypted with the password as the DES key
+///
+/// The password is cut or padded to 8 bytes, and the bits of each byte are
+/// mirrored as the first implementation did
+pub fn vnc_auth(challenge: &[u8; 16], password: &str) -> [u8; 16] {
+    let mut key = [0; 8];
+    for (k, b) in key.iter_mut().zip(password.bytes()) {
+        *k = b.reverse_bits();
+    }
+    let mut response = [0; 16];
+    for (out, block) in response.chunks_exact_mut(8).zip(challenge.chunks_exact(8)) {
+        out.copy_from_slice(&des::encrypt(block.try_into().unwrap(), key));
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vnc_auth() {
+        let challenge = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef].repeat(2);
+        let response = vnc_auth(&challenge.try_into().unwrap(), "");
+        // an empty password is the zero key
+        let block = des::encrypt([0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef], [0; 8]);
+        assert_eq!(response[..8], block);
+        assert_eq!(response[8..], block);
+
+        // the bits of the key are reversed
+        let key = [0x80, 0, 0, 0, 0, 0, 0, 0];
+        let response = vnc_auth(&[0; 16], "\x01");
+        assert_eq!(response[..8], des::encrypt([0; 8], key));
+        assert_ne!(response[..8], des::encrypt([0; 8], [0; 8]));
+    }
+}
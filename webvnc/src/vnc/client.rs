@@ -0,0 +1,550 @@
+use super::{
+    clipboard::{self, Clipboard},
+    codec::{self, TightDecoder, TightImage, ZrleDecoder},
+    msg::{self, VncEncoding},
+    Rect, Screen, VncError, VncEvent, VncReader, VncResult, X11Event,
+};
+use std::collections::VecDeque;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const READ_SIZE: usize = 64 * 1024;
+// A larger cut text is taken as a broken server
+const MAX_CUT_TEXT: usize = 64 * 1024 * 1024;
+
+/// A connected client, see [super::VncConnector]
+///
+/// The events of the server are read with [VncClient::recv], the input is
+/// queued with [VncClient::input] until [VncClient::flush]
+pub struct VncClient<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream: S,
+    name: String,
+    // the bytes received, of which the first `rpos` have been parsed
+    rbuf: Vec<u8>,
+    rpos: usize,
+    state: State,
+}
+
+// What is not bound to the stream
+struct State {
+    screen: Screen,
+    // the id and the flags of the first screen, once the server has told
+    // that it can be resized
+    layout: Option<(u32, u32)>,
+    // the size asked for by the client
+    wanted: Option<Screen>,
+    // the rects of the current update which are to come
+    rects: u16,
+    // the server takes the keys by their scancodes
+    qemu_keys: bool,
+    zrle: ZrleDecoder,
+    tight: TightDecoder,
+    clipboard: Clipboard,
+    events: VecDeque<VncEvent>,
+    wbuf: Vec<u8>,
+}
+
+impl<S> VncClient<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub(super) fn new(stream: S, name: String, screen: Screen) -> Self {
+        let mut events = VecDeque::new();
+        events.push_back(VncEvent::SetResolution(screen.clone()));
+        Self {
+            stream,
+            name,
+            rbuf: Vec::with_capacity(READ_SIZE),
+            rpos: 0,
+            state: State {
+                screen,
+                layout: None,
+                wanted: None,
+                rects: 0,
+                qemu_keys: false,
+                zrle: ZrleDecoder::new(),
+                tight: TightDecoder::new(),
+                clipboard: Clipboard::new(),
+                events,
+                wbuf: Vec::new(),
+            },
+        }
+    }
+
+    /// The name of the desktop
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.state.screen
+    }
+
+    /// The next event of the server, the first one is the size of the desktop
+    ///
+    /// It is cancel safe, so that it can be raced against the input
+    pub async fn recv(&mut self) -> VncResult<VncEvent> {
+        loop {
+            if let Some(event) = self.state.events.pop_front() {
+                return Ok(event);
+            }
+            let mut r = VncReader::new(&self.rbuf[self.rpos..]);
+            match self.state.parse(&mut r) {
+                Ok(()) => {
+                    self.rpos += r.pos();
+                    continue;
+                }
+                Err(VncError::Incomplete) => (),
+                Err(e) => return Err(e),
+            }
+
+            self.rbuf.drain(..self.rpos);
+            self.rpos = 0;
+            self.rbuf.reserve(READ_SIZE);
+            if self.stream.read_buf(&mut self.rbuf).await? == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Connection closed",
+                )
+                .into());
+            }
+        }
+    }
+
+    /// Queue an input for the server
+    pub fn input(&mut self, event: X11Event) -> VncResult<()> {
+        let out = &mut self.state.wbuf;
+        match event {
+            X11Event::Refresh => self.request(true),
+            X11Event::KeyEvent(key) if self.state.qemu_keys && key.scancode != 0 => {
+                out.extend_from_slice(&[msg::QEMU_CLIENT_MESSAGE, msg::QEMU_EXTENDED_KEY_EVENT]);
+                out.extend_from_slice(&(key.down as u16).to_be_bytes());
+                out.extend_from_slice(&key.keycode.to_be_bytes());
+                out.extend_from_slice(&key.scancode.to_be_bytes());
+            }
+            // a key without keysym can only be sent by its scancode
+            X11Event::KeyEvent(key) if key.keycode == 0 => (),
+            X11Event::KeyEvent(key) => {
+                out.extend_from_slice(&[msg::KEY_EVENT, key.down as u8, 0, 0]);
+                out.extend_from_slice(&key.keycode.to_be_bytes());
+            }
+            X11Event::PointerEvent(mouse) => {
+                out.extend_from_slice(&[msg::POINTER_EVENT, mouse.buttons]);
+                out.extend_from_slice(&mouse.position_x.to_be_bytes());
+                out.extend_from_slice(&mouse.position_y.to_be_bytes());
+            }
+            X11Event::CopyText(text) => {
+                let message = self.state.clipboard.send(&text)?;
+                self.state.wbuf.extend_from_slice(&message);
+            }
+            X11Event::Resize(screen) => {
+                self.state.wanted = Some(screen);
+                self.state.resize();
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask for the whole desktop, or for its changes if `incremental`
+    pub(super) fn request(&mut self, incremental: bool) {
+        let screen = &self.state.screen;
+        let out = &mut self.state.wbuf;
+        out.extend_from_slice(&[
+            msg::FRAMEBUFFER_UPDATE_REQUEST,
+            incremental as u8,
+            0,
+            0,
+            0,
+            0,
+        ]);
+        out.extend_from_slice(&screen.width.to_be_bytes());
+        out.extend_from_slice(&screen.height.to_be_bytes());
+    }
+
+    /// Send what has been queued
+    pub async fn flush(&mut self) -> VncResult<()> {
+        if !self.state.wbuf.is_empty() {
+            self.stream.write_all(&self.state.wbuf).await?;
+            self.state.wbuf.clear();
+        }
+        Ok(())
+    }
+
+    pub async fn close(mut self) -> VncResult<()> {
+        self.flush().await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+impl State {
+    // SetDesktopSize of one screen, once the server is known to support it
+    fn resize(&mut self) {
+        let (Some((id, flags)), Some(wanted)) = (self.layout, &self.wanted) else {
+            return;
+        };
+        if wanted.width == 0 || wanted.height == 0 || *wanted == self.screen {
+            return;
+        }
+        let out = &mut self.wbuf;
+        out.extend_from_slice(&[msg::SET_DESKTOP_SIZE, 0]);
+        out.extend_from_slice(&wanted.width.to_be_bytes());
+        out.extend_from_slice(&wanted.height.to_be_bytes());
+        out.extend_from_slice(&[1, 0]);
+        out.extend_from_slice(&id.to_be_bytes());
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        out.extend_from_slice(&wanted.width.to_be_bytes());
+        out.extend_from_slice(&wanted.height.to_be_bytes());
+        out.extend_from_slice(&flags.to_be_bytes());
+    }
+
+    // A message, or a rect of an update, all of it or nothing
+    fn parse(&mut self, r: &mut VncReader) -> VncResult<()> {
+        if self.rects > 0 {
+            return self.rect(r);
+        }
+        match r.read_u8()? {
+            msg::FRAMEBUFFER_UPDATE => {
+                r.skip(1)?;
+                self.rects = r.read_u16()?;
+                if self.rects == 0 {
+                    self.events.push_back(VncEvent::UpdateEnd);
+                }
+            }
+            msg::SET_COLOUR_MAP_ENTRIES => {
+                // there is no colour map in true colour
+                r.skip(3)?;
+                let colours = r.read_u16()?;
+                r.skip(colours as usize * 6)?;
+            }
+            msg::BELL => self.events.push_back(VncEvent::Bell),
+            msg::SERVER_CUT_TEXT => {
+                r.skip(3)?;
+                let len = r.read_i32()?;
+                if len.unsigned_abs() as usize > MAX_CUT_TEXT {
+                    return Err(VncError::General(format!("Cut text of {} bytes", len)));
+                }
+                let text = r.read_bytes(len.unsigned_abs() as usize)?;
+                if len >= 0 {
+                    let text = clipboard::from_latin1(text);
+                    self.events.push_back(VncEvent::Text(text));
+                } else {
+                    let (text, reply) = self.clipboard.receive(text)?;
+                    self.wbuf.extend_from_slice(&reply);
+                    if let Some(text) = text {
+                        self.events.push_back(VncEvent::Text(text));
+                    }
+                }
+            }
+            other => return Err(VncError::WrongServerMessage(other)),
+        }
+        Ok(())
+    }
+
+    fn rect(&mut self, r: &mut VncReader) -> VncResult<()> {
+        let rect = Rect {
+            x: r.read_u16()?,
+            y: r.read_u16()?,
+            width: r.read_u16()?,
+            height: r.read_u16()?,
+        };
+        let encoding = r.read_i32()?;
+        let event = match VncEncoding::from_code(encoding) {
+            Some(VncEncoding::Raw) => Some(VncEvent::RawImage(rect, codec::raw(r, &rect)?)),
+            Some(VncEncoding::CopyRect) => {
+                let src = Rect {
+                    x: r.read_u16()?,
+                    y: r.read_u16()?,
+                    ..rect
+                };
+                Some(VncEvent::Copy(rect, src))
+            }
+            Some(VncEncoding::Tight) => Some(match self.tight.decode(r, &rect)? {
+                TightImage::Raw(data) => VncEvent::RawImage(rect, data),
+                TightImage::Jpeg(data) => VncEvent::JpegImage(rect, data),
+            }),
+            Some(VncEncoding::Zrle) => Some(VncEvent::RawImage(rect, self.zrle.decode(r, &rect)?)),
+            Some(VncEncoding::CursorPseudo) => {
+                Some(VncEvent::SetCursor(rect, codec::cursor(r, &rect)?))
+            }
+            Some(VncEncoding::DesktopSizePseudo) => self.set_screen(&rect, true),
+            Some(VncEncoding::ExtendedDesktopSizePseudo) => {
+                let screens = r.read_u8()?;
+                r.skip(3)?;
+                let layout = r.read_bytes(screens as usize * 16)?;
+                // the position of the rect carries the reason and the status
+                let known = self.layout.is_some();
+                if let Some(first) = layout.get(..16) {
+                    let id = u32::from_be_bytes(first[..4].try_into().unwrap());
+                    let flags = u32::from_be_bytes(first[12..].try_into().unwrap());
+                    self.layout = Some((id, flags));
+                }
+                let event = self.set_screen(&rect, rect.y == 0);
+                if !known {
+                    self.resize();
+                }
+                event
+            }
+            // the server tells that it takes the extended key events
+            Some(VncEncoding::QemuExtendedKeyEventPseudo) => {
+                self.qemu_keys = true;
+                None
+            }
+            Some(VncEncoding::LastRectPseudo) => {
+                self.rects = 0;
+                self.events.push_back(VncEvent::UpdateEnd);
+                return Ok(());
+            }
+            _ => return Err(VncError::WrongEncoding(encoding)),
+        };
+        self.rects -= 1;
+        if let Some(event) = event {
+            self.events.push_back(event);
+        }
+        if self.rects == 0 {
+            self.events.push_back(VncEvent::UpdateEnd);
+        }
+        Ok(())
+    }
+
+    // The desktop has been resized, unless its size is the same or the
+    // resize failed
+    fn set_screen(&mut self, rect: &Rect, done: bool) -> Option<VncEvent> {
+        let screen = Screen {
+            width: rect.width,
+            height: rect.height,
+        };
+        if !done || screen == self.screen {
+            return None;
+        }
+        self.screen = screen;
+        Some(VncEvent::SetResolution(self.screen.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+    use tokio::io::DuplexStream;
+
+    fn client() -> (VncClient<DuplexStream>, DuplexStream) {
+        let (stream, server) = tokio::io::duplex(1 << 20);
+        let mut client = VncClient::new(stream, "desk".to_owned(), (4, 4).into());
+        assert!(matches!(
+            client.recv().now_or_never(),
+            Some(Ok(VncEvent::SetResolution(_)))
+        ));
+        (client, server)
+    }
+
+    fn rect(x: u16, y: u16, width: u16, height: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let (mut client, mut server) = client();
+        let mut update = vec![msg::FRAMEBUFFER_UPDATE, 0, 0, 3];
+        update.extend_from_slice(&[0, 1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 1, 2, 3, 0]);
+        update.extend_from_slice(&[0, 2, 0, 2, 0, 1, 0, 1, 0, 0, 0, 1, 0, 1, 0, 1]);
+        update.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0x20]);
+        update.push(msg::BELL);
+
+        // the events come once whole, whichever way the bytes are cut
+        for byte in &update[..20] {
+            assert!(client.recv().now_or_never().is_none());
+            server.write_all(&[*byte]).await.unwrap();
+        }
+        let event = client.recv().now_or_never().unwrap().unwrap();
+        assert_eq!(
+            event,
+            VncEvent::RawImage(rect(1, 1, 1, 1), vec![1, 2, 3, 255])
+        );
+
+        server.write_all(&update[20..]).await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            VncEvent::Copy(rect(2, 2, 1, 1), rect(1, 1, 1, 1))
+        );
+        // the last rect ends the update early
+        assert_eq!(client.recv().await.unwrap(), VncEvent::UpdateEnd);
+        assert_eq!(client.recv().await.unwrap(), VncEvent::Bell);
+
+        server
+            .write_all(&[msg::FRAMEBUFFER_UPDATE, 0, 0, 0])
+            .await
+            .unwrap();
+        assert_eq!(client.recv().await.unwrap(), VncEvent::UpdateEnd);
+
+        server
+            .write_all(&[msg::FRAMEBUFFER_UPDATE, 0, 0, 1])
+            .await
+            .unwrap();
+        server.write_all(&[0; 8]).await.unwrap();
+        server.write_all(&5_i32.to_be_bytes()).await.unwrap();
+        assert!(matches!(
+            client.recv().await,
+            Err(VncError::WrongEncoding(5))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_input() {
+        let (mut client, mut server) = client();
+        client
+            .input(X11Event::KeyEvent((0xff0d, true).into()))
+            .unwrap();
+        client
+            .input(X11Event::PointerEvent((3, 4, 1).into()))
+            .unwrap();
+        client.input(X11Event::Refresh).unwrap();
+        client.input(X11Event::CopyText("hé".to_owned())).unwrap();
+        client.flush().await.unwrap();
+
+        let mut input = [0; 8 + 6 + 10 + 10];
+        server.read_exact(&mut input).await.unwrap();
+        assert_eq!(input[..8], [msg::KEY_EVENT, 1, 0, 0, 0, 0, 0xff, 0x0d]);
+        assert_eq!(input[8..14], [msg::POINTER_EVENT, 1, 0, 3, 0, 4]);
+        assert_eq!(input[14..24], [3, 1, 0, 0, 0, 0, 0, 4, 0, 4]);
+        assert_eq!(
+            input[24..],
+            [msg::CLIENT_CUT_TEXT, 0, 0, 0, 0, 0, 0, 2, b'h', 0xe9]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_qemu_keys() {
+        let (mut client, mut server) = client();
+        // keysyms until the server tells it takes the scancodes
+        client
+            .input(X11Event::KeyEvent((0x61, 0x1e, true).into()))
+            .unwrap();
+        client
+            .input(X11Event::KeyEvent((0, 0x1e, true).into()))
+            .unwrap();
+        client.flush().await.unwrap();
+        let mut input = [0; 8];
+        server.read_exact(&mut input).await.unwrap();
+        assert_eq!(input, [msg::KEY_EVENT, 1, 0, 0, 0, 0, 0, 0x61]);
+
+        let mut update = vec![msg::FRAMEBUFFER_UPDATE, 0, 0, 1];
+        update.extend_from_slice(&[0; 8]);
+        update.extend_from_slice(&(-258_i32).to_be_bytes());
+        server.write_all(&update).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), VncEvent::UpdateEnd);
+
+        client
+            .input(X11Event::KeyEvent((0x61, 0x1e, false).into()))
+            .unwrap();
+        client
+            .input(X11Event::KeyEvent((0xff0d, true).into()))
+            .unwrap();
+        client.flush().await.unwrap();
+        let mut input = [0; 12 + 8];
+        server.read_exact(&mut input).await.unwrap();
+        assert_eq!(
+            input[..12],
+            [
+                msg::QEMU_CLIENT_MESSAGE,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x61,
+                0,
+                0,
+                0,
+                0x1e
+            ]
+        );
+        assert_eq!(input[12..], [msg::KEY_EVENT, 1, 0, 0, 0, 0, 0xff, 0x0d]);
+    }
+
+    #[tokio::test]
+    async fn test_cut_text() {
+        let (mut client, mut server) = client();
+        server
+            .write_all(&[msg::SERVER_CUT_TEXT, 0, 0, 0, 0, 0, 0, 2, b'h', 0xe9])
+            .await
+            .unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            VncEvent::Text("hé".to_owned())
+        );
+
+        // the capabilities of the extended clipboard are answered
+        let caps = (1_u32 << 24 | 1 << 27 | 1).to_be_bytes();
+        server
+            .write_all(&[msg::SERVER_CUT_TEXT, 0, 0, 0])
+            .await
+            .unwrap();
+        server.write_all(&(-4_i32).to_be_bytes()).await.unwrap();
+        server.write_all(&caps).await.unwrap();
+        server.write_all(&[msg::BELL]).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), VncEvent::Bell);
+        client.flush().await.unwrap();
+        let mut reply = [0; 16];
+        server.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[4..8], (-8_i32).to_be_bytes());
+
+        // the text is notified then
+        client.input(X11Event::CopyText("hé".to_owned())).unwrap();
+        client.flush().await.unwrap();
+        let mut notify = [0; 12];
+        server.read_exact(&mut notify).await.unwrap();
+        assert_eq!(notify[8..], (1_u32 << 27 | 1).to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_resize() {
+        let (mut client, mut server) = client();
+        let desktop = |reason: u16, status: u16, width: u16, height: u16| {
+            let mut update = vec![msg::FRAMEBUFFER_UPDATE, 0, 0, 1];
+            for value in [reason, status, width, height] {
+                update.extend_from_slice(&value.to_be_bytes());
+            }
+            update.extend_from_slice(&(-308_i32).to_be_bytes());
+            update.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0]);
+            update.extend_from_slice(&[0, 4, 0, 4, 0, 0, 0, 0]);
+            update.push(msg::BELL);
+            update
+        };
+
+        // not sent until the server can do it
+        client.input(X11Event::Resize((8, 6).into())).unwrap();
+        client.flush().await.unwrap();
+        server.write_all(&desktop(0, 0, 4, 4)).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), VncEvent::UpdateEnd);
+        assert_eq!(client.recv().await.unwrap(), VncEvent::Bell);
+        client.flush().await.unwrap();
+
+        let mut request = [0; 8 + 16];
+        server.read_exact(&mut request).await.unwrap();
+        assert_eq!(request[..8], [msg::SET_DESKTOP_SIZE, 0, 0, 8, 0, 6, 1, 0]);
+        assert_eq!(
+            request[8..],
+            [0, 0, 0, 7, 0, 0, 0, 0, 0, 8, 0, 6, 0, 0, 0, 0]
+        );
+
+        // refused, then done
+        server.write_all(&desktop(1, 1, 4, 4)).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), VncEvent::UpdateEnd);
+        assert_eq!(client.recv().await.unwrap(), VncEvent::Bell);
+        server.write_all(&desktop(1, 0, 8, 6)).await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            VncEvent::SetResolution((8, 6).into())
+        );
+        assert_eq!(client.screen(), &Screen::from((8, 6)));
+    }
+}
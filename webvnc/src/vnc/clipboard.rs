@@ -0,0 +1,207 @@
+// The Extended Clipboard pseudo-encoding, the UTF-8 text of the clipboard
+// travels in zlib streams instead of Latin-1
+//     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#extended-clipboard-pseudo-encoding
+
+use super::{codec::Inflater, msg, VncError, VncReader, VncResult};
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+const FORMAT_TEXT: u32 = 1;
+
+const ACTION_CAPS: u32 = 1 << 24;
+const ACTION_REQUEST: u32 = 1 << 25;
+const ACTION_PEEK: u32 = 1 << 26;
+const ACTION_NOTIFY: u32 = 1 << 27;
+const ACTION_PROVIDE: u32 = 1 << 28;
+
+/// The largest text we take
+const MAX_TEXT: u32 = 10 * 1024 * 1024;
+
+/// The clipboard of the client and what the server can do with it
+#[derive(Default)]
+pub struct Clipboard {
+    /// The actions of the server, once it has told its capabilities
+    server: Option<u32>,
+    /// The text offered to the server, sent once it asks
+    text: Option<String>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A ServerCutText of a negative length, the text it carries if any and
+    /// the answer to the server
+    pub fn receive(&mut self, payload: &[u8]) -> VncResult<(Option<String>, Vec<u8>)> {
+        let mut r = VncReader::new(payload);
+        let flags = r.read_u32().map_err(|_| invalid())?;
+        let text = flags & FORMAT_TEXT != 0;
+
+        if flags & ACTION_CAPS != 0 {
+            self.server = Some(flags);
+            let mut caps = (ACTION_CAPS
+                | ACTION_REQUEST
+                | ACTION_PEEK
+                | ACTION_NOTIFY
+                | ACTION_PROVIDE
+                | FORMAT_TEXT)
+                .to_be_bytes()
+                .to_vec();
+            caps.extend_from_slice(&MAX_TEXT.to_be_bytes());
+            return Ok((None, message(&caps)));
+        }
+        if flags & ACTION_PROVIDE != 0 {
+            return Ok((provided(flags, r.read_bytes(r.remain())?)?, Vec::new()));
+        }
+
+        let reply = if flags & ACTION_REQUEST != 0 && text {
+            match &self.text {
+                Some(text) => provide(text)?,
+                None => Vec::new(),
+            }
+        } else if flags & ACTION_PEEK != 0 {
+            let formats = if self.text.is_some() { FORMAT_TEXT } else { 0 };
+            message(&(ACTION_NOTIFY | formats).to_be_bytes())
+        } else if flags & ACTION_NOTIFY != 0 && text && self.server_can(ACTION_REQUEST) {
+            message(&(ACTION_REQUEST | FORMAT_TEXT).to_be_bytes())
+        } else {
+            Vec::new()
+        };
+        Ok((None, reply))
+    }
+
+    /// The messages which put `text` to the clipboard of the server
+    pub fn send(&mut self, text: &str) -> VncResult<Vec<u8>> {
+        let extended = self.server.is_some_and(|flags| flags & FORMAT_TEXT != 0);
+        if extended && self.server_can(ACTION_NOTIFY) {
+            self.text = Some(text.to_owned());
+            Ok(message(&(ACTION_NOTIFY | FORMAT_TEXT).to_be_bytes()))
+        } else if extended && self.server_can(ACTION_PROVIDE) {
+            provide(text)
+        } else {
+            let text = latin1(text);
+            let mut out = vec![msg::CLIENT_CUT_TEXT, 0, 0, 0];
+            out.extend_from_slice(&(text.len() as u32).to_be_bytes());
+            out.extend_from_slice(&text);
+            Ok(out)
+        }
+    }
+
+    fn server_can(&self, action: u32) -> bool {
+        self.server.is_some_and(|flags| flags & action != 0)
+    }
+}
+
+fn invalid() -> VncError {
+    VncError::General("Invalid extended clipboard message".to_owned())
+}
+
+// A ClientCutText of the extended clipboard, its length is negated
+fn message(payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![msg::CLIENT_CUT_TEXT, 0, 0, 0];
+    out.extend_from_slice(&(-(payload.len() as i32)).to_be_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+// The text is null terminated, with CRLF line endings
+fn provide(text: &str) -> VncResult<Vec<u8>> {
+    let mut text = text
+        .replace("\r\n", "\n")
+        .replace('\n', "\r\n")
+        .into_bytes();
+    text.push(0);
+
+    let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+    z.write_all(&(text.len() as u32).to_be_bytes())?;
+    z.write_all(&text)?;
+    let mut payload = (ACTION_PROVIDE | FORMAT_TEXT).to_be_bytes().to_vec();
+    payload.extend_from_slice(&z.finish()?);
+    Ok(message(&payload))
+}
+
+// Each format of the flags comes in its own size and data, the lower bits
+// first, the text only is kept
+fn provided(flags: u32, data: &[u8]) -> VncResult<Option<String>> {
+    let data = Inflater::new()
+        .inflate(data, MAX_TEXT as usize + 4)
+        .map_err(|_| invalid())?;
+    let mut r = VncReader::new(&data);
+    if flags & FORMAT_TEXT == 0 {
+        return Ok(None);
+    }
+    let len = r.read_u32().map_err(|_| invalid())?;
+    let text = r.read_bytes(len as usize).map_err(|_| invalid())?;
+    let text = text.strip_suffix(&[0]).unwrap_or(text);
+    Ok(Some(String::from_utf8_lossy(text).replace("\r\n", "\n")))
+}
+
+/// The text of a ClientCutText, which has to be Latin-1
+pub fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+        .collect()
+}
+
+/// The text of a ServerCutText
+pub fn from_latin1(text: &[u8]) -> String {
+    text.iter().map(|&b| b as char).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn flags(message: &[u8]) -> u32 {
+        u32::from_be_bytes(message[8..12].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_legacy() {
+        let mut clipboard = Clipboard::new();
+        assert_eq!(
+            clipboard.send("é€").unwrap(),
+            [msg::CLIENT_CUT_TEXT, 0, 0, 0, 0, 0, 0, 2, 0xe9, b'?']
+        );
+        assert_eq!(from_latin1(&[b'a', 0xe9]), "aé");
+    }
+
+    #[test]
+    fn test_extended() {
+        let mut clipboard = Clipboard::new();
+        let mut caps =
+            (ACTION_CAPS | ACTION_REQUEST | ACTION_NOTIFY | ACTION_PROVIDE | FORMAT_TEXT)
+                .to_be_bytes()
+                .to_vec();
+        caps.extend_from_slice(&1024_u32.to_be_bytes());
+        let (text, reply) = clipboard.receive(&caps).unwrap();
+        assert_eq!(text, None);
+        assert_eq!(reply[4..8], (-8_i32).to_be_bytes());
+        assert_eq!(
+            flags(&reply) & (ACTION_CAPS | FORMAT_TEXT),
+            ACTION_CAPS | FORMAT_TEXT
+        );
+
+        // the server is notified, then it asks for the text
+        let notify = clipboard.send("crème\nbrûlée").unwrap();
+        assert_eq!(flags(&notify), ACTION_NOTIFY | FORMAT_TEXT);
+        let request = (ACTION_REQUEST | FORMAT_TEXT).to_be_bytes();
+        let (_, provide) = clipboard.receive(&request).unwrap();
+        assert_eq!(flags(&provide), ACTION_PROVIDE | FORMAT_TEXT);
+
+        // which comes back the same
+        let (text, reply) = Clipboard::new().receive(&provide[8..]).unwrap();
+        assert_eq!(text.as_deref(), Some("crème\nbrûlée"));
+        assert!(reply.is_empty());
+
+        // the text of the server is asked for when it changes
+        let notify = (ACTION_NOTIFY | FORMAT_TEXT).to_be_bytes();
+        let (_, request) = clipboard.receive(&notify).unwrap();
+        assert_eq!(flags(&request), ACTION_REQUEST | FORMAT_TEXT);
+
+        let peek = ACTION_PEEK.to_be_bytes();
+        let (_, reply) = clipboard.receive(&peek).unwrap();
+        assert_eq!(flags(&reply), ACTION_NOTIFY | FORMAT_TEXT);
+    }
+}
@@ -0,0 +1,40 @@
+// The Cursor pseudo-encoding
+//     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#cursor-pseudo-encoding
+
+use super::{pixels, Rect, VncReader, VncResult};
+
+/// The pixels of the cursor, transparent where the bitmask is not set
+pub fn cursor(r: &mut VncReader, rect: &Rect) -> VncResult<Vec<u8>> {
+    let row = (rect.width as usize).div_ceil(8);
+    let data = r.read_bytes(pixels(rect) * 4)?;
+    let mask = r.read_bytes(row * rect.height as usize)?;
+
+    let mut out = data.to_vec();
+    for (i, pixel) in out.chunks_exact_mut(4).enumerate() {
+        let (y, x) = (i / rect.width as usize, i % rect.width as usize);
+        let bit = mask[y * row + x / 8] & (0x80 >> (x % 8));
+        pixel[3] = if bit != 0 { 255 } else { 0 };
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let rect = Rect {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let mut bytes = vec![9; 16];
+        bytes.extend_from_slice(&[0b1000_0000, 0b0100_0000]);
+        let cursor = cursor(&mut VncReader::new(&bytes), &rect).unwrap();
+        let alpha: Vec<u8> = cursor.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, [255, 0, 0, 255]);
+        assert_eq!(cursor[0..3], [9, 9, 9]);
+    }
+}
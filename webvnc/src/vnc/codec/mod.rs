@@ -0,0 +1,125 @@
+//! The decoders of the rect encodings, all of them output RGBA pixels
+//!
+//! The pixel format set by the client is 32 bits true colour, red in the
+//! first byte on the wire, so that a raw pixel is already RGBA but for the
+//! alpha
+
+mod cursor;
+mod tight;
+mod zrle;
+
+pub use cursor::cursor;
+pub use tight::{TightDecoder, TightImage};
+pub use zrle::ZrleDecoder;
+
+use super::{Rect, VncError, VncReader, VncResult};
+use flate2::{Decompress, FlushDecompress, Status};
+
+const CHUNK: usize = 4096;
+
+/// A raw rect, `width * height` pixels
+pub fn raw(r: &mut VncReader, rect: &Rect) -> VncResult<Vec<u8>> {
+    let mut data = r.read_bytes(pixels(rect) * 4)?.to_vec();
+    opaque(&mut data);
+    Ok(data)
+}
+
+fn pixels(rect: &Rect) -> usize {
+    rect.width as usize * rect.height as usize
+}
+
+fn opaque(data: &mut [u8]) {
+    for pixel in data.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+}
+
+/// A zlib stream, which lives as long as the connection unless the server
+/// resets it
+pub struct Inflater(Decompress);
+
+impl Inflater {
+    pub fn new() -> Self {
+        Self(Decompress::new(true))
+    }
+
+    pub fn reset(&mut self) {
+        self.0.reset(true);
+    }
+
+    /// Inflate all of `data`, which may not be larger than `limit`
+    pub fn inflate(&mut self, data: &[u8], limit: usize) -> VncResult<Vec<u8>> {
+        let mut out = Vec::with_capacity((data.len() * 2).min(limit) + CHUNK);
+        let start = self.0.total_in();
+        loop {
+            let consumed = (self.0.total_in() - start) as usize;
+            if out.len() == out.capacity() {
+                out.reserve(CHUNK);
+            }
+            let before = (consumed, out.len());
+            let status = self
+                .0
+                .decompress_vec(&data[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|_| VncError::InvalidImageData)?;
+            if out.len() > limit {
+                return Err(VncError::InvalidImageData);
+            }
+            let consumed = (self.0.total_in() - start) as usize;
+            if status == Status::StreamEnd || (consumed == data.len() && out.len() < out.capacity())
+            {
+                return Ok(out);
+            }
+            if (consumed, out.len()) == before {
+                return Err(VncError::InvalidImageData);
+            }
+        }
+    }
+}
+
+/// The stream of a server, for the tests
+#[cfg(test)]
+pub struct Deflater(flate2::Compress);
+
+#[cfg(test)]
+impl Deflater {
+    pub fn new() -> Self {
+        Self(flate2::Compress::new(flate2::Compression::default(), true))
+    }
+
+    pub fn deflate(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + CHUNK);
+        self.0
+            .compress_vec(data, &mut out, flate2::FlushCompress::Sync)
+            .unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_raw() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        let mut r = VncReader::new(&[1, 2, 3, 0, 4, 5, 6, 0]);
+        assert_eq!(raw(&mut r, &rect).unwrap(), [1, 2, 3, 255, 4, 5, 6, 255]);
+        let mut r = VncReader::new(&[1, 2, 3, 0]);
+        assert!(matches!(raw(&mut r, &rect), Err(VncError::Incomplete)));
+    }
+
+    #[test]
+    fn test_inflate() {
+        let (mut server, mut z) = (Deflater::new(), Inflater::new());
+        assert_eq!(z.inflate(&server.deflate(b"hello"), 100).unwrap(), b"hello");
+        let data = vec![7; 100_000];
+        assert_eq!(z.inflate(&server.deflate(&data), 100_000).unwrap(), data);
+        assert!(z.inflate(&server.deflate(&data), 1000).is_err());
+        assert!(Inflater::new().inflate(&[1, 2, 3, 4], 100).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+// The Tight encoding
+//     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#tight-encoding
+
+use super::{pixels, Inflater, Rect, VncError, VncReader, VncResult};
+
+const FILL: u8 = 8;
+const JPEG: u8 = 9;
+
+const FILTER_COPY: u8 = 0;
+const FILTER_PALETTE: u8 = 1;
+const FILTER_GRADIENT: u8 = 2;
+
+// Shorter data is not compressed
+const MIN_TO_COMPRESS: usize = 12;
+
+pub enum TightImage {
+    /// RGBA pixels
+    Raw(Vec<u8>),
+    Jpeg(Vec<u8>),
+}
+
+/// The four zlib streams of Tight, which the server may reset
+pub struct TightDecoder {
+    streams: [Inflater; 4],
+}
+
+impl TightDecoder {
+    pub fn new() -> Self {
+        Self {
+            streams: [
+                Inflater::new(),
+                Inflater::new(),
+                Inflater::new(),
+                Inflater::new(),
+            ],
+        }
+    }
+
+    pub fn decode(&mut self, r: &mut VncReader, rect: &Rect) -> VncResult<TightImage> {
+        let control = r.read_u8()?;
+        // the streams are not touched before the whole rect is received
+        match control >> 4 {
+            FILL => {
+                let rgb = r.read_bytes(3)?;
+                self.reset(control);
+                Ok(TightImage::Raw(
+                    [rgb[0], rgb[1], rgb[2], 255].repeat(pixels(rect)),
+                ))
+            }
+            JPEG => {
+                let len = r.read_compact_len()?;
+                let data = r.read_bytes(len)?;
+                self.reset(control);
+                Ok(TightImage::Jpeg(data.to_vec()))
+            }
+            compression if compression & 8 == 0 => {
+                let filter = if compression & 4 != 0 {
+                    r.read_u8()?
+                } else {
+                    FILTER_COPY
+                };
+                let (palette, size) = match filter {
+                    FILTER_COPY | FILTER_GRADIENT => (&[][..], pixels(rect) * 3),
+                    FILTER_PALETTE => {
+                        let colours = r.read_u8()? as usize + 1;
+                        let palette = r.read_bytes(colours * 3)?;
+                        let bits = if colours <= 2 { 1 } else { 8 };
+                        let row = (rect.width as usize * bits).div_ceil(8);
+                        (palette, row * rect.height as usize)
+                    }
+                    _ => return Err(VncError::InvalidImageData),
+                };
+                let data = if size < MIN_TO_COMPRESS {
+                    r.read_bytes(size)?
+                } else {
+                    let len = r.read_compact_len()?;
+                    r.read_bytes(len)?
+                };
+                self.reset(control);
+
+                let data = if size < MIN_TO_COMPRESS {
+                    data.to_vec()
+                } else {
+                    self.streams[(compression & 3) as usize].inflate(data, size)?
+                };
+                if data.len() != size {
+                    return Err(VncError::InvalidImageData);
+                }
+                let image = match filter {
+                    FILTER_PALETTE => unpalette(&data, palette, rect)?,
+                    FILTER_GRADIENT => ungradient(&data, rect),
+                    _ => data
+                        .chunks_exact(3)
+                        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                        .collect(),
+                };
+                Ok(TightImage::Raw(image))
+            }
+            _ => Err(VncError::InvalidImageData),
+        }
+    }
+
+    // The low bits of the control byte tell the streams to reset
+    fn reset(&mut self, control: u8) {
+        for (i, stream) in self.streams.iter_mut().enumerate() {
+            if control & (1 << i) != 0 {
+                stream.reset();
+            }
+        }
+    }
+}
+
+fn unpalette(data: &[u8], palette: &[u8], rect: &Rect) -> VncResult<Vec<u8>> {
+    let (width, height) = (rect.width as usize, rect.height as usize);
+    let colours = palette.len() / 3;
+    let mut image = Vec::with_capacity(pixels(rect) * 4);
+    for y in 0..height {
+        for x in 0..width {
+            // the rows of two colours are bitmaps, most significant bit first
+            let index = if colours <= 2 {
+                let byte = data[y * width.div_ceil(8) + x / 8];
+                ((byte >> (7 - x % 8)) & 1) as usize
+            } else {
+                data[y * width + x] as usize
+            };
+            if index >= colours {
+                return Err(VncError::InvalidImageData);
+            }
+            image.extend_from_slice(&palette[index * 3..index * 3 + 3]);
+            image.push(255);
+        }
+    }
+    Ok(image)
+}
+
+// Each colour was sent as the difference to its prediction from the left,
+// upper and upper left pixels
+fn ungradient(data: &[u8], rect: &Rect) -> Vec<u8> {
+    let width = rect.width as usize;
+    let mut image = vec![255; pixels(rect) * 4];
+    for (i, diff) in data.chunks_exact(3).enumerate() {
+        let (x, y) = (i % width, i / width);
+        for c in 0..3 {
+            let at = |x: usize, y: usize| image[(y * width + x) * 4 + c] as i32;
+            let left = if x > 0 { at(x - 1, y) } else { 0 };
+            let up = if y > 0 { at(x, y - 1) } else { 0 };
+            let corner = if x > 0 && y > 0 { at(x - 1, y - 1) } else { 0 };
+            let predicted = (left + up - corner).clamp(0, 255);
+            image[i * 4 + c] = (predicted as u8).wrapping_add(diff[c]);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Deflater;
+    use super::*;
+
+    fn rect(width: u16, height: u16) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    fn raw(image: TightImage) -> Vec<u8> {
+        match image {
+            TightImage::Raw(data) => data,
+            TightImage::Jpeg(_) => panic!("jpeg"),
+        }
+    }
+
+    #[test]
+    fn test_tight() {
+        let mut tight = TightDecoder::new();
+        let image = tight
+            .decode(&mut VncReader::new(&[0x80, 1, 2, 3]), &rect(2, 1))
+            .unwrap();
+        assert_eq!(raw(image), [1, 2, 3, 255, 1, 2, 3, 255]);
+
+        let image = tight
+            .decode(&mut VncReader::new(&[0x90, 3, 7, 8, 9]), &rect(2, 1))
+            .unwrap();
+        assert!(matches!(image, TightImage::Jpeg(data) if data == [7, 8, 9]));
+
+        // copy, too short to be compressed
+        let image = tight
+            .decode(&mut VncReader::new(&[0x00, 1, 2, 3, 4, 5, 6]), &rect(1, 2))
+            .unwrap();
+        assert_eq!(raw(image), [1, 2, 3, 255, 4, 5, 6, 255]);
+
+        // two colours, on stream 1
+        let image = tight
+            .decode(
+                &mut VncReader::new(&[0x50, FILTER_PALETTE, 1, 1, 1, 1, 2, 2, 2, 0b0100_0000]),
+                &rect(3, 1),
+            )
+            .unwrap();
+        assert_eq!(raw(image), [1, 1, 1, 255, 2, 2, 2, 255, 1, 1, 1, 255]);
+
+        // gradient
+        let image = tight
+            .decode(
+                &mut VncReader::new(&[0x40, FILTER_GRADIENT, 10, 10, 10, 5, 5, 5]),
+                &rect(2, 1),
+            )
+            .unwrap();
+        assert_eq!(raw(image), [10, 10, 10, 255, 15, 15, 15, 255]);
+    }
+
+    #[test]
+    fn test_tight_zlib() {
+        let (mut server, mut tight) = (Deflater::new(), TightDecoder::new());
+        let pixels = [9_u8; 4 * 4 * 3];
+        for _ in 0..2 {
+            let data = server.deflate(&pixels);
+            let mut bytes = vec![0x20, data.len() as u8];
+            bytes.extend_from_slice(&data);
+
+            // nothing is inflated until the rect is complete
+            let mut r = VncReader::new(&bytes[..bytes.len() - 1]);
+            assert!(matches!(
+                tight.decode(&mut r, &rect(4, 4)),
+                Err(VncError::Incomplete)
+            ));
+            let mut r = VncReader::new(&bytes);
+            assert_eq!(raw(tight.decode(&mut r, &rect(4, 4)).unwrap()).len(), 64);
+        }
+
+        // the stream is reset along with the server
+        let mut server = Deflater::new();
+        let data = server.deflate(&pixels);
+        let mut bytes = vec![0x24, data.len() as u8];
+        bytes.extend_from_slice(&data);
+        let image = tight.decode(&mut VncReader::new(&bytes), &rect(4, 4));
+        assert_eq!(raw(image.unwrap())[..4], [9, 9, 9, 255]);
+    }
+}
@@ -0,0 +1,239 @@
+// The ZRLE encoding
+//     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#zrle-encoding
+
+use super::{pixels, Inflater, Rect, VncError, VncReader, VncResult};
+
+const TILE: usize = 64;
+
+/// The zlib stream of ZRLE, the server never resets it
+pub struct ZrleDecoder {
+    z: Inflater,
+}
+
+impl ZrleDecoder {
+    pub fn new() -> Self {
+        Self { z: Inflater::new() }
+    }
+
+    pub fn decode(&mut self, r: &mut VncReader, rect: &Rect) -> VncResult<Vec<u8>> {
+        let len = r.read_u32()? as usize;
+        let data = r.read_bytes(len)?;
+
+        let (width, height) = (rect.width as usize, rect.height as usize);
+        // raw tiles with a full palette, or plain RLE of runs of one
+        let tiles = width.div_ceil(TILE) * height.div_ceil(TILE);
+        let data = self
+            .z
+            .inflate(data, pixels(rect) * 4 + tiles * (1 + 127 * 3))?;
+
+        let mut image = Image {
+            data: vec![0; pixels(rect) * 4],
+            width,
+        };
+        let mut t = VncReader::new(&data);
+        for y in (0..height).step_by(TILE) {
+            for x in (0..width).step_by(TILE) {
+                let tile = Tile {
+                    x,
+                    y,
+                    width: TILE.min(width - x),
+                    height: TILE.min(height - y),
+                };
+                tile.decode(&mut t, &mut image).map_err(|e| match e {
+                    VncError::Incomplete => VncError::InvalidImageData,
+                    e => e,
+                })?;
+            }
+        }
+        Ok(image.data)
+    }
+}
+
+struct Image {
+    data: Vec<u8>,
+    width: usize,
+}
+
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Tile {
+    fn decode(&self, t: &mut VncReader, image: &mut Image) -> VncResult<()> {
+        let pixels = self.width * self.height;
+        match t.read_u8()? {
+            0 => {
+                for i in 0..pixels {
+                    let pixel = cpixel(t)?;
+                    self.set(image, i, pixel);
+                }
+            }
+            1 => {
+                let pixel = cpixel(t)?;
+                self.fill(image, 0, pixels, pixel);
+            }
+            size @ 2..=16 => {
+                let palette = palette(t, size as usize)?;
+                let bits = match size {
+                    2 => 1,
+                    3 | 4 => 2,
+                    _ => 4,
+                };
+                for y in 0..self.height {
+                    let row = t.read_bytes((self.width * bits).div_ceil(8))?;
+                    for x in 0..self.width {
+                        let bit = x * bits;
+                        let index = (row[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1);
+                        let pixel = *palette
+                            .get(index as usize)
+                            .ok_or(VncError::InvalidImageData)?;
+                        self.set(image, y * self.width + x, pixel);
+                    }
+                }
+            }
+            128 => {
+                let mut i = 0;
+                while i < pixels {
+                    let pixel = cpixel(t)?;
+                    let run = run_length(t)?;
+                    if i + run > pixels {
+                        return Err(VncError::InvalidImageData);
+                    }
+                    self.fill(image, i, run, pixel);
+                    i += run;
+                }
+            }
+            sub @ 130.. => {
+                let palette = palette(t, sub as usize - 128)?;
+                let mut i = 0;
+                while i < pixels {
+                    let index = t.read_u8()?;
+                    let run = if index & 0x80 != 0 { run_length(t)? } else { 1 };
+                    let pixel = *palette
+                        .get((index & 0x7f) as usize)
+                        .ok_or(VncError::InvalidImageData)?;
+                    if i + run > pixels {
+                        return Err(VncError::InvalidImageData);
+                    }
+                    self.fill(image, i, run, pixel);
+                    i += run;
+                }
+            }
+            _ => return Err(VncError::InvalidImageData),
+        }
+        Ok(())
+    }
+
+    // the `i`th pixel of the tile
+    fn set(&self, image: &mut Image, i: usize, pixel: [u8; 4]) {
+        let (x, y) = (self.x + i % self.width, self.y + i / self.width);
+        let at = (y * image.width + x) * 4;
+        image.data[at..at + 4].copy_from_slice(&pixel);
+    }
+
+    fn fill(&self, image: &mut Image, from: usize, count: usize, pixel: [u8; 4]) {
+        for i in from..from + count {
+            self.set(image, i, pixel);
+        }
+    }
+}
+
+// The compressed pixel, without the byte which is always zero
+fn cpixel(t: &mut VncReader) -> VncResult<[u8; 4]> {
+    let rgb = t.read_bytes(3)?;
+    Ok([rgb[0], rgb[1], rgb[2], 255])
+}
+
+fn palette(t: &mut VncReader, size: usize) -> VncResult<Vec<[u8; 4]>> {
+    (0..size).map(|_| cpixel(t)).collect()
+}
+
+fn run_length(t: &mut VncReader) -> VncResult<usize> {
+    let mut run = 1;
+    loop {
+        let byte = t.read_u8()?;
+        run += byte as usize;
+        if byte != 255 {
+            return Ok(run);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Deflater;
+    use super::*;
+
+    fn rect(width: u16, height: u16) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    fn message(server: &mut Deflater, tiles: &[u8]) -> Vec<u8> {
+        let data = server.deflate(tiles);
+        let mut bytes = (data.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    #[test]
+    fn test_zrle() {
+        let (mut server, mut zrle) = (Deflater::new(), ZrleDecoder::new());
+        const R: [u8; 4] = [255, 0, 0, 255];
+        const B: [u8; 4] = [0, 0, 255, 255];
+
+        // solid
+        let bytes = message(&mut server, &[1, 255, 0, 0]);
+        let image = zrle
+            .decode(&mut VncReader::new(&bytes), &rect(2, 2))
+            .unwrap();
+        assert_eq!(image, [R, R, R, R].concat());
+
+        // packed palette, the rows are padded
+        let bytes = message(
+            &mut server,
+            &[2, 255, 0, 0, 0, 0, 255, 0b0100_0000, 0b1000_0000],
+        );
+        let image = zrle
+            .decode(&mut VncReader::new(&bytes), &rect(3, 2))
+            .unwrap();
+        assert_eq!(image, [R, B, R, B, R, R].concat());
+
+        // plain RLE
+        let bytes = message(&mut server, &[128, 255, 0, 0, 2, 0, 0, 255, 0]);
+        let image = zrle
+            .decode(&mut VncReader::new(&bytes), &rect(2, 2))
+            .unwrap();
+        assert_eq!(image, [R, R, R, B].concat());
+
+        // palette RLE
+        let bytes = message(&mut server, &[130, 255, 0, 0, 0, 0, 255, 0x81, 2, 0]);
+        let image = zrle
+            .decode(&mut VncReader::new(&bytes), &rect(2, 2))
+            .unwrap();
+        assert_eq!(image, [B, B, B, R].concat());
+
+        // two tiles
+        let mut tiles = vec![1, 255, 0, 0];
+        tiles.extend_from_slice(&[1, 0, 0, 255]);
+        let bytes = message(&mut server, &tiles);
+        let image = zrle
+            .decode(&mut VncReader::new(&bytes), &rect(65, 1))
+            .unwrap();
+        assert_eq!(image[..4], R);
+        assert_eq!(image[64 * 4..], B);
+
+        // a run longer than the tile
+        let bytes = message(&mut server, &[128, 255, 0, 0, 4]);
+        assert!(zrle
+            .decode(&mut VncReader::new(&bytes), &rect(2, 2))
+            .is_err());
+    }
+}
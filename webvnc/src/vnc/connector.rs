@@ -0,0 +1,362 @@
+use super::{
+    auth,
+    msg::{self, VncEncoding, VncVersion},
+    Screen, VncClient, VncError, VncResult,
+};
+use std::{future::Future, pin::Pin};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+type PasswordFuture = Pin<Box<dyn Future<Output = VncResult<String>>>>;
+type Chooser = Box<dyn FnOnce(Vec<u8>) -> Pin<Box<dyn Future<Output = VncResult<u8>>>>>;
+
+/// The configuration of a connection
+pub struct VncConnector<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream: S,
+    version: VncVersion,
+    password: Option<PasswordFuture>,
+    chooser: Option<Chooser>,
+    shared: bool,
+    encodings: Vec<VncEncoding>,
+}
+
+impl<S> VncConnector<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// To new a vnc client configuration with stream `S`
+    ///
+    /// `S` should implement async I/O methods, it can be a websocket stream
+    /// or a tcp stream, when testing natively
+    ///
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            version: VncVersion::RFB38,
+            password: None,
+            chooser: None,
+            shared: true,
+            encodings: Vec::new(),
+        }
+    }
+
+    /// The highest version to speak, the one of the server if it is lower
+    ///
+    pub fn set_version(mut self, version: VncVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The password of the VNC Authentication, only awaited if the server
+    /// asks for it
+    ///
+    pub fn set_auth_method<F>(mut self, password: F) -> Self
+    where
+        F: Future<Output = VncResult<String>> + 'static,
+    {
+        self.password = Some(Box::pin(password));
+        self
+    }
+
+    /// Pick one of the security types the server offers, called with all of
+    /// them when more than one is supported, see [msg::SUPPORTED_SECURITY]
+    ///
+    /// The first supported one in the order of the server is taken otherwise
+    ///
+    pub fn set_security_chooser<F, Fut>(mut self, chooser: F) -> Self
+    where
+        F: FnOnce(Vec<u8>) -> Fut + 'static,
+        Fut: Future<Output = VncResult<u8>> + 'static,
+    {
+        self.chooser = Some(Box::new(move |types| Box::pin(chooser(types))));
+        self
+    }
+
+    /// Leave the other clients connected, true by default
+    ///
+    pub fn allow_shared(mut self, shared: bool) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// The encodings the server may use, in the order of preference
+    ///
+    pub fn add_encoding(mut self, encoding: VncEncoding) -> Self {
+        self.encodings.push(encoding);
+        self
+    }
+
+    /// Shake hands with the server, authenticate and ask for the whole
+    /// desktop, which comes in the first updates
+    ///
+    pub async fn connect(mut self) -> VncResult<VncClient<S>> {
+        let mut version = [0; 12];
+        self.stream.read_exact(&mut version).await?;
+        let version = VncVersion::parse(&version)
+            .ok_or_else(|| VncError::InvalidVersion(String::from_utf8_lossy(&version).into()))?
+            .min(self.version);
+        self.stream.write_all(version.as_bytes()).await?;
+
+        self.security(version).await?;
+
+        self.stream.write_all(&[self.shared as u8]).await?;
+        let width = self.stream.read_u16().await?;
+        let height = self.stream.read_u16().await?;
+        // the pixel format of the server, ours is set below
+        self.stream.read_exact(&mut [0; 16]).await?;
+        let name = read_string(&mut self.stream).await?;
+
+        let mut out = vec![msg::SET_PIXEL_FORMAT, 0, 0, 0];
+        // 32 bits per pixel, depth 24, little endian, true colour,
+        // the maximums and the shifts of red, green and blue
+        out.extend_from_slice(&[32, 24, 0, 1, 0, 255, 0, 255, 0, 255, 0, 8, 16, 0, 0, 0]);
+        out.extend_from_slice(&[msg::SET_ENCODINGS, 0]);
+        out.extend_from_slice(&(self.encodings.len() as u16).to_be_bytes());
+        for encoding in &self.encodings {
+            out.extend_from_slice(&encoding.code().to_be_bytes());
+        }
+        self.stream.write_all(&out).await?;
+
+        let mut client = VncClient::new(self.stream, name, Screen { width, height });
+        client.request(false);
+        client.flush().await?;
+        Ok(client)
+    }
+
+    // Pick a security type and authenticate
+    async fn security(&mut self, version: VncVersion) -> VncResult<()> {
+        let types = if version == VncVersion::RFB33 {
+            // the server decides
+            vec![self.stream.read_u32().await? as u8]
+        } else {
+            let count = self.stream.read_u8().await?;
+            let mut types = vec![0; count as usize];
+            self.stream.read_exact(&mut types).await?;
+            types
+        };
+        if types.is_empty() || types == [msg::SECURITY_INVALID] {
+            let reason = read_string(&mut self.stream).await?;
+            return Err(VncError::AuthFailed(reason));
+        }
+
+        let supported: Vec<u8> = types
+            .iter()
+            .copied()
+            .filter(|security| msg::SUPPORTED_SECURITY.contains(security))
+            .collect();
+        let security = match (supported.len(), self.chooser.take()) {
+            (0, _) if version == VncVersion::RFB33 => {
+                return Err(VncError::InvalidSecurityType(types[0]))
+            }
+            (0, _) => {
+                let names: Vec<String> = types.into_iter().map(msg::security_name).collect();
+                return Err(VncError::NoSecurityType(names.join(", ")));
+            }
+            (1, _) | (_, None) => supported[0],
+            (_, Some(chooser)) => {
+                let security = chooser(types).await?;
+                if !supported.contains(&security) {
+                    return Err(VncError::InvalidSecurityType(security));
+                }
+                security
+            }
+        };
+        if version != VncVersion::RFB33 {
+            self.stream.write_all(&[security]).await?;
+        }
+
+        if security == msg::SECURITY_VNC_AUTH {
+            let mut challenge = [0; 16];
+            self.stream.read_exact(&mut challenge).await?;
+            let password = self.password.take().ok_or(VncError::NoPassword)?.await?;
+            self.stream
+                .write_all(&auth::vnc_auth(&challenge, &password))
+                .await?;
+        }
+
+        // there is no result of None before 3.8, and no reason of a failure
+        if (security == msg::SECURITY_VNC_AUTH || version == VncVersion::RFB38)
+            && self.stream.read_u32().await? != 0
+        {
+            let reason = if version == VncVersion::RFB38 {
+                read_string(&mut self.stream).await?
+            } else {
+                "Wrong password".to_owned()
+            };
+            return Err(VncError::AuthFailed(reason));
+        }
+        Ok(())
+    }
+}
+
+// A string after its length
+async fn read_string<S>(stream: &mut S) -> VncResult<String>
+where
+    S: AsyncRead + Unpin,
+{
+    let len = stream.read_u32().await?;
+    let mut buf = Vec::new();
+    stream.take(len as u64).read_to_end(&mut buf).await?;
+    if buf.len() != len as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(String::from_utf8_lossy(&buf).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vnc::VncEvent;
+
+    async fn server_init<S: AsyncRead + AsyncWrite + Unpin>(server: &mut S) {
+        assert_eq!(server.read_u8().await.unwrap(), 1);
+        server.write_all(&[2, 128, 1, 224]).await.unwrap();
+        server.write_all(&[0; 16]).await.unwrap();
+        server.write_all(&[0, 0, 0, 4]).await.unwrap();
+        server.write_all(b"desk").await.unwrap();
+
+        let mut init = [0; 20 + 4 + 4 + 10];
+        server.read_exact(&mut init).await.unwrap();
+        assert_eq!(init[0], msg::SET_PIXEL_FORMAT);
+        assert_eq!(init[4..8], [32, 24, 0, 1]);
+        assert_eq!(init[20..24], [msg::SET_ENCODINGS, 0, 0, 1]);
+        assert_eq!(init[24..28], 7_i32.to_be_bytes());
+        assert_eq!(init[28..], [3, 0, 0, 0, 0, 0, 2, 128, 1, 224]);
+    }
+
+    #[tokio::test]
+    async fn test_connect() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let client = VncConnector::new(stream)
+            .set_auth_method(async { Ok("secret".to_owned()) })
+            .add_encoding(VncEncoding::Tight)
+            .connect();
+        let server = async move {
+            server.write_all(b"RFB 003.008\n").await.unwrap();
+            let mut version = [0; 12];
+            server.read_exact(&mut version).await.unwrap();
+            assert_eq!(&version, b"RFB 003.008\n");
+
+            server
+                .write_all(&[2, 16, msg::SECURITY_VNC_AUTH])
+                .await
+                .unwrap();
+            assert_eq!(server.read_u8().await.unwrap(), msg::SECURITY_VNC_AUTH);
+            let challenge = [7; 16];
+            server.write_all(&challenge).await.unwrap();
+            let mut response = [0; 16];
+            server.read_exact(&mut response).await.unwrap();
+            assert_eq!(response, auth::vnc_auth(&challenge, "secret"));
+            server.write_u32(0).await.unwrap();
+            server_init(&mut server).await;
+            server
+        };
+        let (client, _server) = tokio::join!(client, server);
+        let mut client = client.unwrap();
+        assert_eq!(client.name(), "desk");
+        assert_eq!(
+            client.recv().await.unwrap(),
+            VncEvent::SetResolution((640, 480).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_33() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let client = VncConnector::new(stream)
+            .add_encoding(VncEncoding::Tight)
+            .connect();
+        let server = async move {
+            server.write_all(b"RFB 003.003\n").await.unwrap();
+            let mut version = [0; 12];
+            server.read_exact(&mut version).await.unwrap();
+            assert_eq!(&version, b"RFB 003.003\n");
+            // no security result of None
+            server.write_u32(msg::SECURITY_NONE as u32).await.unwrap();
+            server_init(&mut server).await;
+            server
+        };
+        let (client, _server) = tokio::join!(client, server);
+        assert_eq!(client.unwrap().name(), "desk");
+    }
+
+    #[tokio::test]
+    async fn test_connect_failed() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = async move {
+            server.write_all(b"RFB 003.008\n").await.unwrap();
+            server.write_all(&[0, 0, 0, 0, 8]).await.unwrap();
+            server.write_all(b"Too many").await.unwrap();
+            server
+        };
+        let (client, _server) = tokio::join!(VncConnector::new(stream).connect(), server);
+        assert!(matches!(client, Err(VncError::AuthFailed(reason)) if reason == "Too many"));
+
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let client = VncConnector::new(stream)
+            .set_auth_method(async { Ok("wrong".to_owned()) })
+            .connect();
+        let server = async move {
+            server.write_all(b"RFB 003.003\n").await.unwrap();
+            server
+                .write_u32(msg::SECURITY_VNC_AUTH as u32)
+                .await
+                .unwrap();
+            server.write_all(&[0; 16]).await.unwrap();
+            server.read_exact(&mut [0; 12 + 16]).await.unwrap();
+            server.write_u32(1).await.unwrap();
+            server
+        };
+        let (client, _server) = tokio::join!(client, server);
+        assert!(matches!(client, Err(VncError::AuthFailed(reason)) if reason == "Wrong password"));
+
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = async move {
+            server.write_all(b"RFB 003.008\n").await.unwrap();
+            server
+                .write_all(&[1, msg::SECURITY_VNC_AUTH])
+                .await
+                .unwrap();
+            server.write_all(&[0; 16]).await.unwrap();
+            server
+        };
+        let (client, _server) = tokio::join!(VncConnector::new(stream).connect(), server);
+        assert!(matches!(client, Err(VncError::NoPassword)));
+    }
+
+    #[tokio::test]
+    async fn test_choose_security() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let client = VncConnector::new(stream)
+            .set_security_chooser(|types| async move {
+                assert_eq!(types, [16, msg::SECURITY_VNC_AUTH, msg::SECURITY_NONE]);
+                Ok(msg::SECURITY_NONE)
+            })
+            .add_encoding(VncEncoding::Tight)
+            .connect();
+        let server = async move {
+            server.write_all(b"RFB 003.007\n").await.unwrap();
+            server.read_exact(&mut [0; 12]).await.unwrap();
+            server.write_all(&[3, 16, 2, 1]).await.unwrap();
+            assert_eq!(server.read_u8().await.unwrap(), msg::SECURITY_NONE);
+            // no security result of None before 3.8
+            server_init(&mut server).await;
+            server
+        };
+        let (client, _server) = tokio::join!(client, server);
+        assert_eq!(client.unwrap().name(), "desk");
+
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = async move {
+            server.write_all(b"RFB 003.008\n").await.unwrap();
+            server.write_all(&[2, 16, 19]).await.unwrap();
+            server
+        };
+        let (client, _server) = tokio::join!(VncConnector::new(stream).connect(), server);
+        assert!(
+            matches!(client, Err(VncError::NoSecurityType(types)) if types == "Tight, VeNCrypt")
+        );
+    }
+}
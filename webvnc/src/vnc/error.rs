@@ -0,0 +1,31 @@
+use thiserror::Error;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum VncError {
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+    /// More bytes are needed, never returned by the client
+    #[error("Incomplete message")]
+    Incomplete,
+    #[error("The server sent an invalid version: {0}")]
+    InvalidVersion(String),
+    #[error("Unknown vnc security type: {0}")]
+    InvalidSecurityType(u8),
+    #[error("None of the security types of the server is supported: {0}")]
+    NoSecurityType(String),
+    #[error("Auth is required but no password provided")]
+    NoPassword,
+    #[error("Authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("Unknown server message {0}")]
+    WrongServerMessage(u8),
+    #[error("Unknown encoding {0}")]
+    WrongEncoding(i32),
+    #[error("Image data cannot be decoded correctly")]
+    InvalidImageData,
+    #[error("Vnc Error with message: {0}")]
+    General(String),
+}
+
+pub type VncResult<T> = Result<T, VncError>;
@@ -0,0 +1,110 @@
+/// A rect of the desktop, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// The size of the desktop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl From<(u16, u16)> for Screen {
+    fn from(tuple: (u16, u16)) -> Self {
+        Self {
+            width: tuple.0,
+            height: tuple.1,
+        }
+    }
+}
+
+type ImageData = Vec<u8>;
+type SrcRect = Rect;
+type DstRect = Rect;
+
+/// Something the server sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VncEvent {
+    /// The desktop has this size now, it is also the first event
+    SetResolution(Screen),
+    /// RGBA pixels, the alpha is always opaque
+    RawImage(Rect, ImageData),
+    /// Copy the pixels of the second rect to the first
+    Copy(DstRect, SrcRect),
+    /// A JPEG image of a Tight rect
+    JpegImage(Rect, ImageData),
+    /// The cursor shape as RGBA pixels, the position of the rect is its
+    /// hotspot
+    SetCursor(Rect, ImageData),
+    /// All the rects of an update have come, the next one may be asked for
+    UpdateEnd,
+    Bell,
+    /// The clipboard of the server has changed
+    Text(String),
+}
+
+/// A key press or release, with its keysym, 0 if the key has none, and its
+/// XT scancode, 0 if unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientKeyEvent {
+    pub keycode: u32,
+    pub scancode: u32,
+    pub down: bool,
+}
+
+impl From<(u32, bool)> for ClientKeyEvent {
+    fn from(tuple: (u32, bool)) -> Self {
+        Self {
+            keycode: tuple.0,
+            scancode: 0,
+            down: tuple.1,
+        }
+    }
+}
+
+impl From<(u32, u32, bool)> for ClientKeyEvent {
+    fn from(tuple: (u32, u32, bool)) -> Self {
+        Self {
+            keycode: tuple.0,
+            scancode: tuple.1,
+            down: tuple.2,
+        }
+    }
+}
+
+/// The position of the pointer and its pressed buttons, bit 0 is the left
+/// button
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientMouseEvent {
+    pub position_x: u16,
+    pub position_y: u16,
+    pub buttons: u8,
+}
+
+impl From<(u16, u16, u8)> for ClientMouseEvent {
+    fn from(tuple: (u16, u16, u8)) -> Self {
+        Self {
+            position_x: tuple.0,
+            position_y: tuple.1,
+            buttons: tuple.2,
+        }
+    }
+}
+
+/// Input for the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum X11Event {
+    /// Ask for the changes of the desktop
+    Refresh,
+    KeyEvent(ClientKeyEvent),
+    PointerEvent(ClientMouseEvent),
+    /// Put the text to the clipboard of the server
+    CopyText(String),
+    /// Ask the server to resize the desktop, if it can
+    Resize(Screen),
+}
@@ -0,0 +1,23 @@
+//! An async implementation of the client side of the remote framebuffer
+//! protocol
+//!
+//! The protocol is not bound to the browser, so that it can be tested with
+//! any async stream natively
+
+mod auth;
+mod client;
+mod clipboard;
+mod codec;
+mod connector;
+mod des;
+mod error;
+mod event;
+pub mod msg;
+pub mod wire;
+
+pub use client::VncClient;
+pub use connector::VncConnector;
+pub use error::*;
+pub use event::*;
+pub use msg::{VncEncoding, VncVersion};
+pub use wire::VncReader;
@@ -0,0 +1,159 @@
+// Message types, encodings and security types
+//     https://www.rfc-editor.org/rfc/rfc6143#section-7.5
+//     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#encodings
+
+// Client to server
+pub const SET_PIXEL_FORMAT: u8 = 0;
+pub const SET_ENCODINGS: u8 = 2;
+pub const FRAMEBUFFER_UPDATE_REQUEST: u8 = 3;
+pub const KEY_EVENT: u8 = 4;
+pub const POINTER_EVENT: u8 = 5;
+pub const CLIENT_CUT_TEXT: u8 = 6;
+pub const SET_DESKTOP_SIZE: u8 = 251;
+pub const QEMU_CLIENT_MESSAGE: u8 = 255;
+
+// Submessages of QEMU_CLIENT_MESSAGE
+pub const QEMU_EXTENDED_KEY_EVENT: u8 = 0;
+
+// Server to client
+pub const FRAMEBUFFER_UPDATE: u8 = 0;
+pub const SET_COLOUR_MAP_ENTRIES: u8 = 1;
+pub const BELL: u8 = 2;
+pub const SERVER_CUT_TEXT: u8 = 3;
+
+// Security types
+pub const SECURITY_INVALID: u8 = 0;
+pub const SECURITY_NONE: u8 = 1;
+pub const SECURITY_VNC_AUTH: u8 = 2;
+
+/// The security types the client can do
+pub const SUPPORTED_SECURITY: [u8; 2] = [SECURITY_NONE, SECURITY_VNC_AUTH];
+
+/// The name of a security type, as registered by IANA
+pub fn security_name(security: u8) -> String {
+    match security {
+        SECURITY_NONE => "None",
+        SECURITY_VNC_AUTH => "VNC Authentication",
+        5 => "RA2",
+        6 => "RA2ne",
+        16 => "Tight",
+        17 => "Ultra",
+        18 => "TLS",
+        19 => "VeNCrypt",
+        20 => "SASL",
+        21 => "MD5 hash",
+        22 => "xvp",
+        30 => "Apple Remote Desktop",
+        security => return format!("Unknown ({})", security),
+    }
+    .to_owned()
+}
+
+/// The encodings and pseudo-encodings we know
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VncEncoding {
+    Raw,
+    CopyRect,
+    Tight,
+    Zrle,
+    CursorPseudo,
+    DesktopSizePseudo,
+    LastRectPseudo,
+    ExtendedClipboardPseudo,
+    ExtendedDesktopSizePseudo,
+    QemuExtendedKeyEventPseudo,
+}
+
+impl VncEncoding {
+    const ALL: [VncEncoding; 10] = [
+        VncEncoding::Raw,
+        VncEncoding::CopyRect,
+        VncEncoding::Tight,
+        VncEncoding::Zrle,
+        VncEncoding::CursorPseudo,
+        VncEncoding::DesktopSizePseudo,
+        VncEncoding::LastRectPseudo,
+        VncEncoding::ExtendedClipboardPseudo,
+        VncEncoding::ExtendedDesktopSizePseudo,
+        VncEncoding::QemuExtendedKeyEventPseudo,
+    ];
+
+    /// The encoding-type on the wire
+    pub fn code(self) -> i32 {
+        match self {
+            VncEncoding::Raw => 0,
+            VncEncoding::CopyRect => 1,
+            VncEncoding::Tight => 7,
+            VncEncoding::Zrle => 16,
+            VncEncoding::CursorPseudo => -239,
+            VncEncoding::DesktopSizePseudo => -223,
+            VncEncoding::LastRectPseudo => -224,
+            VncEncoding::ExtendedClipboardPseudo => 0xc0a1_e5ce_u32 as i32,
+            VncEncoding::ExtendedDesktopSizePseudo => -308,
+            VncEncoding::QemuExtendedKeyEventPseudo => -258,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.code() == code)
+    }
+}
+
+/// The versions of the protocol we speak, the older first
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum VncVersion {
+    RFB33,
+    RFB37,
+    RFB38,
+}
+
+impl VncVersion {
+    /// The ProtocolVersion message
+    pub fn as_bytes(self) -> &'static [u8; 12] {
+        match self {
+            VncVersion::RFB33 => b"RFB 003.003\n",
+            VncVersion::RFB37 => b"RFB 003.007\n",
+            VncVersion::RFB38 => b"RFB 003.008\n",
+        }
+    }
+
+    /// The version of the server, None if it is not a ProtocolVersion
+    ///
+    /// Other versions than 3.3, 3.7 and 3.8 should be interpreted as 3.3,
+    /// later ones as 3.8 in practice
+    pub fn parse(version: &[u8; 12]) -> Option<Self> {
+        let text = std::str::from_utf8(version).ok()?;
+        let (major, minor) = text
+            .strip_prefix("RFB ")?
+            .strip_suffix('\n')?
+            .split_once('.')?;
+        let (major, minor): (u32, u32) = (major.parse().ok()?, minor.parse().ok()?);
+        Some(match (major, minor) {
+            (3, 7) => VncVersion::RFB37,
+            (3, 8..) | (4.., _) => VncVersion::RFB38,
+            _ => VncVersion::RFB33,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert_eq!(VncVersion::parse(b"RFB 003.008\n"), Some(VncVersion::RFB38));
+        assert_eq!(VncVersion::parse(b"RFB 003.007\n"), Some(VncVersion::RFB37));
+        assert_eq!(VncVersion::parse(b"RFB 003.005\n"), Some(VncVersion::RFB33));
+        // Apple Remote Desktop
+        assert_eq!(VncVersion::parse(b"RFB 003.889\n"), Some(VncVersion::RFB38));
+        assert_eq!(VncVersion::parse(b"SSH-2.0-abc\n"), None);
+        assert!(VncVersion::RFB33 < VncVersion::RFB38);
+
+        for encoding in VncEncoding::ALL {
+            assert_eq!(VncEncoding::from_code(encoding.code()), Some(encoding));
+        }
+        assert_eq!(VncEncoding::ExtendedClipboardPseudo.code(), -1063131698);
+        assert_eq!(VncEncoding::from_code(5), None);
+    }
+}
@@ -0,0 +1,105 @@
+// Data types of the messages
+//     https://www.rfc-editor.org/rfc/rfc6143#section-7
+
+use super::{VncError, VncResult};
+
+/// Reads the messages out of the bytes received so far, which may end in the
+/// middle of one, [VncError::Incomplete] is returned then
+pub struct VncReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> VncReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// How many bytes have been read
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remain(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn read_bytes(&mut self, n: usize) -> VncResult<&'a [u8]> {
+        if self.remain() < n {
+            return Err(VncError::Incomplete);
+        }
+        let out = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(out)
+    }
+
+    pub fn skip(&mut self, n: usize) -> VncResult<()> {
+        self.read_bytes(n).map(|_| ())
+    }
+
+    pub fn read_u8(&mut self) -> VncResult<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> VncResult<u16> {
+        let buf = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes(buf.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> VncResult<u32> {
+        let buf = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes(buf.try_into().unwrap()))
+    }
+
+    pub fn read_i32(&mut self) -> VncResult<i32> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    /// The length of the Tight data, in 1 to 3 bytes
+    ///     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#tight-encoding
+    pub fn read_compact_len(&mut self) -> VncResult<usize> {
+        let mut len = 0;
+        for i in 0..3 {
+            let byte = self.read_u8()? as usize;
+            if i == 2 {
+                return Ok(len | byte << 14);
+            }
+            len |= (byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reader() {
+        let mut r = VncReader::new(&[1, 0, 2, 0, 0, 0, 3, 0xff]);
+        assert_eq!(r.read_u8().unwrap(), 1);
+        assert_eq!(r.read_u16().unwrap(), 2);
+        assert_eq!(r.read_u32().unwrap(), 3);
+        assert_eq!(r.pos(), 7);
+        assert!(matches!(r.read_u16(), Err(VncError::Incomplete)));
+        // nothing is consumed by a short read
+        assert_eq!(r.read_u8().unwrap(), 0xff);
+
+        for (bytes, len) in [
+            (&[0x10][..], 0x10),
+            (&[0x90, 0x4e], 10000),
+            (&[0xff, 0xff, 0xff], 0x3fffff),
+        ] {
+            let mut r = VncReader::new(bytes);
+            assert_eq!(r.read_compact_len().unwrap(), len);
+            assert_eq!(r.remain(), 0);
+        }
+        assert!(matches!(
+            VncReader::new(&[0x90]).read_compact_len(),
+            Err(VncError::Incomplete)
+        ));
+    }
+}
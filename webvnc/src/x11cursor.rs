@@ -1,9 +1,16 @@
+use web_sys::HtmlCanvasElement;
+
 pub struct MouseUtils;
 
 impl MouseUtils {
-    pub fn get_mouse_sym(event: web_sys::MouseEvent) -> (u16, u16, u8) {
-        let x: u16 = event.offset_x().try_into().unwrap_or(0);
-        let y: u16 = event.offset_y().try_into().unwrap_or(0);
+    /// The position on the desktop, the canvas may be scaled to the page
+    pub fn get_mouse_sym(event: web_sys::MouseEvent, canvas: &HtmlCanvasElement) -> (u16, u16, u8) {
+        let unscale = |offset: i32, size: u32, shown: i32| {
+            let pos = offset as f64 * size as f64 / shown.max(1) as f64;
+            pos.clamp(0.0, size.saturating_sub(1) as f64) as u16
+        };
+        let x = unscale(event.offset_x(), canvas.width(), canvas.client_width());
+        let y = unscale(event.offset_y(), canvas.height(), canvas.client_height());
         let buttons = event.buttons();
 
         // On a conventional mouse, buttons 1, 2, and 3 correspond to the left,
@@ -11,10 +18,13 @@ impl MouseUtils {
         // of the wheel upwards is represented by a press and release of button
         // 4, and each step downwards is represented by a press and release of
         // button 5.
+        // Buttons 6 and 7 are the horizontal wheel, 8 is the back button of
+        // X. The forward one, 9, does not fit in the mask.
         let mut mask = 0;
         let left = buttons & 0x1 > 0;
         let right = buttons & 0x2 > 0;
         let middle = buttons & 0x4 > 0;
+        let back = buttons & 0x8 > 0;
         if left {
             mask |= 1;
         }
@@ -24,6 +34,83 @@ impl MouseUtils {
         if right {
             mask |= 1 << 2;
         }
+        if back {
+            mask |= 1 << 7;
+        }
         (x, y, mask)
     }
 }
+
+// WheelEvent.deltaMode
+//     https://developer.mozilla.org/en-US/docs/Web/API/WheelEvent/deltaMode
+const DELTA_LINE: u32 = 1;
+const DELTA_PAGE: u32 = 2;
+
+// The pixels of a line and of a page, and those that make a step of the wheel
+const LINE_HEIGHT: f64 = 20.0;
+const PAGE_HEIGHT: f64 = 400.0;
+const WHEEL_STEP: f64 = 50.0;
+
+/// The buttons of a step of the wheel up, down, left and right
+pub const WHEEL_UP: u8 = 1 << 3;
+pub const WHEEL_DOWN: u8 = 1 << 4;
+pub const WHEEL_LEFT: u8 = 1 << 5;
+pub const WHEEL_RIGHT: u8 = 1 << 6;
+
+/// Turns the deltas of the wheel into steps, a trackpad scrolls by a few
+/// pixels at a time
+#[derive(Default)]
+pub struct Wheel {
+    x: f64,
+    y: f64,
+}
+
+impl Wheel {
+    /// The buttons to press and release, one for each step
+    pub fn steps(&mut self, delta_x: f64, delta_y: f64, mode: u32) -> Vec<u8> {
+        let scale = match mode {
+            DELTA_LINE => LINE_HEIGHT,
+            DELTA_PAGE => PAGE_HEIGHT,
+            _ => 1.0,
+        };
+        let mut steps = Vec::new();
+        for (sum, delta, back, forth) in [
+            (&mut self.x, delta_x, WHEEL_LEFT, WHEEL_RIGHT),
+            (&mut self.y, delta_y, WHEEL_UP, WHEEL_DOWN),
+        ] {
+            // what is left from the other way is dropped
+            if *sum * delta < 0.0 {
+                *sum = 0.0;
+            }
+            *sum += delta * scale;
+            let n = (*sum / WHEEL_STEP).trunc();
+            *sum -= n * WHEEL_STEP;
+            let button = if n < 0.0 { back } else { forth };
+            steps.extend(std::iter::repeat_n(button, n.abs() as usize));
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wheel() {
+        let mut wheel = Wheel::default();
+        // a notch of a mouse is a step at least
+        assert_eq!(wheel.steps(0.0, -3.0, DELTA_LINE), vec![WHEEL_UP]);
+        assert_eq!(wheel.steps(0.0, 100.0, 0), vec![WHEEL_DOWN, WHEEL_DOWN]);
+        assert_eq!(wheel.steps(0.0, 1.0, DELTA_PAGE).len(), 8);
+
+        // a trackpad adds up
+        let mut wheel = Wheel::default();
+        assert!(wheel.steps(20.0, 0.0, 0).is_empty());
+        assert!(wheel.steps(20.0, 0.0, 0).is_empty());
+        assert_eq!(wheel.steps(20.0, 0.0, 0), vec![WHEEL_RIGHT]);
+        // not with the other way
+        assert!(wheel.steps(-40.0, 0.0, 0).is_empty());
+        assert_eq!(wheel.steps(-20.0, -5.0, 0), vec![WHEEL_LEFT]);
+    }
+}
@@ -22,6 +22,8 @@ pub const XK_Muhenkan: u32 = 0xFF22;
 pub const XK_Henkan_Mode: u32 = 0xFF23;
 pub const XK_Henkan: u32 = 0xFF23;
 pub const XK_Romaji: u32 = 0xFF24;
+pub const XK_Hangul: u32 = 0xFF31;
+pub const XK_Hangul_Hanja: u32 = 0xFF34;
 pub const XK_Hiragana: u32 = 0xFF25;
 pub const XK_Katakana: u32 = 0xFF26;
 pub const XK_Hiragana_Katakana: u32 = 0xFF27;
@@ -1333,618 +1335,328 @@ pub const XK_c_h: u32 = 0xfea3;
 pub const XK_C_h: u32 = 0xfea4;
 pub const XK_C_H: u32 = 0xfea5;
 
+// KeyboardEvent.location
+//     https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/location
+const LOCATION_LEFT: u32 = 1;
+const LOCATION_RIGHT: u32 = 2;
+const LOCATION_NUMPAD: u32 = 3;
+
 pub struct KeyboardUtils;
 
 impl KeyboardUtils {
-    pub fn get_keysym(event: web_sys::KeyboardEvent) -> u32 {
-        // let ctrl = event.ctrl_key();
-        let shift = event.shift_key();
-        // let alt = event.alt_key();
-        // let meta = event.meta_key();
-        let location = event.location();
-        let capslock = event.get_modifier_state("CapsLock");
-        let upper = capslock ^ shift;
-        let which = event.which();
-        // ConsoleService::log(&format!("which {}, shift {}", which, shift));
-        match which {
-            8_u32 => {
-                // Backspace
-                XK_BackSpace
-            }
-            9_u32 => {
-                // Tab
-                XK_Tab
-            }
-            13_u32 => {
-                // Enter
-                XK_Return
-            }
-            16_u32 => {
-                // ShiftLeft
-                match location {
-                    2_u32 => XK_Shift_R,
-                    _ => XK_Shift_L,
-                }
-            }
-            17_u32 => {
-                // ControlLeft
-                match location {
-                    2_u32 => XK_Control_R,
-                    _ => XK_Control_L,
-                }
-            }
-            18_u32 => {
-                // AltLeft
-                match location {
-                    2_u32 => XK_Alt_R,
-                    _ => XK_Alt_L,
-                }
-            }
-            19_u32 => {
-                // Pause
-                XK_Pause
-            }
-            20_u32 => {
-                // CapsLock
-                XK_Caps_Lock
-            }
-            27_u32 => {
-                // Escape
-                XK_Escape
-            }
-            32_u32 => {
-                // Space
-                XK_space
-            }
-            33_u32 => {
-                // PageUp
-                XK_Page_Up
-            }
-            34_u32 => {
-                // PageDown
-                XK_Page_Down
-            }
-            35_u32 => {
-                // End
-                XK_End
-            }
-            36_u32 => {
-                // Home
-                XK_Home
-            }
-            37_u32 => {
-                // ArrowLeft
-                XK_Left
-            }
-            38_u32 => {
-                // ArrowUp
-                XK_Up
-            }
-            39_u32 => {
-                // ArrowRight
-                XK_Right
-            }
-            40_u32 => {
-                // ArrowDown
-                XK_Down
-            }
-            44_u32 => {
-                // PrintScreen
-                XF86XK_ScreenSaver
-            }
-            45_u32 => {
-                // Insert
-                XK_Insert
-            }
-            46_u32 => {
-                // Delete
-                XK_Delete
-            }
-            48_u32 => {
-                // Digit0
-                if shift {
-                    XK_parenright
-                } else {
-                    XK_0
-                }
-            }
-            49_u32 => {
-                // Digit1
-                if shift {
-                    XK_exclam
-                } else {
-                    XK_1
-                }
-            }
-            50_u32 => {
-                // Digit2
-                if shift {
-                    XK_at
-                } else {
-                    XK_2
-                }
-            }
-            51_u32 => {
-                // Digit3
-                if shift {
-                    XK_numbersign
-                } else {
-                    XK_3
-                }
-            }
-            52_u32 => {
-                // Digit4
-                if shift {
-                    XK_dollar
-                } else {
-                    XK_4
-                }
-            }
-            53_u32 => {
-                // Digit5
-                if shift {
-                    XK_percent
-                } else {
-                    XK_5
-                }
-            }
-            54_u32 => {
-                // Digit6
-                if shift {
-                    XK_asciicircum
-                } else {
-                    XK_6
-                }
-            }
-            55_u32 => {
-                // Digit7
-                if shift {
-                    XK_ampersand
-                } else {
-                    XK_7
-                }
-            }
-            56_u32 => {
-                // Digit8
-                if shift {
-                    XK_asterisk
-                } else {
-                    XK_8
-                }
-            }
-            57_u32 => {
-                // Digit9
-                if shift {
-                    XK_parenleft
-                } else {
-                    XK_9
-                }
-            }
-            65_u32 => {
-                // KeyA
-                if upper {
-                    XK_A
-                } else {
-                    XK_a
-                }
-            }
-            66_u32 => {
-                // KeyB
-                if upper {
-                    XK_B
-                } else {
-                    XK_b
-                }
-            }
-            67_u32 => {
-                // KeyC
-                if upper {
-                    XK_C
-                } else {
-                    XK_c
-                }
-            }
-            68_u32 => {
-                // KeyD
-                if upper {
-                    XK_D
-                } else {
-                    XK_d
-                }
-            }
-            69_u32 => {
-                // KeyE
-                if upper {
-                    XK_E
-                } else {
-                    XK_e
-                }
-            }
-            70_u32 => {
-                // KeyF
-                if upper {
-                    XK_F
-                } else {
-                    XK_f
-                }
-            }
-            71_u32 => {
-                // KeyG
-                if upper {
-                    XK_G
-                } else {
-                    XK_g
-                }
-            }
-            72_u32 => {
-                // KeyH
-                if upper {
-                    XK_H
-                } else {
-                    XK_h
-                }
-            }
-            73_u32 => {
-                // KeyI
-                if upper {
-                    XK_I
-                } else {
-                    XK_i
-                }
-            }
-            74_u32 => {
-                // KeyJ
-                if upper {
-                    XK_J
-                } else {
-                    XK_j
-                }
-            }
-            75_u32 => {
-                // KeyK
-                if upper {
-                    XK_K
-                } else {
-                    XK_k
-                }
-            }
-            76_u32 => {
-                // KeyL
-                if upper {
-                    XK_L
-                } else {
-                    XK_l
-                }
-            }
-            77_u32 => {
-                // KeyM
-                if upper {
-                    XK_M
-                } else {
-                    XK_m
-                }
-            }
-            78_u32 => {
-                // KeyN
-                if upper {
-                    XK_N
-                } else {
-                    XK_n
-                }
-            }
-            79_u32 => {
-                // KeyO
-                if upper {
-                    XK_O
-                } else {
-                    XK_o
-                }
-            }
-            80_u32 => {
-                // KeyP
-                if upper {
-                    XK_P
-                } else {
-                    XK_p
-                }
-            }
-            81_u32 => {
-                // KeyQ
-                if upper {
-                    XK_Q
-                } else {
-                    XK_q
-                }
-            }
-            82_u32 => {
-                // KeyR
-                if upper {
-                    XK_R
-                } else {
-                    XK_r
-                }
-            }
-            83_u32 => {
-                // KeyS
-                if upper {
-                    XK_S
-                } else {
-                    XK_s
-                }
-            }
-            84_u32 => {
-                // KeyT
-                if upper {
-                    XK_T
-                } else {
-                    XK_t
-                }
-            }
-            85_u32 => {
-                // KeyU
-                if upper {
-                    XK_U
-                } else {
-                    XK_u
-                }
-            }
-            86_u32 => {
-                // KeyV
-                if upper {
-                    XK_V
-                } else {
-                    XK_v
-                }
-            }
-            87_u32 => {
-                // KeyW
-                if upper {
-                    XK_W
-                } else {
-                    XK_w
-                }
-            }
-            88_u32 => {
-                // KeyX
-                if upper {
-                    XK_X
-                } else {
-                    XK_x
-                }
-            }
-            89_u32 => {
-                // KeyY
-                if upper {
-                    XK_Y
-                } else {
-                    XK_y
-                }
-            }
-            90_u32 => {
-                // KeyZ
-                if upper {
-                    XK_Z
-                } else {
-                    XK_z
-                }
-            }
-            91_u32 => {
-                // MetaLeft
-                XK_Meta_L
-            }
-            92_u32 => {
-                // MetaRight
-                XK_Meta_R
-            }
-            93_u32 => {
-                // ContextMenu
-                XK_Menu
-            }
-            96_u32 => {
-                // Numpad0
-                XK_KP_0
-            }
-            97_u32 => {
-                // Numpad1
-                XK_KP_1
-            }
-            98_u32 => {
-                // Numpad2
-                XK_KP_2
-            }
-            99_u32 => {
-                // Numpad3
-                XK_KP_3
-            }
-            100_u32 => {
-                // Numpad4
-                XK_KP_4
-            }
-            101_u32 => {
-                // Numpad5
-                XK_KP_5
-            }
-            102_u32 => {
-                // Numpad6
-                XK_KP_6
-            }
-            103_u32 => {
-                // Numpad7
-                XK_KP_7
-            }
-            104_u32 => {
-                // Numpad8
-                XK_KP_8
-            }
-            105_u32 => {
-                // Numpad9
-                XK_KP_9
-            }
-            106_u32 => {
-                // NumpadMultiply
-                XK_KP_Multiply
-            }
-            107_u32 => {
-                // NumpadAdd
-                XK_KP_Add
-            }
-            109_u32 => {
-                // NumpadSubtract
-                XK_KP_Subtract
-            }
-            110_u32 => {
-                // NumpadDecimal
-                XK_KP_Decimal
-            }
-            111_u32 => {
-                // NumpadDivide
-                XK_KP_Divide
-            }
-            112_u32 => {
-                // F1
-                XK_F1
-            }
-            113_u32 => {
-                // F2
-                XK_F2
-            }
-            114_u32 => {
-                // F3
-                XK_F3
-            }
-            115_u32 => {
-                // F4
-                XK_F4
-            }
-            116_u32 => {
-                // F5
-                XK_F5
-            }
-            117_u32 => {
-                // F6
-                XK_F6
-            }
-            118_u32 => {
-                // F7
-                XK_F7
-            }
-            119_u32 => {
-                // F8
-                XK_F8
-            }
-            120_u32 => {
-                // F9
-                XK_F9
-            }
-            121_u32 => {
-                // F10
-                XK_F10
-            }
-            122_u32 => {
-                // F11
-                XK_F11
-            }
-            123_u32 => {
-                // F12
-                XK_F12
-            }
-            144_u32 => {
-                // NumLock
-                XK_Num_Lock
-            }
-            145_u32 => {
-                // ScrollLock
-                XK_Scroll_Lock
-            }
-            186_u32 => {
-                // Semicolon
-                if shift {
-                    XK_colon
-                } else {
-                    XK_semicolon
-                }
-            }
-            187_u32 => {
-                // Equal
-                if shift {
-                    XK_plus
-                } else {
-                    XK_equal
-                }
-            }
-            188_u32 => {
-                // Comma
-                if shift {
-                    XK_less
-                } else {
-                    XK_comma
-                }
-            }
-            189_u32 => {
-                // Minus
-                if shift {
-                    XK_underscore
-                } else {
-                    XK_minus
-                }
-            }
-            190_u32 => {
-                // Period
-                if shift {
-                    XK_greater
-                } else {
-                    XK_period
-                }
-            }
-            191_u32 => {
-                // Slash
-                if shift {
-                    XK_question
-                } else {
-                    XK_slash
-                }
-            }
-            192_u32 => {
-                // Backquote
-                if shift {
-                    XK_asciitilde
-                } else {
-                    XK_grave // also quote left
-                }
-            }
-            219_u32 => {
-                // BracketLeft
-                if shift {
-                    XK_braceleft
-                } else {
-                    XK_bracketleft
-                }
-            }
-            220_u32 => {
-                // Backslash
-                if shift {
-                    XK_bar
-                } else {
-                    XK_backslash
-                }
-            }
-            221_u32 => {
-                // BracketRight
-                if shift {
-                    XK_braceright
-                } else {
-                    XK_bracketright
-                }
-            }
-            222_u32 => {
-                // Quote
-                if shift {
-                    XK_quoteright
-                } else {
-                    XK_quotedbl
-                }
+    /// The keysym of the key pressed, and its XT scancode, 0 if unknown
+    pub fn get_key(event: &web_sys::KeyboardEvent) -> (Option<u32>, u32) {
+        let code = event.code();
+        (
+            keysym(&event.key(), &code, event.location()),
+            scancode(&code),
+        )
+    }
+}
+
+/// The keysym of `KeyboardEvent.key`, which is what the layout of the user
+/// makes of the key, or of `code` for the keys without a name
+///
+/// None for the dead keys, the composed character comes with the next key
+pub fn keysym(key: &str, code: &str, location: u32) -> Option<u32> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if location == LOCATION_NUMPAD {
+            if let Some(keysym) = numpad(c) {
+                return Some(keysym);
             }
-            _ => which,
         }
+        return Some(unicode(c));
+    }
+    named(key, location).or_else(|| from_code(code))
+}
+
+// Latin-1 characters are keysyms themselves, the others are offset
+//     https://www.cl.cam.ac.uk/~mgk25/ucs/keysyms.txt
+fn unicode(c: char) -> u32 {
+    match c as u32 {
+        c @ (0x20..=0x7e | 0xa0..=0xff) => c,
+        c => 0x0100_0000 | c,
+    }
+}
+
+fn numpad(c: char) -> Option<u32> {
+    Some(match c {
+        '0'..='9' => XK_KP_0 + (c as u32 - '0' as u32),
+        '.' => XK_KP_Decimal,
+        ',' => XK_KP_Separator,
+        '+' => XK_KP_Add,
+        '-' => XK_KP_Subtract,
+        '*' => XK_KP_Multiply,
+        '/' => XK_KP_Divide,
+        '=' => XK_KP_Equal,
+        _ => return None,
+    })
+}
+
+// The values of KeyboardEvent.key which are not characters
+//     https://developer.mozilla.org/en-US/docs/Web/API/UI_Events/Keyboard_event_key_values
+fn named(key: &str, location: u32) -> Option<u32> {
+    let right = location == LOCATION_RIGHT;
+    let pad = |keysym: u32, kp: u32| {
+        if location == LOCATION_NUMPAD {
+            kp
+        } else {
+            keysym
+        }
+    };
+    Some(match key {
+        "Backspace" => XK_BackSpace,
+        "Tab" => XK_Tab,
+        "Enter" => pad(XK_Return, XK_KP_Enter),
+        "Escape" | "Esc" => XK_Escape,
+        "Space" => XK_space,
+        "Delete" | "Del" => pad(XK_Delete, XK_KP_Delete),
+        "Insert" => pad(XK_Insert, XK_KP_Insert),
+        "Home" => pad(XK_Home, XK_KP_Home),
+        "End" => pad(XK_End, XK_KP_End),
+        "PageUp" => pad(XK_Page_Up, XK_KP_Page_Up),
+        "PageDown" => pad(XK_Page_Down, XK_KP_Page_Down),
+        "ArrowLeft" | "Left" => pad(XK_Left, XK_KP_Left),
+        "ArrowUp" | "Up" => pad(XK_Up, XK_KP_Up),
+        "ArrowRight" | "Right" => pad(XK_Right, XK_KP_Right),
+        "ArrowDown" | "Down" => pad(XK_Down, XK_KP_Down),
+        "Clear" => pad(XK_Clear, XK_KP_Begin),
+        "Shift" if right => XK_Shift_R,
+        "Shift" => XK_Shift_L,
+        "Control" if right => XK_Control_R,
+        "Control" => XK_Control_L,
+        "Alt" if right => XK_Alt_R,
+        "Alt" => XK_Alt_L,
+        "AltGraph" => XK_ISO_Level3_Shift,
+        // Meta of X is not the logo key
+        "Meta" | "OS" | "Super" | "Win" if right => XK_Super_R,
+        "Meta" | "OS" | "Super" | "Win" => XK_Super_L,
+        "CapsLock" => XK_Caps_Lock,
+        "NumLock" => XK_Num_Lock,
+        "ScrollLock" => XK_Scroll_Lock,
+        "Pause" => XK_Pause,
+        "PrintScreen" => XK_Print,
+        "ContextMenu" => XK_Menu,
+        "Help" => XK_Help,
+        "Cancel" => XK_Cancel,
+        "Select" => XK_Select,
+        "Execute" => XK_Execute,
+        "Undo" => XK_Undo,
+        "Redo" => XK_Redo,
+        "Find" => XK_Find,
+        "Compose" => XK_Multi_key,
+        "Convert" => XK_Henkan,
+        "NonConvert" => XK_Muhenkan,
+        "KanaMode" => XK_Kana_Lock,
+        "KanjiMode" => XK_Kanji,
+        "Romaji" => XK_Romaji,
+        "Hiragana" => XK_Hiragana,
+        "Katakana" => XK_Katakana,
+        "HiraganaKatakana" => XK_Hiragana_Katakana,
+        "Zenkaku" => XK_Zenkaku,
+        "Hankaku" => XK_Hankaku,
+        "ZenkakuHankaku" => XK_Zenkaku_Hankaku,
+        "Eisu" => XK_Eisu_toggle,
+        "HangulMode" => XK_Hangul,
+        "HanjaMode" => XK_Hangul_Hanja,
+        _ => {
+            let n: u32 = key.strip_prefix('F')?.parse().ok()?;
+            if !(1..=35).contains(&n) {
+                return None;
+            }
+            XK_F1 + n - 1
+        }
+    })
+}
+
+// KeyboardEvent.code names the physical key, for a key the layout does not
+// name
+fn from_code(code: &str) -> Option<u32> {
+    if let Some(key) = code.strip_prefix("Numpad") {
+        return match key {
+            "Enter" => Some(XK_KP_Enter),
+            "Add" => Some(XK_KP_Add),
+            "Subtract" => Some(XK_KP_Subtract),
+            "Multiply" => Some(XK_KP_Multiply),
+            "Divide" => Some(XK_KP_Divide),
+            "Decimal" => Some(XK_KP_Decimal),
+            key => key
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n < 10)
+                .map(|n| XK_KP_0 + n),
+        };
+    }
+    let sided = |side: &str, location| named(code.strip_suffix(side)?, location);
+    sided("Left", LOCATION_LEFT)
+        .or_else(|| sided("Right", LOCATION_RIGHT))
+        .or_else(|| named(code, 0))
+}
+
+/// The XT scancode of `KeyboardEvent.code`, as the QEMU Extended Key Event
+/// encodes it, the prefix 0xe0 is the high bit
+///     https://github.com/rfbproto/rfbproto/blob/master/rfbproto.rst#qemu-extended-key-event-message
+pub fn scancode(code: &str) -> u32 {
+    let xt: u32 = match code {
+        "Escape" => 0x01,
+        "Digit1" => 0x02,
+        "Digit2" => 0x03,
+        "Digit3" => 0x04,
+        "Digit4" => 0x05,
+        "Digit5" => 0x06,
+        "Digit6" => 0x07,
+        "Digit7" => 0x08,
+        "Digit8" => 0x09,
+        "Digit9" => 0x0a,
+        "Digit0" => 0x0b,
+        "Minus" => 0x0c,
+        "Equal" => 0x0d,
+        "Backspace" => 0x0e,
+        "Tab" => 0x0f,
+        "KeyQ" => 0x10,
+        "KeyW" => 0x11,
+        "KeyE" => 0x12,
+        "KeyR" => 0x13,
+        "KeyT" => 0x14,
+        "KeyY" => 0x15,
+        "KeyU" => 0x16,
+        "KeyI" => 0x17,
+        "KeyO" => 0x18,
+        "KeyP" => 0x19,
+        "BracketLeft" => 0x1a,
+        "BracketRight" => 0x1b,
+        "Enter" => 0x1c,
+        "ControlLeft" => 0x1d,
+        "KeyA" => 0x1e,
+        "KeyS" => 0x1f,
+        "KeyD" => 0x20,
+        "KeyF" => 0x21,
+        "KeyG" => 0x22,
+        "KeyH" => 0x23,
+        "KeyJ" => 0x24,
+        "KeyK" => 0x25,
+        "KeyL" => 0x26,
+        "Semicolon" => 0x27,
+        "Quote" => 0x28,
+        "Backquote" => 0x29,
+        "ShiftLeft" => 0x2a,
+        "Backslash" => 0x2b,
+        "KeyZ" => 0x2c,
+        "KeyX" => 0x2d,
+        "KeyC" => 0x2e,
+        "KeyV" => 0x2f,
+        "KeyB" => 0x30,
+        "KeyN" => 0x31,
+        "KeyM" => 0x32,
+        "Comma" => 0x33,
+        "Period" => 0x34,
+        "Slash" => 0x35,
+        "ShiftRight" => 0x36,
+        "NumpadMultiply" => 0x37,
+        "AltLeft" => 0x38,
+        "Space" => 0x39,
+        "CapsLock" => 0x3a,
+        "F1" => 0x3b,
+        "F2" => 0x3c,
+        "F3" => 0x3d,
+        "F4" => 0x3e,
+        "F5" => 0x3f,
+        "F6" => 0x40,
+        "F7" => 0x41,
+        "F8" => 0x42,
+        "F9" => 0x43,
+        "F10" => 0x44,
+        "NumLock" => 0x45,
+        "ScrollLock" => 0x46,
+        "Numpad7" => 0x47,
+        "Numpad8" => 0x48,
+        "Numpad9" => 0x49,
+        "NumpadSubtract" => 0x4a,
+        "Numpad4" => 0x4b,
+        "Numpad5" => 0x4c,
+        "Numpad6" => 0x4d,
+        "NumpadAdd" => 0x4e,
+        "Numpad1" => 0x4f,
+        "Numpad2" => 0x50,
+        "Numpad3" => 0x51,
+        "Numpad0" => 0x52,
+        "NumpadDecimal" => 0x53,
+        "IntlBackslash" => 0x56,
+        "F11" => 0x57,
+        "F12" => 0x58,
+        "NumpadEqual" => 0x59,
+        "KanaMode" => 0x70,
+        "IntlRo" => 0x73,
+        "Convert" => 0x79,
+        "NonConvert" => 0x7b,
+        "IntlYen" => 0x7d,
+        "NumpadComma" => 0x7e,
+        "NumpadEnter" => 0xe01c,
+        "ControlRight" => 0xe01d,
+        "NumpadDivide" => 0xe035,
+        "PrintScreen" => 0xe037,
+        "AltRight" => 0xe038,
+        "Pause" => 0xe046,
+        "Home" => 0xe047,
+        "ArrowUp" => 0xe048,
+        "PageUp" => 0xe049,
+        "ArrowLeft" => 0xe04b,
+        "ArrowRight" => 0xe04d,
+        "End" => 0xe04f,
+        "ArrowDown" => 0xe050,
+        "PageDown" => 0xe051,
+        "Insert" => 0xe052,
+        "Delete" => 0xe053,
+        "MetaLeft" | "OSLeft" => 0xe05b,
+        "MetaRight" | "OSRight" => 0xe05c,
+        "ContextMenu" => 0xe05d,
+        _ => 0,
+    };
+    if xt >> 8 == 0xe0 {
+        0x80 | (xt & 0x7f)
+    } else {
+        xt
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keysym() {
+        assert_eq!(keysym("a", "KeyA", 0), Some(XK_a));
+        assert_eq!(keysym("A", "KeyA", 0), Some(XK_A));
+        // the layout decides, an AZERTY key
+        assert_eq!(keysym("q", "KeyA", 0), Some(XK_q));
+        assert_eq!(keysym("é", "Digit2", 0), Some(0xe9));
+        assert_eq!(keysym("€", "KeyE", 0), Some(0x0100_20ac));
+        assert_eq!(keysym("ж", "Semicolon", 0), Some(0x0100_0436));
+        assert_eq!(keysym(" ", "Space", 0), Some(XK_space));
+        assert_eq!(keysym("Dead", "BracketLeft", 0), None);
+
+        assert_eq!(keysym("Enter", "Enter", 0), Some(XK_Return));
+        assert_eq!(keysym("Enter", "NumpadEnter", 3), Some(XK_KP_Enter));
+        assert_eq!(keysym("7", "Numpad7", 3), Some(XK_KP_7));
+        assert_eq!(keysym("Home", "Numpad7", 3), Some(XK_KP_Home));
+        assert_eq!(keysym("Shift", "ShiftRight", 2), Some(XK_Shift_R));
+        assert_eq!(keysym("AltGraph", "AltRight", 2), Some(XK_ISO_Level3_Shift));
+        assert_eq!(keysym("F12", "F12", 0), Some(XK_F1 + 11));
+        assert_eq!(keysym("Fn", "", 0), None);
+
+        // the layout does not name it
+        assert_eq!(keysym("Unidentified", "ControlLeft", 1), Some(XK_Control_L));
+        assert_eq!(keysym("Unidentified", "ArrowLeft", 0), Some(XK_Left));
+        assert_eq!(keysym("Unidentified", "Numpad3", 3), Some(XK_KP_3));
+        assert_eq!(keysym("Unidentified", "KeyA", 0), None);
+    }
+
+    #[test]
+    fn test_scancode() {
+        assert_eq!(scancode("KeyA"), 0x1e);
+        assert_eq!(scancode("ArrowUp"), 0xc8);
+        assert_eq!(scancode("ControlRight"), 0x9d);
+        assert_eq!(scancode("Lang1"), 0);
     }
 }